@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use super::inference;
+use super::synthesis;
+use super::types::Type;
+use crate::parser::expression::{Argument, Expr};
+use crate::parser::symbol::{Location, Symbol, SymbolKind};
+use crate::storage::db::{check_canceled, Storage};
+
+/// A call of a document whose callee resolved to a definition
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CallSite {
+    /// The name of the function the call is made in, or `None` at the module level
+    pub caller: Option<Location>,
+    /// The called definition, along with the uri of the document that holds it
+    pub callee: (String, Symbol),
+    /// The name of the callee at the call site
+    pub location: Location,
+    pub arguments: Vec<(Location, Argument)>,
+    /// Whether the callee is accessed on an instance, as in `object.method(...)`
+    pub on_instance: bool,
+}
+
+/// Get the calls of a document that resolve to a definition. Called names are
+/// resolved through the reference graph, and attributes through the inferred
+/// type of their object.
+pub fn document_calls(db: &dyn Storage, uri: String) -> Arc<Vec<CallSite>> {
+    check_canceled(db);
+    let Some(tree) = db.document_tree(uri.clone()) else {
+        return Arc::new(Vec::new());
+    };
+
+    let calls = tree
+        .calls()
+        .into_iter()
+        .filter_map(|call| {
+            let (callee, on_instance) = match unnarrowed(call.callee) {
+                Expr::Name(name, location) => {
+                    (inference::resolve_name(db, &uri, &name, &location)?, false)
+                }
+                Expr::Attribute(object, name, _) => {
+                    let object = db.type_of(uri.clone(), *object);
+                    let on_instance = object
+                        .members()
+                        .iter()
+                        .any(|member| matches!(member, Type::Instance(..)));
+                    (
+                        inference::member_definition(db, &object, &name)?,
+                        on_instance,
+                    )
+                }
+                _ => return None,
+            };
+            Some(CallSite {
+                caller: call.caller,
+                callee,
+                location: call.location,
+                arguments: call.arguments,
+                on_instance,
+            })
+        })
+        .collect();
+    Arc::new(calls)
+}
+
+/// Get the function a call runs, which for classes is their `__init__`, and
+/// whether the first parameter is bound to the instance or class
+pub fn called_function(db: &dyn Storage, call: &CallSite) -> Option<(String, Symbol, bool)> {
+    let (uri, callee) = &call.callee;
+    match callee.kind {
+        SymbolKind::Class => {
+            let (uri, init) =
+                db.class_member(uri.clone(), callee.clone(), "__init__".to_string())?;
+            Some((uri, init, true))
+        }
+        SymbolKind::Function => {
+            let function = synthesis::function_binding(db, uri, callee)?;
+            let decorators: Vec<&str> = function
+                .decorators
+                .iter()
+                .filter_map(inference::expr_name)
+                .collect();
+            // `__new__` is a static method that is passed the class explicitly
+            let bound = function.class.is_some()
+                && callee.name != "__new__"
+                && !decorators.contains(&"staticmethod")
+                && (call.on_instance
+                    || decorators.contains(&"classmethod")
+                    || inference::IMPLICIT_CLASSMETHODS.contains(&callee.name.as_str()));
+            Some((uri.clone(), callee.clone(), bound))
+        }
+        _ => None,
+    }
+}
+
+/// Get the name a narrowed name stands for
+fn unnarrowed(expr: Expr) -> Expr {
+    match expr {
+        Expr::Narrowed(name, _) | Expr::NotNone(name) => unnarrowed(*name),
+        expr => expr,
+    }
+}
@@ -0,0 +1,710 @@
+use super::calls::{self, CallSite};
+use super::diagnostics::Diagnostic;
+use super::inference;
+use super::synthesis;
+use super::types::Type;
+use crate::parser::expression::{
+    Argument, Binding, Expr, Function, Literal, Parameter, ParameterKind,
+};
+use crate::parser::symbol::{Symbol, SymbolKind};
+use crate::storage::db::Storage;
+
+/// Decorators that leave the signature of a function as it is written
+const FUNCTION_DECORATORS: [&str; 5] = [
+    "staticmethod",
+    "classmethod",
+    "abstractmethod",
+    "override",
+    "final",
+];
+
+/// Decorators that leave a class as it is written
+const CLASS_DECORATORS: [&str; 5] = [
+    "final",
+    "type_check_only",
+    "disjoint_base",
+    "runtime_checkable",
+    "total_ordering",
+];
+
+/// Classes whose instances stand for values of other types, or for any class
+const DYNAMIC_CLASSES: [&str; 3] = ["Any", "super", "type"];
+
+/// Find the type errors of a document: calls that don't match the signature of the
+/// function they run, attributes that known classes don't have, calls of values that
+/// can't be called and values returned from functions annotated `-> None`. Only what
+/// is known for sure is reported, so values of unknown types are never wrong.
+pub fn type_errors(db: &dyn Storage, uri: &str) -> Vec<Diagnostic> {
+    let Some(tree) = db.document_tree(uri.to_string()) else {
+        return Vec::new();
+    };
+
+    let mut diagnostics = Vec::new();
+    for call in db.document_calls(uri.to_string()).iter() {
+        diagnostics.extend(call_arguments(db, call));
+    }
+
+    for call in tree.calls() {
+        if is_rebound(db, uri, &call.callee) {
+            continue;
+        }
+        let callee = db.type_of(uri.to_string(), call.callee);
+        if is_not_callable(db, &callee) {
+            diagnostics.push(Diagnostic {
+                rule: "not-callable",
+                message: format!("`{}` is not callable", callee),
+                location: call.location,
+            });
+        }
+    }
+
+    for attribute in tree.attribute_reads() {
+        let Expr::Attribute(object, name, location) = attribute else {
+            continue;
+        };
+        // The runtime sets dunder attributes that stubs leave out, like `__orig_bases__`
+        if name.starts_with("__") && name.ends_with("__") || is_rebound(db, uri, &object) {
+            continue;
+        }
+        let object = db.type_of(uri.to_string(), *object);
+        if lacks_attribute(db, uri, &object, &name) {
+            diagnostics.push(Diagnostic {
+                rule: "unknown-attribute",
+                message: format!("`{}` has no attribute `{}`", object, name),
+                location,
+            });
+        }
+    }
+
+    let bindings = db.document_bindings(uri.to_string());
+    for returned in tree.returns() {
+        let Some(function) = bindings.function(&returned.function) else {
+            continue;
+        };
+        let returns_none = function.returns == Some(Expr::Literal(Literal::None));
+        if !returns_none || function.is_generator {
+            continue;
+        }
+        let value = db.type_of(uri.to_string(), returned.value);
+        if value != Type::None && !value.members().contains(&Type::Unknown) {
+            diagnostics.push(Diagnostic {
+                rule: "return-value",
+                message: format!("Function annotated `-> None` returns `{}`", value),
+                location: returned.location,
+            });
+        }
+    }
+    // Lines can opt out of type checking
+    let lines: Vec<&str> = tree.source().lines().collect();
+    diagnostics.retain(|diagnostic| {
+        lines
+            .get(diagnostic.location.start.0)
+            .is_none_or(|line| !line.contains("# type: ignore"))
+    });
+    diagnostics
+}
+
+/// Check the arguments of a call against the parameters of the function it runs
+fn call_arguments(db: &dyn Storage, call: &CallSite) -> Vec<Diagnostic> {
+    let Some((function, bound)) = signature(db, call) else {
+        return Vec::new();
+    };
+    let name = &call.callee.1.name;
+
+    let mut parameters: Vec<&Parameter> = function.parameters.iter().collect();
+    if bound
+        && parameters
+            .first()
+            .is_some_and(|first| is_positional(first.kind))
+    {
+        parameters.remove(0);
+    }
+    let positional: Vec<&Parameter> = parameters
+        .iter()
+        .copied()
+        .filter(|parameter| is_positional(parameter.kind))
+        .collect();
+    let has_kind = |kind| parameters.iter().any(|parameter| parameter.kind == kind);
+    let var_positional = has_kind(ParameterKind::VarPositional);
+    let var_keyword = has_kind(ParameterKind::VarKeyword);
+    // What `*args` and `**kwargs` pass is unknown
+    let unpacked = call
+        .arguments
+        .iter()
+        .any(|(_, argument)| matches!(argument, Argument::Unpacked(_)));
+
+    let mut diagnostics = Vec::new();
+    let mut assigned: Vec<&str> = Vec::new();
+    let given = call
+        .arguments
+        .iter()
+        .filter(|(_, argument)| matches!(argument, Argument::Positional(_)))
+        .count();
+    let mut index = 0;
+    for (location, argument) in &call.arguments {
+        match argument {
+            Argument::Positional(_) => {
+                match positional.get(index) {
+                    Some(parameter) => assigned.push(&parameter.name),
+                    None if var_positional || unpacked => {}
+                    None if index == positional.len() => diagnostics.push(Diagnostic {
+                        rule: "call-arguments",
+                        message: format!(
+                            "`{}` takes {} but {} given",
+                            name,
+                            plural(positional.len(), "positional argument"),
+                            match given {
+                                1 => "1 was".to_string(),
+                                given => format!("{} were", given),
+                            }
+                        ),
+                        location: location.clone(),
+                    }),
+                    None => {}
+                }
+                index += 1;
+            }
+            Argument::Keyword(keyword, _) => {
+                let parameter = parameters.iter().find(|parameter| {
+                    parameter.name == *keyword
+                        && matches!(
+                            parameter.kind,
+                            ParameterKind::Positional | ParameterKind::KeywordOnly
+                        )
+                });
+                match parameter {
+                    Some(parameter) if assigned.contains(&parameter.name.as_str()) => {
+                        if !unpacked {
+                            diagnostics.push(Diagnostic {
+                                rule: "call-arguments",
+                                message: format!("Multiple values for parameter `{}`", keyword),
+                                location: location.clone(),
+                            });
+                        }
+                    }
+                    Some(parameter) => assigned.push(&parameter.name),
+                    None if var_keyword => {}
+                    None => diagnostics.push(Diagnostic {
+                        rule: "call-arguments",
+                        message: format!("`{}` has no parameter named `{}`", name, keyword),
+                        location: location.clone(),
+                    }),
+                }
+            }
+            Argument::Unpacked(_) => {}
+        }
+    }
+
+    let missing: Vec<String> = parameters
+        .iter()
+        .filter(|parameter| {
+            is_positional(parameter.kind) || parameter.kind == ParameterKind::KeywordOnly
+        })
+        .filter(|parameter| parameter.default.is_none())
+        .filter(|parameter| !assigned.contains(&parameter.name.as_str()))
+        .map(|parameter| format!("`{}`", parameter.name))
+        .collect();
+    if !missing.is_empty() && !unpacked {
+        let arguments = match missing.len() {
+            1 => "argument",
+            _ => "arguments",
+        };
+        diagnostics.push(Diagnostic {
+            rule: "call-arguments",
+            message: format!(
+                "Missing {} {} in call to `{}`",
+                arguments,
+                missing.join(", "),
+                name
+            ),
+            location: call.location.clone(),
+        });
+    }
+    diagnostics
+}
+
+/// Get the function a call is checked against, along with whether its first
+/// parameter is bound. Decorators other than the well known ones may change the
+/// signature, and so may the `__new__` and metaclass of a class.
+fn signature(db: &dyn Storage, call: &CallSite) -> Option<(Function, bool)> {
+    let (uri, callee) = &call.callee;
+    // The members of `object` are also reached through `super()`, which stands for
+    // the classes that come next in the method resolution order
+    if callee.kind != SymbolKind::Class && is_object_member(db, &call.callee) {
+        return None;
+    }
+    // The instances of metaclasses are classes, whose own members come first
+    if call.on_instance && is_metaclass_method(db, &call.callee) {
+        return None;
+    }
+    if callee.kind == SymbolKind::Class {
+        // `NamedTuple`s are made by `tuple.__new__`, from the fields their `__init__` takes
+        let constructed_by_init = is_known_class(db, uri, callee)
+            && (db
+                .class_member(uri.clone(), callee.clone(), "__new__".to_string())
+                .is_none_or(|new| is_object_member(db, &new))
+                || db
+                    .class_member(uri.clone(), callee.clone(), "__init__".to_string())
+                    .is_some_and(|(uri, init)| synthesis::is_synthesized(db, &uri, &init)));
+        if !constructed_by_init {
+            return None;
+        }
+    }
+
+    let (uri, function, bound) = calls::called_function(db, call)?;
+    let function = synthesis::function_binding(db, &uri, &function)?;
+    let decorated = function.decorators.iter().any(|decorator| {
+        inference::expr_name(decorator).is_none_or(|name| !FUNCTION_DECORATORS.contains(&name))
+    });
+    match decorated {
+        true => None,
+        false => Some((function, bound)),
+    }
+}
+
+/// Check if calling a value of the given type surely fails: it is a module, or
+/// an instance of a known class without a `__call__` method
+fn is_not_callable(db: &dyn Storage, callee: &Type) -> bool {
+    callee.members().iter().all(|member| match member {
+        Type::Module(..) => true,
+        Type::Instance(..) => {
+            is_known_instance(db, member)
+                && inference::member_definition(db, member, "__call__").is_none()
+        }
+        _ => false,
+    })
+}
+
+/// Check if a value of the given type, read in the document at `uri`, surely has no
+/// attribute of the given name. Classes that compute their attributes with
+/// `__getattr__` may have any attribute, and those with `__slots__` set them up
+/// without assigning them.
+fn lacks_attribute(db: &dyn Storage, uri: &str, value: &Type, name: &str) -> bool {
+    value.members().iter().all(|member| match member {
+        Type::Instance(class_uri, class, _) => {
+            is_known_instance(db, member)
+                && inference::member_definition(db, member, name).is_none()
+                && inference::member_definition(db, member, "__slots__").is_none()
+                && inference::member_definition(db, member, "__getattr__").is_none()
+                && inference::member_definition(db, member, "__getattribute__")
+                    .is_none_or(|method| is_object_member(db, &method))
+                && !subclass_defines(db, uri, &(class_uri.clone(), class.clone()), name)
+        }
+        // Classes also have the attributes of their metaclass, which is `type`
+        // for classes that are known
+        Type::Class(uri, class) => {
+            let metaclass = db
+                .module_definition("builtins".to_string(), "type".to_string())
+                .map(|(uri, metaclass)| Type::Instance(uri, metaclass, Vec::new()));
+            is_known_class(db, uri, class)
+                && !has_metaclass(db, uri, class)
+                && inference::member_definition(db, member, name).is_none()
+                && metaclass.is_some_and(|metaclass| {
+                    inference::member_definition(db, &metaclass, name).is_none()
+                })
+                && !subclass_defines(db, uri, &(uri.clone(), class.clone()), name)
+        }
+        _ => false,
+    })
+}
+
+/// Check if a value is an instance of a known class, and stands for itself rather
+/// than for what a descriptor's `__get__` returns
+fn is_known_instance(db: &dyn Storage, value: &Type) -> bool {
+    let Type::Instance(uri, class, _) = value else {
+        return false;
+    };
+    is_known_class(db, uri, class) && inference::member_definition(db, value, "__get__").is_none()
+}
+
+/// Check if everything a class derives from is known, so that its members are all
+/// there is. Decorators and metaclasses other than `ABCMeta` may add members, except
+/// for those of models, whose members are synthesized.
+fn is_known_class(db: &dyn Storage, uri: &str, class: &Symbol) -> bool {
+    db.class_mro(uri.to_string(), class.clone())
+        .iter()
+        .all(|(uri, class)| {
+            let bindings = db.document_bindings(uri.clone());
+            let Some(binding) = bindings.class(&class.location) else {
+                return false;
+            };
+            if DYNAMIC_CLASSES.contains(&class.name.as_str()) {
+                return false;
+            }
+            let decorated = binding.decorators.iter().any(|decorator| {
+                !synthesis::is_model_decorator(decorator)
+                    && inference::expr_name(decorator)
+                        .is_none_or(|name| !CLASS_DECORATORS.contains(&name))
+            });
+            let metaclass = binding
+                .metaclass
+                .as_ref()
+                .is_some_and(|metaclass| inference::expr_name(metaclass) != Some("ABCMeta"));
+            let bases_known = binding.bases.iter().all(|base| {
+                // `Generic[T]`, `Protocol` and `TypedDict` are special forms rather than classes
+                if matches!(
+                    special_form(base),
+                    Some("Generic" | "Protocol" | "TypedDict")
+                ) {
+                    return true;
+                }
+                let classes = db.type_of(uri.clone(), base.clone());
+                is_class_definition(db, uri, base)
+                    && classes
+                        .members()
+                        .iter()
+                        .all(|member| matches!(member, Type::Class(..)))
+            });
+            !decorated && !metaclass && bases_known
+        })
+}
+
+/// Check if a base of a class stands for a class statement rather than for what a
+/// call returned, like the classes `namedtuple()` creates, which are typed as just
+/// `tuple`
+fn is_class_definition(db: &dyn Storage, uri: &str, base: &Expr) -> bool {
+    let definition = match base {
+        Expr::Subscript(value, _) => return is_class_definition(db, uri, value),
+        Expr::Name(name, location) => inference::resolve_name(db, uri, name, location),
+        Expr::Attribute(object, name, _) => {
+            let object = db.type_of(uri.to_string(), *object.clone());
+            inference::member_definition(db, &object, name)
+        }
+        _ => return false,
+    };
+    definition.is_some_and(|(uri, definition)| {
+        let bindings = db.document_bindings(uri);
+        !matches!(
+            bindings.get(&definition.location),
+            Some(Binding::Variable {
+                value: Some(Expr::Call(..)),
+                ..
+            })
+        )
+    })
+}
+
+/// Get the name of a special form a class derives from, as in `Generic[T]`
+fn special_form(base: &Expr) -> Option<&str> {
+    match base {
+        Expr::Subscript(value, _) => inference::expr_name(value),
+        base => inference::expr_name(base),
+    }
+}
+
+/// Check if a class or what it derives from has a metaclass. Protocols have one
+/// that derives from `ABCMeta`.
+fn has_metaclass(db: &dyn Storage, uri: &str, class: &Symbol) -> bool {
+    db.class_mro(uri.to_string(), class.clone())
+        .iter()
+        .any(|(uri, class)| {
+            db.document_bindings(uri.clone())
+                .class(&class.location)
+                .is_some_and(|binding| {
+                    binding.metaclass.is_some()
+                        || binding
+                            .bases
+                            .iter()
+                            .any(|base| special_form(base) == Some("Protocol"))
+                })
+        })
+}
+
+/// Check if a class that derives from the given one has an attribute, among the
+/// classes of the document that defines it and of the document at `uri`. Mixins and
+/// base classes may use attributes that only the classes deriving from them define.
+fn subclass_defines(db: &dyn Storage, uri: &str, class: &(String, Symbol), name: &str) -> bool {
+    [class.0.as_str(), uri].into_iter().any(|document| {
+        let Some(graph) = db.document_reference_graph(document.to_string()) else {
+            return false;
+        };
+        let mut classes = graph
+            .definitions()
+            .filter(|definition| definition.kind == SymbolKind::Class);
+        classes.any(|subclass| {
+            db.class_mro(document.to_string(), subclass.clone())[1..].contains(class)
+                && db
+                    .class_member(document.to_string(), subclass.clone(), name.to_string())
+                    .is_some()
+        })
+    })
+}
+
+/// Check if a name is bound more than once in its scope. Names resolve to the closest
+/// definition before them, which may not be the one their value comes from when the
+/// others are made in other branches, or later in a loop.
+fn is_rebound(db: &dyn Storage, uri: &str, expr: &Expr) -> bool {
+    let (name, location) = match expr {
+        Expr::Name(name, location) => (name, location),
+        Expr::Narrowed(name, _) | Expr::NotNone(name) => return is_rebound(db, uri, name),
+        _ => return false,
+    };
+    let Some(graph) = db.document_reference_graph(uri.to_string()) else {
+        return false;
+    };
+    graph
+        .definition_of(name, location)
+        .is_some_and(|definition| {
+            graph
+                .scope_definitions(&definition.name, &definition.scope_path)
+                .len()
+                > 1
+        })
+}
+
+/// Check if a method is defined by a class that derives from `type`
+fn is_metaclass_method(db: &dyn Storage, (uri, method): &(String, Symbol)) -> bool {
+    let bindings = db.document_bindings(uri.clone());
+    let Some(class_location) = bindings
+        .function(&method.location)
+        .and_then(|function| function.class.clone())
+    else {
+        return false;
+    };
+    let Some(graph) = db.document_reference_graph(uri.clone()) else {
+        return false;
+    };
+    let Some(class) = graph
+        .definitions()
+        .find(|definition| definition.location == class_location)
+    else {
+        return false;
+    };
+    let metaclass = db.module_definition("builtins".to_string(), "type".to_string());
+    metaclass.is_some_and(|metaclass| {
+        db.class_mro(uri.clone(), class.clone())
+            .contains(&metaclass)
+    })
+}
+
+/// Check if a member is the one every object has, as defined by `object`
+fn is_object_member(db: &dyn Storage, member: &(String, Symbol)) -> bool {
+    db.module_definition("builtins".to_string(), "object".to_string())
+        .and_then(|(uri, object)| db.class_member(uri, object, member.1.name.clone()))
+        .is_some_and(|object_member| object_member == *member)
+}
+
+fn is_positional(kind: ParameterKind) -> bool {
+    matches!(
+        kind,
+        ParameterKind::PositionalOnly | ParameterKind::Positional
+    )
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        count => format!("{} {}s", count, noun),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use salsa::ParallelDatabase;
+
+    use super::*;
+    use crate::config::{Settings, Severity};
+    use crate::storage::db::LspDatabase;
+    use crate::storage::line_index::PositionEncoding;
+    use crate::storage::snapshot::LspSnapshot;
+
+    const URI: &str = "file:///test.py";
+
+    /// A database where a document made of `source_code` is open, checked against a
+    /// typeshed of the bundled builtins stub, which is removed once it is dropped
+    struct Checked {
+        db: LspDatabase,
+        typeshed: PathBuf,
+    }
+
+    impl Checked {
+        fn new(name: &str, source_code: &str) -> Self {
+            let typeshed =
+                std::env::temp_dir().join(format!("rypy-checker-{}-{}", name, std::process::id()));
+            let stdlib = typeshed.join("stdlib");
+            std::fs::create_dir_all(&stdlib).unwrap();
+            let builtins = include_str!("../../stubs/builtins.pyi");
+            std::fs::write(stdlib.join("builtins.pyi"), builtins).unwrap();
+
+            let mut db = LspDatabase::with_documents(&[(URI, source_code)]);
+            db.set_typeshed_path(Some(typeshed.clone()));
+            Self { db, typeshed }
+        }
+
+        /// The rules and lines of the type errors of the document
+        fn errors(&self) -> Vec<(&'static str, usize)> {
+            let errors = type_errors(&self.db, URI);
+            errors
+                .iter()
+                .map(|error| (error.rule, error.location.start.0))
+                .collect()
+        }
+    }
+
+    impl Drop for Checked {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.typeshed);
+        }
+    }
+
+    #[test]
+    fn argument_count() {
+        let source_code = r#"
+def f(a, b=1): ...
+def g(*args): ...
+f()
+f(1, 2, 3)
+f(1)
+f(1, 2)
+f(b=2, a=1)
+f(*[1, 2, 3])
+g(1, 2, 3)
+"#;
+        let checked = Checked::new("argument-count", source_code);
+        assert_eq!(
+            checked.errors(),
+            [("call-arguments", 3), ("call-arguments", 4)]
+        );
+    }
+
+    #[test]
+    fn unknown_keyword() {
+        let source_code = r#"
+def f(a, *, b=1): ...
+def g(**kwargs): ...
+f(1, c=2)
+f(1, a=1)
+f(1, b=2)
+g(c=1)
+"#;
+        let checked = Checked::new("unknown-keyword", source_code);
+        // Passing a parameter twice is reported too
+        assert_eq!(
+            checked.errors(),
+            [("call-arguments", 3), ("call-arguments", 4)]
+        );
+    }
+
+    #[test]
+    fn unknown_attribute() {
+        let source_code = r#"
+class A:
+    x = 1
+    def __init__(self):
+        self.y = 2
+class Dynamic:
+    def __getattr__(self, name): ...
+A().z
+A.y
+A().x
+A().y
+Dynamic().z
+A().z  # type: ignore
+"#;
+        let checked = Checked::new("unknown-attribute", source_code);
+        assert_eq!(checked.errors(), [("unknown-attribute", 7)]);
+    }
+
+    #[test]
+    fn not_callable() {
+        let source_code = r#"
+class A: pass
+class C:
+    def __call__(self): ...
+a = A()
+n = 1
+a()
+n()
+A()
+c = C()
+c()
+len("")
+"#;
+        let checked = Checked::new("not-callable", source_code);
+        assert_eq!(checked.errors(), [("not-callable", 6), ("not-callable", 7)]);
+    }
+
+    #[test]
+    fn value_returned_from_none_function() {
+        let source_code = r#"
+def f() -> None:
+    return 1
+def g() -> None:
+    return
+def h() -> None:
+    return None
+def i():
+    return 1
+def j() -> None:
+    yield 1
+"#;
+        let checked = Checked::new("return-value", source_code);
+        assert_eq!(checked.errors(), [("return-value", 2)]);
+    }
+
+    #[test]
+    fn configured_severity() {
+        let source_code = r#"
+class A: pass
+a = A()
+a.x
+a()
+len(1, 2)
+"#;
+        let checked = Checked::new("severity", source_code);
+        // The rules, lines and LSP severities of the diagnostics sent to the client
+        let diagnostics = |configured: &[(&str, Severity)]| {
+            let configured = configured
+                .iter()
+                .map(|(rule, severity)| (rule.to_string(), *severity));
+            let settings = Settings {
+                diagnostics: Some(HashMap::from_iter(configured)),
+                ..Settings::default()
+            };
+            let snapshot = LspSnapshot::new(
+                checked.db.snapshot(),
+                PositionEncoding::Utf16,
+                Arc::default(),
+                Arc::new(settings),
+                Arc::default(),
+            );
+            let diagnostics = snapshot.get_diagnostics(URI);
+            diagnostics
+                .into_iter()
+                .map(|diagnostic| {
+                    let rule = diagnostic.code.unwrap();
+                    (
+                        rule,
+                        diagnostic.range.start.line,
+                        diagnostic.severity.unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            diagnostics(&[]),
+            [
+                ("unknown-attribute".to_string(), 3, 1),
+                ("not-callable".to_string(), 4, 1),
+                ("call-arguments".to_string(), 5, 1),
+            ]
+        );
+        // Rules can be given another severity, or be turned off
+        let configured = [
+            ("unknown-attribute", Severity::Hint),
+            ("not-callable", Severity::None),
+        ];
+        assert_eq!(
+            diagnostics(&configured),
+            [
+                ("unknown-attribute".to_string(), 3, 4),
+                ("call-arguments".to_string(), 5, 1),
+            ]
+        );
+    }
+}
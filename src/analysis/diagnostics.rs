@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use crate::config::Severity;
+use crate::parser::symbol::Location;
+
+/// Rules checked by the server, along with the severity they have unless configured
+pub const RULES: [(&str, Severity); 7] = [
+    ("syntax-error", Severity::Error),
+    ("unresolved-import", Severity::Warning),
+    ("undefined-name", Severity::Warning),
+    ("call-arguments", Severity::Error),
+    ("unknown-attribute", Severity::Error),
+    ("not-callable", Severity::Error),
+    ("return-value", Severity::Error),
+];
+
+/// Names that are there without being defined: the attributes every module has,
+/// those of class bodies and methods, and the helpers type checkers understand
+pub const IMPLICIT_NAMES: [&str; 16] = [
+    "__name__",
+    "__file__",
+    "__doc__",
+    "__package__",
+    "__spec__",
+    "__loader__",
+    "__path__",
+    "__cached__",
+    "__builtins__",
+    "__annotations__",
+    "__dict__",
+    "__debug__",
+    "__module__",
+    "__qualname__",
+    "__class__",
+    "reveal_type",
+];
+
+/// A problem found in a document. The severity is decided
+/// later on, from the rule and the settings.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub message: String,
+    pub location: Location,
+}
+
+/// Get the severity of a rule, taking the configured overrides into account
+pub fn severity(rule: &str, configured: Option<&HashMap<String, Severity>>) -> Severity {
+    configured
+        .and_then(|configured| configured.get(rule).copied())
+        .or_else(|| {
+            RULES
+                .iter()
+                .find(|(name, _)| *name == rule)
+                .map(|(_, severity)| *severity)
+        })
+        .unwrap_or(Severity::Warning)
+}
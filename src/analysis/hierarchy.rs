@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use super::inference;
+use super::types::Type;
+use crate::parser::symbol::Symbol;
+use crate::storage::db::Storage;
+
+/// Get the classes a class derives from, in the order they are listed.
+/// Classes that list no bases derive from `object`.
+pub fn class_bases(db: &dyn Storage, uri: String, class: Symbol) -> Arc<Vec<(String, Symbol)>> {
+    let bindings = db.document_bindings(uri.clone());
+    let Some(binding) = bindings.class(&class.location) else {
+        return Arc::new(Vec::new());
+    };
+
+    let mut bases = Vec::new();
+    for base in &binding.bases {
+        // `TypedDict`s are mappings, with the methods `_TypedDict` declares
+        if inference::expr_name(base) == Some("TypedDict") {
+            bases.extend(db.module_definition("typing".to_string(), "_TypedDict".to_string()));
+            continue;
+        }
+        for base in db.type_of(uri.clone(), base.clone()).members() {
+            // Generic bases like `Generic[T]` are typed as the class itself
+            if let Type::Class(base_uri, base) = base {
+                bases.push((base_uri.clone(), base.clone()));
+            }
+        }
+    }
+
+    // Classes derive from `object` when nothing else is known, as with `Protocol`
+    if bases.is_empty() && class.name != "object" {
+        bases.extend(db.module_definition("builtins".to_string(), "object".to_string()));
+    }
+    Arc::new(bases)
+}
+
+/// Get the method resolution order of a class: the class itself followed by the
+/// classes it derives from, in the order members are looked up in. When the
+/// bases can't be linearized, they are listed depth first instead.
+pub fn class_mro(db: &dyn Storage, uri: String, class: Symbol) -> Arc<Vec<(String, Symbol)>> {
+    let bases = db.class_bases(uri.clone(), class.clone());
+    let mut sequences: Vec<Vec<(String, Symbol)>> = bases
+        .iter()
+        .map(|(base_uri, base)| db.class_mro(base_uri.clone(), base.clone()).to_vec())
+        .collect();
+    sequences.push(bases.to_vec());
+
+    let mut mro = vec![(uri, class)];
+    match c3_merge(sequences.clone()) {
+        Some(merged) => mro.extend(merged),
+        None => {
+            for entry in sequences.into_iter().flatten() {
+                if !mro.contains(&entry) {
+                    mro.push(entry);
+                }
+            }
+        }
+    }
+    Arc::new(mro)
+}
+
+/// Merge linearizations the C3 way: repeatedly take the first head that isn't in the
+/// tail of any sequence. `None` means there is no consistent order.
+pub fn c3_merge<T: Clone + PartialEq>(mut sequences: Vec<Vec<T>>) -> Option<Vec<T>> {
+    let mut merged = Vec::new();
+    loop {
+        sequences.retain(|sequence| !sequence.is_empty());
+        if sequences.is_empty() {
+            return Some(merged);
+        }
+
+        let head = sequences
+            .iter()
+            .map(|sequence| &sequence[0])
+            .find(|head| {
+                sequences
+                    .iter()
+                    .all(|sequence| !sequence[1..].contains(head))
+            })?
+            .clone();
+        for sequence in &mut sequences {
+            if sequence[0] == head {
+                sequence.remove(0);
+            }
+        }
+        merged.push(head);
+    }
+}
+
+/// Classes whose bases derive from themselves are left without bases
+pub fn recover_class_bases(
+    _db: &dyn Storage,
+    _cycle: &[String],
+    _uri: &str,
+    _class: &Symbol,
+) -> Arc<Vec<(String, Symbol)>> {
+    Arc::new(Vec::new())
+}
+
+/// Classes that derive from themselves only have themselves to look members up in
+pub fn recover_class_mro(
+    _db: &dyn Storage,
+    _cycle: &[String],
+    uri: &str,
+    class: &Symbol,
+) -> Arc<Vec<(String, Symbol)>> {
+    Arc::new(vec![(uri.to_string(), class.clone())])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::symbol::SymbolKind;
+    use crate::storage::db::LspDatabase;
+
+    const URI: &str = "file:///test.py";
+
+    /// The names of the classes in the method resolution order of each class,
+    /// in the order they are defined
+    fn mro_names(db: &LspDatabase) -> Vec<Vec<String>> {
+        let graph = db.document_reference_graph(URI.to_string()).unwrap();
+        let mut classes: Vec<&Symbol> = graph
+            .definitions()
+            .filter(|definition| definition.kind == SymbolKind::Class)
+            .collect();
+        classes.sort_by_key(|class| class.location.start);
+        classes
+            .into_iter()
+            .map(|class| {
+                let mro = db.class_mro(URI.to_string(), class.clone());
+                mro.iter().map(|(_, class)| class.name.clone()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn c3_linearization() {
+        // class A; class B(A); class C(A); class D(B, C)
+        let a = vec!["A", "object"];
+        let b = vec!["B", "A", "object"];
+        let c = vec!["C", "A", "object"];
+        assert_eq!(
+            c3_merge(vec![b.clone(), c, vec!["B", "C"]]),
+            Some(vec!["B", "C", "A", "object"])
+        );
+
+        // class E(A, B) can't put A both before and after B
+        assert_eq!(c3_merge(vec![a, b, vec!["A", "B"]]), None);
+    }
+
+    #[test]
+    fn diamond_mro() {
+        let source_code = r#"
+class A: pass
+class B(A): pass
+class C(A): pass
+class D(B, C): pass
+class E(A, B): pass
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        let mros = mro_names(&db);
+        assert_eq!(mros[3], ["D", "B", "C", "A", "object"]);
+        // Bases that can't be linearized are listed depth first
+        assert_eq!(mros[4], ["E", "A", "object", "B"]);
+    }
+
+    #[test]
+    fn cyclic_bases() {
+        // A class listing itself, and classes listing each other
+        let source_code = r#"
+class A(A): pass
+class B(C): pass
+class C(B): pass
+class D(A, B):
+    x = 1
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        // Classes in a cycle only look members up in themselves
+        assert_eq!(
+            mro_names(&db),
+            [vec!["A"], vec!["B"], vec!["C"], vec!["D", "A", "B"]]
+        );
+
+        let graph = db.document_reference_graph(URI.to_string()).unwrap();
+        let d = graph
+            .definitions()
+            .find(|definition| definition.name == "D")
+            .unwrap();
+        let member = db.class_member(URI.to_string(), d.clone(), "x".to_string());
+        assert_eq!(
+            member.map(|(_, member)| member.location.start),
+            Some((5, 4))
+        );
+        let missing = db.class_member(URI.to_string(), d.clone(), "y".to_string());
+        assert_eq!(missing, None);
+    }
+}
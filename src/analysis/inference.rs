@@ -0,0 +1,834 @@
+use super::synthesis;
+use super::types::Type;
+use crate::parser::expression::{Binding, Bindings, Expr, Literal, ParameterKind};
+use crate::parser::symbol::{Location, Symbol, SymbolKind};
+use crate::storage::db::{module_symbol, Storage};
+
+/// Methods that are class methods without being decorated as such
+pub const IMPLICIT_CLASSMETHODS: [&str; 2] = ["__init_subclass__", "__class_getitem__"];
+
+/// Infer the type of an expression of the document at `uri`
+pub fn type_of(db: &dyn Storage, uri: String, expr: Expr) -> Type {
+    match expr {
+        Expr::Name(name, location) => match resolve_name(db, &uri, &name, &location) {
+            Some((uri, definition)) => db.definition_type(uri, definition),
+            None => Type::Unknown,
+        },
+        Expr::Attribute(object, name, _) => member_type(db, &db.type_of(uri, *object), &name),
+        Expr::Call(function, _) => call_type(db, &db.type_of(uri, *function)),
+        // Slices of sequences are sequences of the same kind
+        Expr::Subscript(value, index) if index == [Expr::Slice] => {
+            let value = db.type_of(uri, *value);
+            Type::union(value.members().iter().map(slice_type))
+        }
+        Expr::Subscript(value, _) => {
+            let value = db.type_of(uri, *value);
+            Type::union(
+                value
+                    .members()
+                    .iter()
+                    .map(|value| subscript_type(db, value)),
+            )
+        }
+        Expr::Literal(literal) => literal_type(db, literal),
+        Expr::List(elements) => {
+            let element = Type::union(elements.into_iter().map(|e| db.type_of(uri.clone(), e)));
+            builtin_instance(db, "list", vec![element])
+        }
+        Expr::Set(elements) => {
+            let element = Type::union(elements.into_iter().map(|e| db.type_of(uri.clone(), e)));
+            builtin_instance(db, "set", vec![element])
+        }
+        Expr::Tuple(elements) => {
+            let elements = elements.into_iter().map(|e| db.type_of(uri.clone(), e));
+            builtin_instance(db, "tuple", elements.collect())
+        }
+        Expr::Dict(pairs) => {
+            let (keys, values): (Vec<Expr>, Vec<Expr>) = pairs.into_iter().unzip();
+            let key = Type::union(keys.into_iter().map(|key| db.type_of(uri.clone(), key)));
+            let value = Type::union(values.into_iter().map(|v| db.type_of(uri.clone(), v)));
+            builtin_instance(db, "dict", vec![key, value])
+        }
+        Expr::BinaryOperation(left, operator, _) => {
+            let Some(method) = operator_method(&operator) else {
+                return Type::Unknown;
+            };
+            let left = db.type_of(uri, *left);
+            Type::union(
+                left.members()
+                    .iter()
+                    .map(|left| call_type(db, &member_type(db, left, method))),
+            )
+        }
+        Expr::Either(left, right) => {
+            Type::union([db.type_of(uri.clone(), *left), db.type_of(uri, *right)])
+        }
+        // Calls to coroutine functions are typed by what they return
+        Expr::Await(awaited) => {
+            let awaited = db.type_of(uri, *awaited);
+            Type::union(
+                awaited
+                    .members()
+                    .iter()
+                    .map(|value| awaited_type(db, value)),
+            )
+        }
+        Expr::Narrowed(_, classes) => instance_of(&db.type_of(uri, *classes)),
+        Expr::NotNone(inner) => match db.type_of(uri, *inner) {
+            Type::Union(members) => {
+                Type::union(members.into_iter().filter(|member| *member != Type::None))
+            }
+            other => other,
+        },
+        Expr::Iterated(iterable) => {
+            let iterable = db.type_of(uri, *iterable);
+            Type::union(iterable.members().iter().map(element_type))
+        }
+        Expr::Unpacked(value, index) => match db.type_of(uri, *value) {
+            Type::Instance(_, class, arguments)
+                if class.name == "tuple" && arguments.len() > index =>
+            {
+                arguments[index].clone()
+            }
+            value => Type::union(value.members().iter().map(element_type)),
+        },
+        Expr::Entered(value) => {
+            let value = db.type_of(uri, *value);
+            call_type(db, &member_type(db, &value, "__enter__"))
+        }
+        Expr::Caught(classes) => instance_of(&db.type_of(uri, *classes)),
+        Expr::Slice | Expr::Lambda | Expr::Unknown => Type::Unknown,
+    }
+}
+
+/// Get the type of the name a definition binds
+pub fn definition_type(db: &dyn Storage, uri: String, definition: Symbol) -> Type {
+    // Imported names have the type of what they import
+    let imports = db.document_imports(uri.clone());
+    if let Some(import) = imports
+        .iter()
+        .find(|import| import.location == definition.location)
+    {
+        return match db.import_definition(uri, import.clone()) {
+            Some((uri, module)) if module.kind == SymbolKind::Module => Type::Module(uri, module),
+            Some((uri, imported)) => db.definition_type(uri, imported),
+            None => Type::Unknown,
+        };
+    }
+
+    match definition.kind {
+        SymbolKind::Module => Type::Module(uri, definition),
+        SymbolKind::Class => Type::Class(uri, definition),
+        SymbolKind::Function => Type::Function(uri, definition),
+        SymbolKind::TypeParameter => Type::Unknown,
+        SymbolKind::Variable | SymbolKind::Parameter | SymbolKind::Unknown => {
+            if let Some(member) = synthesis::enum_member_type(db, &uri, &definition) {
+                return member;
+            }
+            let bindings = db.document_bindings(uri.clone());
+            match bindings.get(&definition.location) {
+                Some(Binding::Variable { annotation, value }) => {
+                    // Annotations like `Final` don't say anything about the type
+                    let annotated = match annotation {
+                        Some(annotation) => db.annotation_type(uri.clone(), annotation.clone()),
+                        None => Type::Unknown,
+                    };
+                    match (annotated, value) {
+                        (Type::Unknown, Some(value)) => db.type_of(uri, value.clone()),
+                        (annotated, _) => annotated,
+                    }
+                }
+                Some(Binding::Parameter { function, index }) => {
+                    parameter_type(db, &uri, &bindings, function, *index)
+                }
+                _ => Type::Unknown,
+            }
+        }
+    }
+}
+
+/// Get the type of the values an annotation describes
+pub fn annotation_type(db: &dyn Storage, uri: String, annotation: Expr) -> Type {
+    match annotation {
+        Expr::Literal(Literal::None) => Type::None,
+        Expr::Either(left, right) => Type::union([
+            db.annotation_type(uri.clone(), *left),
+            db.annotation_type(uri, *right),
+        ]),
+        Expr::BinaryOperation(left, operator, right) if operator == "|" => Type::union([
+            db.annotation_type(uri.clone(), *left),
+            db.annotation_type(uri, *right),
+        ]),
+        Expr::Subscript(value, arguments) => {
+            // The type parameters of generic aliases aren't substituted
+            if let Some((alias_uri, value)) = alias_value(db, &uri, &value) {
+                return db.annotation_type(alias_uri, value);
+            }
+            let mut arguments = arguments.into_iter();
+            let name = expr_name(&value).map(str::to_string);
+            match name.as_deref() {
+                Some("Optional") => Type::union([
+                    arguments.next().map_or(Type::Unknown, |argument| {
+                        db.annotation_type(uri.clone(), argument)
+                    }),
+                    Type::None,
+                ]),
+                Some("Union") => {
+                    Type::union(arguments.map(|argument| db.annotation_type(uri.clone(), argument)))
+                }
+                Some(
+                    "Annotated" | "Final" | "ClassVar" | "Required" | "NotRequired" | "ReadOnly",
+                ) => arguments.next().map_or(Type::Unknown, |argument| {
+                    db.annotation_type(uri.clone(), argument)
+                }),
+                Some("Type" | "type") => match arguments.next() {
+                    Some(argument) => class_of(&db.annotation_type(uri, argument)),
+                    None => Type::Unknown,
+                },
+                Some("Literal") => {
+                    Type::union(arguments.map(|argument| db.type_of(uri.clone(), argument)))
+                }
+                name => {
+                    let arguments: Vec<Type> = arguments
+                        .map(|argument| db.annotation_type(uri.clone(), argument))
+                        .collect();
+                    match db.type_of(uri, *value) {
+                        Type::Class(uri, class) => Type::Instance(uri, class, arguments),
+                        _ => match name.and_then(typing_alias) {
+                            Some(builtin) => builtin_instance(db, builtin, arguments),
+                            None => Type::Unknown,
+                        },
+                    }
+                }
+            }
+        }
+        Expr::Name(..) | Expr::Attribute(..) => {
+            if let Some((alias_uri, value)) = alias_value(db, &uri, &annotation) {
+                return db.annotation_type(alias_uri, value);
+            }
+            let name = expr_name(&annotation).map(str::to_string);
+            match db.type_of(uri, annotation) {
+                Type::Class(uri, class) => Type::Instance(uri, class, Vec::new()),
+                Type::None => Type::None,
+                _ => match name.as_deref().and_then(typing_alias) {
+                    Some(builtin) => builtin_instance(db, builtin, Vec::new()),
+                    None => Type::Unknown,
+                },
+            }
+        }
+        _ => Type::Unknown,
+    }
+}
+
+/// Get the type of what calling a function returns
+pub fn return_type(db: &dyn Storage, uri: String, function: Symbol) -> Type {
+    let bindings = db.document_bindings(uri.clone());
+    let Some(binding) = bindings.function(&function.location) else {
+        return Type::Unknown;
+    };
+
+    if let Some(returns) = &binding.returns {
+        return db.annotation_type(uri, returns.clone());
+    }
+    // Stubs only say what they return through annotations
+    if uri.ends_with(".pyi") || binding.is_generator {
+        return Type::Unknown;
+    }
+    if binding.return_values.is_empty() {
+        return Type::None;
+    }
+    Type::union(
+        binding
+            .return_values
+            .iter()
+            .map(|value| db.type_of(uri.clone(), value.clone())),
+    )
+}
+
+/// Find a member of a class, looking through the classes it derives from
+/// in method resolution order when it doesn't define it
+pub fn class_member(
+    db: &dyn Storage,
+    uri: String,
+    class: Symbol,
+    name: String,
+) -> Option<(String, Symbol)> {
+    db.class_mro(uri, class).iter().find_map(|(uri, class)| {
+        let bindings = db.document_bindings(uri.clone());
+        let members = &bindings.class(&class.location)?.members;
+        match members.iter().find(|member| member.name == name) {
+            Some(member) => Some((uri.clone(), member.clone())),
+            // Models have an `__init__` made from their fields
+            None if name == "__init__" => db
+                .synthesized_init(uri.clone(), class.clone())
+                .map(|_| (uri.clone(), synthesis::init_symbol(class))),
+            None => None,
+        }
+    })
+}
+
+/// Find the definition of an attribute of a value of the given type. The
+/// submodules of a package stand in for themselves when it doesn't define them.
+pub fn member_definition(db: &dyn Storage, value: &Type, name: &str) -> Option<(String, Symbol)> {
+    match value {
+        Type::Module(uri, module) => db
+            .exported_definition(uri.clone(), name.to_string())
+            .or_else(|| {
+                if module.name.starts_with('.') {
+                    return None;
+                }
+                let submodule = format!("{}.{}", module.name, name);
+                let uri = db.module_uri(submodule.clone())?;
+                Some((uri, module_symbol(&submodule)))
+            }),
+        Type::Class(uri, class) | Type::Instance(uri, class, _) => {
+            db.class_member(uri.clone(), class.clone(), name.to_string())
+        }
+        Type::Union(members) => members
+            .iter()
+            .find_map(|member| member_definition(db, member, name)),
+        _ => None,
+    }
+}
+
+/// Get the type of an attribute of a value of the given type
+pub fn member_type(db: &dyn Storage, value: &Type, name: &str) -> Type {
+    if let Type::Union(members) = value {
+        return Type::union(members.iter().map(|member| member_type(db, member, name)));
+    }
+    let Some((uri, member)) = member_definition(db, value, name) else {
+        return Type::Unknown;
+    };
+
+    // Properties are accessed like attributes, but only on instances
+    let is_instance = matches!(value, Type::Instance(..));
+    match member.kind == SymbolKind::Function && is_instance && is_property(db, &uri, &member) {
+        true => db.return_type(uri, member),
+        false => db.definition_type(uri, member),
+    }
+}
+
+/// Get the attributes of a value of the given type: the members of its class and
+/// of the classes it derives from, or what a module defines. Each name is only
+/// listed once, as it is found first.
+pub fn members(db: &dyn Storage, value: &Type) -> Vec<(String, Symbol)> {
+    let mut members: Vec<(String, Symbol)> = Vec::new();
+    let mut add = |uri: &str, member: &Symbol| {
+        if !members.iter().any(|(_, seen)| seen.name == member.name) {
+            members.push((uri.to_string(), member.clone()));
+        }
+    };
+
+    match value {
+        Type::Module(uri, _) => {
+            if let Some(graph) = db.document_reference_graph(uri.clone()) {
+                let mut definitions: Vec<&Symbol> = graph
+                    .definitions()
+                    .filter(|definition| definition.scope_path == ["module"])
+                    .collect();
+                definitions.sort_by_key(|definition| definition.location.start);
+                for definition in definitions {
+                    add(uri, definition);
+                }
+            }
+        }
+        Type::Class(uri, class) | Type::Instance(uri, class, _) => {
+            for (uri, class) in db.class_mro(uri.clone(), class.clone()).iter() {
+                let bindings = db.document_bindings(uri.clone());
+                if let Some(binding) = bindings.class(&class.location) {
+                    for member in &binding.members {
+                        add(uri, member);
+                    }
+                }
+            }
+        }
+        Type::Union(types) => {
+            for member in types {
+                for (uri, member) in self::members(db, member) {
+                    add(&uri, &member);
+                }
+            }
+        }
+        _ => {}
+    }
+    members
+}
+
+/// Get the type of what calling a value of the given type returns
+pub fn call_type(db: &dyn Storage, callee: &Type) -> Type {
+    match callee {
+        Type::Class(uri, class) => Type::Instance(uri.clone(), class.clone(), Vec::new()),
+        Type::Function(uri, function) => db.return_type(uri.clone(), function.clone()),
+        Type::Instance(..) => match member_type(db, callee, "__call__") {
+            Type::Function(uri, function) => db.return_type(uri, function),
+            _ => Type::Unknown,
+        },
+        Type::Union(members) => Type::union(members.iter().map(|member| call_type(db, member))),
+        _ => Type::Unknown,
+    }
+}
+
+/// Resolve a name of the document at `uri` to its definition. Imported names resolve
+/// to what they import, and names that aren't defined in the document to builtins.
+pub fn resolve_name(
+    db: &dyn Storage,
+    uri: &str,
+    name: &str,
+    location: &Location,
+) -> Option<(String, Symbol)> {
+    let graph = db.document_reference_graph(uri.to_string())?;
+    let definition = graph.definition_of(name, location).or_else(|| {
+        graph
+            .definitions()
+            .find(|definition| definition.location == *location)
+    });
+
+    // The names of a class body aren't visible in the functions nested in it
+    let definition = definition.filter(|definition| {
+        !db.document_bindings(uri.to_string())
+            .is_class_member(&definition.location)
+            || graph
+                .references()
+                .iter()
+                .find(|reference| reference.location == *location)
+                .is_none_or(|reference| reference.scope_path.len() <= definition.scope_path.len())
+    });
+
+    let Some(definition) = definition else {
+        return db
+            .exported_definition(uri.to_string(), name.to_string())
+            .or_else(|| db.module_definition("builtins".to_string(), name.to_string()));
+    };
+    let imports = db.document_imports(uri.to_string());
+    match imports
+        .iter()
+        .find(|import| import.location == definition.location)
+    {
+        Some(import) => db.import_definition(uri.to_string(), import.clone()),
+        None => Some((uri.to_string(), definition.clone())),
+    }
+}
+
+fn parameter_type(
+    db: &dyn Storage,
+    uri: &str,
+    bindings: &Bindings,
+    function_location: &Location,
+    index: usize,
+) -> Type {
+    let Some(function) = bindings.function(function_location) else {
+        return Type::Unknown;
+    };
+    let Some(parameter) = function.parameters.get(index) else {
+        return Type::Unknown;
+    };
+
+    let declared = match (&parameter.annotation, &function.class) {
+        (Some(annotation), _) => db.annotation_type(uri.to_string(), annotation.clone()),
+        // The first parameter of a method is the instance, or the class for class methods
+        (None, Some(class_location)) if index == 0 => {
+            let decorators: Vec<&str> = function.decorators.iter().filter_map(expr_name).collect();
+            let graph = db.document_reference_graph(uri.to_string());
+            let definition = |location: &Location| {
+                graph.as_ref().and_then(|graph| {
+                    graph
+                        .definitions()
+                        .find(|definition| definition.location == *location)
+                        .cloned()
+                })
+            };
+            // `__new__` is passed the class too, as a static method
+            let implicit_classmethod = definition(function_location).is_some_and(|method| {
+                method.name == "__new__" || IMPLICIT_CLASSMETHODS.contains(&method.name.as_str())
+            });
+            match definition(class_location) {
+                _ if decorators.contains(&"staticmethod") => Type::Unknown,
+                Some(class) if decorators.contains(&"classmethod") || implicit_classmethod => {
+                    Type::Class(uri.to_string(), class)
+                }
+                Some(class) => Type::Instance(uri.to_string(), class, Vec::new()),
+                None => Type::Unknown,
+            }
+        }
+        (None, _) => match &parameter.default {
+            Some(Expr::Literal(Literal::None)) | None => Type::Unknown,
+            Some(default) => db.type_of(uri.to_string(), default.clone()),
+        },
+    };
+
+    match parameter.kind {
+        ParameterKind::VarPositional => builtin_instance(db, "tuple", vec![declared]),
+        ParameterKind::VarKeyword => {
+            let key = builtin_instance(db, "str", Vec::new());
+            builtin_instance(db, "dict", vec![key, declared])
+        }
+        _ => declared,
+    }
+}
+
+/// Get the type an annotation names when it is an alias, as in `type Users = list[User]`
+/// or `Users: TypeAlias = list[User]`, along with the uri of the alias's document
+fn alias_value(db: &dyn Storage, uri: &str, annotation: &Expr) -> Option<(String, Expr)> {
+    let (alias_uri, alias) = match annotation {
+        Expr::Name(name, location) => resolve_name(db, uri, name, location)?,
+        Expr::Attribute(object, name, _) => {
+            let object = db.type_of(uri.to_string(), (**object).clone());
+            member_definition(db, &object, name)?
+        }
+        _ => return None,
+    };
+    let bindings = db.document_bindings(alias_uri.clone());
+    let value = match bindings.get(&alias.location)? {
+        Binding::TypeAlias(value) => value,
+        Binding::Variable {
+            annotation: Some(annotation),
+            value: Some(value),
+        } if expr_name(annotation) == Some("TypeAlias") => value,
+        _ => return None,
+    };
+    Some((alias_uri, value.clone()))
+}
+
+/// Check whether a method is decorated to be accessed like an attribute
+fn is_property(db: &dyn Storage, uri: &str, method: &Symbol) -> bool {
+    let bindings = db.document_bindings(uri.to_string());
+    bindings.function(&method.location).is_some_and(|function| {
+        function
+            .decorators
+            .iter()
+            .filter_map(expr_name)
+            .any(|name| matches!(name, "property" | "cached_property"))
+    })
+}
+
+/// Get the type of the elements of an iterable
+fn element_type(iterable: &Type) -> Type {
+    match iterable {
+        Type::Instance(_, class, _) if class.name == "str" => iterable.clone(),
+        Type::Instance(_, class, arguments) if class.name == "tuple" => {
+            Type::union(arguments.iter().cloned())
+        }
+        Type::Instance(_, _, arguments) => arguments.first().cloned().unwrap_or(Type::Unknown),
+        _ => Type::Unknown,
+    }
+}
+
+/// Get the type of `value[index]`
+fn subscript_type(db: &dyn Storage, value: &Type) -> Type {
+    match value {
+        // A generic class like `list[int]`, used as a value
+        Type::Class(..) => value.clone(),
+        Type::Instance(_, class, arguments) => match (class.name.as_str(), arguments.as_slice()) {
+            ("str" | "bytes", _) => value.clone(),
+            ("tuple", arguments) => Type::union(arguments.iter().cloned()),
+            (
+                "dict" | "defaultdict" | "OrderedDict" | "Counter" | "Mapping" | "MutableMapping",
+                [_, value, ..],
+            ) => value.clone(),
+            (_, [element]) => element.clone(),
+            _ => call_type(db, &member_type(db, value, "__getitem__")),
+        },
+        _ => Type::Unknown,
+    }
+}
+
+/// Get the result of awaiting a value. Awaitables other than coroutines, like
+/// futures, give what their `__await__` generator returns.
+fn awaited_type(db: &dyn Storage, value: &Type) -> Type {
+    match call_type(db, &member_type(db, value, "__await__")) {
+        Type::Unknown => value.clone(),
+        Type::Instance(_, class, arguments)
+            if class.name == "Generator" && arguments.len() == 3 =>
+        {
+            arguments[2].clone()
+        }
+        _ => Type::Unknown,
+    }
+}
+
+fn slice_type(value: &Type) -> Type {
+    match value {
+        Type::Instance(_, class, _)
+            if matches!(
+                class.name.as_str(),
+                "list" | "tuple" | "str" | "bytes" | "bytearray" | "range"
+            ) =>
+        {
+            value.clone()
+        }
+        _ => Type::Unknown,
+    }
+}
+
+fn literal_type(db: &dyn Storage, literal: Literal) -> Type {
+    let class = match literal {
+        Literal::Int => "int",
+        Literal::Float => "float",
+        Literal::Str => "str",
+        Literal::Bytes => "bytes",
+        Literal::Bool(_) => "bool",
+        Literal::None => return Type::None,
+        Literal::Ellipsis => return Type::Unknown,
+    };
+    builtin_instance(db, class, Vec::new())
+}
+
+/// Get an instance of a builtin class
+fn builtin_instance(db: &dyn Storage, name: &str, arguments: Vec<Type>) -> Type {
+    match db.module_definition("builtins".to_string(), name.to_string()) {
+        Some((uri, class)) if class.kind == SymbolKind::Class => {
+            Type::Instance(uri, class, arguments)
+        }
+        _ => Type::Unknown,
+    }
+}
+
+/// Get the instances of the classes a type holds, as in `isinstance(value, classes)`
+fn instance_of(classes: &Type) -> Type {
+    match classes {
+        Type::Class(uri, class) => Type::Instance(uri.clone(), class.clone(), Vec::new()),
+        Type::Instance(_, class, arguments) if class.name == "tuple" => {
+            Type::union(arguments.iter().map(instance_of))
+        }
+        Type::Union(members) => Type::union(members.iter().map(instance_of)),
+        _ => Type::Unknown,
+    }
+}
+
+/// Get the class of instances of a type, as in `type[C]`
+fn class_of(instances: &Type) -> Type {
+    match instances {
+        Type::Instance(uri, class, _) => Type::Class(uri.clone(), class.clone()),
+        Type::Union(members) => Type::union(members.iter().map(class_of)),
+        _ => Type::Unknown,
+    }
+}
+
+/// The `typing` names for builtin classes
+fn typing_alias(name: &str) -> Option<&'static str> {
+    match name {
+        "List" => Some("list"),
+        "Dict" => Some("dict"),
+        "Set" => Some("set"),
+        "FrozenSet" => Some("frozenset"),
+        "Tuple" => Some("tuple"),
+        "Text" | "LiteralString" => Some("str"),
+        _ => None,
+    }
+}
+
+/// The method an arithmetic or bitwise operator calls on its left operand
+fn operator_method(operator: &str) -> Option<&'static str> {
+    let method = match operator {
+        "+" => "__add__",
+        "-" => "__sub__",
+        "*" => "__mul__",
+        "/" => "__truediv__",
+        "//" => "__floordiv__",
+        "%" => "__mod__",
+        "**" => "__pow__",
+        "@" => "__matmul__",
+        "&" => "__and__",
+        "|" => "__or__",
+        "^" => "__xor__",
+        "<<" => "__lshift__",
+        ">>" => "__rshift__",
+        _ => return None,
+    };
+    Some(method)
+}
+
+/// The last name of a possibly dotted name, or of the function a decorator calls
+pub fn expr_name(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Name(name, _) | Expr::Attribute(_, name, _) => Some(name),
+        Expr::Call(function, _) => expr_name(function),
+        _ => None,
+    }
+}
+
+pub fn recover_type_of(_db: &dyn Storage, _cycle: &[String], _uri: &String, _expr: &Expr) -> Type {
+    Type::Unknown
+}
+
+pub fn recover_definition_type(
+    _db: &dyn Storage,
+    _cycle: &[String],
+    _uri: &String,
+    _definition: &Symbol,
+) -> Type {
+    Type::Unknown
+}
+
+pub fn recover_annotation_type(
+    _db: &dyn Storage,
+    _cycle: &[String],
+    _uri: &String,
+    _annotation: &Expr,
+) -> Type {
+    Type::Unknown
+}
+
+pub fn recover_return_type(
+    _db: &dyn Storage,
+    _cycle: &[String],
+    _uri: &String,
+    _function: &Symbol,
+) -> Type {
+    Type::Unknown
+}
+
+pub fn recover_class_member(
+    _db: &dyn Storage,
+    _cycle: &[String],
+    _uri: &String,
+    _class: &Symbol,
+    _name: &String,
+) -> Option<(String, Symbol)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::db::{LspDatabase, Storage};
+
+    const URI: &str = "file:///test.py";
+
+    /// The type inferred for the expression at a position of the document
+    fn type_at(db: &LspDatabase, line: usize, character: usize) -> String {
+        let tree = db.document_tree(URI.to_string()).unwrap();
+        let expr = tree.expression_at((line, character)).unwrap();
+        db.type_of(URI.to_string(), expr).to_string()
+    }
+
+    #[test]
+    fn literals() {
+        let source_code = r#"
+a = 1
+b = 's'
+c = b'x'
+d = 1.5
+e = True
+f = None
+g = [1, 's']
+h = {'k': 1.0}
+i = (1, b'x')
+j = {1}
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        let types: Vec<String> = (1..11).map(|line| type_at(&db, line, 0)).collect();
+        assert_eq!(
+            types,
+            [
+                "int",
+                "str",
+                "bytes",
+                "float",
+                "bool",
+                "None",
+                "list[int | str]",
+                "dict[str, float]",
+                "tuple[int, bytes]",
+                "set[int]",
+            ]
+        );
+    }
+
+    #[test]
+    fn constructor_calls() {
+        let source_code = r#"
+class A: pass
+class B(A):
+    def __init__(self, x: int): ...
+a = A()
+b = B(1)
+c = list()
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        assert_eq!(type_at(&db, 4, 0), "A");
+        assert_eq!(type_at(&db, 5, 0), "B");
+        assert_eq!(type_at(&db, 6, 0), "list");
+        // The class itself, as opposed to its instances
+        assert_eq!(type_at(&db, 4, 4), "type[A]");
+    }
+
+    #[test]
+    fn annotations() {
+        let source_code = r#"
+from typing import List, Optional
+class Item: pass
+def f(x: int, y: Optional[str], z: Item, *args: int, **kwargs: str) -> List[int]:
+    return [x]
+def g(item: Item) -> Item | None: ...
+a = f(1, None, Item())
+b = g(Item())
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        // Parameters, including variadic ones, are typed by their annotation
+        assert_eq!(type_at(&db, 3, 6), "int");
+        assert_eq!(type_at(&db, 3, 14), "str | None");
+        assert_eq!(type_at(&db, 3, 32), "Item");
+        assert_eq!(type_at(&db, 3, 45), "tuple[int]");
+        assert_eq!(type_at(&db, 3, 56), "dict[str, str]");
+        // Calls by the return annotation, rather than what the function returns
+        assert_eq!(type_at(&db, 6, 0), "list[int]");
+        assert_eq!(type_at(&db, 7, 0), "Item | None");
+    }
+
+    #[test]
+    fn narrowing() {
+        let source_code = r#"
+from typing import Optional, Union
+class A: pass
+class B: pass
+def f(x: Optional[A], y: Union[A, B]):
+    print(x)
+    if x is not None:
+        print(x)
+    if x:
+        print(x)
+    if isinstance(y, B):
+        print(y)
+    if isinstance(y, (A, B)):
+        print(y)
+    print(y)
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        assert_eq!(type_at(&db, 5, 10), "A | None");
+        assert_eq!(type_at(&db, 7, 14), "A");
+        assert_eq!(type_at(&db, 9, 14), "A");
+        assert_eq!(type_at(&db, 11, 14), "B");
+        assert_eq!(type_at(&db, 13, 14), "A | B");
+        assert_eq!(type_at(&db, 14, 10), "A | B");
+    }
+
+    #[test]
+    fn attributes_calls_and_subscripts() {
+        let source_code = r#"
+from typing import Optional
+class Item:
+    name: str
+    def size(self) -> int: ...
+class Box:
+    items: list[Item]
+    first: Optional[Item]
+    def get(self) -> Item | None: ...
+box = Box()
+print(box.items[0].name, box.first.name)
+a = box.items[0].size()
+b = box.get()
+c = box.first.size()
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        assert_eq!(type_at(&db, 10, 10), "list[Item]");
+        assert_eq!(type_at(&db, 10, 14), "list[Item]");
+        assert_eq!(type_at(&db, 10, 17), "Item");
+        assert_eq!(type_at(&db, 10, 20), "str");
+        assert_eq!(type_at(&db, 10, 30), "Item | None");
+        assert_eq!(type_at(&db, 11, 0), "int");
+        assert_eq!(type_at(&db, 12, 0), "Item | None");
+        // `None` has no attributes, which leaves the type of those it lacks unknown
+        assert_eq!(type_at(&db, 10, 36), "str | Unknown");
+        assert_eq!(type_at(&db, 13, 0), "int | Unknown");
+    }
+}
@@ -1 +1,9 @@
-pub mod reference;
\ No newline at end of file
+pub mod calls;
+pub mod checker;
+pub mod diagnostics;
+pub mod hierarchy;
+pub mod inference;
+pub mod reference;
+pub mod synthesis;
+pub mod tokens;
+pub mod types;
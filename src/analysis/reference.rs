@@ -40,7 +40,7 @@ impl ReferenceGraph {
 
         // Then resolve each reference to its symbol
         for reference in &graph.references {
-            if let Some(definition) = graph.resolve_reference(&reference) {
+            if let Some(definition) = graph.resolve_reference(reference) {
                 let definition_id = graph.create_symbol_id(&reference.name, &reference.location);
                 graph.resolved_references.insert(definition_id, definition);
             }
@@ -79,6 +79,110 @@ impl ReferenceGraph {
         self.resolved_references.get(&id)
     }
 
+    /// Get the definition whose name is at the given position
+    pub fn get_definition_by_location(&self, position: Position) -> Option<&symbol::Symbol> {
+        self.definitions
+            .values()
+            .flatten()
+            .find(|definition| definition.location.contains((position.line, position.character)))
+    }
+
+    /// Find the occurrences of the name at the given position: the definitions that
+    /// bind it in its scope, and the references that resolve to one of them. Names
+    /// that aren't defined in the document, like builtins, are matched by name.
+    pub fn find_occurrences(
+        &self,
+        position: Position,
+    ) -> Option<(Vec<&symbol::Symbol>, Vec<&symbol::Reference>)> {
+        let definition = match self.get_definition_by_location(position.clone()) {
+            Some(definition) => Some(definition),
+            None => {
+                let reference = self.get_symbol_by_location(position)?;
+                match self.resolved_definition(reference) {
+                    Some(definition) => Some(definition),
+                    None => {
+                        let references = self
+                            .references
+                            .iter()
+                            .filter(|other| {
+                                other.name == reference.name
+                                    && self.resolved_definition(other).is_none()
+                            })
+                            .collect();
+                        return Some((Vec::new(), references));
+                    }
+                }
+            }
+        };
+
+        // Every definition of the name in the same scope binds the same variable
+        let definition = definition?;
+        let id = self.create_symbol_scope_id(&definition.name, &definition.scope_path);
+        let definitions = self.definitions.get(&id)?.iter().collect();
+        let references = self
+            .references
+            .iter()
+            .filter(|reference| {
+                self.resolved_definition(reference).is_some_and(|resolved| {
+                    self.create_symbol_scope_id(&resolved.name, &resolved.scope_path) == id
+                })
+            })
+            .collect();
+        Some((definitions, references))
+    }
+
+    /// Get the definition a reference resolves to in the document
+    pub fn resolved_definition(&self, reference: &symbol::Reference) -> Option<&symbol::Symbol> {
+        let id = self.create_symbol_id(&reference.name, &reference.location);
+        self.resolved_references.get(&id)
+    }
+
+    /// Get the definition the reference to `name` at a location resolves to
+    pub fn definition_of(&self, name: &str, location: &Location) -> Option<&symbol::Symbol> {
+        self.resolved_references
+            .get(&self.create_symbol_id(name, location))
+    }
+
+    /// Get all the definitions of the document
+    pub fn definitions(&self) -> impl Iterator<Item = &symbol::Symbol> {
+        self.definitions.values().flatten()
+    }
+
+    /// Get all the references of the document
+    pub fn references(&self) -> &[symbol::Reference] {
+        &self.references
+    }
+
+    /// Get the definitions of a name made at the top level of the module, in order.
+    /// Definitions in class bodies are also in the module scope.
+    pub fn module_definitions(&self, name: &str) -> &[symbol::Symbol] {
+        self.scope_definitions(name, &["module".to_string()])
+    }
+
+    /// Get the definitions of a name made in a scope, in order
+    pub fn scope_definitions(&self, name: &str, scope_path: &[String]) -> &[symbol::Symbol] {
+        let id = self.create_symbol_scope_id(name, scope_path);
+        self.definitions.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Get the definitions visible from the given scope, innermost scopes first.
+    /// Only the first definition of each name in a scope is returned.
+    pub fn visible_definitions(&self, scope_path: &[String]) -> Vec<&symbol::Symbol> {
+        let mut visible: Vec<&symbol::Symbol> = self
+            .definitions
+            .values()
+            .filter_map(|definitions| definitions.first())
+            .filter(|definition| scope_path.starts_with(&definition.scope_path))
+            .collect();
+        visible.sort_by(|a, b| {
+            b.scope_path
+                .len()
+                .cmp(&a.scope_path.len())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        visible
+    }
+
     /// Create a ID for a symbol based on its name, scope path (! not unique)
     fn create_symbol_scope_id(&self, name: &str, scope_path: &[String]) -> String {
         format!("{}:{}", scope_path.join("/"), name)
@@ -105,17 +209,7 @@ impl ReferenceGraph {
                     current_scope, definitions
                 );
 
-                // Find the definition that is closest to the reference
-                // by comparing their locations
-                if let Some(best_definition) = definitions.iter().min_by_key(|d| {
-                    // If the definition is before the reference, use the distance between them
-                    // Otherwise, use a large value to prefer definitions that come before
-                    if d.location.start.0 <= reference.location.start.0 {
-                        reference.location.start.0 - d.location.start.0
-                    } else {
-                        std::usize::MAX
-                    }
-                }) {
+                if let Some(best_definition) = self.closest_definition(definitions, reference) {
                     return Some(best_definition.clone());
                 }
             }
@@ -128,18 +222,112 @@ impl ReferenceGraph {
         let global_id = self.create_symbol_scope_id(&reference.name, &["module".to_string()]);
         if let Some(definitions) = self.definitions.get(&global_id) {
             debug!("Found symbols in global scope: {:?}", definitions);
-            return definitions
-                .iter()
-                .min_by_key(|d| {
-                    if d.location.start.0 <= reference.location.start.0 {
-                        reference.location.start.0 - d.location.start.0
-                    } else {
-                        std::usize::MAX
-                    }
-                })
-                .cloned();
+            return self.closest_definition(definitions, reference).cloned();
         }
 
         None
     }
+
+    /// Find the definition that is closest to the reference by comparing their locations.
+    /// Deferred references see the last definition, which is the one bound once the
+    /// scope has run.
+    fn closest_definition<'a>(
+        &self,
+        definitions: &'a [symbol::Symbol],
+        reference: &symbol::Reference,
+    ) -> Option<&'a symbol::Symbol> {
+        if reference.deferred {
+            return definitions.iter().max_by_key(|d| d.location.start);
+        }
+        // The targets of an assignment aren't bound yet when its value is computed
+        let assigned = |d: &symbol::Symbol| {
+            reference.assigned_at.is_some_and(|start| {
+                start <= d.location.start && d.location.start < reference.location.start
+            })
+        };
+        definitions.iter().min_by_key(|d| {
+            // If the definition is before the reference, use the distance between them
+            // Otherwise, use a large value to prefer definitions that come before
+            if d.location.start.0 <= reference.location.start.0 && !assigned(d) {
+                reference.location.start.0 - d.location.start.0
+            } else {
+                usize::MAX
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parser::{Parser, TreeSitterParser};
+
+    #[test]
+    fn shadowed_occurrences() {
+        let source_code = r#"
+x = 1
+def outer(y):
+    print(x, y)
+    def inner():
+        x = 2
+        return x
+    return inner
+for i in range(x):
+    print(i)
+x += 1
+"#;
+        let mut parser = TreeSitterParser::new().unwrap();
+        let (definitions, references) = parser.parse(source_code).unwrap();
+        let graph = ReferenceGraph::build(definitions, references);
+
+        // The lines of the definitions and references found from a position
+        let lines = |line, character| {
+            let (definitions, references) = graph
+                .find_occurrences(Position { line, character })
+                .unwrap();
+            let mut definitions: Vec<usize> =
+                definitions.iter().map(|d| d.location.start.0).collect();
+            definitions.sort();
+            let references: Vec<usize> = references.iter().map(|r| r.location.start.0).collect();
+            (definitions, references)
+        };
+
+        // The module's `x` is read in `outer`, while `inner` has its own
+        assert_eq!(lines(1, 0), (vec![1, 10], vec![3, 8]));
+        assert_eq!(lines(6, 15), (vec![5], vec![6]));
+        assert_eq!(lines(9, 10), (vec![8], vec![9]));
+
+        // Builtins are matched by name
+        assert_eq!(lines(3, 4), (vec![], vec![3, 9]));
+    }
+
+    #[test]
+    fn assigned_names_read_in_their_value() {
+        let source_code = r#"
+path = "a"
+path = normalize(path)
+items = [item for item in path]
+"#;
+        let mut parser = TreeSitterParser::new().unwrap();
+        let (definitions, references) = parser.parse(source_code).unwrap();
+        let graph = ReferenceGraph::build(definitions, references);
+
+        // The line of the definition the reference at a position resolves to
+        let resolved = |line, character| {
+            let reference = graph
+                .references()
+                .iter()
+                .find(|reference| reference.location.start == (line, character))
+                .unwrap();
+            graph
+                .resolved_definition(reference)
+                .map(|definition| definition.location.start.0)
+        };
+
+        // `path` is read before the assignment rebinds it, while comprehension
+        // variables are bound before the element is computed
+        assert_eq!(resolved(2, 17), Some(1));
+        assert_eq!(resolved(3, 26), Some(2));
+        assert_eq!(resolved(3, 9), Some(3));
+    }
 }
@@ -0,0 +1,542 @@
+use std::sync::Arc;
+
+use super::inference;
+use super::types::Type;
+use crate::parser::expression::{
+    Argument, Binding, Class, Expr, Function, Literal, Parameter, ParameterKind,
+};
+use crate::parser::symbol::{Symbol, SymbolKind};
+use crate::storage::db::Storage;
+
+/// The kinds of classes whose `__init__` is made from the fields of their body
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Model {
+    Dataclass,
+    /// `attrs` classes, whose classic API (`@attr.s`) only takes `attr.ib()` fields
+    Attrs {
+        classic: bool,
+    },
+    NamedTuple,
+    TypedDict,
+}
+
+/// Check if a class decorator makes a model, like `@dataclass` and `@attr.s`
+pub fn is_model_decorator(decorator: &Expr) -> bool {
+    decorator_model(decorator).is_some()
+}
+
+/// Get the symbol of the `__init__` synthesized for a model, which is at the name
+/// of its class
+pub fn init_symbol(class: &Symbol) -> Symbol {
+    Symbol {
+        name: "__init__".to_string(),
+        kind: SymbolKind::Function,
+        location: class.location.clone(),
+        scope_path: class.scope_path.clone(),
+    }
+}
+
+/// Check if a method is the `__init__` synthesized for a model
+pub fn is_synthesized(db: &dyn Storage, uri: &str, method: &Symbol) -> bool {
+    method.kind == SymbolKind::Function
+        && db
+            .document_bindings(uri.to_string())
+            .class(&method.location)
+            .is_some()
+}
+
+/// Get the function a definition binds, which for the `__init__` of a model is the
+/// one synthesized from its fields
+pub fn function_binding(db: &dyn Storage, uri: &str, function: &Symbol) -> Option<Function> {
+    if !is_synthesized(db, uri, function) {
+        let bindings = db.document_bindings(uri.to_string());
+        return bindings.function(&function.location).cloned();
+    }
+    let graph = db.document_reference_graph(uri.to_string())?;
+    let class = graph.definitions().find(|definition| {
+        definition.kind == SymbolKind::Class && definition.location == function.location
+    })?;
+    let init = db.synthesized_init(uri.to_string(), class.clone())?;
+    Some((*init).clone())
+}
+
+/// Synthesize the `__init__` of a dataclass, `attrs` class, `NamedTuple` or
+/// `TypedDict` from its fields, after those of the models of the same kind it derives
+/// from. Classes that derive from a model without being one use its `__init__`,
+/// except for the classes deriving from a `TypedDict`, which add up its keys.
+pub fn synthesized_init(db: &dyn Storage, uri: String, class: Symbol) -> Option<Arc<Function>> {
+    let (model, arguments) = class_model(db, &uri, &class)?;
+    if flag(&arguments, "init") == Some(false) {
+        return None;
+    }
+
+    let mut fields: Vec<Parameter> = Vec::new();
+    for (base_uri, base) in db.class_mro(uri.clone(), class.clone()).iter().rev() {
+        let Some((base_model, arguments)) = class_model(db, base_uri, base) else {
+            continue;
+        };
+        if std::mem::discriminant(&base_model) != std::mem::discriminant(&model) {
+            continue;
+        }
+        // Fields that are defined again keep their place
+        for field in own_fields(db, base_uri, base, base_model, &arguments) {
+            match fields
+                .iter_mut()
+                .find(|existing| existing.name == field.name)
+            {
+                Some(existing) => *existing = field,
+                None => fields.push(field),
+            }
+        }
+    }
+    fields.sort_by_key(|field| field.kind == ParameterKind::KeywordOnly);
+
+    let mut parameters = vec![Parameter {
+        name: "self".to_string(),
+        location: class.location.clone(),
+        kind: ParameterKind::Positional,
+        annotation: None,
+        default: None,
+    }];
+    parameters.extend(fields);
+    Some(Arc::new(Function {
+        parameters,
+        parameters_end: class.location.end,
+        returns: Some(Expr::Literal(Literal::None)),
+        return_values: Vec::new(),
+        decorators: Vec::new(),
+        class: Some(class.location.clone()),
+        is_async: false,
+        is_generator: false,
+    }))
+}
+
+/// Get the type of an enum member, which is an instance of its class rather than the
+/// value assigned to it. Names with underscores at both ends, private names and
+/// annotations without a value aren't members.
+pub fn enum_member_type(db: &dyn Storage, uri: &str, definition: &Symbol) -> Option<Type> {
+    let bindings = db.document_bindings(uri.to_string());
+    let class_location = bindings.member_class(&definition.location)?;
+    let name = &definition.name;
+    if name.starts_with('_') && name.ends_with('_') || name.starts_with("__") {
+        return None;
+    }
+    let Some(Binding::Variable {
+        value: Some(value), ..
+    }) = bindings.get(&definition.location)
+    else {
+        return None;
+    };
+    match value {
+        Expr::Lambda => return None,
+        Expr::Call(function, _) if inference::expr_name(function) == Some("nonmember") => {
+            return None
+        }
+        _ => {}
+    }
+
+    let graph = db.document_reference_graph(uri.to_string())?;
+    let class = graph
+        .definitions()
+        .find(|definition| definition.location == *class_location)?;
+    // Attributes that methods assign to `self` aren't members either
+    if definition.scope_path != class.scope_path {
+        return None;
+    }
+    let enum_class = db.module_definition("enum".to_string(), "Enum".to_string())?;
+    db.class_mro(uri.to_string(), class.clone())
+        .contains(&enum_class)
+        .then(|| Type::Instance(uri.to_string(), class.clone(), Vec::new()))
+}
+
+/// Get the kind of model a class is, along with the arguments of the decorator that
+/// makes it one. The classes deriving from a `TypedDict` are `TypedDict`s too.
+fn class_model(db: &dyn Storage, uri: &str, class: &Symbol) -> Option<(Model, Vec<Argument>)> {
+    let bindings = db.document_bindings(uri.to_string());
+    let binding = bindings.class(&class.location)?;
+    if let Some((model, arguments)) = own_model(binding) {
+        return Some((model, arguments.to_vec()));
+    }
+    let typed_dict = db
+        .class_mro(uri.to_string(), class.clone())
+        .iter()
+        .any(|(uri, base)| {
+            let bindings = db.document_bindings(uri.clone());
+            bindings
+                .class(&base.location)
+                .and_then(own_model)
+                .is_some_and(|(model, _)| model == Model::TypedDict)
+        });
+    typed_dict.then(|| (Model::TypedDict, Vec::new()))
+}
+
+/// Get the kind of model the decorators or bases of a class statement make
+fn own_model(class: &Class) -> Option<(Model, &[Argument])> {
+    if let Some(model) = class.decorators.iter().find_map(decorator_model) {
+        return Some(model);
+    }
+    class
+        .bases
+        .iter()
+        .find_map(|base| match special_name(base)? {
+            "NamedTuple" => Some((Model::NamedTuple, [].as_slice())),
+            "TypedDict" => Some((Model::TypedDict, [].as_slice())),
+            _ => None,
+        })
+}
+
+/// Get the kind of model a class decorator makes, along with its arguments
+fn decorator_model(decorator: &Expr) -> Option<(Model, &[Argument])> {
+    let (function, arguments) = match decorator {
+        Expr::Call(function, arguments) => (function.as_ref(), arguments.as_slice()),
+        decorator => (decorator, [].as_slice()),
+    };
+    let attrs = |classic| Model::Attrs {
+        classic: classic && flag(arguments, "auto_attribs") != Some(true),
+    };
+    let model = match function {
+        Expr::Attribute(module, name, _)
+            if matches!(inference::expr_name(module), Some("attr" | "attrs")) =>
+        {
+            match name.as_str() {
+                "s" | "attrs" => attrs(true),
+                "define" | "mutable" | "frozen" | "dataclass" => attrs(false),
+                _ => return None,
+            }
+        }
+        function => match inference::expr_name(function)? {
+            "dataclass" => Model::Dataclass,
+            "attrs" => attrs(true),
+            "define" | "mutable" | "frozen" => attrs(false),
+            _ => return None,
+        },
+    };
+    Some((model, arguments))
+}
+
+/// Get the fields a model defines in its own body, as parameters of its `__init__`
+fn own_fields(
+    db: &dyn Storage,
+    uri: &str,
+    class: &Symbol,
+    model: Model,
+    arguments: &[Argument],
+) -> Vec<Parameter> {
+    let bindings = db.document_bindings(uri.to_string());
+    let Some(binding) = bindings.class(&class.location) else {
+        return Vec::new();
+    };
+    let mut keyword_only = model == Model::TypedDict || flag(arguments, "kw_only") == Some(true);
+    // `total=False` makes the keys of a `TypedDict` optional
+    let total = binding.keywords.iter().all(|(name, value)| {
+        name != "total" || *value != Expr::Literal(Literal::Bool(Some(false)))
+    });
+
+    let mut fields = Vec::new();
+    for member in &binding.members {
+        // Fields are assigned in the class body, unlike the attributes of `self`
+        if member.kind != SymbolKind::Variable || member.scope_path != class.scope_path {
+            continue;
+        }
+        let Some(Binding::Variable { annotation, value }) = bindings.get(&member.location) else {
+            continue;
+        };
+        let special = annotation.as_ref().and_then(special_name);
+        match special {
+            Some("ClassVar") => continue,
+            // The fields after `_: KW_ONLY` are keyword-only
+            Some("KW_ONLY") => {
+                keyword_only = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        // Fields can be set up with `field(...)`, or `attr.ib(...)` for `attrs`
+        let field = match value {
+            Some(Expr::Call(function, arguments))
+                if matches!(
+                    inference::expr_name(function),
+                    Some("field" | "ib" | "attrib")
+                ) =>
+            {
+                Some(arguments.as_slice())
+            }
+            _ => None,
+        };
+        let is_field = match model {
+            Model::Attrs { classic: true } => field.is_some(),
+            _ => annotation.is_some() || field.is_some(),
+        };
+        let field_arguments = field.unwrap_or_default();
+        if !is_field || flag(field_arguments, "init") == Some(false) {
+            continue;
+        }
+
+        let optional = match (model, field) {
+            (Model::TypedDict, _) => {
+                special == Some("NotRequired") || !total && special != Some("Required")
+            }
+            (_, Some(arguments)) => arguments.iter().any(|argument| match argument {
+                Argument::Keyword(name, _) => {
+                    matches!(name.as_str(), "default" | "default_factory" | "factory")
+                }
+                Argument::Positional(_) => true,
+                Argument::Unpacked(_) => false,
+            }),
+            (_, None) => value.is_some(),
+        };
+        // `attrs` leaves the underscores of private attributes out of their parameter
+        let name = match model {
+            Model::Attrs { .. } => member.name.trim_start_matches('_').to_string(),
+            _ => member.name.clone(),
+        };
+        let kind = match keyword_only || flag(field_arguments, "kw_only") == Some(true) {
+            true => ParameterKind::KeywordOnly,
+            false => ParameterKind::Positional,
+        };
+        fields.push(Parameter {
+            name,
+            location: member.location.clone(),
+            kind,
+            annotation: annotation.clone(),
+            // Optional keys of a `TypedDict` have no default, but can be left out
+            default: optional.then(|| value.clone().unwrap_or(Expr::Unknown)),
+        });
+    }
+    fields
+}
+
+/// Get the value of a `True` or `False` keyword argument
+fn flag(arguments: &[Argument], keyword: &str) -> Option<bool> {
+    arguments.iter().find_map(|argument| match argument {
+        Argument::Keyword(name, Expr::Literal(Literal::Bool(value))) if name == keyword => *value,
+        _ => None,
+    })
+}
+
+/// Get the name of a special form, as in `ClassVar[int]` or `NamedTuple`
+fn special_name(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Subscript(value, _) => inference::expr_name(value),
+        expr => inference::expr_name(expr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::db::LspDatabase;
+
+    const URI: &str = "file:///test.py";
+
+    /// The signature of the `__init__` synthesized for each class of the document,
+    /// or `-` for the classes that have none
+    fn signatures(db: &LspDatabase) -> Vec<(String, String)> {
+        let graph = db.document_reference_graph(URI.to_string()).unwrap();
+        let mut classes: Vec<&Symbol> = graph
+            .definitions()
+            .filter(|definition| definition.kind == SymbolKind::Class)
+            .collect();
+        classes.sort_by_key(|class| class.location.start);
+        classes
+            .into_iter()
+            .map(|class| {
+                let signature = match db.synthesized_init(URI.to_string(), class.clone()) {
+                    Some(init) => signature(db, &init),
+                    None => "-".to_string(),
+                };
+                (class.name.clone(), signature)
+            })
+            .collect()
+    }
+
+    fn signature(db: &LspDatabase, function: &Function) -> String {
+        let mut parameters = Vec::new();
+        let mut keyword_only = false;
+        for parameter in &function.parameters {
+            if parameter.kind == ParameterKind::KeywordOnly && !keyword_only {
+                keyword_only = true;
+                parameters.push("*".to_string());
+            }
+            let mut text = parameter.name.clone();
+            if let Some(annotation) = &parameter.annotation {
+                let annotation = db.annotation_type(URI.to_string(), annotation.clone());
+                text.push_str(&format!(": {}", annotation));
+            }
+            if parameter.default.is_some() {
+                text.push_str(" = ...");
+            }
+            parameters.push(text);
+        }
+        format!("({})", parameters.join(", "))
+    }
+
+    fn expected(signatures: &[(&str, &str)]) -> Vec<(String, String)> {
+        signatures
+            .iter()
+            .map(|(class, signature)| (class.to_string(), signature.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn dataclasses() {
+        let source_code = r#"
+from dataclasses import dataclass, field, KW_ONLY
+from typing import ClassVar
+@dataclass
+class A:
+    x: int
+    y: str = "a"
+    z: list[int] = field(default_factory=list)
+    w: int = field(kw_only=True)
+    c: ClassVar[int] = 1
+    n: int = field(init=False)
+    def method(self): ...
+@dataclass(kw_only=True)
+class B(A):
+    v: int
+    x: float = 1.0
+@dataclass
+class C:
+    a: int
+    _: KW_ONLY
+    b: int
+@dataclass(init=False)
+class D:
+    a: int
+class E(A): pass
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        // Fields defined again keep their place, and keyword-only fields go last.
+        // Classes that derive from a dataclass without being one use its `__init__`.
+        assert_eq!(
+            signatures(&db),
+            expected(&[
+                (
+                    "A",
+                    "(self, x: int, y: str = ..., z: list[int] = ..., *, w: int)"
+                ),
+                (
+                    "B",
+                    "(self, y: str = ..., z: list[int] = ..., *, x: float = ..., w: int, v: int)"
+                ),
+                ("C", "(self, a: int, *, b: int)"),
+                ("D", "-"),
+                ("E", "-"),
+            ])
+        );
+    }
+
+    #[test]
+    fn attrs_classes() {
+        let source_code = r#"
+import attr
+@attr.s
+class A:
+    a = attr.ib()
+    b: int
+    _c = attr.ib(default=1)
+@attr.s(auto_attribs=True)
+class B:
+    a: int
+    b: int = attr.ib(kw_only=True)
+@attr.define
+class C:
+    a: int
+    _b: str = ""
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        // The classic API only takes `attr.ib()` fields, and private fields lose
+        // their underscore
+        assert_eq!(
+            signatures(&db),
+            expected(&[
+                ("A", "(self, a, c = ...)"),
+                ("B", "(self, a: int, *, b: int)"),
+                ("C", "(self, a: int, b: str = ...)"),
+            ])
+        );
+    }
+
+    #[test]
+    fn named_tuples() {
+        let source_code = r#"
+from typing import NamedTuple
+class A(NamedTuple):
+    a: int
+    b: str = ""
+    def method(self): ...
+class B(A): pass
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        assert_eq!(
+            signatures(&db),
+            expected(&[("A", "(self, a: int, b: str = ...)"), ("B", "-")])
+        );
+    }
+
+    #[test]
+    fn typed_dicts() {
+        let source_code = r#"
+from typing import NotRequired, Required, TypedDict
+class A(TypedDict):
+    a: int
+    b: NotRequired[str]
+class B(A, total=False):
+    c: int
+    d: Required[int]
+"#;
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        // Keys are passed by keyword, and those that aren't required can be left out
+        assert_eq!(
+            signatures(&db),
+            expected(&[
+                ("A", "(self, *, a: int, b: str = ...)"),
+                ("B", "(self, *, a: int, b: str = ..., c: int = ..., d: int)"),
+            ])
+        );
+    }
+
+    #[test]
+    fn enum_members() {
+        let source_code = r#"
+from enum import Enum, IntEnum
+class Color(Enum):
+    RED = 1
+    GREEN = "g"
+    _ignore_ = 1
+    describe = lambda self: 1
+class Level(IntEnum):
+    LOW = 1
+class Plain:
+    RED = 1
+"#;
+        // Enums are only known from the stubs of the standard library
+        let typeshed =
+            std::env::temp_dir().join(format!("rypy-synthesis-enum-{}", std::process::id()));
+        let stdlib = typeshed.join("stdlib");
+        std::fs::create_dir_all(&stdlib).unwrap();
+        let builtins = include_str!("../../stubs/builtins.pyi");
+        std::fs::write(stdlib.join("builtins.pyi"), builtins).unwrap();
+        let enum_stub = "class Enum: ...\nclass IntEnum(int, Enum): ...\n";
+        std::fs::write(stdlib.join("enum.pyi"), enum_stub).unwrap();
+        let mut db = LspDatabase::with_documents(&[(URI, source_code)]);
+        db.set_typeshed_path(Some(typeshed.clone()));
+
+        let graph = db.document_reference_graph(URI.to_string()).unwrap();
+        let member_type = |line: usize| {
+            let definition = graph
+                .definitions()
+                .find(|definition| definition.location.start.0 == line)
+                .unwrap();
+            enum_member_type(&db, URI, definition).map(|member| member.to_string())
+        };
+        let members: Vec<Option<String>> = [3, 4, 5, 6, 8, 10].map(member_type).into();
+        std::fs::remove_dir_all(&typeshed).unwrap();
+
+        let color = Some("Color".to_string());
+        let level = Some("Level".to_string());
+        assert_eq!(members, [color.clone(), color, None, None, level, None]);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::parser::symbol::{Location, MemberUse, Symbol, SymbolKind};
+
+/// Types of semantic tokens, in the order of the legend sent to the client
+pub const TOKEN_TYPES: [&str; 8] = [
+    "namespace",
+    "class",
+    "function",
+    "method",
+    "parameter",
+    "variable",
+    "property",
+    "typeParameter",
+];
+
+/// Modifiers of semantic tokens, each a bit in the order of the legend
+pub const TOKEN_MODIFIERS: [&str; 3] = ["declaration", "readonly", "defaultLibrary"];
+
+pub const DECLARATION: u32 = 1;
+pub const READONLY: u32 = 1 << 1;
+pub const DEFAULT_LIBRARY: u32 = 1 << 2;
+
+/// The type of a token, as an index into `TOKEN_TYPES`
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TokenType {
+    Namespace,
+    Class,
+    Function,
+    Method,
+    Parameter,
+    Variable,
+    Property,
+    TypeParameter,
+}
+
+/// A name in a document, classified by what it refers to
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Token {
+    pub location: Location,
+    pub token_type: TokenType,
+    /// The `TOKEN_MODIFIERS` bits of the token
+    pub modifiers: u32,
+}
+
+/// Classify a definition. Functions and variables defined in the body of
+/// a class are methods and properties, and upper case variables are constants.
+pub fn classify(symbol: &Symbol, in_class: bool) -> (TokenType, u32) {
+    match symbol.kind {
+        SymbolKind::Module => (TokenType::Namespace, 0),
+        SymbolKind::Class => (TokenType::Class, 0),
+        SymbolKind::Function if in_class => (TokenType::Method, 0),
+        SymbolKind::Function => (TokenType::Function, 0),
+        SymbolKind::Parameter => (TokenType::Parameter, 0),
+        SymbolKind::TypeParameter => (TokenType::TypeParameter, 0),
+        SymbolKind::Variable | SymbolKind::Unknown => {
+            let token_type = match in_class {
+                true => TokenType::Property,
+                false => TokenType::Variable,
+            };
+            match is_constant(&symbol.name) {
+                true => (token_type, READONLY),
+                false => (token_type, 0),
+            }
+        }
+    }
+}
+
+/// Classify a name that is used after a dot or as a keyword argument
+pub fn classify_member(member_use: MemberUse) -> TokenType {
+    match member_use {
+        MemberUse::Attribute => TokenType::Property,
+        MemberUse::MethodCall => TokenType::Method,
+        MemberUse::KeywordArgument => TokenType::Parameter,
+    }
+}
+
+/// Names like `MAX_SIZE` are constants by convention
+fn is_constant(name: &str) -> bool {
+    name.chars().any(|c| c.is_ascii_uppercase()) && !name.chars().any(|c| c.is_lowercase())
+}
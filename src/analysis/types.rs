@@ -0,0 +1,96 @@
+use std::fmt;
+
+use crate::parser::symbol::Symbol;
+
+/// The type of an expression. Definitions are identified by the uri of
+/// the document that holds them along with their symbol.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Type {
+    Unknown,
+    None,
+    /// A module, with the symbol standing in for it
+    Module(String, Symbol),
+    /// A class itself, as opposed to an instance of it
+    Class(String, Symbol),
+    /// An instance of a class, with the type arguments of generic classes like `list[int]`
+    Instance(String, Symbol, Vec<Type>),
+    Function(String, Symbol),
+    Union(Vec<Type>),
+}
+
+impl Type {
+    /// Combine types into one, flattening nested unions and leaving out duplicates
+    pub fn union(types: impl IntoIterator<Item = Type>) -> Type {
+        let mut members: Vec<Type> = Vec::new();
+        for member in types {
+            let flattened = match member {
+                Type::Union(inner) => inner,
+                other => vec![other],
+            };
+            for member in flattened {
+                if !members.contains(&member) {
+                    members.push(member);
+                }
+            }
+        }
+        match members.len() {
+            0 => Type::Unknown,
+            1 => members.pop().unwrap(),
+            _ => Type::Union(members),
+        }
+    }
+
+    /// The types a union is made of, or the type itself
+    pub fn members(&self) -> &[Type] {
+        match self {
+            Type::Union(members) => members,
+            other => std::slice::from_ref(other),
+        }
+    }
+
+    /// The classes a type involves, including the type arguments of
+    /// generic classes, along with the uris of their documents
+    pub fn classes(&self) -> Vec<(&str, &Symbol)> {
+        let involved: Vec<(&str, &Symbol)> = match self {
+            Type::Class(uri, class) => vec![(uri, class)],
+            Type::Instance(uri, class, arguments) => std::iter::once((uri.as_str(), class))
+                .chain(arguments.iter().flat_map(Type::classes))
+                .collect(),
+            Type::Union(members) => members.iter().flat_map(Type::classes).collect(),
+            _ => Vec::new(),
+        };
+
+        let mut classes = Vec::new();
+        for class in involved {
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+        classes
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Unknown => write!(f, "Unknown"),
+            Type::None => write!(f, "None"),
+            Type::Module(_, module) => {
+                write!(f, "Module[{}]", module.name.trim_start_matches('.'))
+            }
+            Type::Class(_, class) => write!(f, "type[{}]", class.name),
+            Type::Instance(_, class, arguments) if arguments.is_empty() => {
+                write!(f, "{}", class.name)
+            }
+            Type::Instance(_, class, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(Type::to_string).collect();
+                write!(f, "{}[{}]", class.name, arguments.join(", "))
+            }
+            Type::Function(_, function) => write!(f, "def {}", function.name),
+            Type::Union(members) => {
+                let members: Vec<String> = members.iter().map(Type::to_string).collect();
+                write!(f, "{}", members.join(" | "))
+            }
+        }
+    }
+}
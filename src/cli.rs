@@ -0,0 +1,251 @@
+use log::LevelFilter;
+use std::path::PathBuf;
+
+use crate::transport::Transport;
+
+pub const USAGE: &str = "\
+Usage: rypy [options] [command]
+
+Commands:
+  serve                        Run the language server over stdio (default)
+  check [paths...]             Print the diagnostics of the files below the paths,
+                               failing when there are errors
+  symbols <file>               Print the symbols and references of a file as JSON
+  definition <file:line:col>   Print the location of the definition at a position
+
+Options:
+  --stdio              Talk to the client over stdin and stdout (default)
+  --listen <port>      Accept clients on a TCP port of localhost
+  --connect <address>  Connect to a client listening on <host>:<port>
+  --socket <path>      Accept clients on a Unix domain socket
+  --log-file <path>    Write logs to a file (env: RYPY_LOG_FILE)
+  --log-level <level>  off, error, warn, info, debug or trace (env: RYPY_LOG)
+  -h, --help           Print this help
+  -V, --version        Print the version";
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Command {
+    Serve,
+    Check {
+        paths: Vec<PathBuf>,
+    },
+    Symbols {
+        file: PathBuf,
+    },
+    /// Lines and columns are 1-based, and columns count characters
+    Definition {
+        file: PathBuf,
+        line: usize,
+        column: usize,
+    },
+}
+
+/// Options given on the command line, with the environment as fallback
+#[derive(Debug, Eq, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub transport: Transport,
+    pub log_file: Option<PathBuf>,
+    pub log_level: LevelFilter,
+    pub help: bool,
+    pub version: bool,
+    /// Problems with the environment that don't keep the server from starting
+    pub warnings: Vec<String>,
+}
+
+impl Options {
+    /// Parse the arguments, without the program name
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        // A level in the environment outlives the command line it was meant
+        // for, so an invalid one is only warned about
+        let mut warnings = Vec::new();
+        let log_level = match std::env::var("RYPY_LOG").map(|level| parse_level(&level)) {
+            Ok(Ok(level)) => level,
+            Ok(Err(e)) => {
+                warnings.push(format!("{} in RYPY_LOG, using info", e));
+                LevelFilter::Info
+            }
+            Err(_) => LevelFilter::Info,
+        };
+        let mut options = Self {
+            command: Command::Serve,
+            transport: Transport::Stdio,
+            log_file: std::env::var_os("RYPY_LOG_FILE").map(PathBuf::from),
+            log_level,
+            help: false,
+            version: false,
+            warnings,
+        };
+
+        let mut positional = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') {
+                positional.push(arg);
+                continue;
+            }
+
+            // Accept both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("Missing value for {}", flag))
+            };
+
+            match flag.as_str() {
+                "--log-file" => options.log_file = Some(PathBuf::from(value()?)),
+                "--log-level" => options.log_level = parse_level(&value()?)?,
+                "--stdio" => options.transport = Transport::Stdio,
+                "--listen" => {
+                    let port = value()?;
+                    let port = port
+                        .parse()
+                        .map_err(|_| format!("Invalid port: {}", port))?;
+                    options.transport = Transport::Listen(port);
+                }
+                "--connect" => options.transport = Transport::Connect(value()?),
+                "--socket" => options.transport = Transport::Socket(PathBuf::from(value()?)),
+                "-h" | "--help" => options.help = true,
+                "-V" | "--version" => options.version = true,
+                _ => return Err(format!("Unknown argument: {}", flag)),
+            }
+        }
+
+        options.command = parse_command(positional)?;
+        Ok(options)
+    }
+}
+
+fn parse_command(positional: Vec<String>) -> Result<Command, String> {
+    let mut positional = positional.into_iter();
+    let Some(name) = positional.next() else {
+        return Ok(Command::Serve);
+    };
+    let arguments: Vec<String> = positional.collect();
+
+    let single_argument = || match arguments.as_slice() {
+        [argument] => Ok(argument.clone()),
+        _ => Err(format!("`{}` takes exactly one argument", name)),
+    };
+
+    match name.as_str() {
+        "serve" if arguments.is_empty() => Ok(Command::Serve),
+        "check" => Ok(Command::Check {
+            paths: arguments.into_iter().map(PathBuf::from).collect(),
+        }),
+        "symbols" => Ok(Command::Symbols {
+            file: PathBuf::from(single_argument()?),
+        }),
+        "definition" => {
+            let argument = single_argument()?;
+            let invalid = || format!("Expected <file>:<line>:<column>, got {}", argument);
+
+            // Split from the end, paths may contain colons themselves
+            let mut parts = argument.rsplitn(3, ':');
+            let column = parts.next().and_then(|column| column.parse().ok());
+            let line = parts.next().and_then(|line| line.parse().ok());
+            let (Some(column), Some(line), Some(file)) = (column, line, parts.next()) else {
+                return Err(invalid());
+            };
+            if line == 0 || column == 0 {
+                return Err(invalid());
+            }
+            Ok(Command::Definition {
+                file: PathBuf::from(file),
+                line,
+                column,
+            })
+        }
+        _ => Err(format!("Unknown command: {}", name)),
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level
+        .parse()
+        .map_err(|_| format!("Invalid log level: {}", level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn log_options() {
+        let options = parse(&["--log-file", "rypy.log", "--log-level=debug"]).unwrap();
+        assert_eq!(options.log_file, Some(PathBuf::from("rypy.log")));
+        assert_eq!(options.log_level, LevelFilter::Debug);
+
+        assert!(parse(&["--log-level", "loud"]).is_err());
+        assert!(parse(&["--log-file"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+    }
+
+    #[test]
+    fn invalid_log_level_in_environment() {
+        std::env::set_var("RYPY_LOG", "loud");
+        let options = parse(&["--help"]);
+        std::env::remove_var("RYPY_LOG");
+
+        let options = options.unwrap();
+        assert!(options.help);
+        assert_eq!(options.log_level, LevelFilter::Info);
+        assert_eq!(
+            options.warnings,
+            vec!["Invalid log level: loud in RYPY_LOG, using info".to_string()]
+        );
+    }
+
+    #[test]
+    fn transports() {
+        assert_eq!(parse(&[]).unwrap().transport, Transport::Stdio);
+        assert_eq!(
+            parse(&["--listen", "2087"]).unwrap().transport,
+            Transport::Listen(2087)
+        );
+        assert_eq!(
+            parse(&["--connect=localhost:2087"]).unwrap().transport,
+            Transport::Connect("localhost:2087".to_string())
+        );
+        assert_eq!(
+            parse(&["--socket", "/tmp/rypy.sock"]).unwrap().transport,
+            Transport::Socket(PathBuf::from("/tmp/rypy.sock"))
+        );
+        assert!(parse(&["--listen", "http"]).is_err());
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse(&[]).unwrap().command, Command::Serve);
+        assert_eq!(
+            parse(&["check", "src", "tests", "--log-level", "off"])
+                .unwrap()
+                .command,
+            Command::Check {
+                paths: vec![PathBuf::from("src"), PathBuf::from("tests")]
+            }
+        );
+        assert_eq!(
+            parse(&["definition", "C:/src/app.py:3:14"])
+                .unwrap()
+                .command,
+            Command::Definition {
+                file: PathBuf::from("C:/src/app.py"),
+                line: 3,
+                column: 14
+            }
+        );
+        assert!(parse(&["definition", "app.py:3"]).is_err());
+        assert!(parse(&["symbols"]).is_err());
+        assert!(parse(&["lint"]).is_err());
+    }
+}
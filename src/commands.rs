@@ -0,0 +1,339 @@
+use serde_json::json;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cli::Command;
+use crate::config::Settings;
+use crate::lsp::document_sync::{Position, TextDocumentIdentifier};
+use crate::lsp::language_features::GotoDefinitionParams;
+use crate::parser::parser::{Parser, TreeSitterParser};
+use crate::parser::platform::PythonPlatform;
+use crate::parser::version::PythonVersion;
+use crate::storage::files::python_files;
+use crate::storage::line_index::PositionEncoding;
+use crate::storage::snapshot::LspSnapshot;
+use crate::storage::state::LspState;
+use crate::storage::uri::{path_to_uri, uri_to_path};
+
+/// Run a command without a client, for the workspace at `root`, writing its output
+/// to `out`. The exit code is 1 when `check` found errors, and 2 when the command
+/// failed, which is reported on stderr.
+pub fn run(command: Command, root: &Path, out: &mut impl Write) -> i32 {
+    let result = match command {
+        Command::Serve => Err("`serve` runs the language server".to_string()),
+        Command::Check { paths } => check(root, paths, out),
+        Command::Symbols { file } => symbols(root, &file, out).map(|_| false),
+        Command::Definition { file, line, column } => {
+            definition(root, &file, line, column, out).map(|_| false)
+        }
+    };
+    match result {
+        Ok(failed) => failed as i32,
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    }
+}
+
+/// Set up the same state the server has, for the workspace at `root`
+fn workspace_state(root: &Path) -> LspState {
+    let mut state = LspState::new();
+    state.set_workspace(Some(root.to_path_buf()), Settings::default());
+    if let Some(source) = state.take_environment_detection() {
+        let environment = source.detect();
+        state.set_python_environment(source, environment);
+    }
+    state
+}
+
+/// Take a snapshot that counts columns in characters, like editors display them
+fn snapshot(state: &LspState) -> LspSnapshot {
+    state.snapshot(PositionEncoding::Utf32, Arc::default())
+}
+
+/// Make a path absolute, relative to the workspace, checking that it exists
+fn absolute_path(path: &Path, root: &Path) -> Result<PathBuf, String> {
+    let absolute = root.join(path);
+    if !absolute.exists() {
+        return Err(format!("{}: No such file or directory", path.display()));
+    }
+    std::path::absolute(absolute).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Stop writing once the reader is gone, which is no failure: the output
+/// was piped into a command like `head` that has seen enough of it
+fn finish_output(written: io::Result<()>) -> Result<(), String> {
+    match written {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+            Err(format!("Failed to write output: {}", e))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Show paths relative to the workspace when they are inside of it
+fn display_path(path: &Path, root: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Print the diagnostics of the files below `paths`, returning whether there were errors
+pub fn check(root: &Path, paths: Vec<PathBuf>, out: &mut impl Write) -> Result<bool, String> {
+    let state = workspace_state(root);
+    let snapshot = snapshot(&state);
+    let paths = match paths.is_empty() {
+        true => vec![root.to_path_buf()],
+        false => paths
+            .iter()
+            .map(|path| absolute_path(path, root))
+            .collect::<Result<_, _>>()?,
+    };
+
+    let files = python_files(&paths, root, snapshot.settings());
+    let mut errors = 0;
+    let mut warnings = 0;
+    let mut write_diagnostics = || -> io::Result<()> {
+        for file in &files {
+            let uri = path_to_uri(file);
+            for diagnostic in snapshot.get_diagnostics(&uri) {
+                let severity = match diagnostic.severity {
+                    Some(1) => {
+                        errors += 1;
+                        "error"
+                    }
+                    Some(2) => {
+                        warnings += 1;
+                        "warning"
+                    }
+                    Some(3) => "info",
+                    _ => "hint",
+                };
+                writeln!(
+                    out,
+                    "{}:{}:{}: {}[{}] {}",
+                    display_path(file, root),
+                    diagnostic.range.start.line + 1,
+                    diagnostic.range.start.character + 1,
+                    severity,
+                    diagnostic.code.unwrap_or_default(),
+                    diagnostic.message
+                )?;
+            }
+        }
+
+        writeln!(
+            out,
+            "Checked {} files: {} errors, {} warnings",
+            files.len(),
+            errors,
+            warnings
+        )?;
+        out.flush()
+    };
+    finish_output(write_diagnostics())?;
+    Ok(errors > 0)
+}
+
+/// Print the symbols and references the parser collects for a file as JSON.
+/// Locations are the parser's, which are 0-based and count bytes.
+pub fn symbols(root: &Path, file: &Path, out: &mut impl Write) -> Result<(), String> {
+    let source = std::fs::read_to_string(root.join(file))
+        .map_err(|e| format!("{}: {}", file.display(), e))?;
+
+    // Only the settings files matter here, there is no need to look for an environment
+    let settings = Settings::load(root);
+    let python_version = settings
+        .python_version
+        .and_then(|version| PythonVersion::parse(&version))
+        .unwrap_or_default();
+    let python_platform = settings
+        .python_platform
+        .and_then(|platform| PythonPlatform::parse(&platform))
+        .unwrap_or_default();
+
+    let mut parser =
+        TreeSitterParser::new().map_err(|e| format!("Failed to create parser: {}", e))?;
+    parser.set_python_version(python_version);
+    parser.set_python_platform(python_platform);
+    let (symbols, references) = parser.parse(&source).map_err(|errors| {
+        errors
+            .into_iter()
+            .map(|error| error.message)
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+
+    // Large files make for a lot of output, which is written as it is serialized
+    let output = json!({ "symbols": symbols, "references": references });
+    let written = serde_json::to_writer_pretty(&mut *out, &output)
+        .map_err(io::Error::from)
+        .and_then(|_| writeln!(out))
+        .and_then(|_| out.flush());
+    finish_output(written)
+}
+
+/// Print where the name at a 1-based line and column is defined
+pub fn definition(
+    root: &Path,
+    file: &Path,
+    line: usize,
+    column: usize,
+    out: &mut impl Write,
+) -> Result<(), String> {
+    let state = workspace_state(root);
+    let params = GotoDefinitionParams {
+        text_document: TextDocumentIdentifier {
+            uri: path_to_uri(&absolute_path(file, root)?),
+        },
+        position: Position {
+            line: line - 1,
+            character: column - 1,
+        },
+    };
+
+    let location = snapshot(&state).get_definition(params).ok_or_else(|| {
+        format!(
+            "No definition found at {}:{}:{}",
+            file.display(),
+            line,
+            column
+        )
+    })?;
+    let path = uri_to_path(&location.uri).unwrap_or_else(|| PathBuf::from(&location.uri));
+    let written = writeln!(
+        out,
+        "{}:{}:{}",
+        display_path(&path, root),
+        location.range.start.line + 1,
+        location.range.start.character + 1
+    );
+    finish_output(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::typeshed;
+
+    /// A workspace below the temporary directory, removed when dropped
+    struct Workspace(PathBuf);
+
+    impl Workspace {
+        fn new(name: &str) -> Self {
+            typeshed::write_bundled_builtins();
+            let root = std::env::temp_dir().join(format!("rypy-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            std::fs::write(root.join("a.py"), "from b import f\n\nf(1, 2)\n").unwrap();
+            std::fs::write(root.join("b.py"), "def f(x):\n    return x\n").unwrap();
+            Self(root)
+        }
+
+        /// The exit code and output of a command
+        fn run(&self, command: Command) -> (i32, String) {
+            let mut out = Vec::new();
+            let code = run(command, &self.0, &mut out);
+            (code, String::from_utf8(out).unwrap())
+        }
+    }
+
+    impl Drop for Workspace {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A pipe whose reader is gone, counting the writes that were tried
+    #[derive(Default)]
+    struct ClosedPipe(usize);
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            self.0 += 1;
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn check_command() {
+        let workspace = Workspace::new("check");
+        let (code, output) = workspace.run(Command::Check { paths: Vec::new() });
+        assert_eq!(code, 1);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("a.py:3:"));
+        assert!(lines[0].contains(": error[call-arguments] "));
+        assert_eq!(lines[1], "Checked 2 files: 1 errors, 0 warnings");
+
+        let paths = vec![PathBuf::from("b.py")];
+        let (code, output) = workspace.run(Command::Check { paths });
+        assert_eq!(code, 0);
+        assert_eq!(output, "Checked 1 files: 0 errors, 0 warnings\n");
+
+        let paths = vec![PathBuf::from("c.py")];
+        assert_eq!(workspace.run(Command::Check { paths }), (2, String::new()));
+    }
+
+    #[test]
+    fn definition_command() {
+        let workspace = Workspace::new("definition");
+        let definition = |line, column| Command::Definition {
+            file: PathBuf::from("a.py"),
+            line,
+            column,
+        };
+        assert_eq!(
+            workspace.run(definition(3, 1)),
+            (0, "b.py:1:5\n".to_string())
+        );
+        assert_eq!(workspace.run(definition(2, 1)), (2, String::new()));
+    }
+
+    #[test]
+    fn symbols_command() {
+        let workspace = Workspace::new("symbols");
+        let (code, output) = workspace.run(Command::Symbols {
+            file: PathBuf::from("b.py"),
+        });
+        assert_eq!(code, 0);
+        let output: serde_json::Value = serde_json::from_str(&output).unwrap();
+        let names: Vec<&str> = output["symbols"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|symbol| symbol["name"].as_str())
+            .collect();
+        assert_eq!(names, ["f", "x"]);
+        assert!(output["references"].is_array());
+
+        let file = PathBuf::from("missing.py");
+        assert_eq!(workspace.run(Command::Symbols { file }), (2, String::new()));
+    }
+
+    #[test]
+    fn closed_pipes_stop_the_output() {
+        let workspace = Workspace::new("closed-pipe");
+        // Errors found before the pipe closed still fail the check
+        for (command, code) in [
+            (Command::Check { paths: Vec::new() }, 1),
+            (
+                Command::Symbols {
+                    file: PathBuf::from("b.py"),
+                },
+                0,
+            ),
+        ] {
+            let mut pipe = ClosedPipe::default();
+            assert_eq!(run(command, &workspace.0, &mut pipe), code);
+            assert_eq!(pipe.0, 1);
+        }
+    }
+}
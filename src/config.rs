@@ -0,0 +1,259 @@
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Files we read settings from, later ones take precedence
+pub const CONFIG_FILES: [&str; 2] = ["pyproject.toml", "rypy.toml"];
+
+#[derive(Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+    /// The rule is turned off
+    #[serde(alias = "off")]
+    None,
+}
+
+/// Which kinds of inlay hints are shown. Every kind is shown unless turned off.
+#[derive(Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintSettings {
+    /// The inferred types of variables assigned without an annotation
+    #[serde(alias = "variable-types")]
+    pub variable_types: Option<bool>,
+    /// The inferred return types of functions without a return annotation
+    #[serde(alias = "return-types")]
+    pub return_types: Option<bool>,
+    /// The names of the parameters positional arguments are passed to
+    #[serde(alias = "parameter-names")]
+    pub parameter_names: Option<bool>,
+}
+
+impl InlayHintSettings {
+    /// Layer `other` on top of these settings
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            variable_types: other.variable_types.or(self.variable_types),
+            return_types: other.return_types.or(self.return_types),
+            parameter_names: other.parameter_names.or(self.parameter_names),
+        }
+    }
+}
+
+/// Settings of the server. They can come from `[tool.rypy]` in `pyproject.toml`,
+/// `rypy.toml` or the client, so every field is optional and layers are merged
+/// on top of each other. Keys are accepted in both camelCase and kebab-case.
+#[derive(Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    /// Globs of the files that are part of the project
+    pub include: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    /// Additional roots for import resolution, searched before the workspace
+    #[serde(alias = "extra-paths")]
+    pub extra_paths: Option<Vec<PathBuf>>,
+    #[serde(alias = "python-version")]
+    pub python_version: Option<String>,
+    /// The platform `sys.platform` checks are evaluated for, the host by default
+    #[serde(alias = "python-platform")]
+    pub python_platform: Option<String>,
+    /// Interpreter or environment directory to resolve installed packages from
+    #[serde(alias = "python-path")]
+    pub python_path: Option<PathBuf>,
+    #[serde(alias = "typeshed-path")]
+    pub typeshed_path: Option<PathBuf>,
+    /// Severity of each diagnostic rule, by rule name
+    pub diagnostics: Option<HashMap<String, Severity>>,
+    #[serde(alias = "inlay-hints")]
+    pub inlay_hints: Option<InlayHintSettings>,
+    #[serde(alias = "log-file")]
+    pub log_file: Option<PathBuf>,
+}
+
+impl Settings {
+    /// Read the settings files in the workspace root
+    pub fn load(root: &Path) -> Self {
+        let mut settings = Self::default();
+        for name in CONFIG_FILES {
+            let path = root.join(name);
+            let Ok(text) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            match Self::from_toml(name, &text) {
+                Ok(Some(file_settings)) => {
+                    info!("Loaded settings from {:?}", path);
+                    settings = settings.merge(file_settings);
+                }
+                Ok(None) => {}
+                Err(e) => error!("Invalid settings in {:?}: {}", path, e),
+            }
+        }
+        settings.resolve_paths(root)
+    }
+
+    /// Parse a settings file. `pyproject.toml` only holds settings
+    /// in its `[tool.rypy]` table, so it may have none.
+    pub fn from_toml(name: &str, text: &str) -> Result<Option<Self>, String> {
+        let table: toml::Table = text.parse().map_err(|e| format!("{}", e))?;
+        let table = match name {
+            "pyproject.toml" => match table.get("tool").and_then(|tool| tool.get("rypy")) {
+                Some(rypy) => rypy.clone(),
+                None => return Ok(None),
+            },
+            _ => toml::Value::Table(table),
+        };
+        let mut settings: Self = table.try_into().map_err(|e| format!("{}", e))?;
+
+        // A checkout shouldn't pick a file for us to write, so the log file only
+        // comes from the command line, the environment or the client
+        if let Some(log_file) = settings.log_file.take() {
+            warn!("Ignoring log-file {:?} from {}", log_file, name);
+        }
+        Ok(Some(settings))
+    }
+
+    /// Parse settings sent by the client. They can be wrapped in a `rypy` section.
+    pub fn from_json(value: Json) -> Result<Self, String> {
+        let value = match value.get("rypy") {
+            Some(section) => section.clone(),
+            None => value,
+        };
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+        serde_json::from_value(value).map_err(|e| format!("{}", e))
+    }
+
+    /// Layer `other` on top of these settings
+    pub fn merge(self, other: Self) -> Self {
+        // Severities are merged rule by rule
+        let diagnostics = match (self.diagnostics, other.diagnostics) {
+            (Some(mut base), Some(overrides)) => {
+                base.extend(overrides);
+                Some(base)
+            }
+            (base, overrides) => overrides.or(base),
+        };
+        let inlay_hints = match (self.inlay_hints, other.inlay_hints) {
+            (Some(base), Some(overrides)) => Some(base.merge(overrides)),
+            (base, overrides) => overrides.or(base),
+        };
+
+        Self {
+            include: other.include.or(self.include),
+            exclude: other.exclude.or(self.exclude),
+            extra_paths: other.extra_paths.or(self.extra_paths),
+            python_version: other.python_version.or(self.python_version),
+            python_platform: other.python_platform.or(self.python_platform),
+            python_path: other.python_path.or(self.python_path),
+            typeshed_path: other.typeshed_path.or(self.typeshed_path),
+            diagnostics,
+            inlay_hints,
+            log_file: other.log_file.or(self.log_file),
+        }
+    }
+
+    /// Make relative paths relative to the workspace root
+    pub fn resolve_paths(self, root: &Path) -> Self {
+        let resolve = |path: PathBuf| match path.is_relative() {
+            true => root.join(path),
+            false => path,
+        };
+
+        Self {
+            extra_paths: self
+                .extra_paths
+                .map(|paths| paths.into_iter().map(resolve).collect()),
+            python_path: self.python_path.map(resolve),
+            typeshed_path: self.typeshed_path.map(resolve),
+            log_file: self.log_file.map(resolve),
+            ..self
+        }
+    }
+}
+
+/// Check if a uri points to one of the settings files
+pub fn is_config_file(uri: &str) -> bool {
+    CONFIG_FILES
+        .iter()
+        .any(|name| uri.ends_with(&format!("/{}", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn pyproject_settings() {
+        let pyproject = r#"
+[project]
+name = "example"
+
+[tool.rypy]
+python-version = "3.10"
+extra-paths = ["src"]
+exclude = ["build/**"]
+
+[tool.rypy.diagnostics]
+unknown-attribute = "warning"
+"#;
+        let settings = Settings::from_toml("pyproject.toml", pyproject)
+            .unwrap()
+            .unwrap()
+            .resolve_paths(Path::new("/project"));
+
+        assert_eq!(settings.python_version.as_deref(), Some("3.10"));
+        assert_eq!(
+            settings.extra_paths,
+            Some(vec![PathBuf::from("/project/src")])
+        );
+        assert_eq!(settings.exclude, Some(vec!["build/**".to_string()]));
+        assert_eq!(
+            settings.diagnostics.unwrap().get("unknown-attribute"),
+            Some(&Severity::Warning)
+        );
+
+        let without_table = Settings::from_toml("pyproject.toml", "[project]\nname = \"x\"");
+        assert_eq!(without_table, Ok(None));
+    }
+
+    #[test]
+    fn client_settings_take_precedence() {
+        let file = Settings::from_toml(
+            "rypy.toml",
+            "python-version = \"3.9\"\nlog-file = \"rypy.log\"\n[diagnostics]\na = \"error\"\nb = \"error\"\n[inlay-hints]\nreturn-types = false",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(file.log_file, None);
+        let client = Settings::from_json(json!({
+            "rypy": {
+                "pythonVersion": "3.12",
+                "logFile": "client.log",
+                "diagnostics": { "b": "none", "c": "off" },
+                "inlayHints": { "parameterNames": false }
+            }
+        }))
+        .unwrap();
+
+        let merged = file.merge(client);
+        assert_eq!(merged.python_version.as_deref(), Some("3.12"));
+        assert_eq!(merged.log_file, Some(PathBuf::from("client.log")));
+
+        let diagnostics = merged.diagnostics.unwrap();
+        assert_eq!(diagnostics.get("a"), Some(&Severity::Error));
+        assert_eq!(diagnostics.get("b"), Some(&Severity::None));
+        assert_eq!(diagnostics.get("c"), Some(&Severity::None));
+
+        let inlay_hints = merged.inlay_hints.unwrap();
+        assert_eq!(inlay_hints.return_types, Some(false));
+        assert_eq!(inlay_hints.parameter_names, Some(false));
+        assert_eq!(inlay_hints.variable_types, None);
+    }
+}
@@ -0,0 +1,233 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{json, Value as Json};
+use simplelog::{CombinedLogger, Config, ConfigBuilder, SharedLogger, WriteLogger};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::lsp::lifecycle::{LogTraceParams, TraceValue};
+use crate::lsp::window::LogMessageParams;
+
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Sends notifications to the client, once there is a connection to it
+type ClientSink = Box<dyn Fn(&str, Json) + Send>;
+static CLIENT: Mutex<Option<ClientSink>> = Mutex::new(None);
+
+static TRACE: Mutex<TraceValue> = Mutex::new(TraceValue::Off);
+
+/// The level asked for on the command line, which traces go below
+static LEVEL: Mutex<LevelFilter> = Mutex::new(LevelFilter::Info);
+
+/// Writer handed to the logger, which forwards to the current log file.
+/// This lets the log file be moved once the settings are known.
+pub struct LogFile;
+
+impl Write for LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG_FILE.lock().unwrap().as_mut() {
+            Some(file) => file.write(buf),
+            None => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG_FILE.lock().unwrap().as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Set up the logger. Records go to the log file, if there is one, and to the client.
+/// Opening the log file is allowed to fail, we don't want to crash in read-only directories.
+pub fn init(level: LevelFilter, log_file: Option<&Path>) {
+    let mut config = ConfigBuilder::new();
+    config.set_target_level(LevelFilter::Error); // Prevents verbose target logs
+    config.add_filter_ignore_str("salsa"); // Ignore salsa logs
+    let config = config.build();
+    *LEVEL.lock().unwrap() = level;
+
+    let loggers: Vec<Box<dyn SharedLogger>> = vec![
+        WriteLogger::new(level, config.clone(), LogFile),
+        Box::new(ClientLogger { level, config }),
+    ];
+    if CombinedLogger::init(loggers).is_err() {
+        return;
+    }
+
+    if let Some(path) = log_file {
+        if let Err(e) = set_log_file(path) {
+            eprintln!("Failed to open log file {:?}: {}", path, e);
+        }
+    }
+}
+
+/// Start writing logs to the file at `path`. Nothing is created when logging is off.
+pub fn set_log_file(path: &Path) -> io::Result<()> {
+    if *LEVEL.lock().unwrap() == LevelFilter::Off {
+        return Ok(());
+    }
+    let file = File::create(path)?;
+    *LOG_FILE.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+/// Forward logs to the client through `sink`, which is given the method and params
+/// of the notification to send. The sink must not log itself.
+pub fn set_client(sink: impl Fn(&str, Json) + Send + 'static) {
+    *CLIENT.lock().unwrap() = Some(Box::new(sink));
+}
+
+/// Change how much of the debug output is traced to the client, see `$/setTrace`.
+/// Traces are sent whatever the log level, so while they are on every record
+/// is let through to the loggers, which filter by level themselves.
+pub fn set_trace(trace: TraceValue) {
+    *TRACE.lock().unwrap() = trace;
+    log::set_max_level(match trace {
+        TraceValue::Off => *LEVEL.lock().unwrap(),
+        TraceValue::Messages | TraceValue::Verbose => LevelFilter::Trace,
+    });
+}
+
+/// Sends records to the client. Errors, warnings and infos are shown with
+/// `window/logMessage` up to the log level, debug output is only sent with
+/// `$/logTrace` when the client asked for traces, at any log level.
+struct ClientLogger {
+    level: LevelFilter,
+    config: Config,
+}
+
+impl ClientLogger {
+    fn is_ignored(&self, record: &Record) -> bool {
+        record.target().starts_with("salsa")
+    }
+}
+
+impl Log for ClientLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let traced =
+            metadata.level() > log::Level::Info && *TRACE.lock().unwrap() != TraceValue::Off;
+        metadata.level() <= self.level || traced
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) || self.is_ignored(record) {
+            return;
+        }
+
+        let notification = match record.level() {
+            log::Level::Error | log::Level::Warn | log::Level::Info => {
+                let kind = match record.level() {
+                    log::Level::Error => 1,
+                    log::Level::Warn => 2,
+                    _ => 3,
+                };
+                let params = LogMessageParams {
+                    kind,
+                    message: format!("{}", record.args()),
+                };
+                ("window/logMessage", json!(params))
+            }
+            log::Level::Debug | log::Level::Trace => {
+                let trace = *TRACE.lock().unwrap();
+                let verbose = match trace {
+                    TraceValue::Off => return,
+                    TraceValue::Messages => None,
+                    TraceValue::Verbose => Some(format!(
+                        "{}:{}",
+                        record.file().unwrap_or(record.target()),
+                        record.line().unwrap_or_default()
+                    )),
+                };
+                let params = LogTraceParams {
+                    message: format!("{}", record.args()),
+                    verbose,
+                };
+                ("$/logTrace", json!(params))
+            }
+        };
+
+        if let Some(sink) = CLIENT.lock().unwrap().as_ref() {
+            sink(notification.0, notification.1);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for ClientLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        Some(&self.config)
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use std::sync::Arc;
+
+    #[test]
+    fn traces_below_the_log_level() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sink = sent.clone();
+        set_client(move |method, params| {
+            sink.lock()
+                .unwrap()
+                .push((method.to_string(), params["message"].clone()))
+        });
+        let logger = ClientLogger {
+            level: LevelFilter::Info,
+            config: Config::default(),
+        };
+
+        set_trace(TraceValue::Off);
+        assert_eq!(log::max_level(), LevelFilter::Info);
+        logger.log(
+            &Record::builder()
+                .level(Level::Debug)
+                .args(format_args!("hidden"))
+                .build(),
+        );
+
+        set_trace(TraceValue::Messages);
+        assert_eq!(log::max_level(), LevelFilter::Trace);
+        logger.log(
+            &Record::builder()
+                .level(Level::Debug)
+                .args(format_args!("debug"))
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .level(Level::Trace)
+                .args(format_args!("trace"))
+                .build(),
+        );
+
+        set_trace(TraceValue::Off);
+        assert_eq!(log::max_level(), LevelFilter::Info);
+        logger.log(
+            &Record::builder()
+                .level(Level::Trace)
+                .args(format_args!("hidden"))
+                .build(),
+        );
+
+        let traces = vec![
+            ("$/logTrace".to_string(), json!("debug")),
+            ("$/logTrace".to_string(), json!("trace")),
+        ];
+        assert_eq!(*sent.lock().unwrap(), traces);
+    }
+}
@@ -0,0 +1,196 @@
+use serde::Serialize;
+use serde_json::{json, Value as Json};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::handler::LspHandler;
+use super::lifecycle::ClientCapabilities;
+use super::window::ShowMessageParams;
+use crate::rpc;
+use crate::storage::line_index::PositionEncoding;
+
+/// Called with the `result` of a response, or its `error` object
+type ResponseHandler = Box<dyn FnOnce(&mut LspHandler, Result<Json, Json>) + Send>;
+
+/// The messages going out to one client. They are put in a queue, which a thread
+/// writes to the connection in order, so anything holding the client can send
+/// notifications and requests of its own without waiting for the writer.
+/// What was agreed on with the client in `initialize` is kept here too, since
+/// clients connected side by side each agree on their own.
+pub struct Client {
+    /// Tells the connection apart from the others of the server
+    id: usize,
+    queue: Mutex<Option<Sender<String>>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+    next_id: AtomicI64,
+    /// Requests sent to the client that are waiting for a response, by id
+    pending: Mutex<HashMap<i64, ResponseHandler>>,
+    /// Cancellation flags of the progress shown in the client, by token
+    progress: Mutex<HashMap<String, Arc<AtomicBool>>>,
+    position_encoding: Mutex<PositionEncoding>,
+    capabilities: Mutex<Arc<ClientCapabilities>>,
+}
+
+impl Client {
+    pub fn new(mut writer: Box<dyn Write + Send>) -> Arc<Self> {
+        let (sender, receiver) = mpsc::channel::<String>();
+        let writer = thread::spawn(move || {
+            for encoded in receiver {
+                // Logging here would queue another message for a client that is gone
+                if rpc::write_message(&mut writer, &encoded).is_err() {
+                    break;
+                }
+            }
+        });
+
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Arc::new(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            queue: Mutex::new(Some(sender)),
+            writer: Mutex::new(Some(writer)),
+            next_id: AtomicI64::new(0),
+            pending: Mutex::new(HashMap::new()),
+            progress: Mutex::new(HashMap::new()),
+            position_encoding: Mutex::new(PositionEncoding::default()),
+            capabilities: Mutex::new(Arc::default()),
+        })
+    }
+
+    /// Remember how positions are counted and what the client supports
+    pub fn initialize(
+        &self,
+        position_encoding: PositionEncoding,
+        capabilities: ClientCapabilities,
+    ) {
+        *self.position_encoding.lock().unwrap() = position_encoding;
+        *self.capabilities.lock().unwrap() = Arc::new(capabilities);
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn position_encoding(&self) -> PositionEncoding {
+        *self.position_encoding.lock().unwrap()
+    }
+
+    /// What the client supports, which decides the shape of responses
+    pub fn capabilities(&self) -> Arc<ClientCapabilities> {
+        self.capabilities.lock().unwrap().clone()
+    }
+
+    /// Queue an encoded message, which is dropped once the client is closed.
+    /// Logs are sent through here, so this must not log itself.
+    pub fn send(&self, encoded: String) {
+        if let Some(queue) = self.queue.lock().unwrap().as_ref() {
+            let _ = queue.send(encoded);
+        }
+    }
+
+    pub fn notify(&self, method: &str, params: impl Serialize) {
+        self.send(rpc::encode_notification(method, json!(params)));
+    }
+
+    /// Send a request to the client. The handler runs when the response
+    /// comes in, with the same access to the state as any other message.
+    pub fn request(
+        &self,
+        method: &str,
+        params: impl Serialize,
+        handler: impl FnOnce(&mut LspHandler, Result<Json, Json>) + Send + 'static,
+    ) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock().unwrap().insert(id, Box::new(handler));
+        self.send(rpc::encode_request(id, method, json!(params)));
+    }
+
+    /// Take the handler of the request a response belongs to
+    pub fn take_response_handler(&self, id: i64) -> Option<ResponseHandler> {
+        self.pending.lock().unwrap().remove(&id)
+    }
+
+    /// Create a token for progress, along with the flag that tells when the user canceled it
+    pub fn register_progress(&self) -> (String, Arc<AtomicBool>) {
+        let token = format!("rypy/{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let canceled = Arc::new(AtomicBool::new(false));
+        self.progress
+            .lock()
+            .unwrap()
+            .insert(token.clone(), canceled.clone());
+        (token, canceled)
+    }
+
+    pub fn unregister_progress(&self, token: &str) {
+        self.progress.lock().unwrap().remove(token);
+    }
+
+    /// Handle `window/workDoneProgress/cancel`, which is a no-op once the progress ended
+    pub fn cancel_progress(&self, token: &str) {
+        if let Some(canceled) = self.progress.lock().unwrap().get(token) {
+            canceled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Show a message in the user interface, where 1 = error, 2 = warning, 3 = info
+    pub fn show_message(&self, kind: u8, message: String) {
+        self.notify("window/showMessage", ShowMessageParams { kind, message });
+    }
+
+    /// Stop taking messages and wait until the queued ones have been written
+    pub fn close(&self) {
+        self.queue.lock().unwrap().take();
+        if let Some(writer) = self.writer.lock().unwrap().take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Keeps what a client writes, so tests can read the messages back
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Recording(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Recording {
+    /// A client whose messages are recorded
+    pub fn client(&self) -> Arc<Client> {
+        Client::new(Box::new(self.clone()))
+    }
+
+    /// The messages written so far, which are all of them once the client is closed
+    pub fn messages(&self) -> Vec<Json> {
+        let written = std::io::Cursor::new(self.0.lock().unwrap().clone());
+        let mut reader = crate::transport::Connection::new(written, std::io::sink()).reader;
+        std::iter::from_fn(|| reader.read_message().ok())
+            .map(|message| serde_json::from_str(&message).unwrap())
+            .collect()
+    }
+
+    /// Wait until `client` sent a request for `method`, and answer it with `result`
+    pub fn respond(&self, client: &Client, method: &str, result: Result<Json, Json>) {
+        let id = loop {
+            let messages = self.messages();
+            if let Some(request) = messages.iter().find(|message| message["method"] == method) {
+                break request["id"].as_i64().unwrap();
+            }
+            thread::yield_now();
+        };
+        let handler = client.take_response_handler(id).unwrap();
+        handler(&mut LspHandler::initialize().unwrap(), result);
+    }
+}
+
+#[cfg(test)]
+impl Write for Recording {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
@@ -35,7 +35,7 @@ pub struct TextDocumentContentChangeEvent {
     pub range_length: Option<i64>,
     pub text: String,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Range {
     pub start: Position,
@@ -57,3 +57,9 @@ pub struct DidCloseTextDocumentParams {
 pub struct TextDocumentIdentifier {
     pub uri: String,
 }
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DidSaveTextDocumentParams {
+    pub text_document: TextDocumentIdentifier,
+    pub text: Option<String>,
+}
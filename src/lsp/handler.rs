@@ -1,49 +1,348 @@
-use std::io::{self, BufRead, Read};
-
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use serde_json::{json, Value as Json};
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use super::client::Client;
+use super::indexing::Indexing;
+use super::progress::Progress;
 
 use super::document_sync::{
     DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DidSaveTextDocumentParams,
+};
+use super::language_features::PublishDiagnosticsParams;
+use super::lifecycle::{
+    ClientCapabilities, CodeLensOptions, CompletionOptions, DiagnosticOptions,
+    ExecuteCommandOptions, InitializeParams, InitializeResult, InlayHintOptions, Registration,
+    RegistrationParams, SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions,
+    ServerCapabilities, ServerInfo, SetTraceParams,
 };
-use super::language_features::Location;
-use super::lifecycle::{InitializeResult, ServerCapabilities, ServerInfo};
 
-use crate::lsp::language_features::GotoDefinitionParams;
+use super::window::{
+    MessageActionItem, ShowDocumentParams, ShowMessageRequestParams, WorkDoneProgressCancelParams,
+};
+use super::workspace::{
+    ConfigurationItem, ConfigurationParams, DidChangeConfigurationParams,
+    DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions, ExecuteCommandParams,
+    FileEvent, FileSystemWatcher,
+};
+
+use crate::analysis::tokens::{TOKEN_MODIFIERS, TOKEN_TYPES};
+use crate::config::{is_config_file, Settings, CONFIG_FILES};
+use crate::logging;
+use crate::lsp::language_features::{
+    CallHierarchyIncomingCall, CallHierarchyIncomingCallsParams, CallHierarchyItem,
+    CallHierarchyOutgoingCall, CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams,
+    CodeLens, CodeLensParams, CompletionItem, CompletionParams, DocumentDiagnosticParams,
+    DocumentHighlight, DocumentHighlightParams, DocumentSymbolParams, DocumentSymbolResponse,
+    FoldingRange, FoldingRangeParams, FullDocumentDiagnosticReport, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverParams, ImplementationParams, InlayHint, InlayHintParams,
+    Location, SelectionRange, SelectionRangeParams, SemanticTokens, SemanticTokensDeltaParams,
+    SemanticTokensDeltaResponse, SemanticTokensParams, SemanticTokensRangeParams,
+    TypeDefinitionParams, TypeHierarchyItem, TypeHierarchyPrepareParams,
+    TypeHierarchySubtypesParams, TypeHierarchySupertypesParams,
+};
+use crate::rpc::ResponseError;
+use crate::storage::line_index::PositionEncoding;
+use crate::storage::snapshot::{LspSnapshot, SHOW_LOCATIONS_COMMAND};
 use crate::storage::state::LspState;
+use crate::storage::uri::{is_python_file, uri_to_path};
+
+/// Globs of the Python files the client is asked to watch
+const PYTHON_FILES: [&str; 2] = ["*.py", "*.pyi"];
+
+/// Requests that only read the analysis, which are answered from a snapshot
+const READ_REQUESTS: [&str; 22] = [
+    "textDocument/definition",
+    "textDocument/hover",
+    "textDocument/completion",
+    "textDocument/documentHighlight",
+    "textDocument/documentSymbol",
+    "textDocument/diagnostic",
+    "textDocument/semanticTokens/full",
+    "textDocument/semanticTokens/full/delta",
+    "textDocument/semanticTokens/range",
+    "textDocument/foldingRange",
+    "textDocument/selectionRange",
+    "textDocument/prepareTypeHierarchy",
+    "typeHierarchy/supertypes",
+    "typeHierarchy/subtypes",
+    "textDocument/implementation",
+    "textDocument/codeLens",
+    "textDocument/prepareCallHierarchy",
+    "callHierarchy/incomingCalls",
+    "callHierarchy/outgoingCalls",
+    "textDocument/typeDefinition",
+    "textDocument/inlayHint",
+    "inlayHint/resolve",
+];
+
+/// The work left for a message once the handler has seen it
+pub enum Task {
+    /// The message has been handled, with this result
+    Done(Result<Option<Json>, ResponseError>),
+    /// A read request, to run on a worker thread. Setting `canceled` makes
+    /// the queries of its snapshot stop.
+    Snapshot {
+        canceled: Arc<AtomicBool>,
+        request: Box<dyn FnOnce() -> Result<Option<Json>, ResponseError> + Send>,
+    },
+}
+
+/// Work that follows from a message without answering it, like publishing
+/// diagnostics, which runs on a worker thread
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Work that runs on a worker thread, like detecting the python environment,
+/// and returns how to update the handler with its outcome
+pub type Update = Box<dyn FnOnce() -> Box<dyn FnOnce(&mut LspHandler) + Send> + Send>;
 
 pub struct LspHandler {
-    shutdown: bool,
     state: LspState,
+    /// The client whose message is being handled
+    client: Option<Arc<Client>>,
+    jobs: Vec<Job>,
+    updates: Vec<Update>,
+    indexing: Option<Arc<Indexing>>,
+    /// The log file the client settings asked for, once it is opened
+    log_file: Option<PathBuf>,
 }
 
 impl LspHandler {
     /// Handles a JSON-RPC message.
     pub fn initialize() -> Result<Self, Box<dyn std::error::Error>> {
         Ok(Self {
-            shutdown: false,
             state: LspState::new(),
+            client: None,
+            jobs: Vec::new(),
+            updates: Vec::new(),
+            indexing: None,
+            log_file: None,
         })
     }
 
-    pub fn handle_response(
+    /// Handles a message, deferring requests that only read the analysis
+    /// to a snapshot which can be answered on another thread.
+    pub fn dispatch(&mut self, client: &Arc<Client>, method: String, params: Json) -> Task {
+        self.client = Some(client.clone());
+        if !READ_REQUESTS.contains(&method.as_str()) {
+            let result = self.handle_response(method, params);
+            self.detect_environment();
+            self.resume_indexing();
+            return Task::Done(result);
+        }
+
+        let snapshot = self.snapshot();
+        Task::Snapshot {
+            canceled: snapshot.cancellation(),
+            request: Box::new(move || Self::handle_read_request(&snapshot, method, params)),
+        }
+    }
+
+    /// Handles the response to a request the server sent to the client.
+    pub fn handle_client_response(
         &mut self,
+        client: &Arc<Client>,
+        id: i64,
+        result: Result<Json, Json>,
+    ) {
+        self.client = Some(client.clone());
+        match client.take_response_handler(id) {
+            Some(handler) => handler(self, result),
+            None => warn!("Received a response to unknown request {}", id),
+        }
+        self.detect_environment();
+        self.resume_indexing();
+    }
+
+    /// Forget a client that went away, closing the documents it left open
+    pub fn disconnect(&mut self, client: &Arc<Client>) {
+        self.state.close_connection(client.id());
+        if self
+            .client
+            .as_ref()
+            .is_some_and(|other| Arc::ptr_eq(other, client))
+        {
+            self.client = None;
+        }
+    }
+
+    /// The jobs queued while handling the last message
+    pub fn take_jobs(&mut self) -> Vec<Job> {
+        std::mem::take(&mut self.jobs)
+    }
+
+    /// The updates queued while handling the last message
+    pub fn take_updates(&mut self) -> Vec<Update> {
+        std::mem::take(&mut self.updates)
+    }
+
+    /// Handles a request from a snapshot of the state.
+    fn handle_read_request(
+        snapshot: &LspSnapshot,
         method: String,
         params: Json,
-    ) -> Result<Option<Json>, String> {
-        info!("Received method: {:?}", method);
-        // Check if client requested shutdown
-        if self.shutdown && method != "exit" {
-            return Err("Server is shutting down".to_string());
+    ) -> Result<Option<Json>, ResponseError> {
+        debug!("Received method: {:?}", method);
+        match method.as_str() {
+            "textDocument/definition" => {
+                let params: GotoDefinitionParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_go_to_definition(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/hover" => {
+                let params: HoverParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_hover(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/completion" => {
+                let params: CompletionParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_completion(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/documentHighlight" => {
+                let params: DocumentHighlightParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_document_highlight(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/documentSymbol" => {
+                let params: DocumentSymbolParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_document_symbol(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/diagnostic" => {
+                let params: DocumentDiagnosticParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_document_diagnostic(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/semanticTokens/full" => {
+                let params: SemanticTokensParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_semantic_tokens(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/semanticTokens/full/delta" => {
+                let params: SemanticTokensDeltaParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_semantic_tokens_delta(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/semanticTokens/range" => {
+                let params: SemanticTokensRangeParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_semantic_tokens_range(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/foldingRange" => {
+                let params: FoldingRangeParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_folding_range(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/selectionRange" => {
+                let params: SelectionRangeParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_selection_range(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/prepareTypeHierarchy" => {
+                let params: TypeHierarchyPrepareParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_prepare_type_hierarchy(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "typeHierarchy/supertypes" => {
+                let params: TypeHierarchySupertypesParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_supertypes(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "typeHierarchy/subtypes" => {
+                let params: TypeHierarchySubtypesParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_subtypes(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/implementation" => {
+                let params: ImplementationParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_implementation(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/codeLens" => {
+                let params: CodeLensParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_code_lens(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/prepareCallHierarchy" => {
+                let params: CallHierarchyPrepareParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_prepare_call_hierarchy(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "callHierarchy/incomingCalls" => {
+                let params: CallHierarchyIncomingCallsParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_incoming_calls(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "callHierarchy/outgoingCalls" => {
+                let params: CallHierarchyOutgoingCallsParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_outgoing_calls(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/typeDefinition" => {
+                let params: TypeDefinitionParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_type_definition(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "textDocument/inlayHint" => {
+                let params: InlayHintParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_inlay_hint(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            "inlayHint/resolve" => {
+                let params: InlayHint = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = Self::handle_inlay_hint_resolve(snapshot, params)?;
+                Ok(Some(json!(result)))
+            }
+            _ => Err(ResponseError::method_not_found(&method)),
         }
+    }
+
+    pub fn handle_response(
+        &mut self,
+        method: String,
+        params: Json,
+    ) -> Result<Option<Json>, ResponseError> {
+        debug!("Received method: {:?}", method);
         match method.as_str() {
             "initialize" => {
-                let result = self.handle_initialize();
+                let params: InitializeParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                let result = self.handle_initialize(params);
                 Ok(Some(json!(result)))
             }
-            "initialized" => Ok(None),
+            "initialized" => {
+                self.register_watched_files();
+                self.request_configuration();
+                self.start_indexing();
+                Ok(None)
+            }
             "shutdown" => {
-                self.shutdown = true;
                 warn!("Shutting down");
                 Ok(Some(json!(null)))
             }
@@ -54,62 +353,238 @@ impl LspHandler {
             "textDocument/didOpen" => {
                 let params: DidOpenTextDocumentParams = serde_json::from_value(params)
                     .map_err(|e| format!("Failed to deserialize params: {}", e))?;
-                let result = self.handle_open_document(params)?;
-                Ok(Some(json!(result)))
-            }
-            "textDocument/definition" => {
-                let params: GotoDefinitionParams = serde_json::from_value(params)
-                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
-                let result = self.handle_go_to_definition(params)?;
-                Ok(Some(json!(result)))
+                self.handle_open_document(params)?;
+                Ok(Some(json!(null)))
             }
             "textDocument/didSave" => {
-                let params: DidChangeTextDocumentParams = serde_json::from_value(params)
+                let params: DidSaveTextDocumentParams = serde_json::from_value(params)
                     .map_err(|e| format!("Failed to deserialize params: {}", e))?;
-                let result = self.handle_save_document(params)?;
-                Ok(Some(json!(result)))
+                self.handle_save_document(params)?;
+                Ok(Some(json!(null)))
             }
             "textDocument/didChange" => {
                 let params: DidChangeTextDocumentParams = serde_json::from_value(params)
                     .map_err(|e| format!("Failed to deserialize params: {}", e))?;
-                let result = self.handle_save_document(params)?;
-                Ok(Some(json!(result)))
+                self.handle_change_document(params)?;
+                Ok(Some(json!(null)))
             }
             "textDocument/didClose" => {
                 let params: DidCloseTextDocumentParams = serde_json::from_value(params)
                     .map_err(|e| format!("Failed to deserialize params: {}", e))?;
-                let result = self.handle_close_document(params)?;
-                Ok(Some(json!(result)))
+                self.handle_close_document(params)?;
+                Ok(Some(json!(null)))
+            }
+            "workspace/didChangeConfiguration" => {
+                let params: DidChangeConfigurationParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                self.handle_change_configuration(params)?;
+                Ok(Some(json!(null)))
             }
-            _ => Err(format!("Unknown method: {}", method)),
+            "workspace/didChangeWatchedFiles" => {
+                let params: DidChangeWatchedFilesParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                self.handle_change_watched_files(params)?;
+                Ok(Some(json!(null)))
+            }
+            "workspace/executeCommand" => {
+                let params: ExecuteCommandParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                self.handle_execute_command(params)?;
+                Ok(Some(json!(null)))
+            }
+            "window/workDoneProgress/cancel" => {
+                let params: WorkDoneProgressCancelParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                if let Some(client) = &self.client {
+                    client.cancel_progress(&params.token);
+                }
+                Ok(None)
+            }
+            "$/setTrace" => {
+                let params: SetTraceParams = serde_json::from_value(params)
+                    .map_err(|e| format!("Failed to deserialize params: {}", e))?;
+                logging::set_trace(params.value);
+                Ok(None)
+            }
+            // Protocol specific notifications may be ignored, but
+            // requests the server doesn't know are answered with an error
+            _ => Err(ResponseError::method_not_found(&method)),
         }
     }
 
     /// Handles the `initialize` request.
-pub fn handle_initialize(&mut self) -> InitializeResult {
-    // Load server name and version from Cargo.toml
-    let server_name = env!("CARGO_PKG_NAME").to_string();
-    let server_version = env!("CARGO_PKG_VERSION").to_string();
-
-    // Return the server capabilities and info
-    InitializeResult {
-        capabilities: ServerCapabilities {
-            text_document_sync: Some(1),
-            hover_provider: Some(false),
-            definition_provider: Some(true),
-        },
-        server_info: Some(ServerInfo {
-            name: server_name,
-            version: Some(server_version),
-        }),
+    pub fn handle_initialize(&mut self, params: InitializeParams) -> InitializeResult {
+        // Load server name and version from Cargo.toml
+        let server_name = env!("CARGO_PKG_NAME").to_string();
+        let server_version = env!("CARGO_PKG_VERSION").to_string();
+
+        logging::set_trace(params.trace.unwrap_or_default());
+
+        // Agree on how position columns are counted
+        let offered_encodings = params
+            .capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.clone())
+            .unwrap_or_default();
+        let position_encoding = PositionEncoding::negotiate(&offered_encodings);
+        info!("Using position encoding: {}", position_encoding.as_str());
+
+        // Pick up the workspace and the settings the client passed along
+        let root = params
+            .root_uri
+            .or_else(|| {
+                params
+                    .workspace_folders
+                    .and_then(|folders| folders.into_iter().next())
+                    .map(|folder| folder.uri)
+            })
+            .and_then(|uri| uri_to_path(&uri));
+        let settings = params
+            .initialization_options
+            .map(Settings::from_json)
+            .transpose()
+            .unwrap_or_else(|e| {
+                warn!("Invalid initialization options: {}", e);
+                None
+            })
+            .unwrap_or_default();
+        self.state.set_workspace(root, settings);
+        self.update_log_file();
+
+        // Diagnostics are only pushed to clients that don't pull them
+        let diagnostic_provider =
+            params
+                .capabilities
+                .pull_diagnostics()
+                .then_some(DiagnosticOptions {
+                    inter_file_dependencies: true,
+                    workspace_diagnostics: false,
+                });
+        if let Some(client) = &self.client {
+            client.initialize(position_encoding, params.capabilities);
+        }
+
+        // Return the server capabilities and info
+        InitializeResult {
+            capabilities: ServerCapabilities {
+                position_encoding: Some(position_encoding.as_str().to_string()),
+                text_document_sync: Some(1),
+                hover_provider: Some(true),
+                completion_provider: Some(CompletionOptions {
+                    resolve_provider: Some(false),
+                }),
+                definition_provider: Some(true),
+                document_highlight_provider: Some(true),
+                document_symbol_provider: Some(true),
+                diagnostic_provider,
+                semantic_tokens_provider: Some(SemanticTokensOptions {
+                    legend: SemanticTokensLegend {
+                        token_types: TOKEN_TYPES.iter().map(|t| t.to_string()).collect(),
+                        token_modifiers: TOKEN_MODIFIERS.iter().map(|m| m.to_string()).collect(),
+                    },
+                    range: true,
+                    full: SemanticTokensFullOptions { delta: true },
+                }),
+                folding_range_provider: Some(true),
+                selection_range_provider: Some(true),
+                type_hierarchy_provider: Some(true),
+                type_definition_provider: Some(true),
+                implementation_provider: Some(true),
+                call_hierarchy_provider: Some(true),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: false,
+                }),
+                inlay_hint_provider: Some(InlayHintOptions {
+                    resolve_provider: true,
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![SHOW_LOCATIONS_COMMAND.to_string()],
+                }),
+            },
+            server_info: Some(ServerInfo {
+                name: server_name,
+                version: Some(server_version),
+            }),
+        }
     }
-}
-    /// Handles the `textDocument/didSave` notification.
-    pub fn handle_save_document(
+    /// Handles the `textDocument/didChange` notification.
+    pub fn handle_change_document(
         &mut self,
         params: DidChangeTextDocumentParams,
     ) -> Result<(), String> {
         self.state.update_document(params);
+        self.publish_all_diagnostics();
+        Ok(())
+    }
+
+    /// Handles the `textDocument/didSave` notification.
+    pub fn handle_save_document(
+        &mut self,
+        params: DidSaveTextDocumentParams,
+    ) -> Result<(), String> {
+        let uri = params.text_document.uri;
+        if is_config_file(&uri) {
+            self.state.reload_settings_files();
+            self.publish_all_diagnostics();
+            self.refresh_diagnostics();
+            self.start_indexing();
+        } else if is_python_file(&uri) {
+            // Clients that don't watch files still tell us about saves
+            self.state.change_files(&[uri]);
+        }
+        Ok(())
+    }
+
+    /// Handles the `workspace/didChangeConfiguration` notification.
+    pub fn handle_change_configuration(
+        &mut self,
+        params: DidChangeConfigurationParams,
+    ) -> Result<(), String> {
+        // Clients that expect the server to ask for the settings don't send them
+        if params.settings.is_null() {
+            self.request_configuration();
+            return Ok(());
+        }
+        self.apply_configuration(params.settings);
+        Ok(())
+    }
+
+    /// Handles the `workspace/didChangeWatchedFiles` notification.
+    pub fn handle_change_watched_files(
+        &mut self,
+        params: DidChangeWatchedFilesParams,
+    ) -> Result<(), String> {
+        if params
+            .changes
+            .iter()
+            .any(|change| is_config_file(&change.uri))
+        {
+            self.state.reload_settings_files();
+            self.publish_all_diagnostics();
+            self.refresh_diagnostics();
+            self.start_indexing();
+        }
+
+        let changed: Vec<&FileEvent> = params
+            .changes
+            .iter()
+            .filter(|change| is_python_file(&change.uri))
+            .collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let uris: Vec<String> = changed.iter().map(|change| change.uri.clone()).collect();
+        self.state.change_files(&uris);
+        // Created and deleted files change which files the workspace has,
+        // and which modules imports resolve to
+        if changed.iter().any(|change| change.kind != 2) {
+            self.state.invalidate_module_resolution();
+            self.state.update_workspace_files();
+            self.start_indexing();
+        }
+        self.publish_all_diagnostics();
+        self.refresh_diagnostics();
         Ok(())
     }
 
@@ -118,7 +593,20 @@ pub fn handle_initialize(&mut self) -> InitializeResult {
         &mut self,
         params: DidCloseTextDocumentParams,
     ) -> Result<(), String> {
-        self.state.close_document(params);
+        let uri = params.text_document.uri.clone();
+        self.state.close_document(self.connection(), params);
+
+        // Closed documents aren't checked, so their diagnostics are cleared
+        if let Some(client) = self.client.as_ref().filter(|_| !self.pulls_diagnostics()) {
+            client.notify(
+                "textDocument/publishDiagnostics",
+                PublishDiagnosticsParams {
+                    uri,
+                    version: None,
+                    diagnostics: Vec::new(),
+                },
+            );
+        }
         Ok(())
     }
 
@@ -127,69 +615,486 @@ pub fn handle_initialize(&mut self) -> InitializeResult {
         &mut self,
         params: DidOpenTextDocumentParams,
     ) -> Result<(), String> {
-        self.state.open_document(params);
+        self.state.open_document(self.connection(), params);
+        self.publish_all_diagnostics();
         Ok(())
     }
 
-    /// Handles the `textDocument/definition` request.
-    pub fn handle_go_to_definition(
-        &mut self,
-        params: GotoDefinitionParams,
-    ) -> Result<Location, String> {
-        match self.state.get_definition(params) {
-            Some(location) => Ok(location),
-            None => Err("Definition not found".to_string()),
+    /// Take a snapshot to answer the client whose message is being handled
+    fn snapshot(&self) -> LspSnapshot {
+        match &self.client {
+            Some(client) => self
+                .state
+                .snapshot(client.position_encoding(), client.capabilities()),
+            None => self
+                .state
+                .snapshot(PositionEncoding::default(), Arc::default()),
         }
     }
 
-    /// Extracts the JSON-RPC message from stdin.
-    pub fn read_message(&mut self) -> io::Result<String> {
-        let stdin = io::stdin();
-        let mut handle = stdin.lock();
-        let mut buffer = String::new();
-        let mut content_length = None;
+    /// The connection of the client whose message is being handled
+    fn connection(&self) -> usize {
+        self.client.as_ref().map_or(0, |client| client.id())
+    }
+
+    /// What the client whose message is being handled supports
+    fn client_capabilities(&self) -> Arc<ClientCapabilities> {
+        self.client
+            .as_ref()
+            .map(|client| client.capabilities())
+            .unwrap_or_default()
+    }
 
-        // Read headers
-        loop {
-            buffer.clear();
-            handle.read_line(&mut buffer)?;
+    fn pulls_diagnostics(&self) -> bool {
+        self.client_capabilities().pull_diagnostics()
+    }
+
+    /// Check a document on a worker thread and send its diagnostics to the client
+    fn publish_diagnostics(&mut self, uri: String) {
+        let Some(client) = self.client.clone().filter(|_| !self.pulls_diagnostics()) else {
+            return;
+        };
+        let snapshot = self.snapshot();
+        self.jobs.push(Box::new(move || {
+            let params = PublishDiagnosticsParams {
+                version: snapshot.document_version(&uri),
+                diagnostics: snapshot.get_diagnostics(&uri),
+                uri,
+            };
+            client.notify("textDocument/publishDiagnostics", params);
+        }));
+    }
+
+    /// Check the open documents again. A change can affect the diagnostics of
+    /// other documents, and cancels the checks that are still running for them.
+    fn publish_all_diagnostics(&mut self) {
+        for uri in self.state.open_documents() {
+            self.publish_diagnostics(uri);
+        }
+    }
+
+    /// Have a client that pulls diagnostics ask for them again, after a change
+    /// that affects other documents than the one that changed
+    fn refresh_diagnostics(&mut self) {
+        let Some(client) = &self.client else {
+            return;
+        };
+        if self.client_capabilities().diagnostic_refresh() {
+            client.request("workspace/diagnostic/refresh", json!(null), |_, _| {});
+        }
+    }
 
-            // Check for the end of headers
-            if buffer.trim().is_empty() {
-                break;
+    /// Index the files of the workspace, unless they already are. Indexing
+    /// that is still running for a different set of files is canceled.
+    fn start_indexing(&mut self) {
+        let files = self.state.workspace_files();
+        if let Some(indexing) = &self.indexing {
+            if indexing.is_for(&files) {
+                return;
             }
+            indexing.cancel();
+        }
+        if files.is_empty() {
+            self.indexing = None;
+            return;
+        }
+
+        let client = self
+            .client
+            .clone()
+            .filter(|_| self.client_capabilities().work_done_progress());
+        let progress = Progress::begin(client, "Indexing", true);
+        self.indexing = Some(Indexing::new(files, progress));
+        self.resume_indexing();
+    }
 
-            // Parse headers
-            if let Some((key, value)) = buffer.split_once(": ") {
-                if key == "Content-Length" {
-                    content_length = Some(value.trim().parse::<usize>().map_err(|e| {
-                        error!("Invalid Content-Length: {}", e);
-                        io::Error::new(io::ErrorKind::InvalidData, "Invalid Content-Length")
-                    })?);
+    /// Detect the python environment on a worker thread when the settings
+    /// call for another one, and analyze the workspace against it once known
+    fn detect_environment(&mut self) {
+        let Some(source) = self.state.take_environment_detection() else {
+            return;
+        };
+        self.updates.push(Box::new(move || {
+            let environment = source.detect();
+            Box::new(move |handler: &mut LspHandler| {
+                handler.state.set_python_environment(source, environment);
+                handler.publish_all_diagnostics();
+                handler.refresh_diagnostics();
+                handler.start_indexing();
+            })
+        }));
+    }
+
+    /// Continue indexing from the current state, after a change canceled it
+    fn resume_indexing(&mut self) {
+        let Some(indexing) = &self.indexing else {
+            return;
+        };
+        if let Some(job) = indexing.resume(self.snapshot()) {
+            self.jobs.push(job);
+        }
+    }
+
+    /// Use settings the client sent, telling the user when they are invalid
+    fn apply_configuration(&mut self, settings: Json) {
+        match Settings::from_json(settings) {
+            Ok(settings) => {
+                self.state.set_configuration_settings(settings);
+                self.update_log_file();
+                self.publish_all_diagnostics();
+                self.refresh_diagnostics();
+                self.start_indexing();
+            }
+            Err(e) => {
+                warn!("Invalid settings: {}", e);
+                if let Some(client) = &self.client {
+                    client.show_message(1, format!("Invalid rypy settings: {}", e));
                 }
-            } else {
-                error!("Malformed header: {}", buffer);
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Malformed header",
-                ));
             }
         }
+    }
 
-        // Ensure Content-Length is found
-        let content_length = content_length.ok_or_else(|| {
-            error!("Missing Content-Length header");
-            io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header")
-        })?;
+    /// Write logs to the file the client settings ask for, when that changed
+    fn update_log_file(&mut self) {
+        let Some(log_file) = self.state.settings().log_file.clone() else {
+            return;
+        };
+        if self.log_file.as_ref() == Some(&log_file) {
+            return;
+        }
+        if let Err(e) = logging::set_log_file(&log_file) {
+            error!("Failed to open log file {:?}: {}", log_file, e);
+        }
+        self.log_file = Some(log_file);
+    }
 
-        // Read the body
-        let mut body = vec![0; content_length];
-        handle.read_exact(&mut body)?;
+    /// Ask the client for the `rypy` section of its settings
+    fn request_configuration(&mut self) {
+        let Some(client) = &self.client else {
+            return;
+        };
+        if !self.client_capabilities().configuration() {
+            return;
+        }
+        let params = ConfigurationParams {
+            items: vec![ConfigurationItem {
+                scope_uri: None,
+                section: Some("rypy".to_string()),
+            }],
+        };
+        client.request(
+            "workspace/configuration",
+            params,
+            |handler, result| match result {
+                Ok(Json::Array(mut settings)) if !settings.is_empty() => {
+                    handler.apply_configuration(settings.swap_remove(0))
+                }
+                Ok(result) => warn!("Unexpected configuration: {:?}", result),
+                Err(error) => debug!("Client didn't provide configuration: {:?}", error),
+            },
+        );
+    }
+
+    /// Handles the `workspace/executeCommand` request.
+    pub fn handle_execute_command(&mut self, params: ExecuteCommandParams) -> Result<(), String> {
+        match params.command.as_str() {
+            SHOW_LOCATIONS_COMMAND => {
+                let (title, locations): (String, Vec<Location>) =
+                    serde_json::from_value(Json::Array(params.arguments))
+                        .map_err(|e| format!("Invalid arguments of {}: {}", params.command, e))?;
+                self.show_locations(title, locations)
+            }
+            command => Err(format!("Unknown command: {}", command)),
+        }
+    }
+
+    /// Open a location in the client. When there are several, the user picks
+    /// one from a message titled `title`.
+    fn show_locations(
+        &mut self,
+        title: String,
+        mut locations: Vec<Location>,
+    ) -> Result<(), String> {
+        let Some(client) = &self.client else {
+            return Ok(());
+        };
+        if !self.client_capabilities().show_document() {
+            return Err("The client can't show documents".to_string());
+        }
+        if locations.len() < 2 {
+            if let Some(location) = locations.pop() {
+                self.show_document(location);
+            }
+            return Ok(());
+        }
+
+        let labels: Vec<String> = locations
+            .iter()
+            .map(|location| {
+                let name = uri_to_path(&location.uri)
+                    .and_then(|path| Some(path.file_name()?.to_string_lossy().to_string()))
+                    .unwrap_or_else(|| location.uri.clone());
+                format!("{}:{}", name, location.range.start.line + 1)
+            })
+            .collect();
+        let params = ShowMessageRequestParams {
+            kind: 3,
+            message: title,
+            actions: labels
+                .iter()
+                .map(|label| MessageActionItem {
+                    title: label.clone(),
+                })
+                .collect(),
+        };
+        client.request(
+            "window/showMessageRequest",
+            params,
+            move |handler, result| {
+                // The user may have dismissed the message without picking one
+                let picked = result.ok().and_then(|action| {
+                    let title = action["title"].as_str()?.to_string();
+                    labels.iter().position(|label| *label == title)
+                });
+                if let Some(index) = picked {
+                    handler.show_document(locations.swap_remove(index));
+                }
+            },
+        );
+        Ok(())
+    }
+
+    fn show_document(&self, location: Location) {
+        let Some(client) = &self.client else {
+            return;
+        };
+        let params = ShowDocumentParams {
+            uri: location.uri,
+            take_focus: Some(true),
+            selection: Some(location.range),
+        };
+        client.request("window/showDocument", params, |_, result| {
+            if let Err(error) = result {
+                debug!("Client didn't show the document: {:?}", error);
+            }
+        });
+    }
+
+    /// Ask the client to tell the server when settings files and Python files change
+    fn register_watched_files(&mut self) {
+        let Some(client) = &self.client else {
+            return;
+        };
+        if !self.client_capabilities().watched_files_registration() {
+            return;
+        }
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: CONFIG_FILES
+                .iter()
+                .chain(&PYTHON_FILES)
+                .map(|name| FileSystemWatcher {
+                    glob_pattern: format!("**/{}", name),
+                })
+                .collect(),
+        };
+        let params = RegistrationParams {
+            registrations: vec![Registration {
+                id: "rypy-watched-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: Some(json!(options)),
+            }],
+        };
+        client.request("client/registerCapability", params, |_, result| {
+            if let Err(error) = result {
+                debug!("Client didn't register watched files: {:?}", error);
+            }
+        });
+    }
 
-        // Convert body to UTF-8 string
-        String::from_utf8(body).map_err(|e| {
-            error!("Invalid UTF-8 body: {}", e);
-            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid UTF-8: {}", e))
+    /// Handles the `textDocument/definition` request.
+    pub fn handle_go_to_definition(
+        snapshot: &LspSnapshot,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>, String> {
+        let response = match snapshot.client_capabilities().definition_links() {
+            true => snapshot
+                .get_definition_link(params)
+                .map(|link| GotoDefinitionResponse::Links(vec![link])),
+            false => snapshot
+                .get_definition(params)
+                .map(GotoDefinitionResponse::Location),
+        };
+        Ok(response)
+    }
+
+    /// Handles the `textDocument/hover` request.
+    pub fn handle_hover(
+        snapshot: &LspSnapshot,
+        params: HoverParams,
+    ) -> Result<Option<Hover>, String> {
+        Ok(snapshot.get_hover(params))
+    }
+
+    /// Handles the `textDocument/documentHighlight` request.
+    pub fn handle_document_highlight(
+        snapshot: &LspSnapshot,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>, String> {
+        Ok(snapshot.get_document_highlights(params))
+    }
+
+    /// Handles the `textDocument/documentSymbol` request.
+    pub fn handle_document_symbol(
+        snapshot: &LspSnapshot,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>, String> {
+        Ok(snapshot.get_document_symbols(params))
+    }
+
+    /// Handles the `textDocument/diagnostic` request.
+    pub fn handle_document_diagnostic(
+        snapshot: &LspSnapshot,
+        params: DocumentDiagnosticParams,
+    ) -> Result<FullDocumentDiagnosticReport, String> {
+        Ok(FullDocumentDiagnosticReport {
+            kind: "full".to_string(),
+            items: snapshot.get_diagnostics(&params.text_document.uri),
         })
     }
+
+    /// Handles the `textDocument/semanticTokens/full` request.
+    pub fn handle_semantic_tokens(
+        snapshot: &LspSnapshot,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokens>, String> {
+        Ok(snapshot.get_semantic_tokens(&params.text_document.uri))
+    }
+
+    /// Handles the `textDocument/semanticTokens/full/delta` request.
+    pub fn handle_semantic_tokens_delta(
+        snapshot: &LspSnapshot,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensDeltaResponse>, String> {
+        Ok(snapshot.get_semantic_tokens_delta(params))
+    }
+
+    /// Handles the `textDocument/semanticTokens/range` request.
+    pub fn handle_semantic_tokens_range(
+        snapshot: &LspSnapshot,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokens>, String> {
+        Ok(snapshot.get_semantic_tokens_range(params))
+    }
+
+    /// Handles the `textDocument/foldingRange` request.
+    pub fn handle_folding_range(
+        snapshot: &LspSnapshot,
+        params: FoldingRangeParams,
+    ) -> Result<Option<Vec<FoldingRange>>, String> {
+        Ok(snapshot.get_folding_ranges(params))
+    }
+
+    /// Handles the `textDocument/selectionRange` request.
+    pub fn handle_selection_range(
+        snapshot: &LspSnapshot,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>, String> {
+        Ok(snapshot.get_selection_ranges(params))
+    }
+
+    /// Handles the `textDocument/prepareTypeHierarchy` request.
+    pub fn handle_prepare_type_hierarchy(
+        snapshot: &LspSnapshot,
+        params: TypeHierarchyPrepareParams,
+    ) -> Result<Option<Vec<TypeHierarchyItem>>, String> {
+        Ok(snapshot.prepare_type_hierarchy(params))
+    }
+
+    /// Handles the `typeHierarchy/supertypes` request.
+    pub fn handle_supertypes(
+        snapshot: &LspSnapshot,
+        params: TypeHierarchySupertypesParams,
+    ) -> Result<Option<Vec<TypeHierarchyItem>>, String> {
+        Ok(snapshot.get_supertypes(params))
+    }
+
+    /// Handles the `typeHierarchy/subtypes` request.
+    pub fn handle_subtypes(
+        snapshot: &LspSnapshot,
+        params: TypeHierarchySubtypesParams,
+    ) -> Result<Option<Vec<TypeHierarchyItem>>, String> {
+        Ok(snapshot.get_subtypes(params))
+    }
+
+    /// Handles the `textDocument/typeDefinition` request.
+    pub fn handle_type_definition(
+        snapshot: &LspSnapshot,
+        params: TypeDefinitionParams,
+    ) -> Result<Option<Vec<Location>>, String> {
+        Ok(snapshot.get_type_definitions(params))
+    }
+
+    /// Handles the `textDocument/implementation` request.
+    pub fn handle_implementation(
+        snapshot: &LspSnapshot,
+        params: ImplementationParams,
+    ) -> Result<Option<Vec<Location>>, String> {
+        Ok(snapshot.get_implementations(params))
+    }
+
+    /// Handles the `textDocument/codeLens` request.
+    pub fn handle_code_lens(
+        snapshot: &LspSnapshot,
+        params: CodeLensParams,
+    ) -> Result<Option<Vec<CodeLens>>, String> {
+        Ok(snapshot.get_code_lenses(params))
+    }
+
+    /// Handles the `textDocument/prepareCallHierarchy` request.
+    pub fn handle_prepare_call_hierarchy(
+        snapshot: &LspSnapshot,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>, String> {
+        Ok(snapshot.prepare_call_hierarchy(params))
+    }
+
+    /// Handles the `callHierarchy/incomingCalls` request.
+    pub fn handle_incoming_calls(
+        snapshot: &LspSnapshot,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>, String> {
+        Ok(snapshot.get_incoming_calls(params))
+    }
+
+    /// Handles the `callHierarchy/outgoingCalls` request.
+    pub fn handle_outgoing_calls(
+        snapshot: &LspSnapshot,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>, String> {
+        Ok(snapshot.get_outgoing_calls(params))
+    }
+
+    /// Handles the `textDocument/inlayHint` request.
+    pub fn handle_inlay_hint(
+        snapshot: &LspSnapshot,
+        params: InlayHintParams,
+    ) -> Result<Option<Vec<InlayHint>>, String> {
+        Ok(snapshot.get_inlay_hints(params))
+    }
+
+    /// Handles the `inlayHint/resolve` request.
+    pub fn handle_inlay_hint_resolve(
+        snapshot: &LspSnapshot,
+        params: InlayHint,
+    ) -> Result<InlayHint, String> {
+        Ok(snapshot.resolve_inlay_hint(params))
+    }
+
+    /// Handles the `textDocument/completion` request.
+    pub fn handle_completion(
+        snapshot: &LspSnapshot,
+        params: CompletionParams,
+    ) -> Result<Vec<CompletionItem>, String> {
+        Ok(snapshot.get_completions(params).unwrap_or_default())
+    }
 }
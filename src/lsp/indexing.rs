@@ -0,0 +1,183 @@
+use log::error;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::handler::Job;
+use super::progress::Progress;
+use crate::storage::db::Canceled;
+use crate::storage::snapshot::LspSnapshot;
+
+/// Indexing of the workspace, which parses every file ahead of time.
+/// It runs on a snapshot, so a change to the state cancels it. The files
+/// that are left are then indexed from a new snapshot, see `resume`.
+pub struct Indexing {
+    workspace_files: Arc<Vec<String>>,
+    files: Mutex<VecDeque<String>>,
+    total: usize,
+    indexed: AtomicUsize,
+    /// Jobs that are working through the files
+    running: AtomicUsize,
+    progress: Progress,
+}
+
+impl Indexing {
+    pub fn new(workspace_files: Arc<Vec<String>>, progress: Progress) -> Arc<Self> {
+        Arc::new(Self {
+            files: Mutex::new(workspace_files.iter().cloned().collect()),
+            total: workspace_files.len(),
+            workspace_files,
+            indexed: AtomicUsize::new(0),
+            running: AtomicUsize::new(0),
+            progress,
+        })
+    }
+
+    /// Whether this indexes the given files of the workspace
+    pub fn is_for(&self, workspace_files: &Arc<Vec<String>>) -> bool {
+        Arc::ptr_eq(&self.workspace_files, workspace_files)
+    }
+
+    /// Stop indexing, e.g. because the files of the workspace changed
+    pub fn cancel(&self) {
+        self.progress.cancel();
+        self.progress.end(None);
+    }
+
+    /// A job that indexes the files that are left from `snapshot`. There is
+    /// none while another job is still working, or when nothing is left.
+    pub fn resume(self: &Arc<Self>, snapshot: LspSnapshot) -> Option<Job> {
+        if self.progress.is_canceled() || self.files.lock().unwrap().is_empty() {
+            return None;
+        }
+        if self.running.fetch_add(1, Ordering::SeqCst) > 0 {
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        let indexing = self.clone();
+        Some(Box::new(move || indexing.run(snapshot)))
+    }
+
+    fn run(&self, snapshot: LspSnapshot) {
+        loop {
+            if self.progress.is_canceled() {
+                self.progress.end(Some("Canceled".to_string()));
+                break;
+            }
+            let Some(uri) = self.files.lock().unwrap().pop_front() else {
+                break;
+            };
+
+            match panic::catch_unwind(AssertUnwindSafe(|| snapshot.index_file(&uri))) {
+                Err(payload) if payload.is::<Canceled>() => {
+                    // The file is indexed again from the next snapshot
+                    self.files.lock().unwrap().push_front(uri);
+                    break;
+                }
+                Err(_) => error!("Indexing {} panicked", uri),
+                Ok(()) => {}
+            }
+
+            let indexed = self.indexed.fetch_add(1, Ordering::SeqCst) + 1;
+            self.progress.report(
+                indexed,
+                self.total,
+                format!("{}/{} files", indexed, self.total),
+            );
+            if indexed == self.total {
+                self.progress
+                    .end(Some(format!("Indexed {} files", self.total)));
+            }
+        }
+
+        // A change waits for the snapshot to be dropped, so by the time it
+        // has been applied this job no longer counts as running
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        drop(snapshot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::client::Recording;
+    use crate::storage::line_index::PositionEncoding;
+    use crate::storage::state::LspState;
+    use serde_json::Value as Json;
+
+    fn workspace_files() -> Arc<Vec<String>> {
+        let files = (0..4).map(|index| format!("file:///missing/{}.py", index));
+        Arc::new(files.collect())
+    }
+
+    fn snapshot(state: &LspState) -> LspSnapshot {
+        state.snapshot(PositionEncoding::default(), Arc::default())
+    }
+
+    #[test]
+    fn indexes_every_file() {
+        let state = LspState::new();
+        let recording = Recording::default();
+        let client = recording.client();
+        let indexing = Indexing::new(
+            workspace_files(),
+            Progress::begin(Some(client.clone()), "Indexing", true),
+        );
+        recording.respond(&client, "window/workDoneProgress/create", Ok(Json::Null));
+
+        indexing.resume(snapshot(&state)).unwrap()();
+        assert_eq!(indexing.indexed.load(Ordering::SeqCst), 4);
+        assert!(indexing.resume(snapshot(&state)).is_none());
+        client.close();
+
+        let kinds: Vec<String> = recording
+            .messages()
+            .iter()
+            .filter(|message| message["method"] == "$/progress")
+            .map(|message| {
+                message["params"]["value"]["kind"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            ["begin", "report", "report", "report", "report", "end"]
+        );
+    }
+
+    #[test]
+    fn indexes_without_progress() {
+        let state = LspState::new();
+        let indexing = Indexing::new(workspace_files(), Progress::begin(None, "Indexing", true));
+        indexing.resume(snapshot(&state)).unwrap()();
+        assert_eq!(indexing.indexed.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn stops_when_canceled_from_the_client() {
+        let state = LspState::new();
+        let recording = Recording::default();
+        let client = recording.client();
+        let indexing = Indexing::new(
+            workspace_files(),
+            Progress::begin(Some(client.clone()), "Indexing", true),
+        );
+        recording.respond(&client, "window/workDoneProgress/create", Ok(Json::Null));
+        let job = indexing.resume(snapshot(&state)).unwrap();
+
+        // The first progress of a client gets the first token
+        client.cancel_progress("rypy/0");
+        job();
+        assert_eq!(indexing.indexed.load(Ordering::SeqCst), 0);
+        assert!(indexing.resume(snapshot(&state)).is_none());
+        client.close();
+
+        let last = recording.messages().pop().unwrap();
+        assert_eq!(last["params"]["value"]["kind"], "end");
+        assert_eq!(last["params"]["value"]["message"], "Canceled");
+    }
+}
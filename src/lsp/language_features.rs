@@ -16,9 +16,318 @@ pub struct TextDocumentPositionParams {
     pub position: Position,
 }
 
+pub type HoverParams = TextDocumentPositionParams;
+pub type CompletionParams = TextDocumentPositionParams;
+pub type DocumentHighlightParams = TextDocumentPositionParams;
+pub type TypeHierarchyPrepareParams = TextDocumentPositionParams;
+pub type ImplementationParams = TextDocumentPositionParams;
+pub type CallHierarchyPrepareParams = TextDocumentPositionParams;
+pub type TypeDefinitionParams = TextDocumentPositionParams;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+pub type DocumentSymbolParams = TextDocumentParams;
+pub type DocumentDiagnosticParams = TextDocumentParams;
+pub type SemanticTokensParams = TextDocumentParams;
+pub type FoldingRangeParams = TextDocumentParams;
+pub type CodeLensParams = TextDocumentParams;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionRangeParams {
+    pub text_document: TextDocumentIdentifier,
+    pub positions: Vec<Position>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensRangeParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensDeltaParams {
+    pub text_document: TextDocumentIdentifier,
+    pub previous_result_id: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Location {
     pub uri: String,
     pub range: Range
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationLink {
+    /// The range of the name the request was made on
+    pub origin_selection_range: Option<Range>,
+    pub target_uri: String,
+    pub target_range: Range,
+    pub target_selection_range: Range,
+}
+
+/// Definitions are returned as links to clients that support them
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum GotoDefinitionResponse {
+    Location(Location),
+    Links(Vec<LocationLink>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Hover {
+    pub contents: MarkupContent,
+    pub range: Option<Range>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkupContent {
+    pub kind: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: Option<u8>,
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_text: Option<String>,
+    /// 1 = plain text, 2 = snippet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentHighlight {
+    pub range: Range,
+    /// 1 = text, 2 = read, 3 = write
+    pub kind: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: u8,
+    /// The whole definition
+    pub range: Range,
+    /// The name of the definition
+    pub selection_range: Range,
+    pub children: Vec<DocumentSymbol>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInformation {
+    pub name: String,
+    pub kind: u8,
+    pub location: Location,
+    pub container_name: Option<String>,
+}
+
+/// Document symbols are nested for clients that support it, and flat otherwise
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum DocumentSymbolResponse {
+    Nested(Vec<DocumentSymbol>),
+    Flat(Vec<SymbolInformation>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    pub range: Range,
+    /// 1 = error, 2 = warning, 3 = information, 4 = hint
+    pub severity: Option<u8>,
+    pub code: Option<String>,
+    pub source: Option<String>,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishDiagnosticsParams {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// The diagnostics of a document the client pulled with `textDocument/diagnostic`
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FullDocumentDiagnosticReport {
+    /// Always `full`, as the server doesn't keep track of what the client has
+    pub kind: String,
+    pub items: Vec<Diagnostic>,
+}
+
+/// Tokens as groups of five integers: the line and start character relative
+/// to the previous token, the length, the type and the modifier bits
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokens {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<String>,
+    pub data: Vec<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensDelta {
+    pub result_id: Option<String>,
+    pub edits: Vec<SemanticTokensEdit>,
+}
+
+/// Replaces `delete_count` integers at `start` of the previous tokens with `data`
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensEdit {
+    pub start: u32,
+    pub delete_count: u32,
+    pub data: Vec<u32>,
+}
+
+/// All tokens are sent again when the previous ones are unknown
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum SemanticTokensDeltaResponse {
+    Full(SemanticTokens),
+    Delta(SemanticTokensDelta),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FoldingRange {
+    pub start_line: usize,
+    pub end_line: usize,
+    /// `comment`, `imports` or `region`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+}
+
+/// A range to select, along with the larger one that contains it
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectionRange {
+    pub range: Range,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Box<SelectionRange>>,
+}
+
+/// A definition in a type or call hierarchy. Clients send it back to ask for the
+/// definitions related to it, which are found from the name at `selection_range`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HierarchyItem {
+    pub name: String,
+    pub kind: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: String,
+    /// The whole definition
+    pub range: Range,
+    /// The name of the definition
+    pub selection_range: Range,
+}
+
+pub type TypeHierarchyItem = HierarchyItem;
+pub type CallHierarchyItem = HierarchyItem;
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HierarchyItemParams {
+    pub item: HierarchyItem,
+}
+
+pub type TypeHierarchySupertypesParams = HierarchyItemParams;
+pub type TypeHierarchySubtypesParams = HierarchyItemParams;
+pub type CallHierarchyIncomingCallsParams = HierarchyItemParams;
+pub type CallHierarchyOutgoingCallsParams = HierarchyItemParams;
+
+/// A function calling the function of an item, with the ranges of the calls in it
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyIncomingCall {
+    pub from: CallHierarchyItem,
+    pub from_ranges: Vec<Range>,
+}
+
+/// A definition the function of an item calls, with the ranges of the calls
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyOutgoingCall {
+    pub to: CallHierarchyItem,
+    pub from_ranges: Vec<Range>,
+}
+
+/// A command shown above a range, which runs when it is clicked
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeLens {
+    pub range: Range,
+    pub command: Option<Command>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Command {
+    pub title: String,
+    pub command: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Vec<serde_json::Value>>,
+}
+
+/// A label shown inline in the editor. Resolving it turns the label into
+/// parts that link to definitions, and adds a tooltip.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: Position,
+    pub label: InlayHintLabel,
+    /// 1 = type, 2 = parameter
+    pub kind: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<MarkupContent>,
+    pub padding_left: bool,
+    pub padding_right: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum InlayHintLabel {
+    String(String),
+    Parts(Vec<InlayHintLabelPart>),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintLabelPart {
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tooltip: Option<MarkupContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+}
@@ -2,20 +2,199 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value as Json;
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct InitializeParams {
     pub capabilities: ClientCapabilities,
     pub process_id: Option<u32>,
     pub root_uri: Option<String>,
     pub initialization_options: Option<Json>,
-    pub trace: Option<String>,
+    pub trace: Option<TraceValue>,
+    pub workspace_folders: Option<Vec<WorkspaceFolder>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspaceFolder {
+    pub uri: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
 pub struct ClientCapabilities {
-    // Define client capabilities here
+    pub general: Option<GeneralClientCapabilities>,
+    pub window: Option<WindowClientCapabilities>,
+    pub workspace: Option<WorkspaceClientCapabilities>,
+    pub text_document: Option<TextDocumentClientCapabilities>,
+}
+
+impl ClientCapabilities {
+    fn text_document(&self) -> Option<&TextDocumentClientCapabilities> {
+        self.text_document.as_ref()
+    }
+
+    /// Whether hover contents are shown as markdown, which clients
+    /// that don't say anything about it are assumed to support
+    pub fn hover_markdown(&self) -> bool {
+        self.text_document()
+            .and_then(|text_document| text_document.hover.as_ref())
+            .and_then(|hover| hover.content_format.as_ref())
+            .is_none_or(|formats| formats.first().is_some_and(|format| format == "markdown"))
+    }
+
+    pub fn definition_links(&self) -> bool {
+        self.text_document()
+            .and_then(|text_document| text_document.definition.as_ref())
+            .and_then(|definition| definition.link_support)
+            .unwrap_or_default()
+    }
+
+    pub fn hierarchical_document_symbols(&self) -> bool {
+        self.text_document()
+            .and_then(|text_document| text_document.document_symbol.as_ref())
+            .and_then(|symbol| symbol.hierarchical_document_symbol_support)
+            .unwrap_or_default()
+    }
+
+    pub fn completion_snippets(&self) -> bool {
+        self.text_document()
+            .and_then(|text_document| text_document.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|item| item.snippet_support)
+            .unwrap_or_default()
+    }
+
+    /// Whether the client asks for diagnostics with `textDocument/diagnostic`,
+    /// instead of having them published
+    pub fn pull_diagnostics(&self) -> bool {
+        self.text_document()
+            .is_some_and(|text_document| text_document.diagnostic.is_some())
+    }
+
+    pub fn diagnostic_refresh(&self) -> bool {
+        self.workspace
+            .as_ref()
+            .and_then(|workspace| workspace.diagnostics.as_ref())
+            .and_then(|diagnostics| diagnostics.refresh_support)
+            .unwrap_or_default()
+    }
+
+    /// Whether `workspace/configuration` can be used to ask for settings
+    pub fn configuration(&self) -> bool {
+        self.workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or_default()
+    }
+
+    /// Whether file watchers can be registered with `client/registerCapability`
+    pub fn watched_files_registration(&self) -> bool {
+        self.workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched_files| watched_files.dynamic_registration)
+            .unwrap_or_default()
+    }
+
+    pub fn work_done_progress(&self) -> bool {
+        self.window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or_default()
+    }
+
+    /// Whether `window/showDocument` can be used to open a document at a range
+    pub fn show_document(&self) -> bool {
+        self.window
+            .as_ref()
+            .and_then(|window| window.show_document.as_ref())
+            .is_some_and(|show_document| show_document.support)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceClientCapabilities {
+    pub configuration: Option<bool>,
+    pub did_change_watched_files: Option<DynamicRegistrationCapabilities>,
+    pub diagnostics: Option<RefreshCapabilities>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentClientCapabilities {
+    pub hover: Option<HoverClientCapabilities>,
+    pub definition: Option<DefinitionClientCapabilities>,
+    pub document_symbol: Option<DocumentSymbolClientCapabilities>,
+    pub completion: Option<CompletionClientCapabilities>,
+    pub diagnostic: Option<DynamicRegistrationCapabilities>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicRegistrationCapabilities {
+    pub dynamic_registration: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshCapabilities {
+    pub refresh_support: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct HoverClientCapabilities {
+    /// Content formats the client supports, in order of preference
+    pub content_format: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DefinitionClientCapabilities {
+    pub link_support: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbolClientCapabilities {
+    pub hierarchical_document_symbol_support: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionClientCapabilities {
+    pub completion_item: Option<CompletionItemCapabilities>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionItemCapabilities {
+    pub snippet_support: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowClientCapabilities {
+    /// Whether the client shows progress the server creates with `window/workDoneProgress/create`
+    pub work_done_progress: Option<bool>,
+    pub show_document: Option<ShowDocumentClientCapabilities>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ShowDocumentClientCapabilities {
+    pub support: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GeneralClientCapabilities {
+    /// Position encodings supported by the client, in order of preference
+    pub position_encodings: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct InitializeResult {
     pub capabilities: ServerCapabilities,
     pub server_info: Option<ServerInfo>,
@@ -24,9 +203,77 @@ pub struct InitializeResult {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ServerCapabilities {
+    pub position_encoding: Option<String>,
     pub text_document_sync: Option<u8>,
     pub hover_provider: Option<bool>,
+    pub completion_provider: Option<CompletionOptions>,
     pub definition_provider: Option<bool>,
+    pub document_highlight_provider: Option<bool>,
+    pub document_symbol_provider: Option<bool>,
+    pub diagnostic_provider: Option<DiagnosticOptions>,
+    pub semantic_tokens_provider: Option<SemanticTokensOptions>,
+    pub folding_range_provider: Option<bool>,
+    pub selection_range_provider: Option<bool>,
+    pub type_hierarchy_provider: Option<bool>,
+    pub type_definition_provider: Option<bool>,
+    pub implementation_provider: Option<bool>,
+    pub call_hierarchy_provider: Option<bool>,
+    pub code_lens_provider: Option<CodeLensOptions>,
+    pub inlay_hint_provider: Option<InlayHintOptions>,
+    pub execute_command_provider: Option<ExecuteCommandOptions>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeLensOptions {
+    pub resolve_provider: bool,
+}
+
+/// The commands the server runs with `workspace/executeCommand`
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecuteCommandOptions {
+    pub commands: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintOptions {
+    pub resolve_provider: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensOptions {
+    pub legend: SemanticTokensLegend,
+    pub range: bool,
+    pub full: SemanticTokensFullOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensLegend {
+    pub token_types: Vec<String>,
+    pub token_modifiers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensFullOptions {
+    pub delta: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticOptions {
+    pub inter_file_dependencies: bool,
+    pub workspace_diagnostics: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionOptions {
+    pub resolve_provider: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -34,3 +281,78 @@ pub struct ServerInfo {
     pub name: String,
     pub version: Option<String>,
 }
+
+/// How much the server traces to the client with `$/logTrace`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TraceValue {
+    #[default]
+    Off,
+    Messages,
+    Verbose,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetTraceParams {
+    pub value: TraceValue,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogTraceParams {
+    pub message: String,
+    /// Only sent when the trace is set to `verbose`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationParams {
+    pub registrations: Vec<Registration>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Registration {
+    pub id: String,
+    pub method: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub register_options: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientCapabilities;
+    use serde_json::json;
+
+    #[test]
+    fn client_capabilities() {
+        let minimal: ClientCapabilities = serde_json::from_value(json!({})).unwrap();
+        assert!(minimal.hover_markdown());
+        assert!(!minimal.definition_links());
+        assert!(!minimal.pull_diagnostics());
+        assert!(!minimal.watched_files_registration());
+
+        let full: ClientCapabilities = serde_json::from_value(json!({
+            "workspace": {
+                "configuration": true,
+                "didChangeWatchedFiles": { "dynamicRegistration": true },
+            },
+            "textDocument": {
+                "hover": { "contentFormat": ["plaintext", "markdown"] },
+                "definition": { "linkSupport": true },
+                "documentSymbol": { "hierarchicalDocumentSymbolSupport": true },
+                "completion": { "completionItem": { "snippetSupport": true } },
+                "diagnostic": { "dynamicRegistration": false },
+            },
+        }))
+        .unwrap();
+        assert!(!full.hover_markdown());
+        assert!(full.definition_links());
+        assert!(full.hierarchical_document_symbols());
+        assert!(full.completion_snippets());
+        assert!(full.pull_diagnostics());
+        assert!(full.configuration());
+        assert!(full.watched_files_registration());
+    }
+}
@@ -1,4 +1,9 @@
+pub mod client;
 pub mod handler;
+pub mod indexing;
 pub mod lifecycle;
 pub mod document_sync;
-pub mod language_features;
\ No newline at end of file
+pub mod language_features;
+pub mod workspace;
+pub mod window;
+pub mod progress;
@@ -0,0 +1,234 @@
+use log::debug;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::client::Client;
+use super::window::{ProgressParams, WorkDoneProgress, WorkDoneProgressCreateParams};
+
+/// Progress of long-running work, shown in the client with `$/progress`.
+/// Without a client that supports it nothing is sent, but the work can still
+/// check whether it was canceled.
+pub struct Progress {
+    client: Option<Arc<Client>>,
+    token: String,
+    canceled: Arc<AtomicBool>,
+    /// The last percentage reported, so reports are only sent when it changes
+    percentage: AtomicU32,
+    ended: AtomicBool,
+    creation: Arc<Mutex<Creation>>,
+}
+
+/// Whether the client created the token, which it has to before any `$/progress` for it
+enum Creation {
+    /// Waiting for the response to `window/workDoneProgress/create`, with the values to send then
+    Pending(Vec<WorkDoneProgress>),
+    Created,
+    /// The client refused, so the progress isn't shown
+    Failed,
+}
+
+impl Progress {
+    /// Create the progress in the client and show it with `title`
+    pub fn begin(client: Option<Arc<Client>>, title: &str, cancellable: bool) -> Self {
+        let Some(client) = client else {
+            return Self {
+                client: None,
+                token: String::new(),
+                canceled: Arc::new(AtomicBool::new(false)),
+                percentage: AtomicU32::new(0),
+                ended: AtomicBool::new(false),
+                creation: Arc::new(Mutex::new(Creation::Failed)),
+            };
+        };
+
+        let (token, canceled) = client.register_progress();
+        let params = WorkDoneProgressCreateParams {
+            token: token.clone(),
+        };
+        let creation = Arc::new(Mutex::new(Creation::Pending(Vec::new())));
+        let (created, created_token) = (creation.clone(), token.clone());
+        let weak_client = Arc::downgrade(&client);
+        client.request(
+            "window/workDoneProgress/create",
+            params,
+            move |_, result| {
+                let mut creation = created.lock().unwrap();
+                let values = match std::mem::replace(&mut *creation, Creation::Failed) {
+                    Creation::Pending(values) => values,
+                    _ => return,
+                };
+                let Some(client) = weak_client.upgrade() else {
+                    return;
+                };
+                match result {
+                    Ok(_) => {
+                        for value in values {
+                            notify(&client, &created_token, value);
+                        }
+                        *creation = Creation::Created;
+                    }
+                    Err(error) => {
+                        debug!("Client didn't create progress: {:?}", error);
+                        client.unregister_progress(&created_token);
+                    }
+                }
+            },
+        );
+
+        // Until the client answers, the values are kept to be sent once it created the token
+        let progress = Self {
+            client: Some(client),
+            token,
+            canceled,
+            percentage: AtomicU32::new(0),
+            ended: AtomicBool::new(false),
+            creation,
+        };
+        progress.send(WorkDoneProgress::Begin {
+            title: title.to_string(),
+            cancellable: Some(cancellable),
+            message: None,
+            percentage: Some(0),
+        });
+        progress
+    }
+
+    /// Report `done` out of `total` steps, when that moves the percentage
+    pub fn report(&self, done: usize, total: usize, message: String) {
+        let percentage = (done * 100 / total.max(1)) as u32;
+        if self.percentage.swap(percentage, Ordering::Relaxed) == percentage && done != total {
+            return;
+        }
+        self.send(WorkDoneProgress::Report {
+            message: Some(message),
+            percentage: Some(percentage),
+        });
+    }
+
+    /// Stop showing the progress, which only the first call does
+    pub fn end(&self, message: Option<String>) {
+        if self.ended.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        self.send(WorkDoneProgress::End { message });
+        if let Some(client) = &self.client {
+            client.unregister_progress(&self.token);
+        }
+    }
+
+    /// Whether the user canceled the work from the client
+    pub fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::Relaxed)
+    }
+
+    /// Cancel the work from the server's side, e.g. when it has to start over
+    pub fn cancel(&self) {
+        self.canceled.store(true, Ordering::Relaxed);
+    }
+
+    fn send(&self, value: WorkDoneProgress) {
+        let Some(client) = &self.client else {
+            return;
+        };
+        // Sending while holding the lock keeps the values in order
+        match &mut *self.creation.lock().unwrap() {
+            Creation::Pending(values) => values.push(value),
+            Creation::Created => notify(client, &self.token, value),
+            Creation::Failed => {}
+        }
+    }
+}
+
+fn notify(client: &Client, token: &str, value: WorkDoneProgress) {
+    let params = ProgressParams {
+        token: token.to_string(),
+        value,
+    };
+    client.notify("$/progress", params);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::client::Recording;
+    use serde_json::{json, Value as Json};
+
+    const CREATE: &str = "window/workDoneProgress/create";
+
+    #[test]
+    fn begin_report_end() {
+        let recording = Recording::default();
+        let client = recording.client();
+        let progress = Progress::begin(Some(client.clone()), "Indexing", true);
+        progress.report(1, 4, "1/4 files".to_string());
+        // Reports that don't move the percentage aren't sent
+        progress.report(1, 4, "1/4 files".to_string());
+        // What was reported before the client created the token is sent once it did
+        recording.respond(&client, CREATE, Ok(Json::Null));
+        progress.report(2, 4, "2/4 files".to_string());
+        progress.end(Some("Done".to_string()));
+        progress.end(None);
+        client.close();
+
+        let messages = recording.messages();
+        assert_eq!(messages[0]["method"], CREATE);
+        let token = messages[0]["params"]["token"].clone();
+        let values: Vec<Json> = messages[1..]
+            .iter()
+            .map(|message| {
+                assert_eq!(message["method"], "$/progress");
+                assert_eq!(message["params"]["token"], token);
+                message["params"]["value"].clone()
+            })
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                json!({"kind": "begin", "title": "Indexing", "cancellable": true, "percentage": 0}),
+                json!({"kind": "report", "message": "1/4 files", "percentage": 25}),
+                json!({"kind": "report", "message": "2/4 files", "percentage": 50}),
+                json!({"kind": "end", "message": "Done"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn client_failed_to_create() {
+        let recording = Recording::default();
+        let client = recording.client();
+        let progress = Progress::begin(Some(client.clone()), "Indexing", true);
+        progress.report(1, 2, "1/2 files".to_string());
+        recording.respond(&client, CREATE, Err(json!({"code": -32603})));
+        progress.report(2, 2, "2/2 files".to_string());
+        progress.end(None);
+        client.close();
+
+        let messages = recording.messages();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["method"], CREATE);
+    }
+
+    #[test]
+    fn canceled_from_the_client() {
+        let recording = Recording::default();
+        let client = recording.client();
+        let progress = Progress::begin(Some(client.clone()), "Indexing", true);
+        client.close();
+        let token = recording.messages()[0]["params"]["token"].clone();
+
+        client.cancel_progress("unknown");
+        assert!(!progress.is_canceled());
+        client.cancel_progress(token.as_str().unwrap());
+        assert!(progress.is_canceled());
+    }
+
+    #[test]
+    fn without_a_client() {
+        let progress = Progress::begin(None, "Indexing", true);
+        progress.report(1, 2, "1/2 files".to_string());
+        progress.end(None);
+        assert!(!progress.is_canceled());
+        progress.cancel();
+        assert!(progress.is_canceled());
+    }
+}
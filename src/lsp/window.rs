@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use super::document_sync::Range;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogMessageParams {
+    /// 1 = error, 2 = warning, 3 = info, 4 = log
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShowMessageParams {
+    /// 1 = error, 2 = warning, 3 = info, 4 = log
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub message: String,
+}
+
+/// A message with actions the user picks from, answered with the picked one
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ShowMessageRequestParams {
+    /// 1 = error, 2 = warning, 3 = info, 4 = log
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub message: String,
+    pub actions: Vec<MessageActionItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessageActionItem {
+    pub title: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ShowDocumentParams {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub take_focus: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection: Option<Range>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkDoneProgressCreateParams {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkDoneProgressCancelParams {
+    pub token: String,
+}
+
+/// The parameters of `$/progress`, whose value depends on the kind of progress
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProgressParams {
+    pub token: String,
+    pub value: WorkDoneProgress,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum WorkDoneProgress {
+    Begin {
+        title: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cancellable: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        percentage: Option<u32>,
+    },
+    Report {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        percentage: Option<u32>,
+    },
+    End {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+}
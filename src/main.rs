@@ -1,80 +1,109 @@
-use log::{debug, error};
-use simplelog::{CombinedLogger, ConfigBuilder, WriteLogger, LevelFilter};
-use std::fs::File;
-use std::io::Write;
+use log::error;
+use std::sync::Arc;
 
+use server::Server;
+use transport::{Connection, Listener, Transport};
+
+mod cli;
+mod commands;
+mod config;
+mod logging;
 mod lsp;
 mod parser;
+mod pool;
 mod rpc;
 mod analysis;
+mod server;
 mod storage;
+mod transport;
 
 fn main() {
-    // Initialize the logger to save to file
-    let mut config = ConfigBuilder::new();
-    config.set_target_level(LevelFilter::Error); // Prevents verbose target logs
-    config.add_filter_ignore_str("salsa"); // Ignore salsa logs
+    let options = match cli::Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    for warning in &options.warnings {
+        eprintln!("{}", warning);
+    }
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    if options.version {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return;
+    }
 
-    CombinedLogger::init(vec![WriteLogger::new(
-        LevelFilter::Debug,
-        config.build(),
-        File::create("lsp.log").unwrap(),
-    )])
-    .unwrap();
+    // Logs go to the client, and to a file only when asked for
+    logging::init(options.log_level, options.log_file.as_deref());
 
-    
+    // Stubs are read from disk, so the bundled one has to be there before any analysis
+    storage::typeshed::write_bundled_builtins();
 
+    // Commands run once without a client, so warnings are shown on stderr
+    if options.command != cli::Command::Serve {
+        logging::set_client(|method, params| {
+            if method == "window/logMessage" && params["type"].as_u64() <= Some(2) {
+                eprintln!("{}", params["message"].as_str().unwrap_or_default());
+            }
+        });
 
-    // Create a new LSP handler
-    let mut lsp_handler = match lsp::handler::LspHandler::initialize() {
+        let code = match std::env::current_dir() {
+            Ok(root) => commands::run(options.command, &root, &mut std::io::stdout().lock()),
+            Err(e) => {
+                eprintln!("No working directory: {}", e);
+                2
+            }
+        };
+        std::process::exit(code);
+    }
+
+    // Create a new LSP handler, which all clients share
+    let lsp_handler = match lsp::handler::LspHandler::initialize() {
         Ok(lsp_handler) => lsp_handler,
         Err(error) => {
             error!("Error: {:?}", error);
             panic!();
         }
     };
+    let server = Arc::new(Server::new(lsp_handler));
 
-    loop {
-        // Read a message from stdin
-        match lsp_handler.read_message() {
-            Ok(message) => {
-                debug!("Received message: {:?}", message);
-
-                // Decode the message
-                let (jsonrpc, id, method, params) = match rpc::decode_request(message) {
-                    Ok(decoded) => decoded,
-                    Err(e) => {
-                        error!("Error decoding message: {:?}", e);
-                        continue;
-                    }
-                };
-
-                // Handle the message
-                let result = match lsp_handler.handle_response(method, params) {
-                    Ok(result) => match result {
-                        Some(result) => result,
-                        None => continue,
-                    },
-                    Err(error) => {
-                        error!("Error: {:?}", error);
-                        continue;
-                    }
-                };
+    // Logs are sent to every connected client
+    let log_server = server.clone();
+    logging::set_client(move |method, params| log_server.broadcast(method, params));
 
-                // Encode the response
-                let encoded = rpc::encode_response(jsonrpc, id, result);
-                debug!("Sending message: {:?}", encoded);
-                // Add the Content-Length header
-                let content_length = encoded.len();
-                let message = format!("Content-Length: {}\r\n\r\n{}", content_length, encoded);
-
-                // Write the response to stdout
-                std::io::stdout().write_all(message.as_bytes()).unwrap();
-                std::io::stdout().flush().unwrap();
-            }
+    // The process exits along with its only client, with the code the protocol asks for
+    match options.transport {
+        Transport::Stdio => std::process::exit(server.serve(Connection::stdio())),
+        Transport::Connect(address) => match Connection::connect(&address) {
+            Ok(connection) => std::process::exit(server.serve(connection)),
             Err(e) => {
-                error!("Error reading message: {:?}", e);
-                break;
+                eprintln!("Failed to connect to {}: {}", address, e);
+                std::process::exit(1);
+            }
+        },
+        transport @ (Transport::Listen(_) | Transport::Socket(_)) => {
+            let listener = match Listener::bind(&transport) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!("Failed to listen on {:?}: {}", transport, e);
+                    std::process::exit(1);
+                }
+            };
+            // Clients are served side by side and share the analysis of the workspace
+            loop {
+                match listener.accept() {
+                    Ok(connection) => {
+                        let server = server.clone();
+                        std::thread::spawn(move || {
+                            server.serve(connection);
+                        });
+                    }
+                    Err(e) => error!("Failed to accept connection: {}", e),
+                }
             }
         }
     }
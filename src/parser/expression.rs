@@ -0,0 +1,1181 @@
+use std::collections::HashMap;
+
+use super::parser::{is_string_annotation, with_string_annotation, SymbolCollector};
+use super::symbol::{Location, Symbol, SymbolKind};
+
+/// An expression, lowered from the syntax tree to the parts that type inference needs
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Expr {
+    /// A name, which is resolved from the reference at its location
+    Name(String, Location),
+    /// `object.name`, with the location of `name`
+    Attribute(Box<Expr>, String, Location),
+    Call(Box<Expr>, Vec<Argument>),
+    Subscript(Box<Expr>, Vec<Expr>),
+    /// `start:stop:step`, as the index of a subscript
+    Slice,
+    Literal(Literal),
+    List(Vec<Expr>),
+    Tuple(Vec<Expr>),
+    Set(Vec<Expr>),
+    Dict(Vec<(Expr, Expr)>),
+    /// `left operator right`, for arithmetic and bitwise operators
+    BinaryOperation(Box<Expr>, String, Box<Expr>),
+    /// `a or b`, `a if condition else b`, and unions in annotations
+    Either(Box<Expr>, Box<Expr>),
+    Await(Box<Expr>),
+    /// A name that an enclosing `isinstance` check narrows to instances of the classes
+    Narrowed(Box<Expr>, Box<Expr>),
+    /// A name that an enclosing check narrows to anything but `None`
+    NotNone(Box<Expr>),
+    /// An element of an iterable, as bound by a `for` loop
+    Iterated(Box<Expr>),
+    /// The element at an index of an unpacked value, as in `a, b = value`
+    Unpacked(Box<Expr>, usize),
+    /// The value bound by `with value as name`
+    Entered(Box<Expr>),
+    /// The exception bound by `except classes as name`
+    Caught(Box<Expr>),
+    Lambda,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Literal {
+    Int,
+    Float,
+    Str,
+    Bytes,
+    /// `True` or `False`, or a comparison whose value isn't known
+    Bool(Option<bool>),
+    None,
+    Ellipsis,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Argument {
+    Positional(Expr),
+    Keyword(String, Expr),
+    /// `*args` or `**kwargs`
+    Unpacked(Expr),
+}
+
+/// What a definition binds its name to
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Binding {
+    /// An assignment, possibly annotated, or an annotation without a value
+    Variable {
+        annotation: Option<Expr>,
+        value: Option<Expr>,
+    },
+    /// The parameter at an index of the function whose name is at `function`
+    Parameter {
+        function: Location,
+        index: usize,
+    },
+    Function(Function),
+    Class(Class),
+    /// A PEP 695 alias, as in `type Users = list[User]`, bound to the type it stands for
+    TypeAlias(Expr),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Function {
+    pub parameters: Vec<Parameter>,
+    /// The end of the parameter list, where the return annotation goes
+    pub parameters_end: (usize, usize),
+    /// The return annotation
+    pub returns: Option<Expr>,
+    /// The values of the `return` statements
+    pub return_values: Vec<Expr>,
+    pub decorators: Vec<Expr>,
+    /// The class the function is a method of, by the location of its name
+    pub class: Option<Location>,
+    pub is_async: bool,
+    pub is_generator: bool,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub location: Location,
+    pub kind: ParameterKind,
+    pub annotation: Option<Expr>,
+    pub default: Option<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParameterKind {
+    PositionalOnly,
+    Positional,
+    /// `*args`
+    VarPositional,
+    KeywordOnly,
+    /// `**kwargs`
+    VarKeyword,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Class {
+    /// The classes derived from, including generic ones like `Generic[T]`
+    pub bases: Vec<Expr>,
+    /// The `metaclass=` keyword of the class statement
+    pub metaclass: Option<Expr>,
+    /// The other keywords of the class statement, like `total=False`
+    pub keywords: Vec<(String, Expr)>,
+    pub decorators: Vec<Expr>,
+    /// The definitions of the class body, followed by the attributes that methods
+    /// assign to `self`
+    pub members: Vec<Symbol>,
+}
+
+/// The bindings of the definitions of a document, by the location of their name
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Bindings {
+    bindings: HashMap<Location, Binding>,
+    /// The locations of the members of every class, along with that of their class
+    members: HashMap<Location, Location>,
+}
+
+impl Bindings {
+    pub fn get(&self, location: &Location) -> Option<&Binding> {
+        self.bindings.get(location)
+    }
+
+    pub fn function(&self, location: &Location) -> Option<&Function> {
+        match self.bindings.get(location) {
+            Some(Binding::Function(function)) => Some(function),
+            _ => None,
+        }
+    }
+
+    pub fn class(&self, location: &Location) -> Option<&Class> {
+        match self.bindings.get(location) {
+            Some(Binding::Class(class)) => Some(class),
+            _ => None,
+        }
+    }
+
+    /// Check if a definition is made in the body of a class, or by a method on `self`
+    pub fn is_class_member(&self, location: &Location) -> bool {
+        self.members.contains_key(location)
+    }
+
+    /// Get the class a member is defined in, by the location of its name
+    pub fn member_class(&self, location: &Location) -> Option<&Location> {
+        self.members.get(location)
+    }
+}
+
+/// A call whose callee is a name or an attribute
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Call {
+    /// The name of the function the call is made in, or `None` at the module level
+    pub caller: Option<Location>,
+    pub callee: Expr,
+    /// The name of the callee, as in `name(...)` or `object.name(...)`
+    pub location: Location,
+    /// The arguments, along with their locations
+    pub arguments: Vec<(Location, Argument)>,
+}
+
+/// A `return` statement that returns a value
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Return {
+    /// The name of the function returned from
+    pub function: Location,
+    pub value: Expr,
+    /// The location of the returned value
+    pub location: Location,
+}
+
+/// Lowers the nodes of a syntax tree into expressions and bindings
+pub struct Lowering<'a> {
+    collector: SymbolCollector<'a>,
+    source: &'a [u8],
+}
+
+impl<'a> Lowering<'a> {
+    pub fn new(collector: SymbolCollector<'a>, source: &'a [u8]) -> Self {
+        Self { collector, source }
+    }
+
+    /// Collect the bindings of every definition in the tree
+    pub fn bindings(&self, root: tree_sitter::Node) -> Bindings {
+        let mut bindings = Bindings::default();
+        self.walk(root, |node| self.bind(node, &mut bindings));
+        bindings
+    }
+
+    /// Collect the calls of the tree whose callee is a name or an attribute
+    pub fn calls(&self, root: tree_sitter::Node) -> Vec<Call> {
+        let mut calls = Vec::new();
+        self.walk(root, |node| {
+            if node.kind() != "call" {
+                return;
+            }
+            let Some(function) = node.child_by_field_name("function") else {
+                return;
+            };
+            let name = match function.kind() {
+                "identifier" => function,
+                "attribute" => match function.child_by_field_name("attribute") {
+                    Some(name) => name,
+                    None => return,
+                },
+                _ => return,
+            };
+
+            // The innermost function the call is made in
+            let mut caller = None;
+            let mut parent = node.parent();
+            while let Some(node) = parent {
+                if node.kind() == "function_definition" {
+                    caller = node
+                        .child_by_field_name("name")
+                        .map(|name| self.location(name));
+                    break;
+                }
+                parent = node.parent();
+            }
+
+            let mut arguments = Vec::new();
+            match node.child_by_field_name("arguments") {
+                Some(argument_list) if argument_list.kind() == "argument_list" => {
+                    let mut cursor = argument_list.walk();
+                    arguments = argument_list
+                        .named_children(&mut cursor)
+                        .filter_map(|argument| {
+                            Some((self.location(argument), self.argument(argument)?))
+                        })
+                        .collect();
+                }
+                // `f(x for x in xs)` passes a generator
+                Some(generator) => arguments.push((
+                    self.location(generator),
+                    Argument::Positional(Expr::Unknown),
+                )),
+                None => {}
+            }
+
+            calls.push(Call {
+                caller,
+                callee: self.lower(function),
+                location: self.location(name),
+                arguments,
+            });
+        });
+        calls
+    }
+
+    /// Collect the `return` statements of the tree's functions that return a value
+    pub fn returns(&self, root: tree_sitter::Node) -> Vec<Return> {
+        let mut returns = Vec::new();
+        self.walk(root, |node| {
+            if node.kind() != "return_statement" {
+                return;
+            }
+            let Some(value) = node.named_child(0) else {
+                return;
+            };
+            let Some(function) = std::iter::successors(node.parent(), |node| node.parent())
+                .find(|ancestor| {
+                    matches!(ancestor.kind(), "function_definition" | "class_definition")
+                })
+                .filter(|scope| scope.kind() == "function_definition")
+                .and_then(|function| function.child_by_field_name("name"))
+            else {
+                return;
+            };
+            returns.push(Return {
+                function: self.location(function),
+                value: self.lower(value),
+                location: self.location(value),
+            });
+        });
+        returns
+    }
+
+    /// Collect the attributes the tree reads, lowered to `Expr::Attribute`. Those that
+    /// are assigned or deleted are left out, and so are those of functions that check
+    /// for attributes with `hasattr` and those guarded against an `AttributeError`.
+    pub fn attribute_reads(&self, root: tree_sitter::Node) -> Vec<Expr> {
+        let mut reads = Vec::new();
+        self.walk(root, |node| {
+            if node.kind() == "attribute" && !is_target(node) && !self.is_guarded(node) {
+                reads.push(self.lower(node));
+            }
+        });
+        reads
+    }
+
+    /// Check if an attribute may be missing on purpose, or be of a type that isn't
+    /// inferred: its function (or module) checks its object with `hasattr` or
+    /// `isinstance`, or it is in a `try` or `with` statement that handles an
+    /// `AttributeError`
+    fn is_guarded(&self, attribute: tree_sitter::Node) -> bool {
+        let object = attribute
+            .child_by_field_name("object")
+            .map(|object| self.text(object));
+        let is_guard = |call: tree_sitter::Node| {
+            let function = call
+                .child_by_field_name("function")
+                .map(|function| self.text(function));
+            let first_argument = call
+                .child_by_field_name("arguments")
+                .and_then(|arguments| arguments.named_child(0))
+                .map(|argument| self.text(argument));
+            match function.as_deref() {
+                Some("hasattr" | "isinstance") => first_argument == object,
+                _ => false,
+            }
+        };
+
+        let mut child = attribute;
+        while let Some(parent) = child.parent() {
+            let guarded = match parent.kind() {
+                "try_statement" if parent.child_by_field_name("body") == Some(child) => {
+                    let mut cursor = parent.walk();
+                    let handles = parent
+                        .named_children(&mut cursor)
+                        .filter(|clause| clause.kind() == "except_clause")
+                        .any(|clause| self.text(clause).contains("AttributeError"));
+                    handles
+                }
+                "with_statement" => parent
+                    .named_child(0)
+                    .is_some_and(|items| self.text(items).contains("AttributeError")),
+                "function_definition" => return self.has_call(parent, is_guard),
+                _ => false,
+            };
+            if guarded {
+                return true;
+            }
+            child = parent;
+        }
+        self.has_call(child, is_guard)
+    }
+
+    /// Check if the code of a scope makes a call, leaving out nested functions
+    fn has_call(
+        &self,
+        scope: tree_sitter::Node,
+        matches: impl Fn(tree_sitter::Node) -> bool,
+    ) -> bool {
+        let mut nodes = vec![scope];
+        while let Some(node) = nodes.pop() {
+            if node.kind() == "call" && matches(node) {
+                return true;
+            }
+            let mut cursor = node.walk();
+            nodes.extend(
+                node.named_children(&mut cursor)
+                    .filter(|child| child.kind() != "function_definition"),
+            );
+        }
+        false
+    }
+
+    /// Visit the reachable nodes of the tree, parents before their children
+    fn walk<'t>(&self, root: tree_sitter::Node<'t>, mut visit: impl FnMut(tree_sitter::Node<'t>)) {
+        let mut cursor = root.walk();
+        'walk: loop {
+            let node = cursor.node();
+            let reachable = !self.collector.is_unreachable(node);
+            if reachable {
+                visit(node);
+            }
+
+            if reachable && cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+    }
+
+    fn bind(&self, node: tree_sitter::Node, bindings: &mut Bindings) {
+        match node.kind() {
+            "assignment" => {
+                let Some(left) = node.child_by_field_name("left") else {
+                    return;
+                };
+                let annotation = node.child_by_field_name("type").map(|t| self.lower(t));
+                let value = self.assigned_value(node);
+                match value {
+                    Some(value) => self.bind_target(left, value, annotation, bindings),
+                    None if left.kind() == "identifier" => {
+                        let binding = Binding::Variable {
+                            annotation,
+                            value: None,
+                        };
+                        bindings.bindings.insert(self.location(left), binding);
+                    }
+                    None => {}
+                }
+            }
+            "augmented_assignment" => {
+                let (Some(left), Some(right)) = (
+                    node.child_by_field_name("left"),
+                    node.child_by_field_name("right"),
+                ) else {
+                    return;
+                };
+                self.bind_target(left, self.lower(right), None, bindings);
+            }
+            "for_statement" => {
+                let (Some(left), Some(right)) = (
+                    node.child_by_field_name("left"),
+                    node.child_by_field_name("right"),
+                ) else {
+                    return;
+                };
+                let value = Expr::Iterated(Box::new(self.lower(right)));
+                self.bind_target(left, value, None, bindings);
+            }
+            "as_pattern" => {
+                let (Some(value), Some(target)) = (
+                    node.named_child(0),
+                    node.child_by_field_name("alias")
+                        .and_then(|alias| alias.named_child(0)),
+                ) else {
+                    return;
+                };
+                let value = Box::new(self.lower(value));
+                let value = match node.parent().map(|parent| parent.kind()) {
+                    Some("with_item") => Expr::Entered(value),
+                    Some("except_clause") => Expr::Caught(value),
+                    _ => return,
+                };
+                self.bind_target(target, value, None, bindings);
+            }
+            "named_expression" => {
+                let (Some(name), Some(value)) = (
+                    node.child_by_field_name("name"),
+                    node.child_by_field_name("value"),
+                ) else {
+                    return;
+                };
+                self.bind_target(name, self.lower(value), None, bindings);
+            }
+            "type_alias_statement" => {
+                let (Some(left), Some(right)) = (
+                    node.child_by_field_name("left"),
+                    node.child_by_field_name("right"),
+                ) else {
+                    return;
+                };
+                // `type Pair[T] = tuple[T, T]` has its type parameters along with the name
+                let name = match left.named_child(0) {
+                    Some(generic) if generic.kind() == "generic_type" => generic.named_child(0),
+                    name => name,
+                };
+                if let Some(name) = name.filter(|name| name.kind() == "identifier") {
+                    let binding = Binding::TypeAlias(self.lower(right));
+                    bindings.bindings.insert(self.location(name), binding);
+                }
+            }
+            "function_definition" => self.bind_function(node, bindings),
+            "class_definition" => self.bind_class(node, bindings),
+            _ => {}
+        }
+    }
+
+    /// The value of an assignment, skipping over the targets of chained assignments
+    fn assigned_value(&self, assignment: tree_sitter::Node) -> Option<Expr> {
+        let right = assignment.child_by_field_name("right")?;
+        match right.kind() {
+            "assignment" => self.assigned_value(right),
+            _ => Some(self.lower(right)),
+        }
+    }
+
+    fn bind_target(
+        &self,
+        target: tree_sitter::Node,
+        value: Expr,
+        annotation: Option<Expr>,
+        bindings: &mut Bindings,
+    ) {
+        match target.kind() {
+            "identifier" => {
+                let binding = Binding::Variable {
+                    annotation,
+                    value: Some(value),
+                };
+                bindings.bindings.insert(self.location(target), binding);
+            }
+            "pattern_list" | "tuple_pattern" | "list_pattern" | "tuple" | "list"
+            | "expression_list" => {
+                let mut cursor = target.walk();
+                for (index, element) in target.named_children(&mut cursor).enumerate() {
+                    let element_value = Expr::Unpacked(Box::new(value.clone()), index);
+                    self.bind_target(element, element_value, None, bindings);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn bind_function(&self, node: tree_sitter::Node, bindings: &mut Bindings) {
+        let Some(name) = node.child_by_field_name("name") else {
+            return;
+        };
+        let function_location = self.location(name);
+
+        let mut parameters: Vec<Parameter> = Vec::new();
+        let mut keyword_only = false;
+        let mut parameters_end = function_location.end;
+        if let Some(parameter_list) = node.child_by_field_name("parameters") {
+            parameters_end = self.location(parameter_list).end;
+            let mut cursor = parameter_list.walk();
+            for parameter in parameter_list.named_children(&mut cursor) {
+                match parameter.kind() {
+                    "keyword_separator" => keyword_only = true,
+                    "positional_separator" => {
+                        for parameter in parameters.iter_mut() {
+                            parameter.kind = ParameterKind::PositionalOnly;
+                        }
+                    }
+                    _ => {
+                        let Some(parameter) = self.lower_parameter(parameter, keyword_only) else {
+                            continue;
+                        };
+                        if parameter.kind == ParameterKind::VarPositional {
+                            keyword_only = true;
+                        }
+                        let binding = Binding::Parameter {
+                            function: function_location.clone(),
+                            index: parameters.len(),
+                        };
+                        bindings
+                            .bindings
+                            .insert(parameter.location.clone(), binding);
+                        parameters.push(parameter);
+                    }
+                }
+            }
+        }
+
+        let mut return_values = Vec::new();
+        let mut is_generator = false;
+        if let Some(body) = node.child_by_field_name("body") {
+            self.collect_returns(body, &mut return_values, &mut is_generator);
+        }
+
+        let function = Function {
+            parameters,
+            parameters_end,
+            returns: node
+                .child_by_field_name("return_type")
+                .map(|t| self.lower(t)),
+            return_values,
+            decorators: self.decorators(node),
+            class: self.enclosing_class(node).map(|class| self.location(class)),
+            is_async: node.child(0).is_some_and(|first| first.kind() == "async"),
+            is_generator,
+        };
+        bindings
+            .bindings
+            .insert(function_location, Binding::Function(function));
+    }
+
+    fn lower_parameter(
+        &self,
+        parameter: tree_sitter::Node,
+        keyword_only: bool,
+    ) -> Option<Parameter> {
+        let (name, annotation, default) = match parameter.kind() {
+            "typed_parameter" => (
+                parameter.named_child(0)?,
+                parameter.child_by_field_name("type"),
+                None,
+            ),
+            "default_parameter" | "typed_default_parameter" => (
+                parameter.child_by_field_name("name")?,
+                parameter.child_by_field_name("type"),
+                parameter.child_by_field_name("value"),
+            ),
+            _ => (parameter, None, None),
+        };
+
+        let (name, kind) = match name.kind() {
+            "identifier" if keyword_only => (name, ParameterKind::KeywordOnly),
+            "identifier" => (name, ParameterKind::Positional),
+            "list_splat_pattern" => (name.named_child(0)?, ParameterKind::VarPositional),
+            "dictionary_splat_pattern" => (name.named_child(0)?, ParameterKind::VarKeyword),
+            _ => return None,
+        };
+        Some(Parameter {
+            name: self.text(name),
+            location: self.location(name),
+            kind,
+            annotation: annotation.map(|annotation| self.lower(annotation)),
+            default: default.map(|default| self.lower(default)),
+        })
+    }
+
+    /// Collect the values a function body returns, leaving out nested functions and classes
+    fn collect_returns(
+        &self,
+        node: tree_sitter::Node,
+        return_values: &mut Vec<Expr>,
+        is_generator: &mut bool,
+    ) {
+        let mut cursor = node.walk();
+        for child in node.named_children(&mut cursor) {
+            match child.kind() {
+                "function_definition" | "class_definition" | "lambda" => continue,
+                "return_statement" => return_values.push(match child.named_child(0) {
+                    Some(value) => self.lower(value),
+                    None => Expr::Literal(Literal::None),
+                }),
+                "yield" => *is_generator = true,
+                _ => {}
+            }
+            self.collect_returns(child, return_values, is_generator);
+        }
+    }
+
+    fn bind_class(&self, node: tree_sitter::Node, bindings: &mut Bindings) {
+        let Some(name) = node.child_by_field_name("name") else {
+            return;
+        };
+
+        let mut bases = Vec::new();
+        let mut metaclass = None;
+        let mut keywords = Vec::new();
+        if let Some(arguments) = node.child_by_field_name("superclasses") {
+            for argument in self.arguments(arguments) {
+                match argument {
+                    Argument::Positional(base) => bases.push(base),
+                    Argument::Keyword(name, value) if name == "metaclass" => {
+                        metaclass = Some(value)
+                    }
+                    Argument::Keyword(name, value) => keywords.push((name, value)),
+                    Argument::Unpacked(_) => {}
+                }
+            }
+        }
+
+        let mut members = Vec::new();
+        if let Some(body) = node.child_by_field_name("body") {
+            self.collect_members(body, &mut members);
+
+            // Attributes that methods assign to `self`
+            let methods: Vec<tree_sitter::Node> = members
+                .iter()
+                .filter(|member| member.kind == SymbolKind::Function)
+                .filter_map(|member| self.node_at(body, &member.location)?.parent())
+                .collect();
+            for method in methods {
+                self.collect_attributes(method, &mut members, bindings);
+            }
+        }
+
+        let class = Class {
+            bases,
+            metaclass,
+            keywords,
+            decorators: self.decorators(node),
+            members,
+        };
+        let location = self.location(name);
+        bindings.members.extend(
+            class
+                .members
+                .iter()
+                .map(|member| (member.location.clone(), location.clone())),
+        );
+        bindings.bindings.insert(location, Binding::Class(class));
+    }
+
+    /// Collect the definitions of a class body, including those in compound statements
+    fn collect_members(&self, block: tree_sitter::Node, members: &mut Vec<Symbol>) {
+        let mut cursor = block.walk();
+        for statement in block.named_children(&mut cursor) {
+            if self.collector.is_unreachable(statement) {
+                continue;
+            }
+            let definition = match statement.kind() {
+                "decorated_definition" => statement.child_by_field_name("definition"),
+                _ => Some(statement),
+            };
+            let Some(definition) = definition else {
+                continue;
+            };
+
+            match definition.kind() {
+                "function_definition" | "class_definition" => {
+                    let Some(name) = definition.child_by_field_name("name") else {
+                        continue;
+                    };
+                    let kind = match definition.kind() {
+                        "class_definition" => SymbolKind::Class,
+                        _ => SymbolKind::Function,
+                    };
+                    members.push(self.symbol(name, kind));
+                }
+                "expression_statement" => {
+                    let Some(left) = definition
+                        .named_child(0)
+                        .filter(|child| child.kind() == "assignment")
+                        .and_then(|assignment| assignment.child_by_field_name("left"))
+                    else {
+                        continue;
+                    };
+                    let mut names = vec![left];
+                    while let Some(target) = names.pop() {
+                        match target.kind() {
+                            "identifier" => members.push(self.symbol(target, SymbolKind::Variable)),
+                            "pattern_list" | "tuple_pattern" | "list_pattern" => {
+                                let mut cursor = target.walk();
+                                names.extend(target.named_children(&mut cursor));
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                "if_statement" | "elif_clause" | "else_clause" | "try_statement"
+                | "except_clause" | "finally_clause" | "with_statement" | "for_statement"
+                | "while_statement" | "block" => {
+                    self.collect_members(definition, members);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Collect the attributes a method assigns to its first parameter, like `self.name = value`
+    fn collect_attributes(
+        &self,
+        method: tree_sitter::Node,
+        members: &mut Vec<Symbol>,
+        bindings: &mut Bindings,
+    ) {
+        let Some(first) = method
+            .child_by_field_name("parameters")
+            .and_then(|parameters| parameters.named_child(0))
+            .and_then(|first| self.lower_parameter(first, false))
+        else {
+            return;
+        };
+        let Some(body) = method.child_by_field_name("body") else {
+            return;
+        };
+
+        let mut nodes = vec![body];
+        while let Some(node) = nodes.pop() {
+            if matches!(
+                node.kind(),
+                "function_definition" | "class_definition" | "lambda"
+            ) {
+                continue;
+            }
+            let mut cursor = node.walk();
+            nodes.extend(node.named_children(&mut cursor));
+
+            if !matches!(node.kind(), "assignment" | "augmented_assignment") {
+                continue;
+            }
+            let Some(left) = node.child_by_field_name("left") else {
+                continue;
+            };
+            let annotation = node.child_by_field_name("type").map(|t| self.lower(t));
+            let value = match node.kind() {
+                "augmented_assignment" => node.child_by_field_name("right").map(|r| self.lower(r)),
+                _ => self.assigned_value(node),
+            };
+
+            // `self.a, self.b = value` assigns the elements of the value
+            let targets: Vec<(tree_sitter::Node, Option<Expr>)> = match left.kind() {
+                "pattern_list" | "tuple_pattern" | "list_pattern" => {
+                    let mut cursor = left.walk();
+                    left.named_children(&mut cursor)
+                        .enumerate()
+                        .map(|(index, target)| {
+                            let value = value
+                                .clone()
+                                .map(|value| Expr::Unpacked(Box::new(value), index));
+                            (target, value)
+                        })
+                        .collect()
+                }
+                _ => vec![(left, value)],
+            };
+            for (target, value) in targets {
+                if target.kind() != "attribute" {
+                    continue;
+                }
+                let (Some(object), Some(name)) = (
+                    target.child_by_field_name("object"),
+                    target.child_by_field_name("attribute"),
+                ) else {
+                    continue;
+                };
+                let name_text = self.text(name);
+                if self.text(object) != first.name
+                    || members.iter().any(|member| member.name == name_text)
+                {
+                    continue;
+                }
+
+                let symbol = self.symbol(name, SymbolKind::Variable);
+                bindings.bindings.insert(
+                    symbol.location.clone(),
+                    Binding::Variable {
+                        annotation: annotation.clone(),
+                        value,
+                    },
+                );
+                members.push(symbol);
+            }
+        }
+    }
+
+    /// Lower the expression at a node
+    pub fn lower(&self, node: tree_sitter::Node) -> Expr {
+        let field = |name| {
+            node.child_by_field_name(name)
+                .map(|child| self.lower(child))
+        };
+        let boxed = |name| Box::new(field(name).unwrap_or(Expr::Unknown));
+
+        match node.kind() {
+            "identifier" => self.lower_name(node),
+            "attribute" => match node.child_by_field_name("attribute") {
+                Some(name) => {
+                    Expr::Attribute(boxed("object"), self.text(name), self.location(name))
+                }
+                None => Expr::Unknown,
+            },
+            "call" => {
+                let arguments = match node.child_by_field_name("arguments") {
+                    Some(arguments) if arguments.kind() == "argument_list" => {
+                        self.arguments(arguments)
+                    }
+                    Some(_) => vec![Argument::Positional(Expr::Unknown)],
+                    None => Vec::new(),
+                };
+                Expr::Call(boxed("function"), arguments)
+            }
+            "subscript" => {
+                let mut cursor = node.walk();
+                let index = node
+                    .children_by_field_name("subscript", &mut cursor)
+                    .map(|index| self.lower(index))
+                    .collect();
+                Expr::Subscript(boxed("value"), index)
+            }
+            // `list[int]` in annotations
+            "generic_type" => {
+                let Some(name) = node.named_child(0) else {
+                    return Expr::Unknown;
+                };
+                let mut arguments = Vec::new();
+                if let Some(parameters) = node.named_child(1) {
+                    let mut cursor = parameters.walk();
+                    arguments = parameters
+                        .named_children(&mut cursor)
+                        .map(|argument| self.lower(argument))
+                        .collect();
+                }
+                Expr::Subscript(Box::new(self.lower(name)), arguments)
+            }
+            "type" | "parenthesized_expression" => match node.named_child(0) {
+                Some(child) => self.lower(child),
+                None => Expr::Unknown,
+            },
+            "named_expression" => field("value").unwrap_or(Expr::Unknown),
+            "union_type" => match (node.named_child(0), node.named_child(1)) {
+                (Some(left), Some(right)) => {
+                    Expr::Either(Box::new(self.lower(left)), Box::new(self.lower(right)))
+                }
+                _ => Expr::Unknown,
+            },
+            "integer" => Expr::Literal(Literal::Int),
+            "float" => Expr::Literal(Literal::Float),
+            // Forward references, as in `x: "User"`, stand for the expression they hold
+            "string" if is_string_annotation(node, self.source) => {
+                with_string_annotation(self.source, node, |source, expression| {
+                    Lowering::new(self.collector.with_source(source), source).lower(expression)
+                })
+                .unwrap_or(Expr::Unknown)
+            }
+            "string" | "concatenated_string" => {
+                let text = self.text(node);
+                let prefix = text.split(['"', '\'']).next().unwrap_or_default();
+                match prefix.contains(['b', 'B']) {
+                    true => Expr::Literal(Literal::Bytes),
+                    false => Expr::Literal(Literal::Str),
+                }
+            }
+            "true" => Expr::Literal(Literal::Bool(Some(true))),
+            "false" => Expr::Literal(Literal::Bool(Some(false))),
+            "comparison_operator" | "not_operator" => Expr::Literal(Literal::Bool(None)),
+            "none" => Expr::Literal(Literal::None),
+            "ellipsis" => Expr::Literal(Literal::Ellipsis),
+            "list" | "tuple" | "set" | "expression_list" => {
+                let mut cursor = node.walk();
+                let elements = node
+                    .named_children(&mut cursor)
+                    .filter(|element| element.kind() != "comment")
+                    .map(|element| match element.kind() {
+                        "list_splat" => Expr::Unknown,
+                        _ => self.lower(element),
+                    })
+                    .collect();
+                match node.kind() {
+                    "list" => Expr::List(elements),
+                    "set" => Expr::Set(elements),
+                    _ => Expr::Tuple(elements),
+                }
+            }
+            "dictionary" => {
+                let mut cursor = node.walk();
+                let pairs = node
+                    .named_children(&mut cursor)
+                    .filter(|pair| pair.kind() == "pair")
+                    .map(|pair| {
+                        let key = pair.child_by_field_name("key").map(|key| self.lower(key));
+                        let value = pair.child_by_field_name("value").map(|v| self.lower(v));
+                        (key.unwrap_or(Expr::Unknown), value.unwrap_or(Expr::Unknown))
+                    })
+                    .collect();
+                Expr::Dict(pairs)
+            }
+            // The names bound by comprehensions aren't tracked, so neither are the elements
+            "list_comprehension" => Expr::List(vec![Expr::Unknown]),
+            "set_comprehension" => Expr::Set(vec![Expr::Unknown]),
+            "dictionary_comprehension" => Expr::Dict(vec![(Expr::Unknown, Expr::Unknown)]),
+            "binary_operator" => {
+                let operator = node
+                    .child_by_field_name("operator")
+                    .map(|operator| operator.kind().to_string())
+                    .unwrap_or_default();
+                Expr::BinaryOperation(boxed("left"), operator, boxed("right"))
+            }
+            "unary_operator" => field("argument").unwrap_or(Expr::Unknown),
+            "boolean_operator" => Expr::Either(boxed("left"), boxed("right")),
+            "conditional_expression" => match (node.named_child(0), node.named_child(2)) {
+                (Some(then), Some(otherwise)) => {
+                    Expr::Either(Box::new(self.lower(then)), Box::new(self.lower(otherwise)))
+                }
+                _ => Expr::Unknown,
+            },
+            "await" => match node.named_child(0) {
+                Some(awaited) => Expr::Await(Box::new(self.lower(awaited))),
+                None => Expr::Unknown,
+            },
+            "slice" => Expr::Slice,
+            "lambda" => Expr::Lambda,
+            _ => Expr::Unknown,
+        }
+    }
+
+    fn arguments(&self, arguments: tree_sitter::Node) -> Vec<Argument> {
+        let mut cursor = arguments.walk();
+        arguments
+            .named_children(&mut cursor)
+            .filter_map(|argument| self.argument(argument))
+            .collect()
+    }
+
+    fn argument(&self, argument: tree_sitter::Node) -> Option<Argument> {
+        match argument.kind() {
+            "comment" => None,
+            "keyword_argument" => {
+                let name = argument.child_by_field_name("name")?;
+                let value = argument.child_by_field_name("value")?;
+                Some(Argument::Keyword(self.text(name), self.lower(value)))
+            }
+            "list_splat" | "dictionary_splat" => Some(Argument::Unpacked(
+                argument
+                    .named_child(0)
+                    .map_or(Expr::Unknown, |value| self.lower(value)),
+            )),
+            _ => Some(Argument::Positional(self.lower(argument))),
+        }
+    }
+
+    /// Lower a name, narrowed by the `if` statements it is in
+    fn lower_name(&self, node: tree_sitter::Node) -> Expr {
+        let name = self.text(node);
+        let mut expr = Expr::Name(name.clone(), self.location(node));
+
+        let mut child = node;
+        while let Some(parent) = child.parent() {
+            // Checks outside of a function don't hold when it is called
+            if matches!(
+                parent.kind(),
+                "function_definition" | "class_definition" | "lambda"
+            ) {
+                break;
+            }
+            let in_consequence = matches!(parent.kind(), "if_statement" | "elif_clause")
+                && parent.child_by_field_name("consequence") == Some(child);
+            if in_consequence {
+                if let Some(condition) = parent.child_by_field_name("condition") {
+                    expr = self.narrow(expr, &name, condition);
+                }
+            }
+            child = parent;
+        }
+        expr
+    }
+
+    /// Narrow a name by a condition that holds
+    fn narrow(&self, expr: Expr, name: &str, condition: tree_sitter::Node) -> Expr {
+        let is_name = |node: Option<tree_sitter::Node>| {
+            node.is_some_and(|node| node.kind() == "identifier" && self.text(node) == name)
+        };
+
+        match condition.kind() {
+            "parenthesized_expression" => match condition.named_child(0) {
+                Some(inner) => self.narrow(expr, name, inner),
+                None => expr,
+            },
+            "boolean_operator" => {
+                let is_and = condition
+                    .child_by_field_name("operator")
+                    .is_some_and(|operator| operator.kind() == "and");
+                match (
+                    is_and,
+                    condition.child_by_field_name("left"),
+                    condition.child_by_field_name("right"),
+                ) {
+                    (true, Some(left), Some(right)) => {
+                        let expr = self.narrow(expr, name, left);
+                        self.narrow(expr, name, right)
+                    }
+                    _ => expr,
+                }
+            }
+            // `if name:`
+            "identifier" if is_name(Some(condition)) => Expr::NotNone(Box::new(expr)),
+            // `if name is not None:`
+            "comparison_operator" => {
+                let text = self.text(condition);
+                let is_not_none =
+                    text.split_whitespace().collect::<Vec<_>>() == [name, "is", "not", "None"];
+                match is_not_none {
+                    true => Expr::NotNone(Box::new(expr)),
+                    false => expr,
+                }
+            }
+            // `if isinstance(name, classes):`
+            "call" => {
+                let is_isinstance = condition
+                    .child_by_field_name("function")
+                    .is_some_and(|function| self.text(function) == "isinstance");
+                let arguments = condition.child_by_field_name("arguments");
+                let (Some(object), Some(classes)) = (
+                    arguments.and_then(|arguments| arguments.named_child(0)),
+                    arguments.and_then(|arguments| arguments.named_child(1)),
+                ) else {
+                    return expr;
+                };
+                match is_isinstance && is_name(Some(object)) {
+                    true => Expr::Narrowed(Box::new(expr), Box::new(self.lower(classes))),
+                    false => expr,
+                }
+            }
+            _ => expr,
+        }
+    }
+
+    fn decorators(&self, definition: tree_sitter::Node) -> Vec<Expr> {
+        let Some(decorated) = definition
+            .parent()
+            .filter(|parent| parent.kind() == "decorated_definition")
+        else {
+            return Vec::new();
+        };
+        let mut cursor = decorated.walk();
+        decorated
+            .named_children(&mut cursor)
+            .filter(|child| child.kind() == "decorator")
+            .filter_map(|decorator| Some(self.lower(decorator.named_child(0)?)))
+            .collect()
+    }
+
+    /// Get the name of the class whose body a definition is in, possibly
+    /// within compound statements like `if sys.version_info >= ...:`
+    fn enclosing_class<'t>(
+        &self,
+        definition: tree_sitter::Node<'t>,
+    ) -> Option<tree_sitter::Node<'t>> {
+        let mut node = definition.parent()?;
+        if node.kind() == "decorated_definition" {
+            node = node.parent()?;
+        }
+        loop {
+            let parent = node.parent()?;
+            match (node.kind(), parent.kind()) {
+                ("block", "class_definition") => return parent.child_by_field_name("name"),
+                (
+                    "block" | "if_statement" | "elif_clause" | "else_clause" | "try_statement"
+                    | "except_clause" | "finally_clause" | "with_statement" | "for_statement"
+                    | "while_statement",
+                    _,
+                ) => node = parent,
+                _ => return None,
+            }
+        }
+    }
+
+    /// Find the node whose location is exactly `location`
+    fn node_at<'t>(
+        &self,
+        root: tree_sitter::Node<'t>,
+        location: &Location,
+    ) -> Option<tree_sitter::Node<'t>> {
+        let start = tree_sitter::Point::new(location.start.0, location.start.1);
+        let end = tree_sitter::Point::new(location.end.0, location.end.1);
+        root.named_descendant_for_point_range(start, end)
+    }
+
+    fn symbol(&self, name: tree_sitter::Node, kind: SymbolKind) -> Symbol {
+        Symbol {
+            name: self.text(name),
+            kind,
+            location: self.location(name),
+            scope_path: self.collector.get_scope_path(name),
+        }
+    }
+
+    fn location(&self, node: tree_sitter::Node) -> Location {
+        self.collector.get_location(node)
+    }
+
+    fn text(&self, node: tree_sitter::Node) -> String {
+        node.utf8_text(self.source).unwrap_or_default().to_string()
+    }
+}
+
+/// Check if an expression is assigned to or deleted, possibly as part of a tuple
+fn is_target(node: tree_sitter::Node) -> bool {
+    let mut child = node;
+    while let Some(parent) = child.parent() {
+        match parent.kind() {
+            "pattern_list"
+            | "tuple_pattern"
+            | "list_pattern"
+            | "tuple"
+            | "list"
+            | "expression_list"
+            | "parenthesized_expression"
+            | "list_splat"
+            | "list_splat_pattern" => child = parent,
+            "assignment" | "augmented_assignment" | "for_statement" | "for_in_clause" => {
+                return parent.child_by_field_name("left") == Some(child);
+            }
+            "delete_statement" | "as_pattern_target" => return true,
+            _ => return false,
+        }
+    }
+    false
+}
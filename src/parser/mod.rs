@@ -1,3 +1,7 @@
+pub mod expression;
+#[allow(clippy::module_inception)]
 pub mod parser;
+pub mod platform;
 mod queries;
-pub mod symbol;
\ No newline at end of file
+pub mod symbol;
+pub mod version;
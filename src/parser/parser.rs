@@ -1,7 +1,12 @@
 use log::debug;
+use std::collections::HashSet;
+use std::sync::Arc;
 
+use super::expression::{self, Lowering};
 use super::queries;
 use super::symbol;
+use super::platform::PythonPlatform;
+use super::version::PythonVersion;
 
 use streaming_iterator::StreamingIterator;
 
@@ -25,6 +30,581 @@ pub trait Parser {
 /// Tree-sitter implementation of the Parser trait
 pub struct TreeSitterParser {
     parser: tree_sitter::Parser,
+    python_version: PythonVersion,
+    python_platform: PythonPlatform,
+}
+
+impl TreeSitterParser {
+    /// Set the Python version used to evaluate `sys.version_info` checks
+    pub fn set_python_version(&mut self, python_version: PythonVersion) {
+        self.python_version = python_version;
+    }
+
+    /// Set the platform used to evaluate `sys.platform` checks
+    pub fn set_python_platform(&mut self, python_platform: PythonPlatform) {
+        self.python_platform = python_platform;
+    }
+
+    /// Parse the given source code, for the configured version and platform
+    pub fn parse_tree(&mut self, source: Arc<String>) -> Option<SyntaxTree> {
+        let tree = self.parser.parse(source.as_str(), None)?;
+        Some(SyntaxTree {
+            tree: Arc::new(tree),
+            source,
+            python_version: self.python_version,
+            python_platform: self.python_platform,
+        })
+    }
+}
+
+/// A parsed source code, which everything that reads its syntax shares.
+/// Trees are only equal when they are the same parse.
+#[derive(Clone)]
+pub struct SyntaxTree {
+    tree: Arc<tree_sitter::Tree>,
+    source: Arc<String>,
+    python_version: PythonVersion,
+    python_platform: PythonPlatform,
+}
+
+impl PartialEq for SyntaxTree {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.tree, &other.tree)
+    }
+}
+
+impl Eq for SyntaxTree {}
+
+impl std::fmt::Debug for SyntaxTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyntaxTree")
+            .field("root", &self.tree.root_node())
+            .finish()
+    }
+}
+
+impl SyntaxTree {
+    /// Get a collector for the source code, analyzed for the version
+    /// and platform it was parsed for
+    fn collector(&self) -> SymbolCollector<'_> {
+        SymbolCollector::new(self.source.as_bytes(), self.python_version, self.python_platform)
+    }
+
+    /// The source code the tree was parsed from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn lowering(&self) -> Lowering<'_> {
+        Lowering::new(self.collector(), self.source.as_bytes())
+    }
+
+    /// Collect the declarations and references of the source code
+    pub fn declarations(
+        &self,
+    ) -> Result<(Vec<symbol::Symbol>, Vec<symbol::Reference>), Vec<symbol::SyntaxError>> {
+        let mut collector = self.collector();
+        if let Err(e) = collector.collect_symbols(&self.tree) {
+            debug!("Failed to collect symbols: {}", e);
+            return Err(vec![symbol::SyntaxError {
+                message: format!("Failed to collect symbols: {}", e),
+                location: symbol::Location {
+                    start: (0, 0),
+                    end: (0, 0),
+                },
+            }]);
+        }
+
+        Ok((collector.declarations, collector.references))
+    }
+
+    /// Collect the imports of the source code
+    pub fn imports(&self) -> Vec<symbol::Import> {
+        let mut collector = self.collector();
+        if let Err(e) = collector.collect_imports(&self.tree) {
+            debug!("Failed to collect imports: {}", e);
+        }
+        collector.imports
+    }
+
+    /// Find the syntax errors of the source code, which tree-sitter
+    /// reports as `ERROR` nodes for unexpected input and as missing nodes
+    pub fn syntax_errors(&self) -> Vec<symbol::SyntaxError> {
+        let collector = self.collector();
+
+        let mut errors = Vec::new();
+        let mut cursor = self.tree.walk();
+        loop {
+            let node = cursor.node();
+            let is_error = node.is_error() || node.is_missing();
+            if node.is_error() {
+                errors.push(symbol::SyntaxError {
+                    message: "Invalid syntax".to_string(),
+                    location: collector.get_location(node),
+                });
+            } else if node.is_missing() {
+                errors.push(symbol::SyntaxError {
+                    message: format!("Expected `{}`", node.kind()),
+                    location: collector.get_location(node),
+                });
+            }
+
+            // Errors within an error node don't add anything
+            if node.has_error() && !is_error && cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return errors;
+                }
+            }
+        }
+    }
+
+    /// Get the outline of the source code: its classes, functions and variables,
+    /// with the definitions of classes and functions nested in them
+    pub fn outline(&self) -> Vec<symbol::OutlineSymbol> {
+        self.collector().collect_outline(self.tree.root_node(), true)
+    }
+
+    /// Find the names that aren't looked up in a scope: attributes after
+    /// a dot and the names of keyword arguments
+    pub fn member_names(&self) -> Vec<(symbol::Location, symbol::MemberUse)> {
+        let Ok(query) = queries::get_member_query() else {
+            return Vec::new();
+        };
+        let collector = self.collector();
+
+        let mut names = Vec::new();
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        let root = self.tree.root_node();
+        let mut matches = query_cursor.matches(&query, root, self.source.as_bytes());
+        while let Some(m) = matches.next() {
+            for capture in m.captures {
+                let member_use = match query.capture_names()[capture.index as usize] {
+                    "keyword" => symbol::MemberUse::KeywordArgument,
+                    _ => {
+                        // The attribute is called if its parent is a call of it
+                        let attribute = capture.node.parent();
+                        let called = attribute
+                            .and_then(|attribute| attribute.parent())
+                            .filter(|parent| parent.kind() == "call")
+                            .and_then(|call| call.child_by_field_name("function"))
+                            == attribute;
+                        match called {
+                            true => symbol::MemberUse::MethodCall,
+                            false => symbol::MemberUse::Attribute,
+                        }
+                    }
+                };
+                names.push((collector.get_location(capture.node), member_use));
+            }
+        }
+        names
+    }
+
+    /// Get the scope path of the innermost node at the given (line, byte column)
+    pub fn scope_at(&self, point: (usize, usize)) -> Vec<String> {
+        let point = tree_sitter::Point::new(point.0, point.1);
+        match self.tree.root_node().descendant_for_point_range(point, point) {
+            Some(node) => self.collector().get_scope_path(node),
+            None => vec!["module".to_string()],
+        }
+    }
+
+    /// Get what the definitions of the source code are bound to
+    pub fn bindings(&self) -> expression::Bindings {
+        self.lowering().bindings(self.tree.root_node())
+    }
+
+    /// Get the calls of the source code, with the function each one is made in
+    pub fn calls(&self) -> Vec<expression::Call> {
+        self.lowering().calls(self.tree.root_node())
+    }
+
+    /// Get the `return` statements of the source code that return a value
+    pub fn returns(&self) -> Vec<expression::Return> {
+        self.lowering().returns(self.tree.root_node())
+    }
+
+    /// Get the attributes the source code reads, leaving out
+    /// those that may be missing on purpose
+    pub fn attribute_reads(&self) -> Vec<expression::Expr> {
+        self.lowering().attribute_reads(self.tree.root_node())
+    }
+
+    /// Lower the expression at the given (line, byte column). A name after
+    /// a dot is lowered along with the object it is an attribute of.
+    pub fn expression_at(&self, point: (usize, usize)) -> Option<expression::Expr> {
+        let point = tree_sitter::Point::new(point.0, point.1);
+        let mut node = self.tree.root_node().named_descendant_for_point_range(point, point)?;
+        if let Some(attribute) = node
+            .parent()
+            .filter(|parent| parent.kind() == "attribute")
+            .filter(|parent| parent.child_by_field_name("attribute") == Some(node))
+        {
+            node = attribute;
+        }
+
+        Some(self.lowering().lower(node))
+    }
+
+    /// Get the lines that can be folded: the bodies of compound statements,
+    /// multi-line literals, docstrings, blocks of imports and of comments,
+    /// and the regions between `# region` and `# endregion` comments
+    pub fn folding_ranges(&self) -> Vec<symbol::FoldingRange> {
+        let source = self.source.as_str();
+        let mut ranges = Vec::new();
+        let mut comments = Vec::new();
+        let mut cursor = self.tree.walk();
+        'walk: loop {
+            let node = cursor.node();
+            let (start, end) = (node.start_position(), node.end_position());
+            match node.kind() {
+                // The header of the statement the block belongs to stays visible
+                "block" => {
+                    if let Some(parent) = node.parent() {
+                        ranges.push(fold(parent.start_position().row, end.row, None));
+                    }
+                }
+                "list" | "tuple" | "set" | "dictionary" | "list_comprehension"
+                | "set_comprehension" | "dictionary_comprehension" | "generator_expression"
+                | "parenthesized_expression" | "argument_list" | "parameters" => {
+                    // The line of the closing bracket stays visible
+                    ranges.push(fold(start.row, end.row.saturating_sub(1), None));
+                }
+                "string" => {
+                    let kind = is_docstring(node).then_some(symbol::FoldKind::Comment);
+                    ranges.push(fold(start.row, end.row, kind));
+                }
+                "comment" => {
+                    let before = &source[..node.start_byte()];
+                    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+                    let standalone = before[line_start..].trim().is_empty();
+                    let text = node.utf8_text(source.as_bytes()).unwrap_or_default();
+                    comments.push((start.row, text, standalone));
+                }
+                _ => {}
+            }
+
+            // Consecutive imports are folded together
+            if is_import(node) && !node.prev_sibling().is_some_and(is_import) {
+                let mut last = node;
+                while let Some(next) = last.next_sibling().filter(|next| is_import(*next)) {
+                    last = next;
+                }
+                ranges.push(fold(
+                    start.row,
+                    last.end_position().row,
+                    Some(symbol::FoldKind::Imports),
+                ));
+            }
+
+            // Strings are only folded as a whole
+            if node.kind() != "string" && cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+
+        let mut regions = Vec::new();
+        let mut block: Option<(usize, usize)> = None;
+        for (line, text, standalone) in comments {
+            let marker = text.trim_start_matches('#').trim_start();
+            if standalone && marker.starts_with("endregion") {
+                if let Some(start) = regions.pop() {
+                    ranges.push(fold(start, line, Some(symbol::FoldKind::Region)));
+                }
+            } else if standalone && marker.starts_with("region") {
+                regions.push(line);
+            } else if standalone {
+                // Extend the block of comments on the lines right before
+                block = match block {
+                    Some((start, end)) if end + 1 == line => Some((start, line)),
+                    _ => {
+                        if let Some((start, end)) = block {
+                            ranges.push(fold(start, end, Some(symbol::FoldKind::Comment)));
+                        }
+                        Some((line, line))
+                    }
+                };
+                continue;
+            }
+            if let Some((start, end)) = block.take() {
+                ranges.push(fold(start, end, Some(symbol::FoldKind::Comment)));
+            }
+        }
+        if let Some((start, end)) = block {
+            ranges.push(fold(start, end, Some(symbol::FoldKind::Comment)));
+        }
+
+        ranges.retain(|range| range.start_line < range.end_line);
+        ranges.sort_by_key(|range| (range.start_line, range.end_line));
+        ranges.dedup_by_key(|range| (range.start_line, range.end_line));
+        ranges
+    }
+
+    /// Get the locations of the nodes around each (line, byte column),
+    /// from the innermost one out to the whole module
+    pub fn selection_ranges(
+        &self,
+        points: &[(usize, usize)],
+    ) -> Vec<Vec<symbol::Location>> {
+        let collector = self.collector();
+
+        points
+            .iter()
+            .map(|point| {
+                let point = tree_sitter::Point::new(point.0, point.1);
+                let mut locations: Vec<symbol::Location> = Vec::new();
+                let root = self.tree.root_node();
+                let mut node = root.named_descendant_for_point_range(point, point);
+                while let Some(current) = node {
+                    let location = collector.get_location(current);
+                    // Nodes with the same extent as their child don't expand the selection
+                    if locations.last() != Some(&location) {
+                        locations.push(location);
+                    }
+                    node = current.parent();
+                }
+                locations
+            })
+            .collect()
+    }
+}
+
+fn fold(
+    start_line: usize,
+    end_line: usize,
+    kind: Option<symbol::FoldKind>,
+) -> symbol::FoldingRange {
+    symbol::FoldingRange {
+        start_line,
+        end_line,
+        kind,
+    }
+}
+
+fn is_import(node: tree_sitter::Node) -> bool {
+    matches!(
+        node.kind(),
+        "import_statement" | "import_from_statement" | "future_import_statement"
+    )
+}
+
+/// Check whether a string is the first statement of a module, class or function
+fn is_docstring(node: tree_sitter::Node) -> bool {
+    let Some(statement) = node.parent().filter(|parent| parent.kind() == "expression_statement")
+    else {
+        return false;
+    };
+    let first_statement = statement
+        .parent()
+        .and_then(|parent| parent.named_child(0))
+        .is_some_and(|first| first == statement);
+    first_statement && statement.named_child_count() == 1
+}
+
+/// Check if a name is looked up in a scope. The name after the dot of an attribute is
+/// looked up on the object, and the names of keywords and of what a `from` import
+/// takes from its module aren't looked up at all.
+fn is_looked_up(node: tree_sitter::Node) -> bool {
+    let Some(parent) = node.parent() else {
+        return true;
+    };
+    match parent.kind() {
+        "attribute" => parent.child_by_field_name("attribute") != Some(node),
+        "keyword_argument" => parent.child_by_field_name("name") != Some(node),
+        "keyword_pattern" => parent.named_child(0) != Some(node),
+        // The alias of a `__future__` import isn't a name of the feature
+        "aliased_import" => parent
+            .parent()
+            .is_none_or(|statement| statement.kind() != "future_import_statement"),
+        "dotted_name" => match parent.parent().map(|grandparent| grandparent.kind()) {
+            // `import a.b as c` names the module `a.b`, but `from a import b as c`
+            // takes `b` from `a`
+            Some("aliased_import") => parent
+                .parent()
+                .and_then(|aliased| aliased.parent())
+                .is_some_and(|statement| statement.kind() == "import_statement"),
+            // The modules of `from` imports are kept, to be classified as namespaces
+            Some("import_from_statement" | "relative_import") => true,
+            Some("future_import_statement") => false,
+            // `import a.b` only binds `a`, and value patterns like `Color.RED` in a
+            // `case` look up `Color`
+            _ => parent.named_child(0) == Some(node),
+        },
+        _ => true,
+    }
+}
+
+/// Get the start of the outermost assignment a name is read in the value of. Names in
+/// lambdas and nested definitions are looked up later, once the targets are bound.
+fn assigned_at(node: tree_sitter::Node) -> Option<(usize, usize)> {
+    let mut start = None;
+    let mut child = node;
+    while let Some(parent) = child.parent() {
+        match parent.kind() {
+            "assignment" | "augmented_assignment"
+                if parent.child_by_field_name("right") == Some(child) =>
+            {
+                let position = parent.start_position();
+                start = Some((position.row, position.column));
+            }
+            "lambda" | "function_definition" | "class_definition" | "block" => break,
+            _ => {}
+        }
+        child = parent;
+    }
+    start
+}
+
+/// Get the function whose scope a node is in. The name of a function is in the
+/// scope around it.
+fn enclosing_function(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    let mut child = node;
+    while let Some(parent) = child.parent() {
+        if parent.kind() == "function_definition" && parent.child_by_field_name("name") != Some(child)
+        {
+            return Some(parent);
+        }
+        child = parent;
+    }
+    None
+}
+
+/// Check if a module starts with `from __future__ import annotations`
+fn has_future_annotations(root: tree_sitter::Node, source: &[u8]) -> bool {
+    let mut cursor = root.walk();
+    let has_future_annotations = root
+        .named_children(&mut cursor)
+        .filter(|statement| statement.kind() == "future_import_statement")
+        .any(|statement| {
+            let mut cursor = statement.walk();
+            let mut names = statement.children_by_field_name("name", &mut cursor);
+            names.any(|name| name.utf8_text(source) == Ok("annotations"))
+        });
+    has_future_annotations
+}
+
+/// Check if a type parameter declares its name, as in `class Box[T]`, rather than
+/// passing it to a generic type, as in `list[T]`
+fn declares_type_parameter(node: tree_sitter::Node) -> bool {
+    let Some(parameters) = std::iter::successors(node.parent(), |node| node.parent())
+        .find(|ancestor| ancestor.kind() == "type_parameter")
+    else {
+        return false;
+    };
+    let Some(parent) = parameters.parent() else {
+        return false;
+    };
+    match parent.kind() {
+        "class_definition" | "function_definition" => {
+            parent.child_by_field_name("type_parameters") == Some(parameters)
+        }
+        // `type Pair[T] = tuple[T, T]`
+        "generic_type" => parent.parent().is_some_and(|annotation| {
+            annotation.parent().is_some_and(|statement| {
+                statement.kind() == "type_alias_statement"
+                    && statement.child_by_field_name("left") == Some(annotation)
+            })
+        }),
+        _ => false,
+    }
+}
+
+/// Check if a string is an annotation or part of one, as in `x: "User"` or
+/// `list["User"]`, which makes it a forward reference. The strings of `Literal`
+/// and the metadata of `Annotated` are values rather than types.
+pub(super) fn is_string_annotation(node: tree_sitter::Node, source: &[u8]) -> bool {
+    let mut in_annotation = false;
+    let mut child = node;
+    while let Some(parent) = child.parent() {
+        // The generic type the string is an argument of, with the index of the argument
+        let generic = match parent.kind() {
+            "type" => {
+                in_annotation = true;
+                None
+            }
+            "type_parameter" => parent.parent().map(|generic| {
+                let mut cursor = parent.walk();
+                let index = parent.named_children(&mut cursor).position(|c| c == child);
+                (generic.named_child(0), index)
+            }),
+            "subscript" if parent.child_by_field_name("value") != Some(child) => {
+                let mut cursor = parent.walk();
+                let index = parent
+                    .children_by_field_name("subscript", &mut cursor)
+                    .position(|c| c == child);
+                Some((parent.child_by_field_name("value"), index))
+            }
+            "generic_type" | "union_type" | "member_type" | "binary_operator" | "tuple"
+            | "list" => None,
+            _ => return in_annotation,
+        };
+
+        if let Some((name, index)) = generic {
+            let name = name.and_then(|name| name.utf8_text(source).ok());
+            match name.map(|name| name.rsplit('.').next().unwrap_or(name)) {
+                Some("Literal") => return false,
+                Some("Annotated") if index != Some(0) => return false,
+                _ => {}
+            }
+        }
+        child = parent;
+    }
+    in_annotation
+}
+
+/// Parse the expression of a string annotation in place, passing it to `f` along with
+/// the source it was parsed from. The expression has the same position in that
+/// source as in the string, so the locations of its nodes are those of the document.
+/// Strings with prefixes, escapes or several lines aren't parsed.
+pub(super) fn with_string_annotation<T>(
+    source: &[u8],
+    string: tree_sitter::Node,
+    f: impl FnOnce(&[u8], tree_sitter::Node) -> T,
+) -> Option<T> {
+    let mut cursor = string.walk();
+    let parts: Vec<_> = string.named_children(&mut cursor).collect();
+    let [start, content, _end] = parts.as_slice() else {
+        return None;
+    };
+    let quote = start.utf8_text(source).ok()?;
+    if content.kind() != "string_content"
+        || content.named_child_count() > 0
+        || !quote.starts_with(['"', '\''])
+        || content.start_position().row != content.end_position().row
+    {
+        return None;
+    }
+
+    // The expression is parenthesized so it may start at any column
+    let point = content.start_position();
+    let text = format!(
+        "{}({}{})",
+        "\n".repeat(point.row),
+        " ".repeat(point.column.checked_sub(1)?),
+        content.utf8_text(source).ok()?
+    );
+    let mut parser = tree_sitter::Parser::new();
+    parser
+        .set_language(&tree_sitter_python::LANGUAGE.into())
+        .ok()?;
+    let tree = parser.parse(&text, None)?;
+    if tree.root_node().has_error() {
+        return None;
+    }
+    let statement = tree.root_node().named_child(0)?;
+    let mut expression = statement.named_child(0)?;
+    if expression.kind() == "parenthesized_expression" {
+        expression = expression.named_child(0)?;
+    }
+    Some(f(text.as_bytes(), expression))
 }
 
 impl Parser for TreeSitterParser {
@@ -32,35 +612,27 @@ impl Parser for TreeSitterParser {
         let mut parser = tree_sitter::Parser::new();
         parser.set_language(&tree_sitter_python::LANGUAGE.into())?;
 
-        Ok(Self { parser })
+        Ok(Self {
+            parser,
+            python_version: PythonVersion::default(),
+            python_platform: PythonPlatform::default(),
+        })
     }
 
     fn parse(
         &mut self,
         source: &str,
     ) -> Result<(Vec<symbol::Symbol>, Vec<symbol::Reference>), Vec<symbol::SyntaxError>> {
-        let tree = self.parser.parse(source, None).ok_or_else(|| {
+        let tree = self.parse_tree(Arc::new(source.to_string())).ok_or_else(|| {
             vec![symbol::SyntaxError {
                 message: "Failed to parse".to_string(),
-                line: 0,
-                column: 0,
+                location: symbol::Location {
+                    start: (0, 0),
+                    end: (0, 0),
+                },
             }]
         })?;
-
-        // Collect symbols
-        let mut collector = SymbolCollector::new(source.as_bytes());
-        let errors = collector.collect_symbols(&tree);
-
-        if let Err(e) = errors {
-            debug!("Failed to collect symbols: {}", e);
-            return Err(vec![symbol::SyntaxError {
-                message: format!("Failed to collect symbols: {}", e),
-                line: 0,
-                column: 0,
-            }]);
-        }
-
-        return Ok((collector.declarations, collector.references));
+        tree.declarations()
     }
 
     fn _get_errors(&self) -> Vec<symbol::SyntaxError> {
@@ -74,16 +646,29 @@ impl Parser for TreeSitterParser {
 pub struct SymbolCollector<'a> {
     pub declarations: Vec<symbol::Symbol>,
     pub references: Vec<symbol::Reference>,
+    pub imports: Vec<symbol::Import>,
 
     source: &'a [u8],
+    python_version: PythonVersion,
+    python_platform: PythonPlatform,
+    /// Whether the module has `from __future__ import annotations`
+    future_annotations: bool,
 }
 
 impl<'a> SymbolCollector<'a> {
-    pub fn new(source: &'a [u8]) -> Self {
+    pub fn new(
+        source: &'a [u8],
+        python_version: PythonVersion,
+        python_platform: PythonPlatform,
+    ) -> Self {
         Self {
             source,
+            python_version,
+            python_platform,
+            future_annotations: false,
             declarations: Vec::new(),
             references: Vec::new(),
+            imports: Vec::new(),
         }
     }
 
@@ -93,6 +678,8 @@ impl<'a> SymbolCollector<'a> {
     ) -> Result<(), Box<dyn std::error::Error>> {
         let declaration_query = queries::get_declaration_query()
             .map_err(|e| format!("Failed to get declaration query: {}", e))?;
+        self.future_annotations = has_future_annotations(tree.root_node(), self.source);
+        let globals = self.global_names(tree)?;
 
         // Collect declarations
         let mut query_cursor = tree_sitter::QueryCursor::new();
@@ -100,18 +687,37 @@ impl<'a> SymbolCollector<'a> {
 
         while let Some(m) = matches.next() {
             for capture in m.captures {
+                // Skip declarations in `sys.version_info` branches that don't apply
+                if self.is_unreachable(capture.node) {
+                    continue;
+                }
+
+                let capture_name = declaration_query.capture_names()[capture.index as usize];
+                if capture_name == "type_parameter.def" && !declares_type_parameter(capture.node) {
+                    continue;
+                }
+
                 let name = capture.node.utf8_text(self.source).unwrap().to_string();
                 let location = self.get_location(capture.node);
-                let scope_path = self.get_scope_path(capture.node);
+                // Functions bind the names they declare `global` in the module
+                let is_global = enclosing_function(capture.node)
+                    .is_some_and(|function| globals.contains(&(function.id(), name.clone())));
+                let scope_path = match is_global {
+                    true => vec!["module".to_string()],
+                    false => self.get_scope_path(capture.node),
+                };
 
-                let kind = match capture.index as u32 {
-                    0 => symbol::SymbolKind::Function,  // Function
-                    1 => symbol::SymbolKind::Class,     // Class
-                    2 => symbol::SymbolKind::Variable,  // Assignment
-                    3 => symbol::SymbolKind::Parameter, // Parameters
-                    4 => symbol::SymbolKind::Module,    // Module
+                let kind = match capture_name {
+                    "function.def" => symbol::SymbolKind::Function,
+                    "class.def" => symbol::SymbolKind::Class,
+                    "variable.def" => symbol::SymbolKind::Variable,
+                    "parameter.def" => symbol::SymbolKind::Parameter,
+                    "module.def" => symbol::SymbolKind::Module,
+                    "import.def" => symbol::SymbolKind::Variable,
+                    "type_alias.def" => symbol::SymbolKind::Variable,
+                    "type_parameter.def" => symbol::SymbolKind::TypeParameter,
                     _ => {
-                        debug!("Unknown symbol kind: {}", capture.index);
+                        debug!("Unknown symbol kind: {}", capture_name);
                         symbol::SymbolKind::Unknown
                     }
                 };
@@ -134,6 +740,12 @@ impl<'a> SymbolCollector<'a> {
         let reference_query = queries::get_reference_query()
             .map_err(|e| format!("Failed to get reference query: {}", e))?;
 
+        let declared_locations: HashSet<symbol::Location> = self
+            .declarations
+            .iter()
+            .map(|d| d.location.clone())
+            .collect();
+
         let mut query_cursor = tree_sitter::QueryCursor::new();
         let mut matches = query_cursor.matches(&reference_query, tree.root_node(), self.source);
 
@@ -141,13 +753,13 @@ impl<'a> SymbolCollector<'a> {
             for capture in m.captures {
                 let node_location = self.get_location(capture.node);
                 // Skip nodes that are already captured as declarations
-                if self
-                    .declarations
-                    .iter()
-                    .any(|d| d.location == node_location)
+                if declared_locations.contains(&node_location) || self.is_unreachable(capture.node)
                 {
                     continue;
                 }
+                if !is_looked_up(capture.node) {
+                    continue;
+                }
 
                 let name = capture.node.utf8_text(self.source).unwrap().to_string();
                 let scope_path = self.get_scope_path(capture.node);
@@ -156,49 +768,459 @@ impl<'a> SymbolCollector<'a> {
                     name,
                     location: node_location,
                     scope_path,
+                    deferred: self.is_deferred(capture.node),
+                    assigned_at: assigned_at(capture.node),
                 });
             }
         }
 
+        // The names of string annotations, which are looked up once their scope has run
+        let string_query = queries::get_string_query()
+            .map_err(|e| format!("Failed to get string query: {}", e))?;
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        let mut matches = query_cursor.matches(&string_query, tree.root_node(), self.source);
+        while let Some(m) = matches.next() {
+            for capture in m.captures {
+                let string = capture.node;
+                if !is_string_annotation(string, self.source) || self.is_unreachable(string) {
+                    continue;
+                }
+                let scope_path = self.get_scope_path(string);
+                let names = with_string_annotation(self.source, string, |source, expression| {
+                    self.with_source(source).names(expression)
+                });
+                for (name, location) in names.unwrap_or_default() {
+                    self.references.push(symbol::Reference {
+                        name,
+                        location,
+                        scope_path: scope_path.clone(),
+                        deferred: true,
+                        assigned_at: None,
+                    });
+                }
+            }
+        }
+
         // Log all the references
         for symbol in &self.references {
             debug!("Symbol reference: {:?}", symbol);
         }
 
-        return Ok(());
+        Ok(())
+    }
+
+    pub fn collect_imports(
+        &mut self,
+        tree: &tree_sitter::Tree,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let import_query =
+            queries::get_import_query().map_err(|e| format!("Failed to get import query: {}", e))?;
+
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        let mut matches = query_cursor.matches(&import_query, tree.root_node(), self.source);
+
+        while let Some(m) = matches.next() {
+            for capture in m.captures {
+                let statement = capture.node;
+                if self.is_unreachable(statement) {
+                    continue;
+                }
+
+                let from_module = statement.child_by_field_name("module_name");
+                let mut cursor = statement.walk();
+                let names: Vec<_> = statement
+                    .children_by_field_name("name", &mut cursor)
+                    .collect();
+
+                if let Some(module_node) = from_module {
+                    let module = self.get_dotted_text(module_node);
+                    let module_location = self.get_location(module_node);
+
+                    // `from module import *` has no name fields
+                    if names.is_empty() {
+                        let mut cursor = statement.walk();
+                        if let Some(wildcard) = statement
+                            .children(&mut cursor)
+                            .find(|child| child.kind() == "wildcard_import")
+                        {
+                            self.imports.push(symbol::Import {
+                                name: "*".to_string(),
+                                location: self.get_location(wildcard),
+                                module,
+                                module_location,
+                                attribute: Some("*".to_string()),
+                            });
+                        }
+                        continue;
+                    }
+
+                    for name in names {
+                        let (imported, bound) = match name.kind() {
+                            "aliased_import" => (
+                                name.child_by_field_name("name"),
+                                name.child_by_field_name("alias"),
+                            ),
+                            _ => (Some(name), name.named_child(0)),
+                        };
+                        let (Some(imported), Some(bound)) = (imported, bound) else {
+                            continue;
+                        };
+
+                        self.imports.push(symbol::Import {
+                            name: self.get_dotted_text(bound),
+                            location: self.get_location(bound),
+                            module: module.clone(),
+                            module_location: module_location.clone(),
+                            attribute: Some(self.get_dotted_text(imported)),
+                        });
+                    }
+                } else {
+                    for name in names {
+                        // `import a.b as c` binds `c` to `a.b`, while `import a.b` binds `a` to `a`
+                        let (module_node, bound) = match name.kind() {
+                            "aliased_import" => (
+                                name.child_by_field_name("name"),
+                                name.child_by_field_name("alias"),
+                            ),
+                            _ => (name.named_child(0), name.named_child(0)),
+                        };
+                        let (Some(module_node), Some(bound)) = (module_node, bound) else {
+                            continue;
+                        };
+
+                        self.imports.push(symbol::Import {
+                            name: self.get_dotted_text(bound),
+                            location: self.get_location(bound),
+                            module: self.get_dotted_text(module_node),
+                            module_location: self.get_location(module_node),
+                            attribute: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collect the definitions in a block. Variables are only part of the
+    /// outline of modules and classes, not of functions.
+    fn collect_outline(
+        &self,
+        block: tree_sitter::Node,
+        with_variables: bool,
+    ) -> Vec<symbol::OutlineSymbol> {
+        let mut symbols = Vec::new();
+        let mut cursor = block.walk();
+        for statement in block.named_children(&mut cursor) {
+            if self.is_unreachable(statement) {
+                continue;
+            }
+
+            // Decorators are part of the definition they decorate
+            let definition = match statement.kind() {
+                "decorated_definition" => statement.child_by_field_name("definition"),
+                _ => Some(statement),
+            };
+            let Some(definition) = definition else {
+                continue;
+            };
+
+            match definition.kind() {
+                "function_definition" | "class_definition" => {
+                    let Some(name) = definition.child_by_field_name("name") else {
+                        continue;
+                    };
+                    let is_class = definition.kind() == "class_definition";
+                    let children = match definition.child_by_field_name("body") {
+                        Some(body) => self.collect_outline(body, is_class),
+                        None => Vec::new(),
+                    };
+                    symbols.push(symbol::OutlineSymbol {
+                        name: name.utf8_text(self.source).unwrap_or_default().to_string(),
+                        kind: match is_class {
+                            true => symbol::SymbolKind::Class,
+                            false => symbol::SymbolKind::Function,
+                        },
+                        location: self.get_location(statement),
+                        name_location: self.get_location(name),
+                        children,
+                    });
+                }
+                "expression_statement" if with_variables => {
+                    let Some(assignment) = definition
+                        .named_child(0)
+                        .filter(|child| child.kind() == "assignment")
+                    else {
+                        continue;
+                    };
+                    let Some(name) = assignment
+                        .child_by_field_name("left")
+                        .filter(|left| left.kind() == "identifier")
+                    else {
+                        continue;
+                    };
+                    symbols.push(symbol::OutlineSymbol {
+                        name: name.utf8_text(self.source).unwrap_or_default().to_string(),
+                        kind: symbol::SymbolKind::Variable,
+                        location: self.get_location(statement),
+                        name_location: self.get_location(name),
+                        children: Vec::new(),
+                    });
+                }
+                // Definitions in compound statements belong to the enclosing block
+                "if_statement" | "elif_clause" | "else_clause" | "try_statement"
+                | "except_clause" | "finally_clause" | "with_statement" | "for_statement"
+                | "while_statement" | "block" => {
+                    symbols.extend(self.collect_outline(definition, with_variables));
+                }
+                _ => {}
+            }
+        }
+        symbols
+    }
+
+    /// Get the names functions declare `global`, along with the ids of the functions
+    fn global_names(
+        &self,
+        tree: &tree_sitter::Tree,
+    ) -> Result<HashSet<(usize, String)>, Box<dyn std::error::Error>> {
+        let global_query = queries::get_global_query()
+            .map_err(|e| format!("Failed to get global query: {}", e))?;
+
+        let mut globals = HashSet::new();
+        let mut query_cursor = tree_sitter::QueryCursor::new();
+        let mut matches = query_cursor.matches(&global_query, tree.root_node(), self.source);
+        while let Some(m) = matches.next() {
+            for capture in m.captures {
+                if let Some(function) = enclosing_function(capture.node) {
+                    let name = capture.node.utf8_text(self.source).unwrap_or_default();
+                    globals.insert((function.id(), name.to_string()));
+                }
+            }
+        }
+        Ok(globals)
+    }
+
+    /// Get the names looked up in a scope within a node, along with their locations
+    fn names(&self, node: tree_sitter::Node) -> Vec<(String, symbol::Location)> {
+        let mut names = Vec::new();
+        let mut cursor = node.walk();
+        'walk: loop {
+            let current = cursor.node();
+            if current.kind() == "identifier" && is_looked_up(current) {
+                let name = current.utf8_text(self.source).unwrap_or_default();
+                names.push((name.to_string(), self.get_location(current)));
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+        names
+    }
+
+    /// Check if a name is looked up once its scope has run rather than where it appears.
+    /// PEP 695 aliases and type parameters are evaluated lazily, and so are annotations
+    /// with `from __future__ import annotations`.
+    fn is_deferred(&self, node: tree_sitter::Node) -> bool {
+        let mut child = node;
+        while let Some(parent) = child.parent() {
+            match parent.kind() {
+                "type_alias_statement" => return true,
+                "class_definition" | "function_definition" => {
+                    return parent.child_by_field_name("type_parameters") == Some(child);
+                }
+                "type" if self.future_annotations => return true,
+                _ => {}
+            }
+            child = parent;
+        }
+        false
+    }
+
+    /// Get a collector for another source, analyzed for the same version and platform
+    pub(super) fn with_source<'b>(&self, source: &'b [u8]) -> SymbolCollector<'b> {
+        SymbolCollector::new(source, self.python_version, self.python_platform)
+    }
+
+    /// Get the text of a (possibly relative) dotted name without any whitespace
+    fn get_dotted_text(&self, node: tree_sitter::Node) -> String {
+        node.utf8_text(self.source)
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect()
     }
 
-    fn get_location(&self, node: tree_sitter::Node) -> symbol::Location {
+    pub(super) fn get_location(&self, node: tree_sitter::Node) -> symbol::Location {
         symbol::Location {
             start: (node.start_position().row, node.start_position().column),
             end: (node.end_position().row, node.end_position().column),
         }
     }
 
-    /// Given a node, walk up the tree to find the scope path,
-    /// which is a list of function names (TODO: more to come) that the node is nested in.
-    fn get_scope_path(&self, node: tree_sitter::Node) -> Vec<String> {
-        let mut path = vec!["module".to_string()];
-        let mut current = node.parent();
+    /// Check whether a node sits in an `if sys.version_info ...` branch
+    /// that is never taken for the configured Python version.
+    pub(super) fn is_unreachable(&self, node: tree_sitter::Node) -> bool {
+        let mut child = node;
+        while let Some(parent) = child.parent() {
+            if parent.kind() == "if_statement" && !self.is_branch_taken(parent, child) {
+                return true;
+            }
+            child = parent;
+        }
+        false
+    }
+
+    /// Given an `if_statement` and one of its direct children, check if that
+    /// child could be executed. Conditions we can't evaluate count as maybe taken.
+    fn is_branch_taken(&self, if_statement: tree_sitter::Node, child: tree_sitter::Node) -> bool {
+        // Each branch paired with its condition, `else` has none
+        let mut branches = vec![(
+            if_statement.child_by_field_name("consequence"),
+            if_statement.child_by_field_name("condition"),
+        )];
+        let mut cursor = if_statement.walk();
+        for alternative in if_statement.children_by_field_name("alternative", &mut cursor) {
+            branches.push((Some(alternative), alternative.child_by_field_name("condition")));
+        }
+
+        let Some(index) = branches.iter().position(|(branch, _)| *branch == Some(child)) else {
+            // The node is part of the first condition itself
+            return true;
+        };
+
+        // A previous branch that is always taken shadows this one
+        if branches[..index]
+            .iter()
+            .any(|(_, condition)| condition.and_then(|c| self.evaluate_condition(c)) == Some(true))
+        {
+            return false;
+        }
+
+        match branches[index].1 {
+            Some(condition) => self.evaluate_condition(condition) != Some(false),
+            None => true,
+        }
+    }
+
+    /// Evaluate `TYPE_CHECKING`, which holds for the analysis as it does for type
+    /// checkers, `sys.version_info <op> (x, y)` comparisons and `sys.platform` checks,
+    /// along with `not`, `and` and `or` of those. Returns None for any other kind of
+    /// condition.
+    fn evaluate_condition(&self, condition: tree_sitter::Node) -> Option<bool> {
+        match condition.kind() {
+            "identifier" | "attribute" => {
+                let text = condition.utf8_text(self.source).ok()?;
+                return (text.rsplit('.').next() == Some("TYPE_CHECKING")).then_some(true);
+            }
+            "not_operator" => {
+                let argument = condition.child_by_field_name("argument")?;
+                return self.evaluate_condition(argument).map(|value| !value);
+            }
+            "parenthesized_expression" => {
+                return self.evaluate_condition(condition.named_child(0)?);
+            }
+            // A side that decides the outcome is enough, even if the other is unknown
+            "boolean_operator" => {
+                let left = self.evaluate_condition(condition.child_by_field_name("left")?);
+                let right = self.evaluate_condition(condition.child_by_field_name("right")?);
+                return match condition.child_by_field_name("operator")?.kind() {
+                    "and" if left == Some(false) || right == Some(false) => Some(false),
+                    "and" => left.zip(right).map(|_| true),
+                    "or" if left == Some(true) || right == Some(true) => Some(true),
+                    "or" => left.zip(right).map(|_| false),
+                    _ => None,
+                };
+            }
+            // `sys.platform.startswith("linux")`
+            "call" => {
+                let function = condition.child_by_field_name("function")?;
+                let arguments = condition.child_by_field_name("arguments")?;
+                if function.utf8_text(self.source).ok()? != "sys.platform.startswith"
+                    || arguments.named_child_count() != 1
+                {
+                    return None;
+                }
+                let prefix = self.string_value(arguments.named_child(0)?)?;
+                let platform = self.python_platform.sys_platform()?;
+                return Some(platform.starts_with(prefix));
+            }
+            _ => {}
+        }
+        if condition.kind() != "comparison_operator" || condition.named_child_count() != 2 {
+            return None;
+        }
 
-        // For declarations of functions, we don't include the function itself in its scope
-        if current.is_none() || current.as_ref().unwrap().kind() == "function_definition" {
-            return path;
+        let left = condition.named_child(0)?;
+        let right = condition.named_child(1)?;
+        if left.utf8_text(self.source).ok()? == "sys.platform" {
+            let platform = self.python_platform.sys_platform()?;
+            let value = self.string_value(right)?;
+            return match condition.child(1)?.kind() {
+                "==" => Some(platform == value),
+                "!=" => Some(platform != value),
+                _ => None,
+            };
+        }
+        if left.utf8_text(self.source).ok()? != "sys.version_info" || right.kind() != "tuple" {
+            return None;
         }
 
+        let tuple = (0..right.named_child_count())
+            .map(|i| right.named_child(i)?.utf8_text(self.source).ok()?.parse().ok())
+            .collect::<Option<Vec<usize>>>()?;
+        let ordering = self.python_version.compare(&tuple);
+
+        match condition.child(1)?.kind() {
+            ">=" => Some(ordering.is_ge()),
+            ">" => Some(ordering.is_gt()),
+            "<=" => Some(ordering.is_le()),
+            "<" => Some(ordering.is_lt()),
+            "==" => Some(ordering.is_eq()),
+            "!=" => Some(ordering.is_ne()),
+            _ => None,
+        }
+    }
+
+    /// Get the value of a plain string literal, without prefixes or escapes
+    fn string_value(&self, node: tree_sitter::Node) -> Option<&'a str> {
+        if node.kind() != "string" {
+            return None;
+        }
+        let text = node.utf8_text(self.source).ok()?;
+        let value = text.strip_prefix(['"', '\''])?.strip_suffix(['"', '\''])?;
+        (!value.contains(['\\', '"', '\''])).then_some(value)
+    }
+
+    /// Given a node, walk up the tree to find the scope path,
+    /// which is a list of function names (TODO: more to come) that the node is nested in.
+    pub(super) fn get_scope_path(&self, node: tree_sitter::Node) -> Vec<String> {
+        let mut functions = Vec::new();
+        let mut child = node;
 
         // Walk up the tree collecting function names
-        while let Some(parent) = current {
+        while let Some(parent) = child.parent() {
             if parent.kind() == "function_definition" {
                 if let Some(name_node) = parent.child_by_field_name("name") {
-                    if let Ok(name) = name_node.utf8_text(self.source) {
-                        path.push(name.to_string());
+                    // For declarations of functions, we don't include the function itself in its scope
+                    if name_node != child {
+                        if let Ok(name) = name_node.utf8_text(self.source) {
+                            functions.push(name.to_string());
+                        }
                     }
                 }
             }
-            current = parent.parent();
+            child = parent;
         }
 
+        // Order the path from the outermost scope inwards
+        let mut path = vec!["module".to_string()];
+        path.extend(functions.into_iter().rev());
         path
     }
 }
@@ -208,6 +1230,11 @@ mod tests {
     use super::*;
     use symbol::{Location, Symbol, SymbolKind};
 
+    fn parse_tree(source: &str) -> SyntaxTree {
+        let mut parser = TreeSitterParser::new().unwrap();
+        parser.parse_tree(Arc::new(source.to_string())).unwrap()
+    }
+
     #[test]
     fn basic_variable_test() {
         let source_code = r#"
@@ -289,7 +1316,7 @@ mod tests {
             },
             Symbol {
                 name: "a".to_string(),
-                kind: SymbolKind::Variable,
+                kind: SymbolKind::Parameter,
                 location: Location {
                     start: (1, 8 + ident_length),
                     end: (1, 9 + ident_length),
@@ -298,7 +1325,7 @@ mod tests {
             },
             Symbol {
                 name: "b".to_string(),
-                kind: SymbolKind::Variable,
+                kind: SymbolKind::Parameter,
                 location: Location {
                     start: (1, 10 + ident_length),
                     end: (1, 11 + ident_length),
@@ -308,4 +1335,418 @@ mod tests {
         ];
         assert_eq!(symbols, expected_symbols);
     }
+
+    #[test]
+    fn version_info_branches() {
+        let source_code = r#"
+import sys
+if sys.version_info >= (3, 10):
+    new = 1
+elif sys.version_info >= (3, 8):
+    middle = 1
+else:
+    old = 1
+"#;
+
+        let mut parser = TreeSitterParser::new().unwrap();
+        parser.set_python_version(PythonVersion::new(3, 9));
+        let (symbols, _) = parser.parse(source_code).unwrap();
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["sys", "middle"]);
+
+        parser.set_python_version(PythonVersion::new(3, 12));
+        let (symbols, _) = parser.parse(source_code).unwrap();
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["sys", "new"]);
+    }
+
+    #[test]
+    fn type_checking_and_global_names() {
+        let source_code = r#"
+from typing import TYPE_CHECKING
+if TYPE_CHECKING:
+    from models import User
+else:
+    User = None
+
+def configure():
+    global DEBUG
+    DEBUG = True
+"#;
+
+        let mut parser = TreeSitterParser::new().unwrap();
+        let (symbols, _) = parser.parse(source_code).unwrap();
+        let scopes: Vec<(&str, Vec<String>)> = symbols
+            .iter()
+            .map(|s| (s.name.as_str(), s.scope_path.clone()))
+            .collect();
+        let module = vec!["module".to_string()];
+        assert_eq!(
+            scopes,
+            vec![
+                ("TYPE_CHECKING", module.clone()),
+                ("User", module.clone()),
+                ("configure", module.clone()),
+                ("DEBUG", module),
+            ]
+        );
+    }
+
+    #[test]
+    fn deferred_references() {
+        let source_code = r#"
+def load() -> "list[User]":
+    return []
+
+type Pair[T] = tuple[T, Node]
+with open() as (src, dst):
+    pass
+
+class User:
+    pass
+"#;
+
+        let mut parser = TreeSitterParser::new().unwrap();
+        let (symbols, references) = parser.parse(source_code).unwrap();
+        let kind_of = |name: &str| symbols.iter().find(|s| s.name == name).map(|s| &s.kind);
+        assert_eq!(kind_of("Pair"), Some(&SymbolKind::Variable));
+        assert_eq!(kind_of("T"), Some(&SymbolKind::TypeParameter));
+        assert_eq!(kind_of("dst"), Some(&SymbolKind::Variable));
+
+        let deferred: Vec<(&str, (usize, usize))> = references
+            .iter()
+            .filter(|r| r.deferred)
+            .map(|r| (r.name.as_str(), r.location.start))
+            .collect();
+        assert_eq!(
+            deferred,
+            vec![
+                ("tuple", (4, 15)),
+                ("T", (4, 21)),
+                ("Node", (4, 24)),
+                ("list", (1, 15)),
+                ("User", (1, 20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn platform_branches() {
+        let source_code = r#"
+import sys
+if sys.platform == "win32":
+    windows = 1
+else:
+    posix = 1
+if sys.platform.startswith("linux") and sys.version_info >= (3, 10):
+    linux = 1
+if sys.platform != "darwin" or unknown:
+    not_darwin = 1
+"#;
+
+        let mut parser = TreeSitterParser::new().unwrap();
+        let mut names = |platform| {
+            parser.set_python_platform(platform);
+            let (symbols, _) = parser.parse(source_code).unwrap();
+            symbols.into_iter().map(|s| s.name).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(PythonPlatform::Linux),
+            vec!["sys", "posix", "linux", "not_darwin"]
+        );
+        assert_eq!(
+            names(PythonPlatform::Windows),
+            vec!["sys", "windows", "not_darwin"]
+        );
+        // Conditions on an unknown platform may go either way
+        assert_eq!(
+            names(PythonPlatform::Other),
+            vec!["sys", "windows", "posix", "linux", "not_darwin"]
+        );
+    }
+
+    #[test]
+    fn nested_function_scopes() {
+        let source_code = r#"
+def outer():
+    def inner():
+        x = 1
+"#;
+
+        let mut parser = TreeSitterParser::new().unwrap();
+        let (symbols, _) = parser.parse(source_code).unwrap();
+
+        let scope_of = |name: &str| {
+            symbols
+                .iter()
+                .find(|s| s.name == name)
+                .map(|s| s.scope_path.clone())
+                .unwrap()
+        };
+        assert_eq!(scope_of("outer"), vec!["module"]);
+        assert_eq!(scope_of("inner"), vec!["module", "outer"]);
+        assert_eq!(scope_of("x"), vec!["module", "outer", "inner"]);
+        assert_eq!(
+            parse_tree(source_code).scope_at((3, 8)),
+            vec!["module", "outer", "inner"]
+        );
+    }
+
+    #[test]
+    fn import_bindings() {
+        let source_code = r#"
+import os.path
+import numpy as np
+from .utils import helper, other as alias
+from typing import *
+"#;
+
+        let imports = parse_tree(source_code).imports();
+        let bindings: Vec<(&str, &str, Option<&str>)> = imports
+            .iter()
+            .map(|i| (i.name.as_str(), i.module.as_str(), i.attribute.as_deref()))
+            .collect();
+        assert_eq!(
+            bindings,
+            vec![
+                ("os", "os", None),
+                ("np", "numpy", None),
+                ("helper", ".utils", Some("helper")),
+                ("alias", ".utils", Some("other")),
+                ("*", "typing", Some("*")),
+            ]
+        );
+
+        // Every binding is declared, `path` in `os.path` is not
+        let (symbols, _) = parse_tree(source_code).declarations().unwrap();
+        let mut names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["alias", "helper", "np", "os"]);
+    }
+
+    #[test]
+    fn outline() {
+        let source_code = r#"
+LIMIT = 10
+
+@dataclass
+class Point:
+    x: int = 0
+
+    def norm(self):
+        total = self.x
+        def helper():
+            pass
+        return total
+
+if True:
+    def main():
+        pass
+"#;
+
+        // Each definition with its depth, in order
+        fn flatten(
+            symbols: &[symbol::OutlineSymbol],
+            depth: usize,
+            out: &mut Vec<(usize, String)>,
+        ) {
+            for symbol in symbols {
+                out.push((depth, symbol.name.clone()));
+                flatten(&symbol.children, depth + 1, out);
+            }
+        }
+
+        let outline = parse_tree(source_code).outline();
+        let mut definitions = Vec::new();
+        flatten(&outline, 0, &mut definitions);
+        let expected = [
+            (0, "LIMIT"),
+            (0, "Point"),
+            (1, "x"),
+            (1, "norm"),
+            (2, "helper"),
+            (0, "main"),
+        ];
+        assert_eq!(
+            definitions,
+            expected.map(|(depth, name)| (depth, name.to_string()))
+        );
+
+        // The class starts at its decorator
+        assert_eq!(outline[1].location.start, (3, 0));
+        assert_eq!(outline[1].name_location.start, (4, 6));
+    }
+
+    #[test]
+    fn folding_ranges() {
+        let source_code = r#"import os
+import sys
+
+# region setup
+# A comment block
+# over two lines
+def main(
+    argument,
+):
+    """Run the program.
+
+    With a docstring.
+    """
+    values = [
+        1,
+        2,
+    ]
+    if values:
+        pass
+    else:
+        return
+# endregion
+"#;
+        let ranges: Vec<(usize, usize, Option<symbol::FoldKind>)> = parse_tree(source_code)
+            .folding_ranges()
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (0, 1, Some(symbol::FoldKind::Imports)),
+                (3, 21, Some(symbol::FoldKind::Region)),
+                (4, 5, Some(symbol::FoldKind::Comment)),
+                (6, 7, None),
+                (6, 20, None),
+                (9, 12, Some(symbol::FoldKind::Comment)),
+                (13, 15, None),
+                (17, 18, None),
+                (19, 20, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn attribute_names_are_not_references() {
+        let source_code = r#"
+name = 1
+print(obj.name)
+"#;
+        let mut parser = TreeSitterParser::new().unwrap();
+        let (_, references) = parser.parse(source_code).unwrap();
+        let names: Vec<&str> = references.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["print", "obj"]);
+    }
+
+    #[test]
+    fn narrowed_bindings() {
+        let source_code = r#"
+def greet(name: str | None, *rest):
+    if name is not None:
+        message = name.upper()
+"#;
+        let bindings = parse_tree(source_code).bindings();
+        let location = |line, start, end| Location {
+            start: (line, start),
+            end: (line, end),
+        };
+
+        let parameters = bindings
+            .function(&location(1, 4, 9))
+            .map(|function| function.parameters.len());
+        assert_eq!(parameters, Some(2));
+
+        // `name` is narrowed in the body of the condition
+        let name = expression::Expr::Name("name".to_string(), location(3, 18, 22));
+        let upper = expression::Expr::Attribute(
+            Box::new(expression::Expr::NotNone(Box::new(name))),
+            "upper".to_string(),
+            location(3, 23, 28),
+        );
+        assert_eq!(
+            bindings.get(&location(3, 8, 15)),
+            Some(&expression::Binding::Variable {
+                annotation: None,
+                value: Some(expression::Expr::Call(Box::new(upper), Vec::new())),
+            })
+        );
+    }
+
+    #[test]
+    fn calls_with_their_caller() {
+        let source_code = r#"
+def run():
+    helper(tool.use())
+helper()
+"#;
+        let calls: Vec<_> = parse_tree(source_code)
+            .calls()
+            .into_iter()
+            .map(|call| (call.caller.map(|caller| caller.start), call.location.start))
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                (Some((1, 4)), (2, 4)),
+                (Some((1, 4)), (2, 16)),
+                (None, (3, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn attribute_reads_and_returns() {
+        let source_code = r#"
+def load(config) -> None:
+    config.path = config.root
+    if hasattr(cache, "size"):
+        print(cache.size)
+    try:
+        config.extra
+    except AttributeError:
+        pass
+    return config.name
+"#;
+        let tree = parse_tree(source_code);
+        // Assigned and guarded attributes aren't read
+        let reads: Vec<String> = tree
+            .attribute_reads()
+            .into_iter()
+            .filter_map(|read| match read {
+                expression::Expr::Attribute(_, name, _) => Some(name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reads, vec!["root", "name"]);
+
+        let returns: Vec<_> = tree
+            .returns()
+            .into_iter()
+            .map(|returned| (returned.function.start, returned.location.start))
+            .collect();
+        assert_eq!(returns, vec![((1, 4), (9, 11))]);
+    }
+
+    #[test]
+    fn class_members_and_keywords() {
+        let source_code = r#"
+class Movie(TypedDict, total=False):
+    name: str
+    def rename(self):
+        self.title = self.name
+"#;
+        let bindings = parse_tree(source_code).bindings();
+        let location = |line, start, end| Location {
+            start: (line, start),
+            end: (line, end),
+        };
+
+        let keywords = bindings
+            .class(&location(1, 6, 11))
+            .map(|class| class.keywords.clone());
+        let total = expression::Expr::Literal(expression::Literal::Bool(Some(false)));
+        assert_eq!(keywords, Some(vec![("total".to_string(), total)]));
+
+        // Fields and attributes of `self` belong to the class, local names don't
+        assert_eq!(bindings.member_class(&location(2, 4, 8)), Some(&location(1, 6, 11)));
+        assert_eq!(bindings.member_class(&location(4, 13, 18)), Some(&location(1, 6, 11)));
+        assert_eq!(bindings.member_class(&location(3, 15, 19)), None);
+    }
 }
@@ -0,0 +1,49 @@
+/// The platform code is analyzed for. Used to pick the branches of
+/// `if sys.platform ...` checks, which stubs use to tell operating systems apart.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum PythonPlatform {
+    Linux,
+    Windows,
+    Darwin,
+    /// Any other platform, whose checks can't be evaluated
+    Other,
+}
+
+impl PythonPlatform {
+    /// The platform the server runs on
+    pub fn host() -> Self {
+        match std::env::consts::OS {
+            "linux" => Self::Linux,
+            "windows" => Self::Windows,
+            "macos" => Self::Darwin,
+            _ => Self::Other,
+        }
+    }
+
+    /// Parse a platform name, either as `sys.platform` has it ("win32") or as
+    /// it is commonly written ("Windows"), ignoring case
+    pub fn parse(platform: &str) -> Option<Self> {
+        match platform.trim().to_lowercase().as_str() {
+            "linux" => Some(Self::Linux),
+            "win32" | "windows" => Some(Self::Windows),
+            "darwin" | "macos" => Some(Self::Darwin),
+            _ => None,
+        }
+    }
+
+    /// The value of `sys.platform` on this platform
+    pub fn sys_platform(&self) -> Option<&'static str> {
+        match self {
+            Self::Linux => Some("linux"),
+            Self::Windows => Some("win32"),
+            Self::Darwin => Some("darwin"),
+            Self::Other => None,
+        }
+    }
+}
+
+impl Default for PythonPlatform {
+    fn default() -> Self {
+        Self::host()
+    }
+}
@@ -8,10 +8,83 @@ pub fn get_declaration_query() -> Result<tree_sitter::Query, tree_sitter::QueryE
           name: (identifier) @class.def)
         (assignment 
           left: (identifier) @variable.def)
+        (augmented_assignment
+          left: (identifier) @variable.def)
+        (for_statement
+          left: (identifier) @variable.def)
+        (for_in_clause
+          left: (identifier) @variable.def)
+        (pattern_list
+          [(identifier) @variable.def
+           (list_splat_pattern (identifier) @variable.def)])
+        (tuple_pattern
+          [(identifier) @variable.def
+           (list_splat_pattern (identifier) @variable.def)])
+        (list_pattern
+          [(identifier) @variable.def
+           (list_splat_pattern (identifier) @variable.def)])
+        (named_expression
+          name: (identifier) @variable.def)
         (parameters 
+          (identifier) @parameter.def)
+        (default_parameter
+          name: (identifier) @parameter.def)
+        (typed_parameter
+          (identifier) @parameter.def)
+        (typed_default_parameter
+          name: (identifier) @parameter.def)
+        (parameters
+          [(list_splat_pattern (identifier) @parameter.def)
+           (dictionary_splat_pattern (identifier) @parameter.def)])
+        (lambda_parameters
+          [(identifier) @parameter.def
+           (list_splat_pattern (identifier) @parameter.def)
+           (dictionary_splat_pattern (identifier) @parameter.def)])
+        (typed_parameter
+          [(list_splat_pattern (identifier) @parameter.def)
+           (dictionary_splat_pattern (identifier) @parameter.def)])
+        (as_pattern
+          alias: (as_pattern_target (identifier) @variable.def))
+        (as_pattern_target
+          [(tuple
+             [(identifier) @variable.def
+              (list_splat (identifier) @variable.def)])
+           (list
+             [(identifier) @variable.def
+              (list_splat (identifier) @variable.def)])])
+        (case_pattern
+          (dotted_name . (identifier) @variable.def .))
+        (keyword_pattern
+          (dotted_name . (identifier) @variable.def .))
+        (splat_pattern
           (identifier) @variable.def)
+        (case_pattern
+          (as_pattern (identifier) @variable.def .))
+        (type_alias_statement
+          left: (type [(identifier) @type_alias.def
+                       (generic_type . (identifier) @type_alias.def)]))
+        (type_parameter
+          (type [(identifier) @type_parameter.def
+                 (splat_type (identifier) @type_parameter.def)
+                 (constrained_type . (type (identifier) @type_parameter.def))]))
+        (import_statement
+          name: (dotted_name . (identifier) @module.def))
         (import_statement
-          (dotted_name (identifier) @module.def))
+          name: (aliased_import alias: (identifier) @module.def))
+        (import_from_statement
+          name: (dotted_name . (identifier) @import.def))
+        (import_from_statement
+          name: (aliased_import alias: (identifier) @import.def))
+    "#,
+    )
+}
+
+pub fn get_import_query() -> Result<tree_sitter::Query, tree_sitter::QueryError> {
+    tree_sitter::Query::new(
+        &tree_sitter_python::LANGUAGE.into(),
+        r#"
+        (import_statement) @import
+        (import_from_statement) @import.from
     "#,
     )
 }
@@ -25,3 +98,32 @@ pub fn get_reference_query() -> Result<tree_sitter::Query, tree_sitter::QueryErr
     )
 }
 
+pub fn get_global_query() -> Result<tree_sitter::Query, tree_sitter::QueryError> {
+    tree_sitter::Query::new(
+        &tree_sitter_python::LANGUAGE.into(),
+        r#"
+        (global_statement (identifier) @global)
+    "#,
+    )
+}
+
+pub fn get_string_query() -> Result<tree_sitter::Query, tree_sitter::QueryError> {
+    tree_sitter::Query::new(
+        &tree_sitter_python::LANGUAGE.into(),
+        r#"
+        (string) @string
+    "#,
+    )
+}
+
+pub fn get_member_query() -> Result<tree_sitter::Query, tree_sitter::QueryError> {
+    tree_sitter::Query::new(
+        &tree_sitter_python::LANGUAGE.into(),
+        r#"
+        (attribute
+          attribute: (identifier) @attribute)
+        (keyword_argument
+          name: (identifier) @keyword)
+    "#,
+    )
+}
@@ -1,13 +1,13 @@
 
-#[derive(Debug)]
-#[warn(dead_code)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct SyntaxError {
     pub message: String,
-    pub line: usize,
-    pub column: usize,
+    pub location: Location,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Symbol {
     pub name: String,
     pub kind: SymbolKind,
@@ -15,26 +15,91 @@ pub struct Symbol {
     pub scope_path: Vec<String>, // e.g. ["module", "class_name", "function_name"]
 }
 
-#[derive(Debug,Clone, Eq, PartialEq)]
+/// A definition in the outline of a document, with the definitions nested in it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OutlineSymbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The whole definition, including decorators
+    pub location: Location,
+    pub name_location: Location,
+    pub children: Vec<OutlineSymbol>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub struct Reference {
     pub name: String,
     pub location: Location,
     pub scope_path: Vec<String>,
+    /// Whether the name is looked up once its scope has run rather than where it
+    /// appears, as in string annotations and PEP 695 aliases
+    pub deferred: bool,
+    /// The start of the assignment the name is read in the value of, whose targets
+    /// are only bound once the name has been looked up
+    pub assigned_at: Option<(usize, usize)>,
 }
 
+/// A name bound by an `import` or `from ... import` statement
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Import {
+    /// The bound name, or `*` for wildcard imports
+    pub name: String,
+    pub location: Location,
+    /// The module the name refers to (or is imported from), with leading dots for relative imports
+    pub module: String,
+    pub module_location: Location,
+    /// The imported name for `from module import name`
+    pub attribute: Option<String>,
+}
+
+/// Lines of a document that can be folded, leaving the start line visible
 #[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FoldingRange {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub kind: Option<FoldKind>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FoldKind {
+    Comment,
+    Imports,
+    Region,
+}
+
+/// How a name that isn't looked up in a scope is used
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MemberUse {
+    /// `object.name`
+    Attribute,
+    /// `object.name(...)`
+    MethodCall,
+    /// `function(name=...)`
+    KeywordArgument,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Location {
     pub start: (usize, usize), // (line, column)
     pub end: (usize, usize),
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+impl Location {
+    /// Check if a (line, column) point lies within the location, bounds included
+    pub fn contains(&self, point: (usize, usize)) -> bool {
+        self.start <= point && point <= self.end
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum SymbolKind {
     Function,
     Variable,
     Class,
     Parameter,
     Module,
+    /// The type parameters of PEP 695 classes, functions and aliases
+    TypeParameter,
     Unknown,
     // TODO: add mores
 }
@@ -0,0 +1,47 @@
+/// The Python version code is analyzed against. Used to pick the
+/// branches of `if sys.version_info ...` checks, mostly found in stubs.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub struct PythonVersion {
+    pub major: usize,
+    pub minor: usize,
+}
+
+impl PythonVersion {
+    pub const fn new(major: usize, minor: usize) -> Self {
+        Self { major, minor }
+    }
+
+    /// Parse a version string such as "3.11" (any patch level is ignored)
+    pub fn parse(version: &str) -> Option<Self> {
+        let mut parts = version.trim().split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().unwrap_or("0").parse().ok()?;
+        Some(Self { major, minor })
+    }
+
+    /// Compare against a `sys.version_info` tuple, which may hold one or more elements
+    pub fn compare(&self, tuple: &[usize]) -> std::cmp::Ordering {
+        let own = [self.major, self.minor];
+        for (index, value) in tuple.iter().enumerate() {
+            match own.get(index) {
+                Some(own) if own != value => return own.cmp(value),
+                Some(_) => continue,
+                // We don't track micro versions, treat them as the lowest
+                None => return std::cmp::Ordering::Less,
+            }
+        }
+        std::cmp::Ordering::Equal
+    }
+}
+
+impl Default for PythonVersion {
+    fn default() -> Self {
+        Self::new(3, 12)
+    }
+}
+
+impl std::fmt::Display for PythonVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
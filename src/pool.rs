@@ -0,0 +1,98 @@
+use log::error;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed number of threads that run jobs in the order they are queued
+pub struct ThreadPool {
+    sender: Mutex<Sender<Job>>,
+}
+
+impl ThreadPool {
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for index in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("worker-{}", index))
+                .spawn(move || loop {
+                    // The lock is only held while waiting for the next job
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                });
+            if let Err(e) = spawned {
+                error!("Failed to start worker thread: {}", e);
+            }
+        }
+
+        Self {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    /// Use one thread per core
+    pub fn with_available_parallelism() -> Self {
+        Self::new(thread::available_parallelism().map_or(4, |threads| threads.get()))
+    }
+
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        if self.sender.lock().unwrap().send(Box::new(job)).is_err() {
+            error!("Worker threads are gone");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn jobs_run_in_order_on_one_thread() {
+        let pool = ThreadPool::new(1);
+        let (sender, receiver) = mpsc::channel();
+        for index in 0..10 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(index).unwrap());
+        }
+        let order: Vec<i32> = receiver.iter().take(10).collect();
+        assert_eq!(order, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn jobs_run_side_by_side() {
+        let pool = ThreadPool::new(2);
+        let (first_sender, first_receiver) = mpsc::channel();
+        let (second_sender, second_receiver) = mpsc::channel();
+
+        // Each job waits for the other, which only works when both run at once
+        pool.execute(move || {
+            first_sender.send(()).unwrap();
+            second_receiver
+                .recv_timeout(Duration::from_secs(5))
+                .unwrap();
+        });
+        let (done_sender, done_receiver) = mpsc::channel();
+        pool.execute(move || {
+            first_receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            second_sender.send(()).unwrap();
+            done_sender.send(()).unwrap();
+        });
+        assert!(done_receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+
+    #[test]
+    fn at_least_one_thread() {
+        let pool = ThreadPool::new(0);
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || sender.send(()).unwrap());
+        assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+    }
+}
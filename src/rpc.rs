@@ -1,6 +1,71 @@
+use serde::{Deserialize, Serialize};
 use serde_json::Value as Json;
+use std::fmt;
+use std::io::{self, Write};
 
-pub fn encode_response(jsonrpc: String, id: Option<i64>, result: Json) -> String {
+/// Error codes of responses, see `ErrorCodes` in the LSP specification
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INTERNAL_ERROR: i64 = -32603;
+pub const SERVER_NOT_INITIALIZED: i64 = -32002;
+pub const REQUEST_CANCELLED: i64 = -32800;
+pub const CONTENT_MODIFIED: i64 = -32801;
+
+/// The id of a request from the client, which may be a number or a string
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(i64),
+    String(String),
+}
+
+impl RequestId {
+    /// The id in `value`, if it is one
+    pub fn from_json(value: &Json) -> Option<Self> {
+        match value {
+            Json::Number(number) => number.as_i64().map(Self::Number),
+            Json::String(string) => Some(Self::String(string.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(number) => write!(f, "{}", number),
+            Self::String(string) => write!(f, "{:?}", string),
+        }
+    }
+}
+
+/// An error to answer a request with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl ResponseError {
+    pub fn method_not_found(method: &str) -> Self {
+        Self {
+            code: METHOD_NOT_FOUND,
+            message: format!("Unknown method: {}", method),
+        }
+    }
+}
+
+/// Handling a request failed
+impl From<String> for ResponseError {
+    fn from(message: String) -> Self {
+        Self {
+            code: INTERNAL_ERROR,
+            message,
+        }
+    }
+}
+
+pub fn encode_response(jsonrpc: String, id: Option<RequestId>, result: Json) -> String {
     let message = serde_json::json!({
         "jsonrpc": jsonrpc,
         "id": id,
@@ -9,7 +74,46 @@ pub fn encode_response(jsonrpc: String, id: Option<i64>, result: Json) -> String
     message.to_string()
 }
 
-pub fn decode_request(message: String) -> Result<(String, Option<i64>, String, Json), String> {
+pub fn encode_error(jsonrpc: String, id: Option<RequestId>, code: i64, message: &str) -> String {
+    let message = serde_json::json!({
+        "jsonrpc": jsonrpc,
+        "id": id,
+        "error": { "code": code, "message": message }
+    });
+    message.to_string()
+}
+
+pub fn encode_notification(method: &str, params: Json) -> String {
+    let message = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params
+    });
+    message.to_string()
+}
+
+pub fn encode_request(id: i64, method: &str, params: Json) -> String {
+    let message = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": params
+    });
+    message.to_string()
+}
+
+/// Write an encoded message with its `Content-Length` header
+pub fn write_message(writer: &mut impl Write, encoded: &str) -> io::Result<()> {
+    let message = format!("Content-Length: {}\r\n\r\n{}", encoded.len(), encoded);
+    writer.write_all(message.as_bytes())?;
+    writer.flush()
+}
+
+/// Decode a message from the client into its `jsonrpc`, id, method and params.
+/// Messages without an id are notifications.
+pub fn decode_request(
+    message: String,
+) -> Result<(String, Option<RequestId>, String, Json), String> {
     let value: Json =
         serde_json::from_str(&message).map_err(|e| format!("Failed to parse JSON: {}", e))?;
 
@@ -18,7 +122,7 @@ pub fn decode_request(message: String) -> Result<(String, Option<i64>, String, J
         .ok_or_else(|| "Missing or invalid 'jsonrpc' field".to_string())?
         .to_string();
 
-    let id = value["id"].as_i64();
+    let id = RequestId::from_json(&value["id"]);
 
     let method = value["method"]
         .as_str()
@@ -30,12 +134,31 @@ pub fn decode_request(message: String) -> Result<(String, Option<i64>, String, J
     Ok((jsonrpc, id, method, params))
 }
 
+/// Decode the response to a request the server sent, into its id and either
+/// the `result` or the `error` object. Returns `None` for any other message.
+pub fn decode_response(message: &str) -> Option<(i64, Result<Json, Json>)> {
+    let value: Json = serde_json::from_str(message).ok()?;
+    if value.get("method").is_some() {
+        return None;
+    }
+    let id = value["id"].as_i64()?;
+    match value.get("error") {
+        Some(error) => Some((id, Err(error.clone()))),
+        None => Some((id, Ok(value["result"].clone()))),
+    }
+}
+
 #[cfg(test)]
 mod main_tests {
-    use crate::rpc;
+    use crate::rpc::{self, RequestId};
     use serde_json::{json, Value as Json};
 
-    fn encode_request(jsonrpc: String, id: Option<i64>, method: String, params: Json) -> String {
+    fn encode_request(
+        jsonrpc: String,
+        id: Option<RequestId>,
+        method: String,
+        params: Json,
+    ) -> String {
         let message = serde_json::json!({
             "jsonrpc": jsonrpc,
             "id": id,
@@ -49,7 +172,7 @@ mod main_tests {
     fn test_encode_decode() {
         let original = (
             "2.0".to_string(),
-            Some(1),
+            Some(RequestId::Number(1)),
             "textDocument/didOpen".to_string(),
             json!({
                 "textDocument": {
@@ -63,7 +186,7 @@ mod main_tests {
 
         let encoded = encode_request(
             original.0.clone(),
-            original.1,
+            original.1.clone(),
             original.2.clone(),
             original.3.clone(),
         );
@@ -74,10 +197,57 @@ mod main_tests {
         assert_eq!(decoded.3, original.3);
     }
 
+    #[test]
+    fn test_request_ids() {
+        let decode = |id: Json| {
+            let message = json!({"jsonrpc": "2.0", "id": id, "method": "shutdown"});
+            rpc::decode_request(message.to_string()).unwrap().1
+        };
+        assert_eq!(decode(json!(7)), Some(RequestId::Number(7)));
+        assert_eq!(
+            decode(json!("a7")),
+            Some(RequestId::String("a7".to_string()))
+        );
+        assert_eq!(decode(json!(null)), None);
+
+        // Responses carry the id the way the client sent it
+        let id = Some(RequestId::String("a7".to_string()));
+        let response = rpc::encode_response("2.0".to_string(), id, json!(null));
+        let parsed: Json = serde_json::from_str(&response).unwrap();
+        assert_eq!(parsed["id"], json!("a7"));
+    }
+
     #[test]
     fn test_encode_response() {
-        let response = rpc::encode_response("2.0".to_string(), Some(1), json!({ "success": true }));
+        let response = rpc::encode_response(
+            "2.0".to_string(),
+            Some(RequestId::Number(1)),
+            json!({ "success": true }),
+        );
         let parsed: serde_json::Value = serde_json::from_str(&response).unwrap();
         assert_eq!(parsed["result"]["success"], json!(true));
     }
+
+    #[test]
+    fn test_decode_response() {
+        let request = rpc::encode_request(3, "workspace/configuration", json!({ "items": [] }));
+        assert_eq!(rpc::decode_response(&request), None);
+
+        let response =
+            rpc::encode_response("2.0".to_string(), Some(RequestId::Number(3)), json!([null]));
+        assert_eq!(
+            rpc::decode_response(&response),
+            Some((3, Ok(json!([null]))))
+        );
+
+        let error = rpc::encode_error(
+            "2.0".to_string(),
+            Some(RequestId::Number(4)),
+            rpc::INTERNAL_ERROR,
+            "Failed",
+        );
+        let (id, result) = rpc::decode_response(&error).unwrap();
+        assert_eq!(id, 4);
+        assert_eq!(result.unwrap_err()["code"], json!(rpc::INTERNAL_ERROR));
+    }
 }
@@ -0,0 +1,694 @@
+use log::{debug, error, info};
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use crate::lsp::client::Client;
+use crate::lsp::handler::{Job, LspHandler, Task, Update};
+use crate::pool::ThreadPool;
+use crate::rpc::{self, RequestId, ResponseError};
+use crate::storage::db::Canceled;
+use crate::transport::Connection;
+
+/// Serves clients, which share the handler and with it the analysis of the workspace.
+/// Messages that change the state are handled one at a time, while read requests
+/// are answered on a pool of worker threads.
+pub struct Server {
+    handler: Mutex<LspHandler>,
+    pool: ThreadPool,
+    /// The connected clients, which logs are sent to
+    clients: Mutex<Vec<Arc<Client>>>,
+}
+
+impl Server {
+    pub fn new(handler: LspHandler) -> Self {
+        Self {
+            handler: Mutex::new(handler),
+            pool: ThreadPool::with_available_parallelism(),
+            clients: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Lock the handler. Panics handling a message are caught, so one that
+    /// still poisoned the lock left a state that is as good as any.
+    fn handler(&self) -> MutexGuard<'_, LspHandler> {
+        self.handler.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Send a notification to every client
+    pub fn broadcast(&self, method: &str, params: Json) {
+        for client in self.clients.lock().unwrap().iter() {
+            client.notify(method, &params);
+        }
+    }
+
+    /// Handle the messages of a client until it exits or disconnects. Returns the
+    /// exit code, which is 0 only if the client asked to shut down before it left.
+    pub fn serve(self: &Arc<Self>, connection: Connection) -> i32 {
+        let Connection { mut reader, writer } = connection;
+        let client = Client::new(writer);
+        self.clients.lock().unwrap().push(client.clone());
+        let mut lifecycle = Lifecycle::Uninitialized;
+
+        // Requests running on the pool, which the client can cancel by id
+        let pending = Arc::new(PendingRequests::default());
+
+        loop {
+            // Read a message from the client
+            let message = match reader.read_message() {
+                Ok(message) => message,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    info!("Client disconnected");
+                    break;
+                }
+                Err(e) => {
+                    error!("Error reading message: {:?}", e);
+                    break;
+                }
+            };
+            debug!("Received message: {:?}", message);
+
+            // Responses to the requests the server sent
+            if let Some((id, result)) = rpc::decode_response(&message) {
+                let mut handler = self.handler();
+                let handled = panic::catch_unwind(AssertUnwindSafe(|| {
+                    handler.handle_client_response(&client, id, result)
+                }));
+                if handled.is_err() {
+                    error!("Handling the response to request {} panicked", id);
+                }
+                self.run_jobs(handler.take_jobs());
+                self.run_updates(handler.take_updates());
+                continue;
+            }
+
+            // Decode the message
+            let (jsonrpc, id, method, params) = match rpc::decode_request(message) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    error!("Error decoding message: {:?}", e);
+                    continue;
+                }
+            };
+
+            if method == "$/cancelRequest" && id.is_none() {
+                if let Some(id) = RequestId::from_json(&params["id"]) {
+                    pending.cancel(&id);
+                }
+                continue;
+            }
+
+            // Only messages that fit the lifecycle are handled
+            match lifecycle.advance(&method) {
+                Ok(Lifecycle::ShuttingDown) if lifecycle != Lifecycle::ShuttingDown => {
+                    // Requests that came in before are still answered
+                    pending.wait_until_finished();
+                    lifecycle = Lifecycle::ShuttingDown;
+                }
+                Ok(next) => lifecycle = next,
+                Err((code, message)) => {
+                    reject(&client, jsonrpc, id, code, message);
+                    continue;
+                }
+            }
+            let exit = method == "exit";
+
+            // Handle the message
+            let (task, jobs, updates) = {
+                let mut handler = self.handler();
+                // A bug handling one message shouldn't take the server down
+                let task = panic::catch_unwind(AssertUnwindSafe(|| {
+                    handler.dispatch(&client, method.clone(), params)
+                }))
+                .unwrap_or_else(|_| {
+                    error!("Handling {} panicked", method);
+                    Task::Done(Err(format!("Handling {} failed", method).into()))
+                });
+                (task, handler.take_jobs(), handler.take_updates())
+            };
+            self.run_jobs(jobs);
+            self.run_updates(updates);
+            if exit {
+                break;
+            }
+
+            match task {
+                Task::Done(result) => {
+                    // The client may try again after a failed `initialize`
+                    if lifecycle == Lifecycle::Initializing && result.is_err() {
+                        lifecycle = Lifecycle::Uninitialized;
+                    }
+                    respond(&client, jsonrpc, id, Ok(result))
+                }
+                Task::Snapshot { canceled, request } => {
+                    if let Some(id) = &id {
+                        pending.start(id.clone(), canceled.clone());
+                    }
+
+                    let client = client.clone();
+                    let pending = pending.clone();
+                    self.pool.execute(move || {
+                        let result = run_request(request, canceled);
+                        respond(&client, jsonrpc, id.clone(), result);
+                        if let Some(id) = &id {
+                            pending.finish(id);
+                        }
+                    });
+                }
+            }
+        }
+
+        // The client may still read the answers after it stopped writing
+        pending.wait_until_finished();
+        self.handler().disconnect(&client);
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|other| !Arc::ptr_eq(other, &client));
+        client.close();
+
+        match lifecycle {
+            Lifecycle::ShuttingDown => 0,
+            _ => 1,
+        }
+    }
+
+    /// Run the jobs a message left, which are dropped when a change cancels them
+    fn run_jobs(&self, jobs: Vec<Job>) {
+        for job in jobs {
+            self.pool.execute(move || {
+                if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                    if !payload.is::<Canceled>() {
+                        error!("Job panicked");
+                    }
+                }
+            });
+        }
+    }
+
+    /// Run updates on threads of their own, then apply their outcome to the handler.
+    /// Changing the state waits for the jobs on the pool to drop their snapshots,
+    /// so it must not happen on the pool itself.
+    fn run_updates(self: &Arc<Self>, updates: Vec<Update>) {
+        for update in updates {
+            let server = self.clone();
+            let spawned = std::thread::Builder::new()
+                .name("update".to_string())
+                .spawn(move || {
+                    let Ok(apply) = panic::catch_unwind(AssertUnwindSafe(update)) else {
+                        error!("Update panicked");
+                        return;
+                    };
+                    let (jobs, updates) = {
+                        let mut handler = server.handler();
+                        if panic::catch_unwind(AssertUnwindSafe(|| apply(&mut handler))).is_err() {
+                            error!("Update panicked");
+                        }
+                        (handler.take_jobs(), handler.take_updates())
+                    };
+                    server.run_jobs(jobs);
+                    server.run_updates(updates);
+                });
+            if let Err(e) = spawned {
+                error!("Failed to start update thread: {}", e);
+            }
+        }
+    }
+}
+
+/// Where a client is in the lifecycle of the protocol
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Lifecycle {
+    /// Waiting for `initialize`
+    Uninitialized,
+    /// Answered `initialize`, waiting for `initialized`
+    Initializing,
+    Running,
+    /// Received `shutdown`, waiting for `exit`
+    ShuttingDown,
+}
+
+impl Lifecycle {
+    /// The state after a message with `method`, or the error code and
+    /// message to reject it with. `exit` is accepted in any state.
+    fn advance(self, method: &str) -> Result<Self, (i64, &'static str)> {
+        match (self, method) {
+            (_, "exit") => Ok(self),
+            (Self::Uninitialized, "initialize") => Ok(Self::Initializing),
+            (Self::Uninitialized, _) => {
+                Err((rpc::SERVER_NOT_INITIALIZED, "Server is not initialized"))
+            }
+            (Self::ShuttingDown, _) => Err((rpc::INVALID_REQUEST, "Server is shutting down")),
+            (_, "initialize") => Err((rpc::INVALID_REQUEST, "Server is already initialized")),
+            (Self::Initializing, "initialized") => Ok(Self::Running),
+            (_, "shutdown") => Ok(Self::ShuttingDown),
+            _ => Ok(self),
+        }
+    }
+}
+
+/// Requests of a client that are running on the pool, by id
+#[derive(Default)]
+struct PendingRequests {
+    requests: Mutex<HashMap<RequestId, Arc<AtomicBool>>>,
+    finished: Condvar,
+}
+
+impl PendingRequests {
+    fn start(&self, id: RequestId, canceled: Arc<AtomicBool>) {
+        self.requests.lock().unwrap().insert(id, canceled);
+    }
+
+    /// Mark a request as canceled, which is a no-op if it already finished
+    fn cancel(&self, id: &RequestId) {
+        if let Some(canceled) = self.requests.lock().unwrap().get(id) {
+            canceled.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn finish(&self, id: &RequestId) {
+        self.requests.lock().unwrap().remove(id);
+        self.finished.notify_all();
+    }
+
+    fn wait_until_finished(&self) {
+        let requests = self.requests.lock().unwrap();
+        let _requests = self
+            .finished
+            .wait_while(requests, |requests| !requests.is_empty())
+            .unwrap();
+    }
+}
+
+/// Why a request didn't produce a result
+enum Aborted {
+    /// The client canceled it
+    Canceled,
+    /// A change came in while it ran
+    ContentModified,
+    Panicked,
+}
+
+/// Run a read request, turning cancellation into an error
+fn run_request(
+    request: Box<dyn FnOnce() -> Result<Option<Json>, ResponseError> + Send>,
+    canceled: Arc<AtomicBool>,
+) -> Result<Result<Option<Json>, ResponseError>, Aborted> {
+    // The client may have lost interest while the request was queued
+    if canceled.load(Ordering::Relaxed) {
+        return Err(Aborted::Canceled);
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(request));
+    result.map_err(|payload| match payload.is::<Canceled>() {
+        true if canceled.load(Ordering::Relaxed) => Aborted::Canceled,
+        true => Aborted::ContentModified,
+        false => Aborted::Panicked,
+    })
+}
+
+/// Answer a request the server won't handle with an error. Notifications are dropped.
+fn reject(client: &Client, jsonrpc: String, id: Option<RequestId>, code: i64, message: &str) {
+    match id {
+        Some(_) => client.send(rpc::encode_error(jsonrpc, id, code, message)),
+        None => debug!("Dropped notification: {}", message),
+    }
+}
+
+/// Send the response to a request, if there is one
+fn respond(
+    client: &Client,
+    jsonrpc: String,
+    id: Option<RequestId>,
+    result: Result<Result<Option<Json>, ResponseError>, Aborted>,
+) {
+    // Notifications are never answered, and the ones the server doesn't know are ignored
+    let Some(request) = &id else {
+        match result {
+            Ok(Err(error)) if error.code == rpc::METHOD_NOT_FOUND => debug!("{}", error.message),
+            Ok(Err(error)) => error!("Error: {}", error.message),
+            _ => {}
+        }
+        return;
+    };
+
+    let encoded = match result {
+        Ok(Ok(Some(result))) => rpc::encode_response(jsonrpc, id, result),
+        Ok(Ok(None)) => return,
+        // Every request is answered, also when handling it failed
+        Ok(Err(error)) => {
+            error!("Error: {}", error.message);
+            rpc::encode_error(jsonrpc, id, error.code, &error.message)
+        }
+        Err(Aborted::Canceled) => {
+            rpc::encode_error(jsonrpc, id, rpc::REQUEST_CANCELLED, "Request canceled")
+        }
+        Err(Aborted::ContentModified) => {
+            rpc::encode_error(jsonrpc, id, rpc::CONTENT_MODIFIED, "Content modified")
+        }
+        Err(Aborted::Panicked) => {
+            error!("Request {} panicked", request);
+            rpc::encode_error(jsonrpc, id, rpc::INTERNAL_ERROR, "Internal error")
+        }
+    };
+    debug!("Sending message: {:?}", encoded);
+    client.send(encoded);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::db::{self, HasCancellation, LspDatabase};
+    use crate::storage::snapshot::SHOW_LOCATIONS_COMMAND;
+    use crate::transport::MessageReader;
+    use serde_json::json;
+    use std::io::{PipeWriter, Write};
+
+    #[test]
+    fn lifecycle() {
+        let code = |state: Lifecycle, method| state.advance(method).map_err(|(code, _)| code);
+
+        let state = Lifecycle::Uninitialized;
+        assert_eq!(
+            code(state, "textDocument/hover"),
+            Err(rpc::SERVER_NOT_INITIALIZED)
+        );
+        assert_eq!(code(state, "exit"), Ok(Lifecycle::Uninitialized));
+
+        let state = state.advance("initialize").unwrap();
+        assert_eq!(state, Lifecycle::Initializing);
+        let state = state.advance("initialized").unwrap();
+        assert_eq!(state, Lifecycle::Running);
+        assert_eq!(code(state, "initialize"), Err(rpc::INVALID_REQUEST));
+
+        let state = state.advance("shutdown").unwrap();
+        assert_eq!(state, Lifecycle::ShuttingDown);
+        assert_eq!(code(state, "textDocument/hover"), Err(rpc::INVALID_REQUEST));
+        assert_eq!(code(state, "exit"), Ok(Lifecycle::ShuttingDown));
+    }
+
+    #[test]
+    fn canceled_requests() {
+        let pending = PendingRequests::default();
+        let canceled = Arc::new(AtomicBool::new(false));
+        pending.start(RequestId::Number(1), canceled.clone());
+        pending.cancel(&RequestId::Number(2));
+        pending.cancel(&RequestId::String("1".to_string()));
+        assert!(!canceled.load(Ordering::Relaxed));
+        pending.cancel(&RequestId::Number(1));
+        assert!(canceled.load(Ordering::Relaxed));
+        pending.finish(&RequestId::Number(1));
+
+        // Clients may use strings as ids
+        let canceled = Arc::new(AtomicBool::new(false));
+        let id = RequestId::String("a1".to_string());
+        pending.start(id.clone(), canceled.clone());
+        pending.cancel(&id);
+        assert!(canceled.load(Ordering::Relaxed));
+        pending.finish(&id);
+        pending.wait_until_finished();
+
+        // Requests canceled while queued don't run
+        let request = Box::new(|| panic!("Canceled request ran"));
+        assert!(matches!(
+            run_request(request, canceled),
+            Err(Aborted::Canceled)
+        ));
+
+        // Queries notice a request was canceled while it ran
+        let db = LspDatabase::default();
+        let canceled = db.cancellation().flag();
+        let flag = canceled.clone();
+        let request = Box::new(move || {
+            flag.store(true, Ordering::Relaxed);
+            db::check_canceled(&db);
+            Ok(None)
+        });
+        assert!(matches!(
+            run_request(request, canceled),
+            Err(Aborted::Canceled)
+        ));
+
+        // A change canceling the query is told apart from a failing one
+        let request = Box::new(|| panic::resume_unwind(Box::new(Canceled)));
+        let result = run_request(request, Arc::default());
+        assert!(matches!(result, Err(Aborted::ContentModified)));
+        let request = Box::new(|| panic!("Request failed"));
+        let result = run_request(request, Arc::default());
+        assert!(matches!(result, Err(Aborted::Panicked)));
+
+        let request = Box::new(|| Ok(Some(json!(1))));
+        assert_eq!(
+            run_request(request, Arc::default()).ok(),
+            Some(Ok(Some(json!(1))))
+        );
+    }
+
+    /// A client connected to `server`, which sends requests and waits for their responses
+    struct TestClient {
+        writer: PipeWriter,
+        reader: MessageReader,
+        next_id: i64,
+        /// The messages that came in while waiting for responses
+        received: Vec<Json>,
+    }
+
+    impl TestClient {
+        fn connect(server: &Arc<Server>) -> (Self, std::thread::JoinHandle<i32>) {
+            let (server_reader, writer) = io::pipe().unwrap();
+            let (reader, server_writer) = io::pipe().unwrap();
+            let server = server.clone();
+            let serving = std::thread::spawn(move || {
+                server.serve(Connection::new(server_reader, server_writer))
+            });
+            let client = Self {
+                writer,
+                reader: Connection::new(reader, io::sink()).reader,
+                next_id: 0,
+                received: Vec::new(),
+            };
+            (client, serving)
+        }
+
+        fn notify(&mut self, method: &str, params: Json) {
+            let encoded = rpc::encode_notification(method, params);
+            rpc::write_message(&mut self.writer, &encoded).unwrap();
+            self.writer.flush().unwrap();
+        }
+
+        /// Send a request and return its result, keeping the messages in between
+        fn request(&mut self, method: &str, params: Json) -> Json {
+            self.next_id += 1;
+            self.call(json!(self.next_id), method, params)["result"].clone()
+        }
+
+        /// Send a request with `id` and return the response to it
+        fn call(&mut self, id: Json, method: &str, params: Json) -> Json {
+            let encoded = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+            rpc::write_message(&mut self.writer, &encoded.to_string()).unwrap();
+            self.writer.flush().unwrap();
+            loop {
+                let message: Json =
+                    serde_json::from_str(&self.reader.read_message().unwrap()).unwrap();
+                if message["id"] == id && message.get("method").is_none() {
+                    return message;
+                }
+                self.received.push(message);
+            }
+        }
+
+        /// Wait for a request or notification from the server with `method`
+        fn receive(&mut self, method: &str) -> Json {
+            if let Some(index) = self.received.iter().position(|m| m["method"] == method) {
+                return self.received.remove(index);
+            }
+            loop {
+                let message: Json =
+                    serde_json::from_str(&self.reader.read_message().unwrap()).unwrap();
+                if message["method"] == method {
+                    return message;
+                }
+                self.received.push(message);
+            }
+        }
+
+        /// Answer a request of the server
+        fn respond(&mut self, id: &Json, result: Json) {
+            let encoded = json!({"jsonrpc": "2.0", "id": id, "result": result});
+            rpc::write_message(&mut self.writer, &encoded.to_string()).unwrap();
+            self.writer.flush().unwrap();
+        }
+    }
+
+    #[test]
+    fn unknown_methods() {
+        let server = Arc::new(Server::new(LspHandler::initialize().unwrap()));
+        let (mut client, serving) = TestClient::connect(&server);
+        client.request("initialize", json!({"capabilities": {}}));
+        client.notify("initialized", json!({}));
+
+        // Notifications the server doesn't know are ignored, requests get an error
+        client.notify("$/unknown", json!({}));
+        client.notify("workspace/unknown", json!({}));
+        for method in ["workspace/unknown", "$/unknown", "$/cancelRequest"] {
+            let response = client.call(json!("a1"), method, json!({"id": 1}));
+            assert_eq!(response["error"]["code"], rpc::METHOD_NOT_FOUND);
+        }
+
+        // Requests with string ids are answered with the same id
+        let response = client.call(json!("a2"), "shutdown", json!(null));
+        assert_eq!(response["id"], "a2");
+        assert_eq!(response["result"], json!(null));
+        // Nothing answered the notifications
+        assert!(client
+            .received
+            .iter()
+            .all(|message| message.get("method").is_some()));
+        drop(client);
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn show_locations_command() {
+        let server = Arc::new(Server::new(LspHandler::initialize().unwrap()));
+        let (mut client, serving) = TestClient::connect(&server);
+        let capabilities = json!({"window": {"showDocument": {"support": true}}});
+        let result = client.request("initialize", json!({"capabilities": capabilities}));
+        assert_eq!(
+            result["capabilities"]["executeCommandProvider"]["commands"],
+            json!([SHOW_LOCATIONS_COMMAND])
+        );
+        client.notify("initialized", json!({}));
+
+        let location = |uri: &str, line: u32| {
+            let position = json!({"line": line, "character": 4});
+            json!({"uri": uri, "range": {"start": position, "end": position}})
+        };
+        let execute = |client: &mut TestClient, locations: Json| {
+            let arguments = json!(["2 overrides", locations]);
+            let params = json!({"command": SHOW_LOCATIONS_COMMAND, "arguments": arguments});
+            client.request("workspace/executeCommand", params)
+        };
+
+        // A single location is shown right away
+        execute(&mut client, json!([location("file:///a.py", 2)]));
+        let shown = client.receive("window/showDocument");
+        assert_eq!(shown["params"]["uri"], "file:///a.py");
+        assert_eq!(shown["params"]["selection"]["start"]["line"], 2);
+        client.respond(&shown["id"], json!({"success": true}));
+
+        // Of several the user picks one
+        let locations = json!([location("file:///a.py", 2), location("file:///b.py", 5)]);
+        execute(&mut client, locations);
+        let message = client.receive("window/showMessageRequest");
+        assert_eq!(message["params"]["message"], "2 overrides");
+        let actions = &message["params"]["actions"];
+        assert_eq!(actions, &json!([{"title": "a.py:3"}, {"title": "b.py:6"}]));
+        client.respond(&message["id"], actions[1].clone());
+        let shown = client.receive("window/showDocument");
+        assert_eq!(shown["params"]["uri"], "file:///b.py");
+        assert_eq!(shown["params"]["selection"]["start"]["line"], 5);
+
+        let params = json!({"command": "rypy.unknown"});
+        let response = client.call(json!("a1"), "workspace/executeCommand", params);
+        assert_eq!(response["error"]["code"], rpc::INTERNAL_ERROR);
+        drop(client);
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn poisoned_handler_lock() {
+        let server = Arc::new(Server::new(LspHandler::initialize().unwrap()));
+        let poisoning = server.clone();
+        let panicked = std::thread::spawn(move || {
+            let _handler = poisoning.handler();
+            panic!("Handler panicked");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(server.handler.is_poisoned());
+
+        // Messages are still handled
+        let (mut client, serving) = TestClient::connect(&server);
+        let result = client.request("initialize", json!({"capabilities": {}}));
+        assert!(result["capabilities"].is_object());
+        drop(client);
+        serving.join().unwrap();
+    }
+
+    #[test]
+    fn connections_negotiate_their_own_encoding() {
+        let server = Arc::new(Server::new(LspHandler::initialize().unwrap()));
+        let (mut first, first_serving) = TestClient::connect(&server);
+        let (mut second, second_serving) = TestClient::connect(&server);
+
+        let initialize = |encodings: Json| json!({"capabilities": {"general": {"positionEncodings": encodings}}});
+        let result = first.request("initialize", initialize(json!(["utf-8"])));
+        assert_eq!(result["capabilities"]["positionEncoding"], "utf-8");
+        first.notify("initialized", json!({}));
+        let result = second.request("initialize", initialize(json!([])));
+        assert_eq!(result["capabilities"]["positionEncoding"], "utf-16");
+        second.notify("initialized", json!({}));
+
+        // Both see the document one of them opened, with columns counted their own way
+        let uri = "file:///tmp/rypy-connections/a.py";
+        first.notify(
+            "textDocument/didOpen",
+            json!({"textDocument": {
+                "uri": uri, "languageId": "python", "version": 1, "text": "'é'; s = 1\ns\n"
+            }}),
+        );
+        let definition =
+            json!({"textDocument": {"uri": uri}, "position": {"line": 1, "character": 0}});
+        let column = |result: Json| result["range"]["start"]["character"].clone();
+        assert_eq!(
+            column(first.request("textDocument/definition", definition.clone())),
+            6
+        );
+        assert_eq!(
+            column(second.request("textDocument/definition", definition)),
+            5
+        );
+
+        drop(first);
+        drop(second);
+        first_serving.join().unwrap();
+        second_serving.join().unwrap();
+    }
+
+    #[test]
+    fn progress_needs_client_support() {
+        let root = std::env::temp_dir().join(format!("rypy-progress-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.py"), "x = 1\n").unwrap();
+
+        for (window, shown) in [
+            (json!({}), false),
+            (json!({"workDoneProgress": true}), true),
+        ] {
+            let server = Arc::new(Server::new(LspHandler::initialize().unwrap()));
+            let (mut client, serving) = TestClient::connect(&server);
+            let params = json!({
+                "capabilities": {"window": window},
+                "rootUri": crate::storage::uri::path_to_uri(&root),
+            });
+            client.request("initialize", params);
+            client.notify("initialized", json!({}));
+            // Indexing starts with `initialized`, so its progress comes before this answer
+            client.request("shutdown", json!(null));
+
+            let created = client
+                .received
+                .iter()
+                .any(|message| message["method"] == "window/workDoneProgress/create");
+            assert_eq!(created, shown);
+            drop(client);
+            serving.join().unwrap();
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,15 +1,34 @@
 use crate::{
-    analysis::{self, reference::ReferenceGraph},
+    analysis::{
+        self,
+        calls::{self, CallSite},
+        checker,
+        diagnostics::{self, Diagnostic},
+        hierarchy, inference,
+        reference::ReferenceGraph,
+        synthesis,
+        tokens::{self, Token, TokenType},
+        types::Type,
+    },
     parser::{
-        parser::{Parser, TreeSitterParser},
-        symbol::{Reference, Symbol},
+        expression::{Bindings, Expr, Function},
+        parser::{Parser, SyntaxTree, TreeSitterParser},
+        symbol::{Import, Location, Reference, Symbol, SymbolKind, SyntaxError},
+        platform::PythonPlatform,
+        version::PythonVersion,
     },
 };
-use salsa;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use super::environment::PythonEnvironment;
+use super::line_index::LineIndex;
+use super::{resolver, typeshed, uri};
+
 #[salsa::query_group(StorageDatabase)]
-pub trait Storage: salsa::Database {
+pub trait Storage: salsa::Database + HasCancellation {
     //  --------- Inputs ----------
     #[salsa::input]
     fn document_text(&self, uri: String) -> Option<Arc<String>>;
@@ -17,30 +36,541 @@ pub trait Storage: salsa::Database {
     #[salsa::input]
     fn document_version(&self, uri: String) -> Option<i32>;
 
+    /// Documents currently managed by the client, all other files are read from disk
+    #[salsa::input]
+    fn open_documents(&self) -> Arc<BTreeSet<String>>;
+
+    /// The contents on disk of a file that changed while the server runs,
+    /// or None when it was deleted
+    #[salsa::input]
+    fn file_text(&self, uri: String) -> Option<Arc<String>>;
+
+    /// The files whose contents on disk are tracked with `file_text`, because the
+    /// client told us they changed
+    #[salsa::input]
+    fn changed_files(&self) -> Arc<BTreeSet<String>>;
+
+    #[salsa::input]
+    fn python_version(&self) -> PythonVersion;
+
+    #[salsa::input]
+    fn python_platform(&self) -> PythonPlatform;
+
+    #[salsa::input]
+    fn typeshed_path(&self) -> Option<PathBuf>;
+
+    /// Roots searched for first-party modules, e.g. the workspace root
+    #[salsa::input]
+    fn search_paths(&self) -> Arc<Vec<PathBuf>>;
+
+    #[salsa::input]
+    fn python_environment(&self) -> Arc<PythonEnvironment>;
+
+    /// Bumped when files are created or deleted and when the environment is detected
+    /// again, since modules are resolved by looking for files
+    #[salsa::input]
+    fn resolution_revision(&self) -> u64;
+
+    /// The Python files of the workspace, as selected by the settings
+    #[salsa::input]
+    fn workspace_files(&self) -> Arc<Vec<String>>;
+
     // ---------- Derived Queries --------------
+    fn source_text(&self, uri: String) -> Option<Arc<String>>;
+    fn document_tree(&self, uri: String) -> Option<SyntaxTree>;
     fn document_declaration(&self, uri: String) -> Option<Arc<(Vec<Symbol>, Vec<Reference>)>>;
-    fn document_reference_graph(&self, uri: String) -> Option<ReferenceGraph>;
+    fn document_reference_graph(&self, uri: String) -> Option<Arc<ReferenceGraph>>;
+    fn document_line_index(&self, uri: String) -> Option<Arc<LineIndex>>;
+    fn document_imports(&self, uri: String) -> Arc<Vec<Import>>;
+    fn module_uri(&self, module: String) -> Option<String>;
+    fn import_uri(&self, importer: String, module: String) -> Option<String>;
+    fn module_definition(&self, module: String, name: String) -> Option<(String, Symbol)>;
+    #[salsa::cycle(recover_definition)]
+    fn exported_definition(&self, uri: String, name: String) -> Option<(String, Symbol)>;
+    #[salsa::cycle(recover_import)]
+    fn import_definition(&self, importer: String, import: Import) -> Option<(String, Symbol)>;
+    fn document_diagnostics(&self, uri: String) -> Arc<Vec<Diagnostic>>;
+    fn document_tokens(&self, uri: String) -> Arc<Vec<Token>>;
+    fn document_bindings(&self, uri: String) -> Arc<Bindings>;
+    #[salsa::invoke(calls::document_calls)]
+    fn document_calls(&self, uri: String) -> Arc<Vec<CallSite>>;
+
+    // ---------- Type Inference --------------
+    #[salsa::invoke(inference::type_of)]
+    #[salsa::cycle(inference::recover_type_of)]
+    fn type_of(&self, uri: String, expr: Expr) -> Type;
+    #[salsa::invoke(inference::definition_type)]
+    #[salsa::cycle(inference::recover_definition_type)]
+    fn definition_type(&self, uri: String, definition: Symbol) -> Type;
+    #[salsa::invoke(inference::annotation_type)]
+    #[salsa::cycle(inference::recover_annotation_type)]
+    fn annotation_type(&self, uri: String, annotation: Expr) -> Type;
+    #[salsa::invoke(inference::return_type)]
+    #[salsa::cycle(inference::recover_return_type)]
+    fn return_type(&self, uri: String, function: Symbol) -> Type;
+    #[salsa::invoke(hierarchy::class_bases)]
+    #[salsa::cycle(hierarchy::recover_class_bases)]
+    fn class_bases(&self, uri: String, class: Symbol) -> Arc<Vec<(String, Symbol)>>;
+    #[salsa::invoke(hierarchy::class_mro)]
+    #[salsa::cycle(hierarchy::recover_class_mro)]
+    fn class_mro(&self, uri: String, class: Symbol) -> Arc<Vec<(String, Symbol)>>;
+    #[salsa::invoke(inference::class_member)]
+    #[salsa::cycle(inference::recover_class_member)]
+    fn class_member(&self, uri: String, class: Symbol, name: String) -> Option<(String, Symbol)>;
+    #[salsa::invoke(synthesis::synthesized_init)]
+    fn synthesized_init(&self, uri: String, class: Symbol) -> Option<Arc<Function>>;
+}
+
+/// How many syntax trees are kept, since they take a lot more memory than
+/// the source they were parsed from
+pub const TREE_CAPACITY: usize = 128;
+
+/// Payload of the panic that unwinds a query whose result isn't wanted anymore,
+/// because the inputs are about to change or the client canceled the request
+#[derive(Debug)]
+pub struct Canceled;
+
+/// The cancellation of the request a snapshot of the database answers
+#[derive(Default)]
+pub struct Cancellation {
+    /// Set when the client cancels the request
+    canceled: Arc<AtomicBool>,
+    /// How many requests on snapshots of the database stopped because they were
+    /// canceled. Threads waiting for a query such a request was computing see it
+    /// panic, and have to tell that apart from a query that failed.
+    canceled_requests: Arc<AtomicUsize>,
+    /// The number of canceled requests when the snapshot was taken
+    canceled_before: usize,
+}
+
+impl Cancellation {
+    /// The cancellation of a new snapshot, which answers a request of its own
+    fn snapshot(&self) -> Self {
+        Self {
+            canceled: Arc::default(),
+            canceled_requests: self.canceled_requests.clone(),
+            canceled_before: self.canceled_requests.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The flag that cancels the request
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.canceled.clone()
+    }
+
+    fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::Relaxed)
+    }
+
+    /// Whether a request stopped since the snapshot was taken
+    fn requests_canceled(&self) -> bool {
+        self.canceled_requests.load(Ordering::Relaxed) != self.canceled_before
+    }
+}
+
+/// Databases whose queries can be canceled along with the request they compute for
+pub trait HasCancellation {
+    fn cancellation(&self) -> &Cancellation;
+}
+
+/// Unwind with `Canceled` if the query should stop. This is checked at the
+/// start of the queries that do real work, like parsing.
+pub(crate) fn check_canceled(db: &dyn Storage) {
+    let request_canceled = db.cancellation().is_canceled();
+    if request_canceled {
+        db.cancellation()
+            .canceled_requests
+            .fetch_add(1, Ordering::Relaxed);
+    }
+    if request_canceled || db.salsa_runtime().is_current_revision_canceled() {
+        std::panic::resume_unwind(Box::new(Canceled));
+    }
+}
+
+fn source_text(db: &dyn Storage, uri: String) -> Option<Arc<String>> {
+    if db.open_documents().contains(&uri) {
+        return db.document_text(uri);
+    }
+    if db.changed_files().contains(&uri) {
+        return db.file_text(uri);
+    }
+
+    // Other files are read as they are when first needed, any later change
+    // comes in through `changed_files`
+    let path = uri::uri_to_path(&uri)?;
+    std::fs::read_to_string(path).ok().map(Arc::new)
+}
+
+/// Parse a document once for everything that reads its syntax. Only the
+/// trees of the documents used last are kept, see `TREE_CAPACITY`.
+fn document_tree(db: &dyn Storage, uri: String) -> Option<SyntaxTree> {
+    check_canceled(db);
+    let text = db.source_text(uri)?;
+
+    let mut parser = TreeSitterParser::new().ok()?;
+    parser.set_python_version(db.python_version());
+    parser.set_python_platform(db.python_platform());
+
+    parser.parse_tree(text)
 }
 
 fn document_declaration(
     db: &dyn Storage,
     uri: String,
 ) -> Option<Arc<(Vec<Symbol>, Vec<Reference>)>> {
-    let text = db.document_text(uri.clone())?;
+    Some(Arc::new(db.document_tree(uri)?.declarations().ok()?))
+}
+
+fn document_reference_graph(db: &dyn Storage, uri: String) -> Option<Arc<ReferenceGraph>> {
+    let (declarations, references) =
+        Arc::try_unwrap(db.document_declaration(uri)?).unwrap_or_else(|arc| (*arc).clone());
+    let reference_graph = analysis::reference::ReferenceGraph::build(declarations, references);
+    Some(Arc::new(reference_graph))
+}
+
+fn document_line_index(db: &dyn Storage, uri: String) -> Option<Arc<LineIndex>> {
+    let text = db.source_text(uri)?;
+    Some(Arc::new(LineIndex::new(text)))
+}
+
+fn document_imports(db: &dyn Storage, uri: String) -> Arc<Vec<Import>> {
+    match db.document_tree(uri) {
+        Some(tree) => Arc::new(tree.imports()),
+        None => Arc::new(Vec::new()),
+    }
+}
 
-    let mut parser: Box<dyn Parser> = match TreeSitterParser::new() {
-        Ok(p) => Box::new(p),
-        Err(_) => return None,
+/// Resolve a dotted module name to the uri of the file that defines it.
+/// The search order is the workspace, the standard library stubs and
+/// then the packages installed in the python environment.
+fn module_uri(db: &dyn Storage, module: String) -> Option<String> {
+    // The files found only change along with the revision
+    db.resolution_revision();
+    let find_first_party = || {
+        db.search_paths()
+            .iter()
+            .find_map(|root| resolver::find_module(root, &module))
+    };
+    let find_stdlib = || match db.typeshed_path() {
+        Some(root) => resolver::find_module(&root.join("stdlib"), &module),
+        None if module == "builtins" => typeshed::bundled_builtins_path(),
+        None => None,
+    };
+    let find_installed = || {
+        let environment = db.python_environment();
+        let parts: Vec<&str> = module.split('.').collect();
+        environment
+            .site_packages
+            .iter()
+            .find_map(|root| resolver::find_module(root, &module))
+            .or_else(|| {
+                environment
+                    .editable_packages
+                    .iter()
+                    .filter(|(package, _)| package == parts[0])
+                    .find_map(|(_, directory)| resolver::find_in_package(directory, &parts[1..]))
+            })
     };
 
-    Some(Arc::new(parser.parse(&text).ok()?))
+    let path = find_first_party()
+        .or_else(find_stdlib)
+        .or_else(find_installed)?;
+    Some(uri::path_to_uri(&path))
 }
 
-fn document_reference_graph(db: &dyn Storage, uri: String) -> Option<ReferenceGraph> {
-    let (declarations, references) =
-        Arc::try_unwrap(db.document_declaration(uri.clone())?).unwrap_or_else(|arc| (*arc).clone());
-    let reference_graph = analysis::reference::ReferenceGraph::build(declarations, references);
-    Some(reference_graph)
+/// Resolve a module imported from the file at `importer`, which may be relative
+fn import_uri(db: &dyn Storage, importer: String, module: String) -> Option<String> {
+    if !module.starts_with('.') {
+        return db.module_uri(module);
+    }
+
+    db.resolution_revision();
+    let importer = uri::uri_to_path(&importer)?;
+    let path = resolver::find_relative_module(&importer, &module)?;
+    Some(uri::path_to_uri(&path))
+}
+
+/// Find a module level definition by name, along with the uri of the module
+fn module_definition(db: &dyn Storage, module: String, name: String) -> Option<(String, Symbol)> {
+    let uri = db.module_uri(module)?;
+    db.exported_definition(uri, name)
+}
+
+/// Find a module level definition of the module at `uri`, following
+/// imports until we reach the place the name is actually defined
+fn exported_definition(db: &dyn Storage, uri: String, name: String) -> Option<(String, Symbol)> {
+    check_canceled(db);
+    let imports = db.document_imports(uri.clone());
+    let graph = db.document_reference_graph(uri.clone())?;
+
+    // Class members share the scope of the module, but aren't its attributes
+    let bindings = db.document_bindings(uri.clone());
+    let Some(symbol) = graph
+        .module_definitions(&name)
+        .iter()
+        .find(|symbol| !bindings.is_class_member(&symbol.location))
+    else {
+        // The name could still come from a `from module import *`
+        return imports
+            .iter()
+            .filter(|import| import.name == "*")
+            .find_map(|import| {
+                let module_uri = db.import_uri(uri.clone(), import.module.clone())?;
+                db.exported_definition(module_uri, name.clone())
+            });
+    };
+
+    match imports.iter().find(|import| import.location == symbol.location) {
+        Some(import) => db.import_definition(uri, import.clone()),
+        None => Some((uri, symbol.clone())),
+    }
+}
+
+/// Find what an import binds its name to: a module, or a definition in a module
+fn import_definition(db: &dyn Storage, importer: String, import: Import) -> Option<(String, Symbol)> {
+    let module_uri = db.import_uri(importer.clone(), import.module.clone());
+    let Some(attribute) = import.attribute else {
+        return Some((module_uri?, module_symbol(&import.module)));
+    };
+
+    // `from package import name` may also import a submodule,
+    // and namespace packages only have submodules
+    let submodule = || {
+        let submodule = match import.module.ends_with('.') {
+            true => format!("{}{}", import.module, attribute),
+            false => format!("{}.{}", import.module, attribute),
+        };
+        let submodule_uri = db.import_uri(importer.clone(), submodule.clone())?;
+        Some((submodule_uri, module_symbol(&submodule)))
+    };
+
+    // A package importing from itself, as in `from . import name`, gets the submodule
+    // rather than the name it binds to it
+    if module_uri.as_ref() == Some(&importer) {
+        if let Some(submodule) = submodule() {
+            return Some(submodule);
+        }
+    }
+    module_uri
+        .and_then(|module_uri| db.exported_definition(module_uri, attribute.clone()))
+        .or_else(submodule)
+}
+
+/// Check a document for problems, independent of the configured severities
+fn document_diagnostics(db: &dyn Storage, uri: String) -> Arc<Vec<Diagnostic>> {
+    check_canceled(db);
+    let Some(tree) = db.document_tree(uri.clone()) else {
+        return Arc::new(Vec::new());
+    };
+
+    let syntax_errors = tree.syntax_errors();
+    let mut diagnostics: Vec<Diagnostic> = syntax_errors
+        .iter()
+        .cloned()
+        .map(|error| Diagnostic {
+            rule: "syntax-error",
+            message: error.message,
+            location: error.location,
+        })
+        .collect();
+
+    // Without typeshed we can't tell missing modules from the standard library
+    if db.typeshed_path().is_some() {
+        let mut reported = Vec::new();
+        for import in db.document_imports(uri.clone()).iter() {
+            if reported.contains(&import.module_location) {
+                continue;
+            }
+            let resolved = match import.attribute {
+                Some(_) => db.import_definition(uri.clone(), import.clone()).is_some()
+                    || db.import_uri(uri.clone(), import.module.clone()).is_some(),
+                None => db.import_uri(uri.clone(), import.module.clone()).is_some(),
+            };
+            if !resolved {
+                reported.push(import.module_location.clone());
+                diagnostics.push(Diagnostic {
+                    rule: "unresolved-import",
+                    message: format!("Cannot resolve module `{}`", import.module),
+                    location: import.module_location.clone(),
+                });
+            }
+        }
+        diagnostics.extend(undefined_names(db, &uri, &syntax_errors));
+        diagnostics.extend(checker::type_errors(db, &uri).into_iter().filter(|diagnostic| {
+            let start = diagnostic.location.start;
+            !syntax_errors.iter().any(|error| error.location.contains(start))
+        }));
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.location.start);
+    Arc::new(diagnostics)
+}
+
+/// Find the references that aren't defined in the document, by a wildcard import
+/// or as builtins. Nothing is reported when a wildcard import can't be resolved,
+/// since any name may come from it, nor within syntax errors.
+fn undefined_names(db: &dyn Storage, uri: &str, syntax_errors: &[SyntaxError]) -> Vec<Diagnostic> {
+    let imports = db.document_imports(uri.to_string());
+    let Some(graph) = db.document_reference_graph(uri.to_string()) else {
+        return Vec::new();
+    };
+    if imports.iter().any(|import| {
+        import.name == "*" && db.import_uri(uri.to_string(), import.module.clone()).is_none()
+    }) {
+        return Vec::new();
+    }
+
+    let mut diagnostics = Vec::new();
+    for reference in graph.references() {
+        let start = reference.location.start;
+        // Modules named by imports are references, but aren't looked up in a scope
+        if graph.resolved_definition(reference).is_some()
+            || diagnostics::IMPLICIT_NAMES.contains(&reference.name.as_str())
+            || imports.iter().any(|import| import.module_location.contains(start))
+            || syntax_errors.iter().any(|error| error.location.contains(start))
+        {
+            continue;
+        }
+        let defined = db
+            .exported_definition(uri.to_string(), reference.name.clone())
+            .or_else(|| db.module_definition("builtins".to_string(), reference.name.clone()));
+        if defined.is_none() {
+            diagnostics.push(Diagnostic {
+                rule: "undefined-name",
+                message: format!("`{}` is not defined", reference.name),
+                location: reference.location.clone(),
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Classify the names of a document for semantic highlighting
+fn document_tokens(db: &dyn Storage, uri: String) -> Arc<Vec<Token>> {
+    check_canceled(db);
+    let (Some(tree), Some(graph)) = (
+        db.document_tree(uri.clone()),
+        db.document_reference_graph(uri.clone()),
+    ) else {
+        return Arc::new(Vec::new());
+    };
+    let members: HashMap<Location, tokens::TokenType> = tree
+        .member_names()
+        .into_iter()
+        .map(|(location, member_use)| (location, tokens::classify_member(member_use)))
+        .collect();
+    let imports = db.document_imports(uri.clone());
+
+    // Classes don't have a scope of their own, so their members are found in the outline
+    let mut class_members = HashSet::new();
+    let mut outline = tree.outline();
+    while let Some(symbol) = outline.pop() {
+        if symbol.kind == SymbolKind::Class {
+            class_members.extend(symbol.children.iter().map(|member| member.name_location.clone()));
+        }
+        outline.extend(symbol.children);
+    }
+
+    // Imported names are classified by what they import
+    let classify = |definition: &Symbol| match imports
+        .iter()
+        .find(|import| import.location == definition.location)
+        .and_then(|import| db.import_definition(uri.clone(), import.clone()))
+    {
+        Some((_, imported)) => tokens::classify(&imported, false),
+        None => tokens::classify(definition, class_members.contains(&definition.location)),
+    };
+
+    let mut document_tokens: Vec<Token> = graph
+        .definitions()
+        .map(|definition| {
+            let (token_type, modifiers) = classify(definition);
+            Token {
+                location: definition.location.clone(),
+                token_type,
+                modifiers: modifiers | tokens::DECLARATION,
+            }
+        })
+        .collect();
+
+    // Names after a dot aren't references, so they are classified by how they are used
+    document_tokens.extend(members.iter().map(|(location, token_type)| Token {
+        location: location.clone(),
+        token_type: *token_type,
+        modifiers: 0,
+    }));
+
+    for reference in graph.references() {
+        let (token_type, modifiers) = if members.contains_key(&reference.location) {
+            continue;
+        } else if imports
+            .iter()
+            .any(|import| import.module_location.contains(reference.location.start))
+        {
+            (TokenType::Namespace, 0)
+        } else if let Some(definition) = graph.resolved_definition(reference) {
+            classify(definition)
+        } else if let Some((_, definition)) =
+            db.exported_definition(uri.clone(), reference.name.clone())
+        {
+            tokens::classify(&definition, false)
+        } else if let Some((_, definition)) =
+            db.module_definition("builtins".to_string(), reference.name.clone())
+        {
+            let (token_type, modifiers) = tokens::classify(&definition, false);
+            (token_type, modifiers | tokens::DEFAULT_LIBRARY)
+        } else {
+            continue;
+        };
+        document_tokens.push(Token {
+            location: reference.location.clone(),
+            token_type,
+            modifiers,
+        });
+    }
+
+    document_tokens.sort_by_key(|token| token.location.start);
+    Arc::new(document_tokens)
+}
+
+/// Lower the definitions of a document into what they bind their names to
+fn document_bindings(db: &dyn Storage, uri: String) -> Arc<Bindings> {
+    check_canceled(db);
+    match db.document_tree(uri) {
+        Some(tree) => Arc::new(tree.bindings()),
+        None => Arc::new(Bindings::default()),
+    }
+}
+
+fn recover_definition(
+    _db: &dyn Storage,
+    _cycle: &[String],
+    _uri: &String,
+    _name: &String,
+) -> Option<(String, Symbol)> {
+    None
+}
+
+fn recover_import(
+    _db: &dyn Storage,
+    _cycle: &[String],
+    _importer: &String,
+    _import: &Import,
+) -> Option<(String, Symbol)> {
+    None
+}
+
+/// A symbol standing in for a whole module, located at the start of its file
+pub fn module_symbol(module: &str) -> Symbol {
+    Symbol {
+        name: module.to_string(),
+        kind: SymbolKind::Module,
+        location: Location {
+            start: (0, 0),
+            end: (0, 0),
+        },
+        scope_path: vec!["module".to_string()],
+    }
 }
 
 // Database implementation
@@ -48,6 +578,107 @@ fn document_reference_graph(db: &dyn Storage, uri: String) -> Option<ReferenceGr
 #[salsa::database(StorageDatabase)]
 pub struct LspDatabase {
     storage: salsa::Storage<Self>,
+    cancellation: Cancellation,
 }
 
-impl salsa::Database for LspDatabase {}
+impl salsa::Database for LspDatabase {
+    /// Another thread panicked computing a query we wait for. When a change or the
+    /// client canceled it, the cancellation reaches us too. Anything else is a bug
+    /// in the query, which fails our work as well.
+    fn on_propagated_panic(&self) -> ! {
+        if self.salsa_runtime().is_current_revision_canceled()
+            || self.cancellation.is_canceled()
+            || self.cancellation.requests_canceled()
+        {
+            std::panic::resume_unwind(Box::new(Canceled));
+        }
+        panic!("A query computed on another thread panicked");
+    }
+}
+
+impl HasCancellation for LspDatabase {
+    fn cancellation(&self) -> &Cancellation {
+        &self.cancellation
+    }
+}
+
+#[cfg(test)]
+impl LspDatabase {
+    /// A database where the given documents, by uri, are open and make up the
+    /// workspace, analyzed against the bundled builtins stub
+    pub fn with_documents(documents: &[(&str, &str)]) -> Self {
+        typeshed::write_bundled_builtins();
+        let mut db = LspDatabase::default();
+        let uris: Vec<String> = documents.iter().map(|(uri, _)| uri.to_string()).collect();
+        for (uri, text) in documents {
+            db.set_document_text(uri.to_string(), Some(Arc::new(text.to_string())));
+        }
+        db.set_open_documents(Arc::new(uris.iter().cloned().collect()));
+        db.set_workspace_files(Arc::new(uris));
+        db.set_changed_files(Arc::new(BTreeSet::new()));
+        db.set_python_version(PythonVersion::default());
+        db.set_python_platform(PythonPlatform::default());
+        db.set_typeshed_path(None);
+        db.set_search_paths(Arc::new(Vec::new()));
+        db.set_python_environment(Arc::new(PythonEnvironment::default()));
+        db.set_resolution_revision(0);
+        db
+    }
+}
+
+impl salsa::ParallelDatabase for LspDatabase {
+    fn snapshot(&self) -> salsa::Snapshot<Self> {
+        salsa::Snapshot::new(LspDatabase {
+            storage: self.storage.snapshot(),
+            cancellation: self.cancellation.snapshot(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use salsa::ParallelDatabase;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn changes_cancel_snapshots() {
+        let mut db = LspDatabase::default();
+        db.set_open_documents(Arc::new(BTreeSet::new()));
+        db.set_changed_files(Arc::new(BTreeSet::new()));
+        let snapshot = db.snapshot();
+
+        // The snapshot keeps computing until it notices the change waiting for it
+        let computing = std::thread::spawn(move || {
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                for index in 0.. {
+                    snapshot.document_tree(format!("file:///missing/{}.py", index));
+                }
+            }))
+            .map_err(|payload| payload.is::<Canceled>())
+        });
+
+        // Changing an input waits until the snapshot is dropped
+        db.set_open_documents(Arc::new(BTreeSet::new()));
+        assert_eq!(computing.join().unwrap(), Err(true));
+    }
+
+    #[test]
+    fn requests_are_canceled_per_snapshot() {
+        let db = LspDatabase::default();
+        let first = db.snapshot();
+        let second = db.snapshot();
+        first.cancellation().flag().store(true, Ordering::Relaxed);
+        assert!(first.cancellation().is_canceled());
+        assert!(!second.cancellation().is_canceled());
+
+        // Snapshots that may have waited for the queries of a request that
+        // stopped notice it, others don't
+        let stopped = panic::catch_unwind(AssertUnwindSafe(|| check_canceled(&*first)));
+        assert!(stopped.unwrap_err().is::<Canceled>());
+        assert!(second.cancellation().requests_canceled());
+        assert!(!db.snapshot().cancellation().requests_canceled());
+        let other = LspDatabase::default();
+        assert!(!other.snapshot().cancellation().requests_canceled());
+    }
+}
@@ -0,0 +1,481 @@
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::parser::version::PythonVersion;
+
+/// The Python environment imports are resolved against
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PythonEnvironment {
+    /// Root of the virtual environment, if one was found
+    pub prefix: Option<PathBuf>,
+    pub version: Option<PythonVersion>,
+    /// `site-packages` directories followed by the paths added by their `.pth` files
+    pub site_packages: Vec<PathBuf>,
+    /// Package directories of editable installs that use an import finder
+    pub editable_packages: Vec<(String, PathBuf)>,
+}
+
+/// What a python environment is detected from, which tells whether
+/// the environment is still current after the settings changed
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EnvironmentSource {
+    /// The configured interpreter or environment directory
+    pub python_path: Option<PathBuf>,
+    /// Whether the python path was configured by the user rather than by a
+    /// settings file of the workspace, so that it may be run
+    pub trusted: bool,
+    pub root: Option<PathBuf>,
+}
+
+impl EnvironmentSource {
+    /// Detect the environment, which may run the interpreter
+    pub fn detect(&self) -> PythonEnvironment {
+        PythonEnvironment::detect(
+            self.python_path.as_deref(),
+            self.trusted,
+            self.root.as_deref(),
+        )
+    }
+}
+
+impl PythonEnvironment {
+    /// Detect the environment of a workspace. The configured interpreter (or
+    /// environment directory) wins, then `VIRTUAL_ENV`, a `.venv` or `venv` in
+    /// the root, and the poetry environment of the root's `pyproject.toml`.
+    /// As a last resort an interpreter is asked for its `sys.path`. The configured
+    /// one is only run if `run_configured` is set, as opening a checkout shouldn't
+    /// run programs of its choosing: otherwise it has to be laid out on disk.
+    pub fn detect(configured: Option<&Path>, run_configured: bool, root: Option<&Path>) -> Self {
+        let mut candidates: Vec<PathBuf> = configured.map(Path::to_path_buf).into_iter().collect();
+        if let Some(virtual_env) = std::env::var_os("VIRTUAL_ENV") {
+            candidates.push(PathBuf::from(virtual_env));
+        }
+        if let Some(root) = root {
+            candidates.push(root.join(".venv"));
+            candidates.push(root.join("venv"));
+            candidates.extend(poetry_environment(root));
+        }
+
+        if let Some(prefix) = candidates.iter().find_map(|path| environment_prefix(path)) {
+            info!("Using python environment at {:?}", prefix);
+            return Self::from_prefix(&prefix);
+        }
+        // An installation that isn't a virtual environment, like a conda environment
+        if let Some(prefix) = configured.and_then(installation_prefix) {
+            info!("Using python installation at {:?}", prefix);
+            return Self::from_prefix(&prefix);
+        }
+
+        // A plain interpreter, which we have to run to find out about its paths
+        let interpreter = match configured {
+            Some(path) if run_configured => path.to_path_buf(),
+            Some(path) => {
+                warn!(
+                    "Not running {:?} from a settings file, it isn't an environment",
+                    path
+                );
+                PathBuf::from("python3")
+            }
+            None => PathBuf::from("python3"),
+        };
+        match Self::from_interpreter(&interpreter) {
+            Some(environment) => environment,
+            None => {
+                warn!("No python environment found, only resolving workspace imports");
+                Self::default()
+            }
+        }
+    }
+
+    /// Compute the paths of a virtual environment from its layout on disk
+    pub fn from_prefix(prefix: &Path) -> Self {
+        let config = std::fs::read_to_string(prefix.join("pyvenv.cfg")).unwrap_or_default();
+        let setting = |key: &str| {
+            config.lines().find_map(|line| {
+                let (name, value) = line.split_once('=')?;
+                (name.trim() == key).then(|| value.trim().to_string())
+            })
+        };
+
+        let version = setting("version")
+            .or_else(|| setting("version_info"))
+            .and_then(|version| PythonVersion::parse(&version));
+
+        let mut site_packages = site_packages_of(prefix, version);
+
+        // The base installation's packages are visible too when the environment allows it
+        if setting("include-system-site-packages").as_deref() == Some("true") {
+            if let Some(base_prefix) = setting("home")
+                .map(PathBuf::from)
+                .and_then(|home| home.parent().map(Path::to_path_buf))
+            {
+                site_packages.extend(site_packages_of(&base_prefix, version));
+            }
+        }
+
+        let mut environment = Self {
+            prefix: Some(prefix.to_path_buf()),
+            version,
+            site_packages: Vec::new(),
+            editable_packages: Vec::new(),
+        };
+        environment.add_site_packages(site_packages, true);
+        environment
+    }
+
+    /// Ask an interpreter for its version and `sys.path`
+    pub fn from_interpreter(interpreter: &Path) -> Option<Self> {
+        let output = Command::new(interpreter)
+            .args([
+                "-c",
+                "import json, sys; print(json.dumps([list(sys.version_info[:2]), sys.path]))",
+            ])
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let (version, paths): ((usize, usize), Vec<String>) =
+            serde_json::from_slice(&output.stdout).ok()?;
+        info!("Using sys.path of {:?}: {:?}", interpreter, paths);
+
+        let mut environment = Self {
+            prefix: None,
+            version: Some(PythonVersion::new(version.0, version.1)),
+            site_packages: Vec::new(),
+            editable_packages: Vec::new(),
+        };
+        // Python already processed the `.pth` files for us
+        let paths = paths
+            .into_iter()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .filter(|path| path.is_dir())
+            .collect();
+        environment.add_site_packages(paths, false);
+        Some(environment)
+    }
+
+    fn add_site_packages(&mut self, directories: Vec<PathBuf>, read_pth_files: bool) {
+        let mut extra_paths = Vec::new();
+        for directory in &directories {
+            if read_pth_files {
+                extra_paths.extend(pth_paths(directory));
+            }
+            self.editable_packages.extend(editable_packages(directory));
+        }
+
+        self.site_packages.extend(directories);
+        for path in extra_paths {
+            if !self.site_packages.contains(&path) {
+                self.site_packages.push(path);
+            }
+        }
+    }
+}
+
+/// Get the installation root for a path that is either the root itself
+/// or an interpreter inside of it (`<root>/bin/python`)
+fn installation_root(path: &Path) -> Option<&Path> {
+    match path.is_dir() {
+        true => Some(path),
+        false => path.parent()?.parent(),
+    }
+}
+
+/// Get the root of the virtual environment a path points to
+fn environment_prefix(path: &Path) -> Option<PathBuf> {
+    let prefix = installation_root(path)?;
+    prefix
+        .join("pyvenv.cfg")
+        .is_file()
+        .then(|| prefix.to_path_buf())
+}
+
+/// Get the root of the installation a path points to, which has to
+/// have `site-packages` directories laid out below it
+fn installation_prefix(path: &Path) -> Option<PathBuf> {
+    let prefix = installation_root(path)?;
+    (!site_packages_of(prefix, None).is_empty()).then(|| prefix.to_path_buf())
+}
+
+/// Find the `site-packages` directories below an installation prefix
+fn site_packages_of(prefix: &Path, version: Option<PythonVersion>) -> Vec<PathBuf> {
+    // Windows layout
+    let windows = prefix.join("Lib").join("site-packages");
+    if windows.is_dir() {
+        return vec![windows];
+    }
+
+    let mut found = Vec::new();
+    for lib in ["lib", "lib64"] {
+        let Ok(entries) = std::fs::read_dir(prefix.join(lib)) else {
+            continue;
+        };
+        let mut directories: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                match version {
+                    Some(version) => name == format!("python{}", version),
+                    None => name.starts_with("python"),
+                }
+            })
+            .map(|path| path.join("site-packages"))
+            .filter(|path| path.is_dir())
+            .collect();
+        directories.sort();
+        for directory in directories {
+            // lib64 is often a symlink to lib
+            let canonical = directory.canonicalize().unwrap_or(directory);
+            if !found.contains(&canonical) {
+                found.push(canonical);
+            }
+        }
+    }
+    found
+}
+
+/// Read the directories added by the `.pth` files of a `site-packages` directory.
+/// Lines starting with `import` are code to execute, which we skip.
+fn pth_paths(site_packages: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(site_packages) else {
+        return Vec::new();
+    };
+    let mut pth_files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "pth"))
+        .collect();
+    pth_files.sort();
+
+    let mut paths = Vec::new();
+    for pth_file in pth_files {
+        let contents = std::fs::read_to_string(&pth_file).unwrap_or_default();
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with("import") {
+                continue;
+            }
+            let path = site_packages.join(line);
+            if path.is_dir() {
+                debug!("Adding {:?} from {:?}", path, pth_file);
+                paths.push(path);
+            }
+        }
+    }
+    paths
+}
+
+/// Read the `MAPPING` of the import finders setuptools generates for
+/// editable installs (`__editable___<name>_finder.py`)
+fn editable_packages(site_packages: &Path) -> Vec<(String, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(site_packages) else {
+        return Vec::new();
+    };
+
+    let mut packages = Vec::new();
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !(name.starts_with("__editable__") && name.ends_with("_finder.py")) {
+            continue;
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap_or_default();
+        let Some(mapping) = contents
+            .lines()
+            .find(|line| line.starts_with("MAPPING"))
+            .and_then(|line| line.split_once('{'))
+            .and_then(|(_, rest)| rest.split_once('}'))
+            .map(|(mapping, _)| mapping)
+        else {
+            continue;
+        };
+
+        for entry in mapping.split(',') {
+            let Some((package, directory)) = entry.split_once(':') else {
+                continue;
+            };
+            let unquote = |text: &str| text.trim().trim_matches(['\'', '"']).to_string();
+            packages.push((unquote(package), PathBuf::from(unquote(directory))));
+        }
+    }
+    packages
+}
+
+/// Find the poetry managed environment of a project, which lives in poetry's
+/// cache as `<name>-<hash>-py<version>`
+fn poetry_environment(root: &Path) -> Option<PathBuf> {
+    let pyproject = std::fs::read_to_string(root.join("pyproject.toml")).ok()?;
+    let pyproject: toml::Table = pyproject.parse().ok()?;
+    let name = pyproject
+        .get("tool")
+        .and_then(|tool| tool.get("poetry"))
+        .and_then(|poetry| poetry.get("name"))
+        .or_else(|| {
+            pyproject
+                .get("project")
+                .and_then(|project| project.get("name"))
+        })?
+        .as_str()?
+        .to_lowercase()
+        .replace([' ', '$', '`', '!', '*', '@', '"', '\\'], "_");
+
+    let cache = match std::env::var_os("POETRY_VIRTUALENVS_PATH") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let home = PathBuf::from(std::env::var_os("HOME")?);
+            let cache = std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| home.join(".cache"));
+            [
+                cache.join("pypoetry/virtualenvs"),
+                home.join("Library/Caches/pypoetry/virtualenvs"),
+            ]
+            .into_iter()
+            .find(|path| path.is_dir())?
+        }
+    };
+
+    let mut environments: Vec<PathBuf> = std::fs::read_dir(cache)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .starts_with(&format!("{}-", name))
+        })
+        .collect();
+    environments.sort();
+    environments.pop()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory tree below the temporary directory, removed when dropped
+    struct Tree(PathBuf);
+
+    impl Tree {
+        fn new(name: &str) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("rypy-env-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&root);
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root.canonicalize().unwrap())
+        }
+
+        fn file(&self, path: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+
+        fn dir(&self, path: &str) -> PathBuf {
+            let path = self.0.join(path);
+            std::fs::create_dir_all(&path).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn virtual_environment() {
+        let tree = Tree::new("venv");
+        let venv = tree.0.join(".venv");
+        tree.file(".venv/pyvenv.cfg", "home = /usr/bin\nversion = 3.11.4\n");
+        tree.file(".venv/bin/python", "");
+        tree.dir(".venv/lib/python3.10/site-packages");
+        let site_packages = tree.dir(".venv/lib/python3.11/site-packages");
+        let src = tree.dir("src");
+        tree.file(
+            ".venv/lib/python3.11/site-packages/project.pth",
+            "# comment\n../../../../src\nimport os; os.system('true')\nmissing\n",
+        );
+        tree.file(
+            ".venv/lib/python3.11/site-packages/__editable___pkg_0_1_finder.py",
+            "import sys\nMAPPING = {'pkg': '/work/pkg/src/pkg', \"other\": '/work/other'}\n",
+        );
+
+        let environment = PythonEnvironment::from_prefix(&venv);
+        assert_eq!(environment.prefix, Some(venv.clone()));
+        assert_eq!(environment.version, Some(PythonVersion::new(3, 11)));
+        assert_eq!(
+            environment.site_packages,
+            vec![site_packages.clone(), site_packages.join("../../../../src")]
+        );
+        assert_eq!(environment.site_packages[1].canonicalize().unwrap(), src);
+        assert_eq!(
+            environment.editable_packages,
+            vec![
+                ("pkg".to_string(), PathBuf::from("/work/pkg/src/pkg")),
+                ("other".to_string(), PathBuf::from("/work/other")),
+            ]
+        );
+
+        // The root or an interpreter inside of it, but not a directory below it
+        assert_eq!(environment_prefix(&venv), Some(venv.clone()));
+        assert_eq!(
+            environment_prefix(&venv.join("bin/python")),
+            Some(venv.clone())
+        );
+        assert_eq!(environment_prefix(&venv.join("lib/python3.11")), None);
+        assert_eq!(environment_prefix(&tree.0), None);
+
+        let detected = PythonEnvironment::detect(Some(&venv.join("bin/python")), false, None);
+        assert_eq!(detected, environment);
+    }
+
+    #[test]
+    fn installation_without_pyvenv_cfg() {
+        let tree = Tree::new("installation");
+        let site_packages = tree.dir("conda/lib/python3.12/site-packages");
+        let interpreter = tree.file("conda/bin/python", "");
+
+        let detected = PythonEnvironment::detect(Some(&interpreter), false, None);
+        assert_eq!(detected.prefix, Some(tree.0.join("conda")));
+        assert_eq!(detected.site_packages, vec![site_packages]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn interpreters_from_settings_files_are_not_run() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tree = Tree::new("interpreter");
+        let marker = tree.0.join("ran");
+        let interpreter = tree.file("python", &format!("#!/bin/sh\ntouch {:?}\n", marker));
+        std::fs::set_permissions(&interpreter, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        PythonEnvironment::detect(Some(&interpreter), false, None);
+        assert!(!marker.exists());
+        PythonEnvironment::detect(Some(&interpreter), true, None);
+        assert!(marker.exists());
+    }
+
+    #[test]
+    fn poetry_environments() {
+        let tree = Tree::new("poetry");
+        let project = tree.dir("project");
+        tree.file(
+            "project/pyproject.toml",
+            "[tool.poetry]\nname = \"My Project\"\n",
+        );
+        tree.dir("virtualenvs/other-Xy12-py3.12");
+        tree.dir("virtualenvs/my_project-Ab34-py3.11");
+        let environment = tree.dir("virtualenvs/my_project-Ab34-py3.12");
+
+        std::env::set_var("POETRY_VIRTUALENVS_PATH", tree.0.join("virtualenvs"));
+        assert_eq!(poetry_environment(&project), Some(environment));
+        assert_eq!(poetry_environment(&tree.0), None);
+        std::env::remove_var("POETRY_VIRTUALENVS_PATH");
+    }
+}
@@ -0,0 +1,113 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::warn;
+use std::path::{Path, PathBuf};
+
+use crate::config::Settings;
+
+/// Directories that never hold project sources
+const SKIPPED_DIRECTORIES: [&str; 3] = ["__pycache__", "node_modules", "site-packages"];
+
+/// Find the Python files below the given paths. Files are filtered by the `include` and
+/// `exclude` globs of the settings, which are matched relative to the workspace root.
+/// Paths that are given explicitly as files are always included.
+pub fn python_files(paths: &[PathBuf], root: &Path, settings: &Settings) -> Vec<PathBuf> {
+    let include = settings.include.as_deref().map(build_globs);
+    let exclude = build_globs(settings.exclude.as_deref().unwrap_or_default());
+    let is_selected = |path: &Path| {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        !exclude.is_match(relative)
+            && include
+                .as_ref()
+                .is_none_or(|include| include.is_match(relative))
+    };
+
+    let mut files = Vec::new();
+    let mut directories = Vec::new();
+    for path in paths {
+        match path.is_dir() {
+            true => directories.push(path.clone()),
+            false => files.push(path.clone()),
+        }
+    }
+
+    while let Some(directory) = directories.pop() {
+        let Ok(entries) = std::fs::read_dir(&directory) else {
+            continue;
+        };
+        let mut entries: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect();
+        entries.sort();
+
+        for path in entries {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if path.is_dir() {
+                // Hidden directories include `.git` and `.venv`, other environments have a `pyvenv.cfg`
+                let skipped = name.starts_with('.')
+                    || SKIPPED_DIRECTORIES.contains(&&*name)
+                    || path.join("pyvenv.cfg").is_file();
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                if !skipped && !exclude.is_match(relative) {
+                    directories.push(path);
+                }
+            } else if (name.ends_with(".py") || name.ends_with(".pyi")) && is_selected(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+fn build_globs(patterns: &[String]) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        match Glob::new(pattern) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(e) => warn!("Invalid glob {:?}: {}", pattern, e),
+        }
+    }
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_and_exclude() {
+        let root = std::env::temp_dir().join(format!("rypy-files-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for file in [
+            "src/app.py",
+            "src/app_test.py",
+            "src/build/generated.py",
+            "scripts/tool.py",
+            ".venv/lib/site.py",
+            "README.md",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+
+        let settings = Settings {
+            include: Some(vec!["src/**".to_string()]),
+            exclude: Some(vec!["**/build".to_string(), "**/*_test.py".to_string()]),
+            ..Settings::default()
+        };
+        assert_eq!(
+            python_files(std::slice::from_ref(&root), &root, &settings),
+            vec![root.join("src/app.py")]
+        );
+        assert_eq!(
+            python_files(&[root.join("scripts/tool.py")], &root, &settings),
+            vec![root.join("scripts/tool.py")]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+
+use crate::lsp::document_sync::Position;
+
+/// The encoding used for the `character` field of LSP positions,
+/// negotiated with the client during `initialize`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    /// The only encoding every client is required to support.
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    pub fn from_kind(kind: &str) -> Option<Self> {
+        match kind {
+            "utf-8" => Some(Self::Utf8),
+            "utf-16" => Some(Self::Utf16),
+            "utf-32" => Some(Self::Utf32),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf16 => "utf-16",
+            Self::Utf32 => "utf-32",
+        }
+    }
+
+    /// Pick the first encoding offered by the client that we understand,
+    /// falling back to UTF-16 when the client did not offer any.
+    pub fn negotiate(offered: &[String]) -> Self {
+        offered
+            .iter()
+            .find_map(|kind| Self::from_kind(kind))
+            .unwrap_or_default()
+    }
+
+    /// Number of code units a character takes up in this encoding
+    fn len(&self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Maps between the byte columns produced by tree-sitter and the
+/// columns of the negotiated position encoding for a single document.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LineIndex {
+    text: Arc<String>,
+    // Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: Arc<String>) -> Self {
+        let mut line_starts = vec![0];
+        for (offset, byte) in text.bytes().enumerate() {
+            if byte == b'\n' {
+                line_starts.push(offset + 1);
+            }
+        }
+        Self { text, line_starts }
+    }
+
+    /// Get the text of a line, without its line terminator
+    fn line(&self, line: usize) -> &str {
+        let Some(&start) = self.line_starts.get(line) else {
+            return "";
+        };
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.text.len());
+        self.text[start..end].trim_end_matches(['\n', '\r'])
+    }
+
+    /// Convert a (line, byte column) pair into a position in the given encoding
+    pub fn to_position(&self, point: (usize, usize), encoding: PositionEncoding) -> Position {
+        let (line, byte_column) = point;
+        let character = self
+            .line(line)
+            .char_indices()
+            .take_while(|(offset, _)| *offset < byte_column)
+            .map(|(_, c)| encoding.len(c))
+            .sum();
+        Position { line, character }
+    }
+
+    /// Convert a position in the given encoding into a (line, byte column) pair.
+    /// Columns past the end of the line are clamped to the line length.
+    pub fn to_point(&self, position: &Position, encoding: PositionEncoding) -> (usize, usize) {
+        let line = self.line(position.line);
+        let mut units = 0;
+        for (offset, c) in line.char_indices() {
+            if units >= position.character {
+                return (position.line, offset);
+            }
+            units += encoding.len(c);
+        }
+        (position.line, line.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(text: &str) -> LineIndex {
+        LineIndex::new(Arc::new(text.to_string()))
+    }
+
+    #[test]
+    fn negotiate_encoding() {
+        let offered = vec!["utf-32".to_string(), "utf-8".to_string()];
+        assert_eq!(
+            PositionEncoding::negotiate(&offered),
+            PositionEncoding::Utf32
+        );
+        assert_eq!(PositionEncoding::negotiate(&[]), PositionEncoding::Utf16);
+        assert_eq!(
+            PositionEncoding::negotiate(&["latin-1".to_string()]),
+            PositionEncoding::Utf16
+        );
+    }
+
+    #[test]
+    fn ascii_columns_are_unchanged() {
+        let index = index("x = 1\ny = x\n");
+        for encoding in [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ] {
+            let position = index.to_position((1, 4), encoding);
+            assert_eq!((position.line, position.character), (1, 4));
+            assert_eq!(index.to_point(&position, encoding), (1, 4));
+        }
+    }
+
+    #[test]
+    fn non_ascii_columns() {
+        // "é" is 2 bytes / 1 UTF-16 unit, "😀" is 4 bytes / 2 UTF-16 units
+        let index = index("s = \"é😀\"; x = s\n");
+        let byte_column = "s = \"é😀\"; ".len();
+
+        let utf16 = index.to_position((0, byte_column), PositionEncoding::Utf16);
+        assert_eq!(utf16.character, 11);
+        assert_eq!(
+            index.to_point(&utf16, PositionEncoding::Utf16),
+            (0, byte_column)
+        );
+
+        let utf32 = index.to_position((0, byte_column), PositionEncoding::Utf32);
+        assert_eq!(utf32.character, 10);
+        assert_eq!(
+            index.to_point(&utf32, PositionEncoding::Utf32),
+            (0, byte_column)
+        );
+
+        let utf8 = index.to_position((0, byte_column), PositionEncoding::Utf8);
+        assert_eq!(utf8.character, byte_column);
+    }
+
+    #[test]
+    fn columns_past_line_end_are_clamped() {
+        let index = index("ab\r\ncd");
+        let position = Position {
+            line: 0,
+            character: 10,
+        };
+        assert_eq!(index.to_point(&position, PositionEncoding::Utf16), (0, 2));
+        assert_eq!(
+            index.to_point(
+                &Position {
+                    line: 1,
+                    character: 1
+                },
+                PositionEncoding::Utf16
+            ),
+            (1, 1)
+        );
+    }
+}
@@ -1,2 +1,9 @@
 pub mod state;
-mod db;
\ No newline at end of file
+pub mod snapshot;
+pub mod line_index;
+pub mod environment;
+pub mod resolver;
+pub mod typeshed;
+pub mod uri;
+pub mod files;
+pub mod db;
\ No newline at end of file
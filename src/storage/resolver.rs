@@ -0,0 +1,115 @@
+use std::path::{Path, PathBuf};
+
+/// Find the file of a dotted module name below a search root.
+/// Stub-only `<package>-stubs` packages (PEP 561) win over the package itself,
+/// and `.pyi` files win over `.py` files.
+pub fn find_module(root: &Path, module: &str) -> Option<PathBuf> {
+    let mut parts = module.split('.');
+    let package = parts.next().filter(|package| !package.is_empty())?;
+    let rest: Vec<&str> = parts.collect();
+
+    find_in_package(&root.join(format!("{}-stubs", package)), &rest)
+        .or_else(|| find_in_package(&root.join(package), &rest))
+}
+
+/// Find a module relative to the file importing it, e.g. `..utils` from `pkg/sub/mod.py`
+pub fn find_relative_module(importer: &Path, module: &str) -> Option<PathBuf> {
+    let name = module.trim_start_matches('.');
+    let level = module.len() - name.len();
+
+    // One dot is the importer's own package, each further dot goes up a level
+    let mut base = importer.parent()?.to_path_buf();
+    for _ in 1..level {
+        base = base.parent()?.to_path_buf();
+    }
+
+    if name.is_empty() {
+        return find_in_package(&base, &[]);
+    }
+    let parts: Vec<&str> = name.split('.').collect();
+    find_in_package(&base.join(parts[0]), &parts[1..])
+}
+
+/// Given the path of a top level package (without extension) and the
+/// remaining parts of a dotted name, find the file that defines it.
+/// Directories without an `__init__` are namespace packages and are
+/// walked through, but don't resolve to a file themselves.
+pub fn find_in_package(package: &Path, rest: &[&str]) -> Option<PathBuf> {
+    let path = rest
+        .iter()
+        .fold(package.to_path_buf(), |path, part| path.join(part));
+
+    let with_suffix = |suffix: &str| {
+        let mut name = path.file_name()?.to_os_string();
+        name.push(suffix);
+        Some(path.with_file_name(name))
+    };
+
+    [
+        with_suffix(".pyi"),
+        with_suffix(".py"),
+        Some(path.join("__init__.pyi")),
+        Some(path.join("__init__.py")),
+    ]
+    .into_iter()
+    .flatten()
+    .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(path: &Path) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+
+    #[test]
+    fn resolution_order() {
+        let root = std::env::temp_dir().join(format!("rypy-resolver-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        touch(&root.join("pkg/__init__.py"));
+        touch(&root.join("pkg/mod.py"));
+        touch(&root.join("pkg/mod.pyi"));
+        touch(&root.join("typed/__init__.py"));
+        touch(&root.join("typed-stubs/__init__.pyi"));
+        touch(&root.join("namespace/inner/leaf.py"));
+
+        assert_eq!(
+            find_module(&root, "pkg"),
+            Some(root.join("pkg/__init__.py"))
+        );
+        assert_eq!(
+            find_module(&root, "pkg.mod"),
+            Some(root.join("pkg/mod.pyi"))
+        );
+        assert_eq!(
+            find_module(&root, "typed"),
+            Some(root.join("typed-stubs/__init__.pyi"))
+        );
+        assert_eq!(
+            find_module(&root, "namespace.inner.leaf"),
+            Some(root.join("namespace/inner/leaf.py"))
+        );
+        assert_eq!(find_module(&root, "namespace"), None);
+        assert_eq!(find_module(&root, "missing"), None);
+
+        let importer = root.join("pkg/mod.py");
+        assert_eq!(
+            find_relative_module(&importer, "."),
+            Some(root.join("pkg/__init__.py"))
+        );
+        assert_eq!(
+            find_relative_module(&importer, ".mod"),
+            Some(root.join("pkg/mod.pyi"))
+        );
+        assert_eq!(
+            find_relative_module(&importer, "..typed"),
+            Some(root.join("typed/__init__.py"))
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
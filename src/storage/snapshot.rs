@@ -0,0 +1,1851 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::db::{module_symbol, HasCancellation, LspDatabase, Storage};
+use super::line_index::PositionEncoding;
+use super::uri::uri_to_path;
+use crate::analysis::calls::{self, CallSite};
+use crate::analysis::synthesis;
+use crate::analysis::tokens::Token;
+use crate::analysis::types::Type;
+use crate::analysis::{diagnostics, inference};
+use crate::config::{Settings, Severity};
+use crate::lsp::{
+    document_sync::{Position, Range},
+    language_features::{
+        self, CallHierarchyIncomingCall, CallHierarchyIncomingCallsParams, CallHierarchyItem,
+        CallHierarchyOutgoingCall, CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams,
+        CodeLens, CodeLensParams, Command, CompletionItem, CompletionParams, Diagnostic,
+        DocumentHighlight, DocumentHighlightParams, DocumentSymbol, DocumentSymbolParams,
+        DocumentSymbolResponse, FoldingRange, FoldingRangeParams, GotoDefinitionParams,
+        HierarchyItem, Hover, HoverParams, ImplementationParams, InlayHint, InlayHintLabel,
+        InlayHintLabelPart, InlayHintParams, LocationLink, MarkupContent, SelectionRange,
+        SelectionRangeParams, SemanticTokens, SemanticTokensDelta, SemanticTokensDeltaParams,
+        SemanticTokensDeltaResponse, SemanticTokensEdit, SemanticTokensRangeParams,
+        SymbolInformation, TypeDefinitionParams, TypeHierarchyItem, TypeHierarchyPrepareParams,
+        TypeHierarchySubtypesParams, TypeHierarchySupertypesParams,
+    },
+    lifecycle::ClientCapabilities,
+};
+use crate::parser::{
+    expression::{Argument, Binding, Expr, ParameterKind},
+    parser::{Parser, TreeSitterParser},
+    symbol::{self, FoldKind, OutlineSymbol, Symbol, SymbolKind},
+};
+
+/// The server command code lenses run to show locations, with the title of
+/// the lens and the locations as arguments
+pub const SHOW_LOCATIONS_COMMAND: &str = "rypy.showLocations";
+
+/// The classes of the workspace that derive from each class, directly or not
+type Subclasses = HashMap<(String, Symbol), Vec<(String, Symbol)>>;
+
+/// What resolving an inlay hint needs: the definitions its label names,
+/// along with the uris of their documents
+#[derive(Serialize, Deserialize)]
+struct InlayHintData {
+    definitions: Vec<(String, Symbol)>,
+}
+
+/// A read-only view of the state at one revision, which requests are answered from.
+/// It can be sent to another thread, and while it is alive changes to the inputs
+/// block. Queries running on it are canceled once a change is waiting.
+pub struct LspSnapshot {
+    db: salsa::Snapshot<LspDatabase>,
+    position_encoding: PositionEncoding,
+    client_capabilities: Arc<ClientCapabilities>,
+    settings: Arc<Settings>,
+    sent_tokens: Arc<SentTokens>,
+}
+
+/// The semantic tokens last sent for each document,
+/// which `full/delta` requests are answered against
+#[derive(Default)]
+pub struct SentTokens {
+    next_result_id: AtomicU64,
+    documents: Mutex<HashMap<String, (String, Vec<u32>)>>,
+}
+
+impl SentTokens {
+    /// Remember the tokens sent for a document, returning their result id
+    fn remember(&self, uri: &str, data: Vec<u32>) -> String {
+        let result_id = self
+            .next_result_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        self.documents
+            .lock()
+            .unwrap()
+            .insert(uri.to_string(), (result_id.clone(), data));
+        result_id
+    }
+
+    /// Get the tokens sent for a document, if they have the given result id
+    fn previous(&self, uri: &str, result_id: &str) -> Option<Vec<u32>> {
+        let documents = self.documents.lock().unwrap();
+        let (previous_id, data) = documents.get(uri)?;
+        (previous_id == result_id).then(|| data.clone())
+    }
+
+    pub fn forget(&self, uri: &str) {
+        self.documents.lock().unwrap().remove(uri);
+    }
+}
+
+impl LspSnapshot {
+    pub fn new(
+        db: salsa::Snapshot<LspDatabase>,
+        position_encoding: PositionEncoding,
+        client_capabilities: Arc<ClientCapabilities>,
+        settings: Arc<Settings>,
+        sent_tokens: Arc<SentTokens>,
+    ) -> Self {
+        Self {
+            db,
+            position_encoding,
+            client_capabilities,
+            settings,
+            sent_tokens,
+        }
+    }
+
+    /// The flag that cancels the request the snapshot answers
+    pub fn cancellation(&self) -> Arc<AtomicBool> {
+        self.db.cancellation().flag()
+    }
+
+    pub fn client_capabilities(&self) -> &ClientCapabilities {
+        &self.client_capabilities
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Parse a file and resolve its names ahead of the requests that need them
+    pub fn index_file(&self, uri: &str) {
+        self.db.document_reference_graph(uri.to_string());
+        self.db.document_imports(uri.to_string());
+    }
+
+    /// The version of an open document, as the client numbered it
+    pub fn document_version(&self, uri: &str) -> Option<i32> {
+        self.db.document_version(uri.to_string())
+    }
+
+    /// Convert a client position into one whose `character` is a byte column,
+    /// which is what the parser's locations are measured in.
+    fn to_byte_position(&self, uri: &str, position: Position) -> Option<Position> {
+        let line_index = self.db.document_line_index(uri.to_string())?;
+        let (line, character) = line_index.to_point(&position, self.position_encoding);
+        Some(Position { line, character })
+    }
+
+    /// Convert a parser location into a range in the negotiated encoding
+    fn to_range(&self, uri: &str, location: &symbol::Location) -> Option<Range> {
+        let line_index = self.db.document_line_index(uri.to_string())?;
+        Some(Range {
+            start: line_index.to_position(location.start, self.position_encoding),
+            end: line_index.to_position(location.end, self.position_encoding),
+        })
+    }
+
+    /// Resolve the name at a position to its definition, returning the location of
+    /// the name along with the uri of the file that holds the definition and the
+    /// definition itself
+    fn resolve_definition(
+        &self,
+        uri: &str,
+        position: Position,
+    ) -> Option<(symbol::Location, String, Symbol)> {
+        let imports = self.db.document_imports(uri.to_string());
+        let point = (position.line, position.character);
+
+        // The module named by an import statement
+        if let Some(import) = imports
+            .iter()
+            .find(|import| import.module_location.contains(point))
+        {
+            let module_uri = self.db.import_uri(uri.to_string(), import.module.clone())?;
+            let origin = import.module_location.clone();
+            return Some((origin, module_uri, module_symbol(&import.module)));
+        }
+
+        // Names after a dot are looked up on the object before it. They aren't
+        // references, so nothing is found when the type of the object is unknown.
+        if let Some(attribute) = self.resolve_attribute(uri, &position) {
+            return Some(attribute);
+        }
+
+        let reference_graph = self.db.document_reference_graph(uri.to_string())?;
+        let reference = reference_graph.get_symbol_by_location(position.clone())?;
+        let origin = reference.location.clone();
+        if let Some(definition) = reference_graph.find_definition(position) {
+            // Imported names are defined wherever the import points to
+            if let Some(import) = imports
+                .iter()
+                .find(|import| import.location == definition.location)
+            {
+                if let Some((imported_uri, imported)) =
+                    self.db.import_definition(uri.to_string(), import.clone())
+                {
+                    return Some((origin, imported_uri, imported));
+                }
+            }
+            return Some((origin, uri.to_string(), definition.clone()));
+        }
+
+        // Names that aren't defined in the document may come
+        // from a wildcard import, or otherwise be builtins
+        let (definition_uri, definition) = self
+            .db
+            .exported_definition(uri.to_string(), reference.name.clone())
+            .or_else(|| {
+                self.db
+                    .module_definition("builtins".to_string(), reference.name.clone())
+            })?;
+        Some((origin, definition_uri, definition))
+    }
+
+    pub fn get_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Option<language_features::Location> {
+        let link = self.get_definition_link(params)?;
+        Some(language_features::Location {
+            uri: link.target_uri,
+            range: link.target_selection_range,
+        })
+    }
+
+    /// Get the definition along with the range of the name it was asked for
+    pub fn get_definition_link(&self, params: GotoDefinitionParams) -> Option<LocationLink> {
+        let uri = params.text_document.uri;
+
+        // Get the symbol's definiton
+        let position = self.to_byte_position(&uri, params.position)?;
+        let (origin, definition_uri, symbol_definition) =
+            self.resolve_definition(&uri, position)?;
+
+        // Create the response
+        let range = self.to_range(&definition_uri, &symbol_definition.location)?;
+        Some(LocationLink {
+            origin_selection_range: self.to_range(&uri, &origin),
+            target_uri: definition_uri,
+            target_range: range.clone(),
+            target_selection_range: range,
+        })
+    }
+
+    /// Get the classes of the inferred type of the name at a position. Generic
+    /// classes come with the classes of their type arguments, and unions with
+    /// the classes of each member.
+    pub fn get_type_definitions(
+        &self,
+        params: TypeDefinitionParams,
+    ) -> Option<Vec<language_features::Location>> {
+        let uri = params.text_document.uri;
+        let position = self.to_byte_position(&uri, params.position)?;
+        let tree = self.db.document_tree(uri.clone())?;
+
+        let point = (position.line, position.character);
+        let expr = tree.expression_at(point)?;
+        let name_location = match &expr {
+            Expr::Name(_, location) | Expr::Attribute(_, _, location) => location,
+            Expr::Narrowed(name, _) | Expr::NotNone(name) => match name.as_ref() {
+                Expr::Name(_, location) => location,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        if !name_location.contains(point) {
+            return None;
+        }
+
+        let expr_type = self.db.type_of(uri, expr);
+        Some(
+            expr_type
+                .classes()
+                .into_iter()
+                .filter_map(|(uri, class)| self.to_location(uri, &class.location))
+                .collect(),
+        )
+    }
+
+    /// Resolve the name after a dot through the inferred type of the object it is
+    /// accessed on, returning the location of the name along with the definition
+    fn resolve_attribute(
+        &self,
+        uri: &str,
+        position: &Position,
+    ) -> Option<(symbol::Location, String, Symbol)> {
+        let tree = self.db.document_tree(uri.to_string())?;
+
+        let point = (position.line, position.character);
+        let Expr::Attribute(object, name, location) = tree.expression_at(point)? else {
+            return None;
+        };
+        if !location.contains(point) {
+            return None;
+        }
+        let object = self.db.type_of(uri.to_string(), *object);
+        let (definition_uri, definition) = inference::member_definition(&*self.db, &object, &name)?;
+        Some((location, definition_uri, definition))
+    }
+
+    pub fn get_hover(&self, params: HoverParams) -> Option<Hover> {
+        let uri = params.text_document.uri;
+        let position = self.to_byte_position(&uri, params.position)?;
+
+        let (location, definition_uri, definition) = self.resolve_definition(&uri, position)?;
+
+        let signature = match definition.kind {
+            SymbolKind::Module => format!("module {}", definition.name),
+            // Variables are shown with their type, when it is known
+            SymbolKind::Variable | SymbolKind::Parameter => {
+                let name = definition.name.clone();
+                match self
+                    .db
+                    .definition_type(definition_uri.clone(), definition.clone())
+                {
+                    Type::Unknown => {
+                        let text = self.db.source_text(definition_uri)?;
+                        definition_source(&text, &definition)
+                    }
+                    definition_type => format!("{}: {}", name, definition_type),
+                }
+            }
+            _ => {
+                let text = self.db.source_text(definition_uri)?;
+                definition_source(&text, &definition)
+            }
+        };
+
+        Some(Hover {
+            contents: self.code_markup(signature),
+            range: self.to_range(&uri, &location),
+        })
+    }
+
+    /// Show code as a python block to clients that render markdown
+    fn code_markup(&self, code: String) -> MarkupContent {
+        match self.client_capabilities.hover_markdown() {
+            true => MarkupContent {
+                kind: "markdown".to_string(),
+                value: format!("```python\n{}\n```", code),
+            },
+            false => MarkupContent {
+                kind: "plaintext".to_string(),
+                value: code,
+            },
+        }
+    }
+
+    /// Get the diagnostics of a document, with the configured severities.
+    /// Rules that are turned off are left out.
+    pub fn get_diagnostics(&self, uri: &str) -> Vec<Diagnostic> {
+        let configured = self.settings.diagnostics.as_ref();
+        self.db
+            .document_diagnostics(uri.to_string())
+            .iter()
+            .filter_map(|diagnostic| {
+                // See `DiagnosticSeverity` in the LSP specification
+                let severity = match diagnostics::severity(diagnostic.rule, configured) {
+                    Severity::Error => 1,
+                    Severity::Warning => 2,
+                    Severity::Information => 3,
+                    Severity::Hint => 4,
+                    Severity::None => return None,
+                };
+                Some(Diagnostic {
+                    range: self.to_range(uri, &diagnostic.location)?,
+                    severity: Some(severity),
+                    code: Some(diagnostic.rule.to_string()),
+                    source: Some(env!("CARGO_PKG_NAME").to_string()),
+                    message: diagnostic.message.clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_completions(&self, params: CompletionParams) -> Option<Vec<CompletionItem>> {
+        let uri = params.text_document.uri;
+        let position = self.to_byte_position(&uri, params.position)?;
+        let tree = self.db.document_tree(uri.clone())?;
+        let text = tree.source();
+        let snippets = self.client_capabilities.completion_snippets();
+
+        // After a dot only the attributes of the object are completed
+        let line = text.lines().nth(position.line).unwrap_or_default();
+        let before = line.get(..position.character).unwrap_or(line);
+        let name_start = before
+            .trim_end_matches(|c: char| c.is_alphanumeric() || c == '_')
+            .len();
+        if before[..name_start].ends_with('.') {
+            let point = (position.line, name_start);
+            return Some(self.member_completions(&uri, text, point, snippets));
+        }
+
+        // Find out which function scopes the cursor is in
+        let scope_path = tree.scope_at((position.line, position.character));
+        let mut seen = HashSet::new();
+        let mut items = Vec::new();
+
+        let reference_graph = self.db.document_reference_graph(uri)?;
+        for definition in reference_graph.visible_definitions(&scope_path) {
+            if seen.insert(definition.name.clone()) {
+                items.push(completion_item(definition, None, snippets));
+            }
+        }
+
+        // Then everything builtins has to offer
+        let builtins = self
+            .db
+            .module_uri("builtins".to_string())
+            .and_then(|uri| self.db.document_reference_graph(uri));
+        if let Some(builtins) = builtins {
+            for definition in builtins.visible_definitions(&["module".to_string()]) {
+                if !definition.name.starts_with('_') && seen.insert(definition.name.clone()) {
+                    items.push(completion_item(definition, Some("builtins"), snippets));
+                }
+            }
+        }
+
+        Some(items)
+    }
+
+    /// Complete the attributes of the object before the dot ending at `point`,
+    /// leaving out dunder methods
+    fn member_completions(
+        &self,
+        uri: &str,
+        text: &str,
+        point: (usize, usize),
+        snippets: bool,
+    ) -> Vec<CompletionItem> {
+        // A dot without a name after it doesn't parse as an attribute,
+        // so one is put in to find the object
+        let offset: usize = text
+            .split_inclusive('\n')
+            .take(point.0)
+            .map(str::len)
+            .sum::<usize>()
+            + point.1;
+        let mut patched = text.to_string();
+        patched.insert_str(offset, "__completion__");
+
+        let Ok(mut parser) = TreeSitterParser::new() else {
+            return Vec::new();
+        };
+        parser.set_python_version(self.db.python_version());
+        parser.set_python_platform(self.db.python_platform());
+        let Some(Expr::Attribute(object, ..)) = parser
+            .parse_tree(Arc::new(patched))
+            .and_then(|tree| tree.expression_at(point))
+        else {
+            return Vec::new();
+        };
+        let object = self.db.type_of(uri.to_string(), *object);
+        inference::members(&*self.db, &object)
+            .iter()
+            .filter(|(_, member)| !member.name.starts_with("__"))
+            .map(|(_, member)| completion_item(member, None, snippets))
+            .collect()
+    }
+
+    /// Get the occurrences of the name at a position in its document. Definitions
+    /// are writes, and references reads.
+    pub fn get_document_highlights(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Option<Vec<DocumentHighlight>> {
+        let uri = params.text_document.uri;
+        let position = self.to_byte_position(&uri, params.position)?;
+
+        let reference_graph = self.db.document_reference_graph(uri.clone())?;
+        let (definitions, references) = reference_graph.find_occurrences(position)?;
+
+        // See `DocumentHighlightKind` in the LSP specification
+        let writes = definitions
+            .iter()
+            .map(|definition| (&definition.location, 3));
+        let reads = references.iter().map(|reference| (&reference.location, 2));
+        let mut highlights: Vec<DocumentHighlight> = writes
+            .chain(reads)
+            .filter_map(|(location, kind)| {
+                Some(DocumentHighlight {
+                    range: self.to_range(&uri, location)?,
+                    kind: Some(kind),
+                })
+            })
+            .collect();
+        highlights
+            .sort_by_key(|highlight| (highlight.range.start.line, highlight.range.start.character));
+        Some(highlights)
+    }
+
+    /// Get the semantic tokens of a whole document
+    pub fn get_semantic_tokens(&self, uri: &str) -> Option<SemanticTokens> {
+        let tokens = self.db.document_tokens(uri.to_string());
+        let data = self.encode_tokens(uri, &tokens)?;
+        Some(SemanticTokens {
+            result_id: Some(self.sent_tokens.remember(uri, data.clone())),
+            data,
+        })
+    }
+
+    /// Get the semantic tokens of the part of a document the client shows
+    pub fn get_semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Option<SemanticTokens> {
+        let uri = params.text_document.uri;
+        let start = self.to_byte_position(&uri, params.range.start)?;
+        let end = self.to_byte_position(&uri, params.range.end)?;
+        let (start, end) = ((start.line, start.character), (end.line, end.character));
+
+        let tokens: Vec<Token> = self
+            .db
+            .document_tokens(uri.clone())
+            .iter()
+            .filter(|token| token.location.end > start && token.location.start < end)
+            .cloned()
+            .collect();
+        Some(SemanticTokens {
+            result_id: None,
+            data: self.encode_tokens(&uri, &tokens)?,
+        })
+    }
+
+    /// Get the changes to the semantic tokens since the ones with the given result
+    /// id, or all of them if those aren't the last ones sent for the document
+    pub fn get_semantic_tokens_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Option<SemanticTokensDeltaResponse> {
+        let uri = params.text_document.uri;
+        let Some(previous) = self.sent_tokens.previous(&uri, &params.previous_result_id) else {
+            return Some(SemanticTokensDeltaResponse::Full(
+                self.get_semantic_tokens(&uri)?,
+            ));
+        };
+
+        let tokens = self.db.document_tokens(uri.clone());
+        let data = self.encode_tokens(&uri, &tokens)?;
+        let edits = token_edits(&previous, &data);
+        Some(SemanticTokensDeltaResponse::Delta(SemanticTokensDelta {
+            result_id: Some(self.sent_tokens.remember(&uri, data)),
+            edits,
+        }))
+    }
+
+    /// Encode tokens in the negotiated encoding, each relative to the one before it.
+    /// Clients don't accept tokens that overlap, so of the tokens that start at the
+    /// same position only the first is kept.
+    fn encode_tokens(&self, uri: &str, tokens: &[Token]) -> Option<Vec<u32>> {
+        let line_index = self.db.document_line_index(uri.to_string())?;
+
+        let mut data = Vec::with_capacity(tokens.len() * 5);
+        let (mut previous_line, mut previous_start) = (0, 0);
+        let mut previous_token: Option<&Token> = None;
+        for token in tokens {
+            if previous_token
+                .is_some_and(|previous| previous.location.start == token.location.start)
+            {
+                continue;
+            }
+            previous_token = Some(token);
+            let start = line_index.to_position(token.location.start, self.position_encoding);
+            let end = line_index.to_position(token.location.end, self.position_encoding);
+            if start.line != end.line {
+                continue;
+            }
+
+            let delta_start = match start.line == previous_line {
+                true => start.character - previous_start,
+                false => start.character,
+            };
+            data.extend([
+                (start.line - previous_line) as u32,
+                delta_start as u32,
+                (end.character - start.character) as u32,
+                token.token_type as u32,
+                token.modifiers,
+            ]);
+            (previous_line, previous_start) = (start.line, start.character);
+        }
+        Some(data)
+    }
+
+    /// Get the lines of a document that can be folded
+    pub fn get_folding_ranges(&self, params: FoldingRangeParams) -> Option<Vec<FoldingRange>> {
+        let tree = self.db.document_tree(params.text_document.uri)?;
+        let ranges = tree
+            .folding_ranges()
+            .into_iter()
+            .map(|range| FoldingRange {
+                start_line: range.start_line,
+                end_line: range.end_line,
+                kind: range.kind.map(|kind| {
+                    match kind {
+                        FoldKind::Comment => "comment",
+                        FoldKind::Imports => "imports",
+                        FoldKind::Region => "region",
+                    }
+                    .to_string()
+                }),
+            })
+            .collect();
+        Some(ranges)
+    }
+
+    /// Get the ranges a selection expands to from each position, following the syntax tree
+    pub fn get_selection_ranges(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Option<Vec<SelectionRange>> {
+        let uri = params.text_document.uri;
+        let tree = self.db.document_tree(uri.clone())?;
+        let points = params
+            .positions
+            .into_iter()
+            .map(|position| {
+                let position = self.to_byte_position(&uri, position)?;
+                Some((position.line, position.character))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        tree.selection_ranges(&points)
+            .into_iter()
+            .map(|locations| {
+                // Build the chain from the outermost range in
+                let mut selection: Option<SelectionRange> = None;
+                for location in locations.iter().rev() {
+                    selection = Some(SelectionRange {
+                        range: self.to_range(&uri, location)?,
+                        parent: selection.map(Box::new),
+                    });
+                }
+                selection
+            })
+            .collect()
+    }
+
+    /// Get the definition at a position, named either by the definition itself or a
+    /// reference to it. Imported names are followed to what they import.
+    fn definition_at(&self, uri: &str, position: Position) -> Option<(String, Symbol)> {
+        if let Some((_, definition_uri, definition)) =
+            self.resolve_definition(uri, position.clone())
+        {
+            return Some((definition_uri, definition));
+        }
+
+        let reference_graph = self.db.document_reference_graph(uri.to_string())?;
+        let definition = reference_graph.get_definition_by_location(position)?;
+        let imports = self.db.document_imports(uri.to_string());
+        match imports
+            .iter()
+            .find(|import| import.location == definition.location)
+        {
+            Some(import) => self.db.import_definition(uri.to_string(), import.clone()),
+            None => Some((uri.to_string(), definition.clone())),
+        }
+    }
+
+    /// Get the class at a position, named either by its definition or a reference to it
+    pub fn prepare_type_hierarchy(
+        &self,
+        params: TypeHierarchyPrepareParams,
+    ) -> Option<Vec<TypeHierarchyItem>> {
+        let uri = params.text_document.uri;
+        let position = self.to_byte_position(&uri, params.position)?;
+        let (class_uri, class) = self.definition_at(&uri, position)?;
+        if class.kind != SymbolKind::Class {
+            return None;
+        }
+        Some(vec![self.hierarchy_item(&class_uri, &class)?])
+    }
+
+    /// Get the classes the class of an item derives from directly
+    pub fn get_supertypes(
+        &self,
+        params: TypeHierarchySupertypesParams,
+    ) -> Option<Vec<TypeHierarchyItem>> {
+        let class = self
+            .hierarchy_definition(&params.item)
+            .filter(|class| class.kind == SymbolKind::Class)?;
+        let bases = self.db.class_bases(params.item.uri, class);
+        Some(
+            bases
+                .iter()
+                .filter_map(|(uri, base)| self.hierarchy_item(uri, base))
+                .collect(),
+        )
+    }
+
+    /// Get the classes of the workspace and the open documents
+    /// that derive from the class of an item directly
+    pub fn get_subtypes(
+        &self,
+        params: TypeHierarchySubtypesParams,
+    ) -> Option<Vec<TypeHierarchyItem>> {
+        let class = self
+            .hierarchy_definition(&params.item)
+            .filter(|class| class.kind == SymbolKind::Class)?;
+        let target = (params.item.uri, class);
+
+        let subtypes = self
+            .workspace_classes()
+            .into_iter()
+            .filter(|(uri, class)| {
+                let bases = self.db.class_bases(uri.clone(), class.clone());
+                bases.contains(&target)
+            })
+            .filter_map(|(uri, class)| self.hierarchy_item(&uri, &class))
+            .collect();
+        Some(subtypes)
+    }
+
+    /// Get the uris of the files of the workspace and of the open documents
+    fn workspace_documents(&self) -> Vec<String> {
+        let mut uris: Vec<String> = self.db.workspace_files().to_vec();
+        uris.extend(self.db.open_documents().iter().cloned());
+        uris.sort();
+        uris.dedup();
+        uris
+    }
+
+    /// Get the function or class at a position, named either by its definition
+    /// or a reference to it
+    pub fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Option<Vec<CallHierarchyItem>> {
+        let uri = params.text_document.uri;
+        let position = self.to_byte_position(&uri, params.position)?;
+        let (definition_uri, definition) = self.definition_at(&uri, position)?;
+        if !matches!(definition.kind, SymbolKind::Function | SymbolKind::Class) {
+            return None;
+        }
+        Some(vec![self.hierarchy_item(&definition_uri, &definition)?])
+    }
+
+    /// Get the functions of the workspace that call the definition of an item, each
+    /// with the calls it makes. Calls at the module level are grouped by module.
+    pub fn get_incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Option<Vec<CallHierarchyIncomingCall>> {
+        let definition = self.hierarchy_definition(&params.item)?;
+        let target = (params.item.uri, definition);
+
+        let mut incoming_calls = Vec::new();
+        for uri in self.workspace_documents() {
+            let calls = self.db.document_calls(uri.clone());
+            let mut callers: Vec<(Option<&symbol::Location>, Vec<Range>)> = Vec::new();
+            for call in calls.iter().filter(|call| call.callee == target) {
+                let Some(range) = self.to_range(&uri, &call.location) else {
+                    continue;
+                };
+                match callers
+                    .iter_mut()
+                    .find(|(caller, _)| *caller == call.caller.as_ref())
+                {
+                    Some((_, ranges)) => ranges.push(range),
+                    None => callers.push((call.caller.as_ref(), vec![range])),
+                }
+            }
+            if callers.is_empty() {
+                continue;
+            }
+
+            // One document that can't be read doesn't hide the calls of the others
+            let Some(reference_graph) = self.db.document_reference_graph(uri.clone()) else {
+                continue;
+            };
+            for (caller, from_ranges) in callers {
+                let caller = match caller {
+                    Some(caller) => reference_graph
+                        .definitions()
+                        .find(|definition| definition.location == *caller)
+                        .cloned(),
+                    None => self.module_name(&uri).map(|name| module_symbol(&name)),
+                };
+                let Some(from) = caller.and_then(|caller| self.hierarchy_item(&uri, &caller))
+                else {
+                    continue;
+                };
+                incoming_calls.push(CallHierarchyIncomingCall { from, from_ranges });
+            }
+        }
+        Some(incoming_calls)
+    }
+
+    /// Get the definitions the function of an item calls, each with the calls to it
+    pub fn get_outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Option<Vec<CallHierarchyOutgoingCall>> {
+        let uri = params.item.uri.clone();
+        let definition = self
+            .hierarchy_definition(&params.item)
+            .filter(|definition| definition.kind == SymbolKind::Function)?;
+
+        let calls = self.db.document_calls(uri.clone());
+        let mut callees: Vec<(&(String, Symbol), Vec<Range>)> = Vec::new();
+        for call in calls
+            .iter()
+            .filter(|call| call.caller.as_ref() == Some(&definition.location))
+        {
+            let Some(range) = self.to_range(&uri, &call.location) else {
+                continue;
+            };
+            match callees
+                .iter_mut()
+                .find(|(callee, _)| *callee == &call.callee)
+            {
+                Some((_, ranges)) => ranges.push(range),
+                None => callees.push((&call.callee, vec![range])),
+            }
+        }
+
+        Some(
+            callees
+                .into_iter()
+                .filter_map(|((callee_uri, callee), from_ranges)| {
+                    let to = self.hierarchy_item(callee_uri, callee)?;
+                    Some(CallHierarchyOutgoingCall { to, from_ranges })
+                })
+                .collect(),
+        )
+    }
+
+    /// Get the name of the module of a document, as it is shown in hierarchies
+    fn module_name(&self, uri: &str) -> Option<String> {
+        let path = uri_to_path(uri)?;
+        Some(path.file_stem()?.to_string_lossy().to_string())
+    }
+
+    /// Get the classes of the workspace and the open documents, in the order of
+    /// their documents and of where they are defined
+    fn workspace_classes(&self) -> Vec<(String, Symbol)> {
+        let mut classes = Vec::new();
+        for uri in self.workspace_documents() {
+            let Some(reference_graph) = self.db.document_reference_graph(uri.clone()) else {
+                continue;
+            };
+            let mut document_classes: Vec<&Symbol> = reference_graph
+                .definitions()
+                .filter(|definition| definition.kind == SymbolKind::Class)
+                .collect();
+            document_classes.sort_by_key(|class| class.location.start);
+            classes.extend(
+                document_classes
+                    .into_iter()
+                    .map(|class| (uri.clone(), class.clone())),
+            );
+        }
+        classes
+    }
+
+    /// Get the subclasses of every class, in the order of the workspace classes
+    fn workspace_subclasses(&self) -> Subclasses {
+        let mut subclasses = Subclasses::new();
+        for (uri, class) in self.workspace_classes() {
+            let mro = self.db.class_mro(uri.clone(), class.clone());
+            for base in &mro[1..] {
+                let subclass = (uri.clone(), class.clone());
+                subclasses.entry(base.clone()).or_default().push(subclass);
+            }
+        }
+        subclasses
+    }
+
+    /// Get the class a method is defined in
+    fn method_class(&self, uri: &str, method: &Symbol) -> Option<Symbol> {
+        let bindings = self.db.document_bindings(uri.to_string());
+        let class_location = bindings.function(&method.location)?.class.clone()?;
+        let reference_graph = self.db.document_reference_graph(uri.to_string())?;
+        let class = reference_graph
+            .definitions()
+            .find(|definition| definition.location == class_location);
+        class.cloned()
+    }
+
+    /// Find the method a class defines itself by name
+    fn own_method(&self, uri: &str, class: &Symbol, name: &str) -> Option<Symbol> {
+        let bindings = self.db.document_bindings(uri.to_string());
+        let member = bindings
+            .class(&class.location)?
+            .members
+            .iter()
+            .find(|member| member.name == name && member.kind == SymbolKind::Function);
+        member.cloned()
+    }
+
+    /// Get the methods of subclasses that override a method
+    fn overrides(
+        &self,
+        subclasses: &Subclasses,
+        uri: &str,
+        method: &Symbol,
+    ) -> Vec<(String, Symbol)> {
+        let Some(class) = self.method_class(uri, method) else {
+            return Vec::new();
+        };
+        let Some(subclasses) = subclasses.get(&(uri.to_string(), class)) else {
+            return Vec::new();
+        };
+        subclasses
+            .iter()
+            .filter_map(|(uri, subclass)| {
+                let method = self.own_method(uri, subclass, &method.name)?;
+                Some((uri.clone(), method))
+            })
+            .collect()
+    }
+
+    /// Find the method a method overrides, along with the class that defines it
+    fn overridden(&self, uri: &str, method: &Symbol) -> Option<(String, Symbol, Symbol)> {
+        let class = self.method_class(uri, method)?;
+        let mro = self.db.class_mro(uri.to_string(), class);
+        mro.iter().skip(1).find_map(|(base_uri, base)| {
+            let overridden = self.own_method(base_uri, base, &method.name)?;
+            Some((base_uri.clone(), base.clone(), overridden))
+        })
+    }
+
+    /// Get the overrides of the method at a position, or the subclasses of the class
+    pub fn get_implementations(
+        &self,
+        params: ImplementationParams,
+    ) -> Option<Vec<language_features::Location>> {
+        let uri = params.text_document.uri;
+        let position = self.to_byte_position(&uri, params.position)?;
+        let (definition_uri, definition) = self.definition_at(&uri, position)?;
+
+        let mut subclasses = match definition.kind {
+            SymbolKind::Class | SymbolKind::Function => self.workspace_subclasses(),
+            _ => return None,
+        };
+        let implementations = match definition.kind {
+            SymbolKind::Class => subclasses
+                .remove(&(definition_uri, definition))
+                .unwrap_or_default(),
+            _ => self.overrides(&subclasses, &definition_uri, &definition),
+        };
+        Some(
+            implementations
+                .iter()
+                .filter_map(|(uri, implementation)| self.to_location(uri, &implementation.location))
+                .collect(),
+        )
+    }
+
+    /// Get the lenses of the methods of a document: the method each one overrides,
+    /// and how many times it is overridden. They run the server's command for
+    /// showing locations.
+    pub fn get_code_lenses(&self, params: CodeLensParams) -> Option<Vec<CodeLens>> {
+        let uri = params.text_document.uri;
+        let reference_graph = self.db.document_reference_graph(uri.clone())?;
+        let mut methods: Vec<&Symbol> = reference_graph
+            .definitions()
+            .filter(|definition| definition.kind == SymbolKind::Function)
+            .collect();
+        methods.sort_by_key(|method| method.location.start);
+
+        let subclasses = self.workspace_subclasses();
+        let mut lenses = Vec::new();
+        for method in methods {
+            let Some(range) = self.to_range(&uri, &method.location) else {
+                continue;
+            };
+            let show = |title: String, targets: Vec<(String, Symbol)>| {
+                let locations: Vec<language_features::Location> = targets
+                    .iter()
+                    .filter_map(|(uri, target)| self.to_location(uri, &target.location))
+                    .collect();
+                let arguments = vec![json!(title), json!(locations)];
+                CodeLens {
+                    range: range.clone(),
+                    command: Some(Command {
+                        title,
+                        command: SHOW_LOCATIONS_COMMAND.to_string(),
+                        arguments: Some(arguments),
+                    }),
+                }
+            };
+
+            if let Some((base_uri, base, overridden)) = self.overridden(&uri, method) {
+                let title = format!("overrides {}.{}", base.name, overridden.name);
+                lenses.push(show(title, vec![(base_uri, overridden)]));
+            }
+            let overrides = self.overrides(&subclasses, &uri, method);
+            let title = match overrides.len() {
+                0 => continue,
+                1 => "1 override".to_string(),
+                count => format!("{} overrides", count),
+            };
+            lenses.push(show(title, overrides));
+        }
+        Some(lenses)
+    }
+
+    /// Get the inlay hints of a range: the inferred types of unannotated variables
+    /// and return types, and the parameters positional arguments are passed to.
+    /// Their data holds the definitions the label names, for resolving them.
+    pub fn get_inlay_hints(&self, params: InlayHintParams) -> Option<Vec<InlayHint>> {
+        let uri = params.text_document.uri;
+        let start = self.to_byte_position(&uri, params.range.start)?;
+        let end = self.to_byte_position(&uri, params.range.end)?;
+        let visible = |location: &symbol::Location| {
+            location.start >= (start.line, start.character)
+                && location.start <= (end.line, end.character)
+        };
+        let settings = self.settings.inlay_hints.clone().unwrap_or_default();
+        let bindings = self.db.document_bindings(uri.clone());
+        let reference_graph = self.db.document_reference_graph(uri.clone())?;
+
+        let mut hints = Vec::new();
+        for definition in reference_graph.definitions() {
+            if !visible(&definition.location) {
+                continue;
+            }
+            match bindings.get(&definition.location) {
+                Some(Binding::Variable {
+                    annotation: None,
+                    value: Some(_),
+                }) if settings.variable_types != Some(false) => {
+                    let variable_type = self.db.definition_type(uri.clone(), definition.clone());
+                    let hint = self.type_hint(&uri, definition.location.end, ": ", &variable_type);
+                    hints.extend(hint);
+                }
+                Some(Binding::Function(function))
+                    if function.returns.is_none() && settings.return_types != Some(false) =>
+                {
+                    let return_type = self.db.return_type(uri.clone(), definition.clone());
+                    let hint = self.type_hint(&uri, function.parameters_end, " -> ", &return_type);
+                    hints.extend(hint);
+                }
+                _ => {}
+            }
+        }
+
+        if settings.parameter_names != Some(false) {
+            for call in self.db.document_calls(uri.clone()).iter() {
+                if visible(&call.location) {
+                    hints.extend(self.parameter_hints(&uri, call));
+                }
+            }
+        }
+        hints.sort_by_key(|hint| (hint.position.line, hint.position.character));
+        Some(hints)
+    }
+
+    /// Make a hint showing a type, unless it isn't known
+    fn type_hint(
+        &self,
+        uri: &str,
+        position: (usize, usize),
+        separator: &str,
+        hint_type: &Type,
+    ) -> Option<InlayHint> {
+        if *hint_type == Type::Unknown {
+            return None;
+        }
+        let definitions = hint_type
+            .classes()
+            .into_iter()
+            .map(|(uri, class)| (uri.to_string(), class.clone()))
+            .collect();
+        Some(InlayHint {
+            position: self.to_position(uri, position)?,
+            label: InlayHintLabel::String(format!("{}{}", separator, hint_type)),
+            kind: Some(1),
+            tooltip: None,
+            padding_left: false,
+            padding_right: false,
+            data: Some(json!(InlayHintData { definitions })),
+        })
+    }
+
+    /// Make hints naming the parameters the positional arguments of a call are
+    /// passed to. Arguments that are names of their parameter go without one.
+    fn parameter_hints(&self, uri: &str, call: &CallSite) -> Vec<InlayHint> {
+        let Some((function_uri, function, bound)) = calls::called_function(&*self.db, call) else {
+            return Vec::new();
+        };
+        let Some(reference_graph) = self.db.document_reference_graph(function_uri.clone()) else {
+            return Vec::new();
+        };
+        let Some(function) = synthesis::function_binding(&*self.db, &function_uri, &function)
+        else {
+            return Vec::new();
+        };
+
+        let parameters = function.parameters.iter().skip(bound as usize);
+        let mut hints = Vec::new();
+        for ((location, argument), parameter) in call.arguments.iter().zip(parameters) {
+            let Argument::Positional(value) = argument else {
+                break;
+            };
+            if !matches!(
+                parameter.kind,
+                ParameterKind::PositionalOnly | ParameterKind::Positional
+            ) {
+                break;
+            }
+            if matches!(value, Expr::Name(name, _) if *name == parameter.name) {
+                continue;
+            }
+            let Some(position) = self.to_position(uri, location.start) else {
+                continue;
+            };
+            let definitions = reference_graph
+                .definitions()
+                .filter(|definition| definition.location == parameter.location)
+                .map(|definition| (function_uri.clone(), definition.clone()))
+                .collect();
+            hints.push(InlayHint {
+                position,
+                label: InlayHintLabel::String(format!("{}:", parameter.name)),
+                kind: Some(2),
+                tooltip: None,
+                padding_left: false,
+                padding_right: true,
+                data: Some(json!(InlayHintData { definitions })),
+            });
+        }
+        hints
+    }
+
+    /// Turn the label of an inlay hint into parts, the names of definitions linking
+    /// to them, and give it a tooltip with their source
+    pub fn resolve_inlay_hint(&self, mut hint: InlayHint) -> InlayHint {
+        let Some(data) = hint.data.take() else {
+            return hint;
+        };
+        let Ok(InlayHintData { definitions }) = serde_json::from_value(data) else {
+            return hint;
+        };
+        let InlayHintLabel::String(label) = &hint.label else {
+            return hint;
+        };
+
+        let sources: Vec<String> = definitions
+            .iter()
+            .filter_map(|(uri, definition)| {
+                let text = self.db.source_text(uri.clone())?;
+                Some(definition_source(&text, definition))
+            })
+            .collect();
+        let mut parts: Vec<InlayHintLabelPart> = Vec::new();
+        for word in label_words(label) {
+            let definition = definitions
+                .iter()
+                .zip(&sources)
+                .find(|((_, definition), _)| definition.name == word);
+            match (definition, parts.last_mut()) {
+                (Some(((uri, definition), source)), _) => parts.push(InlayHintLabelPart {
+                    value: word.to_string(),
+                    tooltip: Some(self.code_markup(source.clone())),
+                    location: self.to_location(uri, &definition.location),
+                }),
+                (None, Some(last)) if last.location.is_none() => last.value.push_str(word),
+                (None, _) => parts.push(InlayHintLabelPart {
+                    value: word.to_string(),
+                    tooltip: None,
+                    location: None,
+                }),
+            }
+        }
+
+        if !sources.is_empty() {
+            hint.tooltip = Some(self.code_markup(sources.join("\n\n")));
+        }
+        hint.label = InlayHintLabel::Parts(parts);
+        hint
+    }
+
+    /// Convert a parser point into a position in the negotiated encoding
+    fn to_position(&self, uri: &str, point: (usize, usize)) -> Option<Position> {
+        let line_index = self.db.document_line_index(uri.to_string())?;
+        Some(line_index.to_position(point, self.position_encoding))
+    }
+
+    fn to_location(
+        &self,
+        uri: &str,
+        location: &symbol::Location,
+    ) -> Option<language_features::Location> {
+        Some(language_features::Location {
+            uri: uri.to_string(),
+            range: self.to_range(uri, location)?,
+        })
+    }
+
+    /// Find the definition a hierarchy item was made for
+    fn hierarchy_definition(&self, item: &HierarchyItem) -> Option<Symbol> {
+        let position = self.to_byte_position(&item.uri, item.selection_range.start.clone())?;
+        let reference_graph = self.db.document_reference_graph(item.uri.clone())?;
+        let definition = reference_graph
+            .definitions()
+            .find(|definition| definition.location.start == (position.line, position.character));
+        definition.cloned()
+    }
+
+    fn hierarchy_item(&self, uri: &str, definition: &Symbol) -> Option<HierarchyItem> {
+        let tree = self.db.document_tree(uri.to_string())?;
+
+        // The outline has the extent of the whole definition
+        let mut outline = tree.outline();
+        let mut location = definition.location.clone();
+        let mut in_class = false;
+        while let Some(symbol) = outline.pop() {
+            if let Some(child) = symbol
+                .children
+                .iter()
+                .find(|child| child.name_location == definition.location)
+            {
+                in_class = symbol.kind == SymbolKind::Class;
+                location = child.location.clone();
+                break;
+            }
+            if symbol.name_location == definition.location {
+                location = symbol.location;
+                break;
+            }
+            outline.extend(symbol.children);
+        }
+
+        // See `SymbolKind` in the LSP specification
+        let kind = match definition.kind {
+            SymbolKind::Module => 2,
+            SymbolKind::Class => 5,
+            SymbolKind::Function if in_class => 6,
+            SymbolKind::Function => 12,
+            _ => 13,
+        };
+        let file_name =
+            uri_to_path(uri).and_then(|path| Some(path.file_name()?.to_string_lossy().to_string()));
+        Some(HierarchyItem {
+            name: definition.name.clone(),
+            kind,
+            detail: file_name,
+            uri: uri.to_string(),
+            range: self.to_range(uri, &location)?,
+            selection_range: self.to_range(uri, &definition.location)?,
+        })
+    }
+
+    /// Get the classes, functions and variables of a document, nested
+    /// for clients that support it and with container names otherwise
+    pub fn get_document_symbols(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Option<DocumentSymbolResponse> {
+        let uri = params.text_document.uri;
+        let outline = self.db.document_tree(uri.clone())?.outline();
+
+        match self.client_capabilities.hierarchical_document_symbols() {
+            true => Some(DocumentSymbolResponse::Nested(
+                self.document_symbols(&uri, &outline, false),
+            )),
+            false => {
+                let mut symbols = Vec::new();
+                self.symbol_information(&uri, &outline, None, &mut symbols);
+                Some(DocumentSymbolResponse::Flat(symbols))
+            }
+        }
+    }
+
+    fn document_symbols(
+        &self,
+        uri: &str,
+        outline: &[OutlineSymbol],
+        in_class: bool,
+    ) -> Vec<DocumentSymbol> {
+        outline
+            .iter()
+            .filter_map(|symbol| {
+                Some(DocumentSymbol {
+                    name: symbol.name.clone(),
+                    kind: document_symbol_kind(symbol, in_class),
+                    range: self.to_range(uri, &symbol.location)?,
+                    selection_range: self.to_range(uri, &symbol.name_location)?,
+                    children: self.document_symbols(
+                        uri,
+                        &symbol.children,
+                        symbol.kind == SymbolKind::Class,
+                    ),
+                })
+            })
+            .collect()
+    }
+
+    fn symbol_information(
+        &self,
+        uri: &str,
+        outline: &[OutlineSymbol],
+        container: Option<&OutlineSymbol>,
+        symbols: &mut Vec<SymbolInformation>,
+    ) {
+        let in_class = container.is_some_and(|container| container.kind == SymbolKind::Class);
+        for symbol in outline {
+            let Some(range) = self.to_range(uri, &symbol.location) else {
+                continue;
+            };
+            symbols.push(SymbolInformation {
+                name: symbol.name.clone(),
+                kind: document_symbol_kind(symbol, in_class),
+                location: language_features::Location {
+                    uri: uri.to_string(),
+                    range,
+                },
+                container_name: container.map(|container| container.name.clone()),
+            });
+            self.symbol_information(uri, &symbol.children, Some(symbol), symbols);
+        }
+    }
+}
+
+/// The edit turning the `previous` tokens into `current` ones: everything
+/// between the part they start with and the part they end with
+fn token_edits(previous: &[u32], current: &[u32]) -> Vec<SemanticTokensEdit> {
+    let prefix = previous
+        .iter()
+        .zip(current)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = previous[prefix..]
+        .iter()
+        .rev()
+        .zip(current[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix == previous.len() && prefix == current.len() {
+        return Vec::new();
+    }
+
+    vec![SemanticTokensEdit {
+        start: prefix as u32,
+        delete_count: (previous.len() - prefix - suffix) as u32,
+        data: current[prefix..current.len() - suffix].to_vec(),
+    }]
+}
+
+/// See `SymbolKind` in the LSP specification
+fn document_symbol_kind(symbol: &OutlineSymbol, in_class: bool) -> u8 {
+    let is_constant = symbol
+        .name
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    match symbol.kind {
+        SymbolKind::Class => 5,
+        SymbolKind::Function if in_class => 6,
+        SymbolKind::Function => 12,
+        SymbolKind::Variable if is_constant => 14,
+        SymbolKind::Variable if in_class => 8,
+        _ => 13,
+    }
+}
+
+/// Split a label into runs of identifier characters and runs of the others
+fn label_words(label: &str) -> Vec<&str> {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_';
+    let mut words = Vec::new();
+    let mut start = 0;
+    for (index, c) in label.char_indices().skip(1) {
+        let previous = label[..index].chars().next_back().unwrap();
+        if is_identifier(c) != is_identifier(previous) {
+            words.push(&label[start..index]);
+            start = index;
+        }
+    }
+    if start < label.len() {
+        words.push(&label[start..]);
+    }
+    words
+}
+
+/// Get the source of a definition for display. For functions and classes this is
+/// the full header, which may span several lines in formatted stubs.
+fn definition_source(text: &str, definition: &Symbol) -> String {
+    let lines = text.lines().skip(definition.location.start.0);
+    match definition.kind {
+        SymbolKind::Function | SymbolKind::Class => {
+            let mut header = Vec::new();
+            let mut indent = None;
+            for line in lines.take(20) {
+                // Dedent relative to the first line of the definition
+                let indent = *indent.get_or_insert(line.len() - line.trim_start().len());
+                let line = line.get(indent..).unwrap_or(line.trim_start()).trim_end();
+                header.push(line);
+                if line.ends_with(':') || line.ends_with("...") {
+                    break;
+                }
+            }
+            header.join("\n")
+        }
+        _ => lines.take(1).map(str::trim).collect(),
+    }
+}
+
+/// Functions and classes are completed with parentheses when the client supports snippets
+fn completion_item(definition: &Symbol, detail: Option<&str>, snippets: bool) -> CompletionItem {
+    // See `CompletionItemKind` in the LSP specification
+    let kind = match definition.kind {
+        SymbolKind::Function => 3,
+        SymbolKind::Variable | SymbolKind::Parameter => 6,
+        SymbolKind::Class => 7,
+        SymbolKind::Module => 9,
+        SymbolKind::TypeParameter => 25,
+        SymbolKind::Unknown => 1,
+    };
+
+    let callable = matches!(definition.kind, SymbolKind::Function | SymbolKind::Class);
+    CompletionItem {
+        label: definition.name.clone(),
+        kind: Some(kind),
+        detail: detail.map(str::to_string),
+        insert_text: (snippets && callable).then(|| format!("{}($0)", definition.name)),
+        insert_text_format: (snippets && callable).then_some(2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::tokens::{TokenType, TOKEN_MODIFIERS, TOKEN_TYPES};
+    use crate::config::InlayHintSettings;
+    use crate::lsp::document_sync::TextDocumentIdentifier;
+    use crate::lsp::language_features::{
+        HierarchyItemParams, TextDocumentParams, TextDocumentPositionParams,
+    };
+    use salsa::ParallelDatabase;
+
+    const URI: &str = "file:///test.py";
+
+    /// A snapshot of a database where a document made of `source_code` is open
+    fn snapshot(source_code: &str) -> LspSnapshot {
+        snapshot_with_settings(source_code, Settings::default())
+    }
+
+    fn snapshot_with_settings(source_code: &str, settings: Settings) -> LspSnapshot {
+        let db = LspDatabase::with_documents(&[(URI, source_code)]);
+        LspSnapshot::new(
+            db.snapshot(),
+            PositionEncoding::Utf16,
+            Arc::default(),
+            Arc::new(settings),
+            Arc::default(),
+        )
+    }
+
+    fn document() -> TextDocumentIdentifier {
+        TextDocumentIdentifier {
+            uri: URI.to_string(),
+        }
+    }
+
+    fn position(line: usize, character: usize) -> TextDocumentPositionParams {
+        TextDocumentPositionParams {
+            text_document: document(),
+            position: Position { line, character },
+        }
+    }
+
+    /// A base class whose methods are overridden in a diamond hierarchy
+    const OVERRIDES: &str = r#"
+class A:
+    def run(self): ...
+    def stop(self): ...
+class B(A): pass
+class C(A):
+    def stop(self): ...
+class D(B, C):
+    def run(self): ...
+    def stop(self): ...
+"#;
+
+    #[test]
+    fn semantic_token_edits() {
+        let previous = [0, 4, 3, 2, 1, 1, 4, 1, 4, 1, 2, 0, 1, 5, 0];
+        // A token inserted in the middle
+        let current = [0, 4, 3, 2, 1, 1, 4, 1, 4, 1, 0, 6, 1, 5, 0, 2, 0, 1, 5, 0];
+        assert_eq!(
+            token_edits(&previous, &current),
+            vec![SemanticTokensEdit {
+                start: 10,
+                delete_count: 0,
+                data: vec![0, 6, 1, 5, 0],
+            }]
+        );
+        assert_eq!(
+            token_edits(&current, &previous),
+            vec![SemanticTokensEdit {
+                start: 10,
+                delete_count: 5,
+                data: vec![],
+            }]
+        );
+        assert!(token_edits(&previous, &previous).is_empty());
+    }
+
+    /// Decode tokens into their absolute position, length, type and modifiers
+    fn decode_tokens(data: &[u32]) -> Vec<(u32, u32, u32, &str, Vec<&str>)> {
+        let (mut line, mut character) = (0, 0);
+        data.chunks(5)
+            .map(|token| {
+                character = match token[0] {
+                    0 => character + token[1],
+                    _ => token[1],
+                };
+                line += token[0];
+                let modifiers = (0..TOKEN_MODIFIERS.len())
+                    .filter(|bit| token[4] & 1 << bit != 0)
+                    .map(|bit| TOKEN_MODIFIERS[bit])
+                    .collect();
+                let token_type = TOKEN_TYPES[token[3] as usize];
+                (line, character, token[2], token_type, modifiers)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn semantic_tokens() {
+        let snapshot = snapshot(
+            "import os\nMAX = 1\n\nclass A:\n    def f(self, x):\n        return len(x)\n\n\
+             A().f(x=MAX)\nos.path\ndef g(): pass\ng()\n",
+        );
+        let data = snapshot.get_semantic_tokens(URI).unwrap().data;
+        assert_eq!(
+            decode_tokens(&data),
+            [
+                (0, 7, 2, "namespace", vec!["declaration"]),
+                (1, 0, 3, "variable", vec!["declaration", "readonly"]),
+                (3, 6, 1, "class", vec!["declaration"]),
+                (4, 8, 1, "method", vec!["declaration"]),
+                (4, 10, 4, "parameter", vec!["declaration"]),
+                (4, 16, 1, "parameter", vec!["declaration"]),
+                (5, 15, 3, "function", vec!["defaultLibrary"]),
+                (5, 19, 1, "parameter", vec![]),
+                (7, 0, 1, "class", vec![]),
+                (7, 4, 1, "method", vec![]),
+                (7, 6, 1, "parameter", vec![]),
+                (7, 8, 3, "variable", vec!["readonly"]),
+                (8, 0, 2, "namespace", vec![]),
+                (8, 3, 4, "property", vec![]),
+                (9, 4, 1, "function", vec!["declaration"]),
+                (10, 0, 1, "function", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn semantic_tokens_at_the_same_position() {
+        let snapshot = snapshot("x = 1\n");
+        let mut tokens = snapshot.db.document_tokens(URI.to_string()).to_vec();
+        let mut property = tokens[0].clone();
+        property.token_type = TokenType::Property;
+        tokens.push(property);
+
+        // Only the first token at a position is sent
+        let data = snapshot.encode_tokens(URI, &tokens).unwrap();
+        assert_eq!(
+            decode_tokens(&data),
+            [(0, 0, 1, "variable", vec!["declaration"])]
+        );
+    }
+
+    #[test]
+    fn implementations() {
+        let snapshot = snapshot(OVERRIDES);
+        let lines = |line, character| -> Vec<usize> {
+            let locations = snapshot
+                .get_implementations(position(line, character))
+                .unwrap();
+            locations
+                .iter()
+                .map(|location| location.range.start.line)
+                .collect()
+        };
+
+        // Methods are overridden in subclasses, directly or not
+        assert_eq!(lines(2, 8), [8]);
+        assert_eq!(lines(3, 8), [6, 9]);
+        assert!(lines(9, 8).is_empty());
+        // Classes are implemented by their subclasses
+        assert_eq!(lines(1, 6), [4, 5, 7]);
+    }
+
+    #[test]
+    fn code_lenses() {
+        let snapshot = snapshot(OVERRIDES);
+        let params = TextDocumentParams {
+            text_document: document(),
+        };
+        let lenses = snapshot.get_code_lenses(params).unwrap();
+        let lenses: Vec<(usize, usize, &str, usize)> = lenses
+            .iter()
+            .map(|lens| {
+                let command = lens.command.as_ref().unwrap();
+                let locations = &command.arguments.as_ref().unwrap()[1];
+                let start = &lens.range.start;
+                let count = locations.as_array().unwrap().len();
+                (start.line, start.character, command.title.as_str(), count)
+            })
+            .collect();
+
+        // `D` looks `stop` up in `C` before `A`, which `B` derives from
+        assert_eq!(
+            lenses,
+            [
+                (2, 8, "1 override", 1),
+                (3, 8, "2 overrides", 2),
+                (6, 8, "overrides A.stop", 1),
+                (6, 8, "1 override", 1),
+                (8, 8, "overrides A.run", 1),
+                (9, 8, "overrides C.stop", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn call_hierarchy() {
+        let snapshot =
+            snapshot("def f(): ...\ndef g():\n    f()\n    f()\ndef h():\n    g()\n    f()\nf()\n");
+        let item = |line, character| {
+            let mut items = snapshot
+                .prepare_call_hierarchy(position(line, character))
+                .unwrap();
+            HierarchyItemParams {
+                item: items.remove(0),
+            }
+        };
+        let lines = |ranges: &[Range]| -> Vec<usize> {
+            ranges.iter().map(|range| range.start.line).collect()
+        };
+
+        // Callers are grouped, with calls at the module level under the module
+        let incoming: Vec<(String, Vec<usize>)> = snapshot
+            .get_incoming_calls(item(0, 4))
+            .unwrap()
+            .iter()
+            .map(|call| (call.from.name.clone(), lines(&call.from_ranges)))
+            .collect();
+        assert_eq!(
+            incoming,
+            [
+                ("g".to_string(), vec![2, 3]),
+                ("h".to_string(), vec![6]),
+                ("test".to_string(), vec![7]),
+            ]
+        );
+
+        // Calls from a reference are those of the definition
+        let outgoing: Vec<(String, Vec<usize>)> = snapshot
+            .get_outgoing_calls(item(5, 4))
+            .unwrap()
+            .iter()
+            .map(|call| (call.to.name.clone(), lines(&call.from_ranges)))
+            .collect();
+        assert_eq!(outgoing, [("f".to_string(), vec![2, 3])]);
+        let outgoing: Vec<String> = snapshot
+            .get_outgoing_calls(item(4, 4))
+            .unwrap()
+            .iter()
+            .map(|call| call.to.name.clone())
+            .collect();
+        assert_eq!(outgoing, ["g", "f"]);
+    }
+
+    #[test]
+    fn type_definitions() {
+        let source_code = r#"
+from typing import Optional, Union
+class A: pass
+class B: pass
+def f(a: A, b: Optional[A], c: Union[A, B], d: list[B]):
+    print(a, b, c, d, e)
+x = A()
+"#;
+        let snapshot = snapshot(source_code);
+        let type_definitions = |line, character| -> Vec<(String, usize)> {
+            let locations = snapshot
+                .get_type_definitions(position(line, character))
+                .unwrap();
+            locations
+                .into_iter()
+                .map(|location| {
+                    let file = location.uri.rsplit('/').next().unwrap().to_string();
+                    (file, location.range.start.line)
+                })
+                .collect()
+        };
+        let test = |line| ("test.py".to_string(), line);
+
+        assert_eq!(type_definitions(5, 10), [test(2)]);
+        assert_eq!(type_definitions(6, 0), [test(2)]);
+        // `None` has no class to go to, unlike each member of a union
+        assert_eq!(type_definitions(5, 13), [test(2)]);
+        assert_eq!(type_definitions(5, 16), [test(2), test(3)]);
+        // Generic classes come with the classes of their type arguments
+        let list = type_definitions(5, 19);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].0, "builtins.pyi");
+        assert_eq!(list[1], test(3));
+        // Names whose type is unknown have no type to go to
+        assert!(type_definitions(5, 22).is_empty());
+    }
+
+    const HINTED: &str = r#"
+class Item: pass
+def make(count, name):
+    return Item()
+items = [make(1, "a")]
+count = 2
+make(count, name="b")
+def annotated(x: int) -> int:
+    y: int = x
+    return y
+"#;
+
+    fn hint_params(start_line: usize, end_line: usize) -> InlayHintParams {
+        let position = |line| Position { line, character: 0 };
+        InlayHintParams {
+            text_document: document(),
+            range: Range {
+                start: position(start_line),
+                end: position(end_line),
+            },
+        }
+    }
+
+    /// The positions, labels and kinds of unresolved inlay hints
+    fn hint_labels(hints: &[InlayHint]) -> Vec<(usize, usize, &str, u8)> {
+        hints
+            .iter()
+            .map(|hint| {
+                let InlayHintLabel::String(label) = &hint.label else {
+                    panic!("Hints get their parts when they are resolved");
+                };
+                let position = &hint.position;
+                (
+                    position.line,
+                    position.character,
+                    label.as_str(),
+                    hint.kind.unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inlay_hints() {
+        let snapshot = snapshot(HINTED);
+
+        // Annotated variables and functions, and arguments named after their
+        // parameter or passed by keyword go without hints
+        let hints = snapshot.get_inlay_hints(hint_params(0, 11)).unwrap();
+        assert_eq!(
+            hint_labels(&hints),
+            [
+                (2, 21, " -> Item", 1),
+                (4, 5, ": list[Item]", 1),
+                (4, 14, "count:", 2),
+                (4, 17, "name:", 2),
+                (5, 5, ": int", 1),
+            ]
+        );
+
+        let hints = snapshot.get_inlay_hints(hint_params(5, 7)).unwrap();
+        assert_eq!(hint_labels(&hints), [(5, 5, ": int", 1)]);
+    }
+
+    #[test]
+    fn inlay_hint_settings() {
+        let hints = |inlay_hints| {
+            let settings = Settings {
+                inlay_hints: Some(inlay_hints),
+                ..Settings::default()
+            };
+            let snapshot = snapshot_with_settings(HINTED, settings);
+            let hints = snapshot.get_inlay_hints(hint_params(0, 11)).unwrap();
+            hint_labels(&hints)
+                .into_iter()
+                .map(|(line, _, label, _)| (line, label.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let labels = |labels: &[(usize, &str)]| {
+            let labels = labels
+                .iter()
+                .map(|(line, label)| (*line, label.to_string()));
+            labels.collect::<Vec<_>>()
+        };
+
+        let types = InlayHintSettings {
+            variable_types: Some(false),
+            return_types: Some(true),
+            parameter_names: None,
+        };
+        assert_eq!(
+            hints(types),
+            labels(&[(2, " -> Item"), (4, "count:"), (4, "name:")])
+        );
+
+        let names = InlayHintSettings {
+            variable_types: None,
+            return_types: Some(false),
+            parameter_names: Some(false),
+        };
+        assert_eq!(hints(names), labels(&[(4, ": list[Item]"), (5, ": int")]));
+    }
+
+    #[test]
+    fn resolve_inlay_hints() {
+        let snapshot = snapshot(HINTED);
+        let mut hints = snapshot.get_inlay_hints(hint_params(4, 5)).unwrap();
+
+        // The classes of a type link to their definitions
+        let hint = snapshot.resolve_inlay_hint(hints.remove(0));
+        let InlayHintLabel::Parts(parts) = &hint.label else {
+            panic!("Resolved hints have parts");
+        };
+        let parts: Vec<(&str, Option<&str>)> = parts
+            .iter()
+            .map(|part| {
+                let uri = part.location.as_ref().map(|location| location.uri.as_str());
+                (part.value.as_str(), uri)
+            })
+            .collect();
+        assert_eq!(parts.len(), 5);
+        assert_eq!(parts[0], (": ", None));
+        assert_eq!(parts[1].0, "list");
+        assert!(parts[1].1.is_some_and(|uri| uri.ends_with("builtins.pyi")));
+        assert_eq!(parts[2..], [("[", None), ("Item", Some(URI)), ("]", None)]);
+        assert!(hint.tooltip.unwrap().value.contains("class Item: pass"));
+        assert!(hint.data.is_none());
+
+        // Parameter names link to the parameter
+        let hint = snapshot.resolve_inlay_hint(hints.remove(0));
+        let InlayHintLabel::Parts(parts) = &hint.label else {
+            panic!("Resolved hints have parts");
+        };
+        let start = &parts[0].location.as_ref().unwrap().range.start;
+        assert_eq!(parts[0].value, "count");
+        assert_eq!((start.line, start.character), (2, 9));
+
+        // Hints without data are left as they are
+        let hint = InlayHint {
+            data: None,
+            ..hints.remove(0)
+        };
+        let hint = snapshot.resolve_inlay_hint(hint);
+        assert!(matches!(hint.label, InlayHintLabel::String(label) if label == "name:"));
+    }
+}
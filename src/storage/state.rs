@@ -1,36 +1,288 @@
-use log::error;
-
-use super::db::{LspDatabase, Storage};
-use crate::lsp::{
-    document_sync::{
-        DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
-        Position, Range,
-    },
-    language_features::{self, GotoDefinitionParams},
+use log::{info, warn};
+use salsa::ParallelDatabase;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use super::db::{DocumentTreeQuery, LspDatabase, Storage, TREE_CAPACITY};
+use super::environment::{EnvironmentSource, PythonEnvironment};
+use super::files::python_files;
+use super::line_index::PositionEncoding;
+use super::snapshot::{LspSnapshot, SentTokens};
+use super::typeshed;
+use super::uri::{path_to_uri, uri_to_path};
+use crate::config::Settings;
+use crate::lsp::document_sync::{
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    TextDocumentIdentifier,
 };
+use crate::lsp::lifecycle::ClientCapabilities;
+use crate::parser::platform::PythonPlatform;
+use crate::parser::version::PythonVersion;
 use std::sync::Arc;
 
 pub struct LspState {
     db: LspDatabase,
-    opened_uri: Option<String>,
+    root: Option<PathBuf>,
+
+    // Settings layers, from lowest to highest precedence
+    file_settings: Settings,
+    initialization_settings: Settings,
+    configuration_settings: Settings,
+    settings: Arc<Settings>,
+    sent_tokens: Arc<SentTokens>,
+    // The connections that have each open document open, by uri
+    document_connections: HashMap<String, HashSet<usize>>,
+    // What the current environment was detected from
+    detected_environment: Option<EnvironmentSource>,
+    // What an environment is being detected from, to not detect it twice
+    detecting_environment: Option<EnvironmentSource>,
 }
 
 impl LspState {
     pub fn new() -> Self {
+        let mut db = LspDatabase::default();
+        DocumentTreeQuery
+            .in_db_mut(&mut db)
+            .set_lru_capacity(TREE_CAPACITY);
+        db.set_open_documents(Arc::new(Default::default()));
+        db.set_changed_files(Arc::new(Default::default()));
+        db.set_python_version(PythonVersion::default());
+        db.set_python_platform(PythonPlatform::default());
+        db.set_typeshed_path(None);
+        db.set_search_paths(Arc::new(Vec::new()));
+        db.set_python_environment(Arc::new(PythonEnvironment::default()));
+        db.set_resolution_revision(0);
+        db.set_workspace_files(Arc::new(Vec::new()));
+
         Self {
-            opened_uri: None,
-            db: LspDatabase::default(),
+            db,
+            root: None,
+            file_settings: Settings::default(),
+            initialization_settings: Settings::default(),
+            configuration_settings: Settings::default(),
+            settings: Arc::default(),
+            sent_tokens: Arc::default(),
+            document_connections: HashMap::new(),
+            detected_environment: None,
+            detecting_environment: None,
+        }
+    }
+
+    /// Take a snapshot to answer requests of a client from, possibly on another thread.
+    /// Clients share the state, but each counts positions and supports features its way.
+    pub fn snapshot(
+        &self,
+        position_encoding: PositionEncoding,
+        client_capabilities: Arc<ClientCapabilities>,
+    ) -> LspSnapshot {
+        LspSnapshot::new(
+            self.db.snapshot(),
+            position_encoding,
+            client_capabilities,
+            self.settings.clone(),
+            self.sent_tokens.clone(),
+        )
+    }
+
+    /// The settings in effect, with all layers merged
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Set the workspace root along with the settings the client sent in `initialize`
+    pub fn set_workspace(&mut self, root: Option<PathBuf>, initialization_settings: Settings) {
+        self.initialization_settings = self.resolve_client_paths(initialization_settings, &root);
+        self.root = root;
+        self.reload_settings_files();
+    }
+
+    /// Replace the settings sent with `workspace/didChangeConfiguration`
+    pub fn set_configuration_settings(&mut self, settings: Settings) {
+        self.configuration_settings = self.resolve_client_paths(settings, &self.root);
+        self.apply_settings();
+    }
+
+    /// Read the settings files of the workspace again
+    pub fn reload_settings_files(&mut self) {
+        self.file_settings = match &self.root {
+            Some(root) => Settings::load(root),
+            None => Settings::default(),
+        };
+        self.apply_settings();
+    }
+
+    fn resolve_client_paths(&self, settings: Settings, root: &Option<PathBuf>) -> Settings {
+        match root {
+            Some(root) => settings.resolve_paths(root),
+            None => settings,
+        }
+    }
+
+    /// Merge the settings layers and update everything that depends on them.
+    /// Inputs are only set when they change, as setting one invalidates its queries.
+    fn apply_settings(&mut self) {
+        let settings = self
+            .file_settings
+            .clone()
+            .merge(self.initialization_settings.clone())
+            .merge(self.configuration_settings.clone());
+        self.settings = Arc::new(settings.clone());
+        info!("Using settings: {:?}", settings);
+
+        let environment = self.db.python_environment();
+        let python_version = match &settings.python_version {
+            Some(version) => PythonVersion::parse(version).unwrap_or_else(|| {
+                warn!("Invalid python version: {}", version);
+                PythonVersion::default()
+            }),
+            None => environment.version.unwrap_or_default(),
+        };
+        if self.db.python_version() != python_version {
+            self.db.set_python_version(python_version);
+        }
+
+        let python_platform = match &settings.python_platform {
+            Some(platform) => PythonPlatform::parse(platform).unwrap_or_else(|| {
+                warn!("Unknown python platform: {}", platform);
+                PythonPlatform::Other
+            }),
+            None => PythonPlatform::host(),
+        };
+        if self.db.python_platform() != python_platform {
+            self.db.set_python_platform(python_platform);
+        }
+
+        let typeshed =
+            typeshed::find_typeshed(settings.typeshed_path.clone(), &environment.site_packages);
+        if self.db.typeshed_path() != typeshed {
+            self.db.set_typeshed_path(typeshed);
+        }
+
+        // Extra paths are searched before the workspace itself
+        let mut search_paths = settings.extra_paths.clone().unwrap_or_default();
+        search_paths.extend(self.root.clone());
+        if *self.db.search_paths() != search_paths {
+            self.db.set_search_paths(Arc::new(search_paths));
+        }
+
+        self.update_workspace_files();
+    }
+
+    /// What the environment should be detected from with the current settings.
+    /// Interpreters the client configured may be run, ones from settings files may not.
+    fn environment_source(&self) -> EnvironmentSource {
+        let client_python_path = self
+            .configuration_settings
+            .python_path
+            .clone()
+            .or_else(|| self.initialization_settings.python_path.clone());
+        EnvironmentSource {
+            trusted: client_python_path.is_some(),
+            python_path: client_python_path.or_else(|| self.file_settings.python_path.clone()),
+            root: self.root.clone(),
+        }
+    }
+
+    /// What to detect the environment from, when the settings call for another
+    /// environment. Detecting may run the interpreter, so it is up to the caller
+    /// to do it off the thread that handles messages.
+    pub fn take_environment_detection(&mut self) -> Option<EnvironmentSource> {
+        let source = self.environment_source();
+        if self.detected_environment.as_ref() == Some(&source)
+            || self.detecting_environment.as_ref() == Some(&source)
+        {
+            return None;
+        }
+        self.detecting_environment = Some(source.clone());
+        Some(source)
+    }
+
+    /// Use the environment detected from `source`, unless the settings changed since
+    pub fn set_python_environment(
+        &mut self,
+        source: EnvironmentSource,
+        environment: PythonEnvironment,
+    ) {
+        if self.detecting_environment.as_ref() == Some(&source) {
+            self.detecting_environment = None;
+        }
+        if source != self.environment_source() {
+            return;
+        }
+        self.detected_environment = Some(source);
+        if *self.db.python_environment() != environment {
+            self.db.set_python_environment(Arc::new(environment));
+            self.invalidate_module_resolution();
+        }
+        self.apply_settings();
+    }
+
+    /// Look for the Python files of the workspace again, after files were
+    /// created or deleted
+    pub fn update_workspace_files(&mut self) {
+        let workspace_files: Vec<String> = match &self.root {
+            Some(root) => python_files(std::slice::from_ref(root), root, &self.settings)
+                .iter()
+                .map(|path| path_to_uri(path))
+                .collect(),
+            None => Vec::new(),
+        };
+        if *self.db.workspace_files() != workspace_files {
+            self.db.set_workspace_files(Arc::new(workspace_files));
         }
     }
 
-    pub fn open_document(&mut self, params: DidOpenTextDocumentParams) {
+    /// Resolve modules again, after files were created or deleted
+    pub fn invalidate_module_resolution(&mut self) {
+        let revision = self.db.resolution_revision();
+        self.db.set_resolution_revision(revision + 1);
+    }
+
+    /// Read files again that changed on disk. Open documents are read too,
+    /// since they fall back to their contents on disk once closed.
+    pub fn change_files(&mut self, uris: &[String]) {
+        let mut changed_files = (*self.db.changed_files()).clone();
+        for uri in uris {
+            let text = uri_to_path(uri)
+                .and_then(|path| std::fs::read_to_string(path).ok())
+                .map(Arc::new);
+            if changed_files.insert(uri.clone()) || self.db.file_text(uri.clone()) != text {
+                self.db.set_file_text(uri.clone(), text);
+            }
+        }
+        if changed_files != *self.db.changed_files() {
+            self.db.set_changed_files(Arc::new(changed_files));
+        }
+    }
+
+    /// The Python files of the workspace
+    pub fn workspace_files(&self) -> Arc<Vec<String>> {
+        self.db.workspace_files()
+    }
+
+    /// The documents the client has open
+    pub fn open_documents(&self) -> Vec<String> {
+        self.db.open_documents().iter().cloned().collect()
+    }
+
+    /// Open a document for a connection. Clients connected side by side may
+    /// each open the same document, which stays open until all closed it.
+    pub fn open_document(&mut self, connection: usize, params: DidOpenTextDocumentParams) {
         let uri = params.text_document.uri;
         self.db
             .set_document_text(uri.clone(), Some(Arc::new(params.text_document.text)));
         self.db
             .set_document_version(uri.clone(), Some(params.text_document.version));
+        self.document_connections
+            .entry(uri.clone())
+            .or_default()
+            .insert(connection);
 
-        self.opened_uri = Some(uri);
+        if !self.db.open_documents().contains(&uri) {
+            let mut open_documents = (*self.db.open_documents()).clone();
+            open_documents.insert(uri);
+            self.db.set_open_documents(Arc::new(open_documents));
+        }
     }
 
     pub fn update_document(&mut self, params: DidChangeTextDocumentParams) {
@@ -43,44 +295,199 @@ impl LspState {
         }
     }
 
-    pub fn close_document(&mut self, _params: DidCloseTextDocumentParams) {
-        // TODO: Add proper error handling
-        self.opened_uri = None;
-    }
-
-    pub fn get_definition(
+    /// Close a document for a connection, returning whether that was the last one
+    pub fn close_document(
         &mut self,
-        params: GotoDefinitionParams,
-    ) -> Option<language_features::Location> {
-        let uri = match &self.opened_uri {
-            Some(uri) => uri,
-            None => {
-                error!("Go to defintion was called without an opened uri");
-                return None;
-            }
+        connection: usize,
+        params: DidCloseTextDocumentParams,
+    ) -> bool {
+        let uri = params.text_document.uri;
+        let Some(connections) = self.document_connections.get_mut(&uri) else {
+            return false;
         };
+        connections.remove(&connection);
+        if !connections.is_empty() {
+            return false;
+        }
+        self.document_connections.remove(&uri);
+
+        // The file on disk is the source of truth again
+        let mut open_documents = (*self.db.open_documents()).clone();
+        open_documents.remove(&uri);
+        self.db.set_open_documents(Arc::new(open_documents));
+        self.sent_tokens.forget(&uri);
+        true
+    }
+
+    /// Close the documents a connection left open when it went away
+    pub fn close_connection(&mut self, connection: usize) {
+        let uris: Vec<String> = self
+            .document_connections
+            .iter()
+            .filter(|(_, connections)| connections.contains(&connection))
+            .map(|(uri, _)| uri.clone())
+            .collect();
+        for uri in uris {
+            let params = DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri },
+            };
+            self.close_document(connection, params);
+        }
+    }
+}
 
-        // Get the reference graph that should be already computed
-        let reference_graph = self.db.document_reference_graph(uri.clone())?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp::document_sync::{Position, TextDocumentIdentifier};
+    use crate::lsp::language_features::GotoDefinitionParams;
 
-        // Get the symbol's definiton
-        let symbol_definition = reference_graph.find_definition(params.position)?;
+    #[test]
+    fn files_changed_on_disk() {
+        let root = std::env::temp_dir().join(format!("rypy-state-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.py"), "from b import x\nx\n").unwrap();
+        std::fs::write(root.join("b.py"), "x = 1\n").unwrap();
+        let (a, b) = (
+            path_to_uri(&root.join("a.py")),
+            path_to_uri(&root.join("b.py")),
+        );
 
-        // Create the response
-        let location = language_features::Location {
-            uri: uri.to_string(),
-            range: Range {
-                start: Position {
-                    line: symbol_definition.location.start.0,
-                    character: symbol_definition.location.start.1,
+        let mut state = LspState::new();
+        state.set_workspace(Some(root.clone()), Settings::default());
+        let definition = |state: &LspState| {
+            let params = GotoDefinitionParams {
+                text_document: TextDocumentIdentifier { uri: a.clone() },
+                position: Position {
+                    line: 1,
+                    character: 0,
                 },
-                end: Position {
-                    line: symbol_definition.location.end.0,
-                    character: symbol_definition.location.end.1,
+            };
+            let location = state
+                .snapshot(PositionEncoding::default(), Arc::default())
+                .get_definition(params)?;
+            Some((location.uri, location.range.start.line))
+        };
+        assert_eq!(definition(&state), Some((b.clone(), 0)));
+
+        // Files are only read again once the client says they changed
+        std::fs::write(root.join("b.py"), "\n\nx = 1\n").unwrap();
+        assert_eq!(definition(&state), Some((b.clone(), 0)));
+        state.change_files(std::slice::from_ref(&b));
+        assert_eq!(definition(&state), Some((b.clone(), 2)));
+
+        std::fs::remove_file(root.join("b.py")).unwrap();
+        state.change_files(std::slice::from_ref(&b));
+        assert_ne!(definition(&state).map(|(uri, _)| uri), Some(b));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn modules_created_later() {
+        let root = std::env::temp_dir().join(format!("rypy-modules-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.py"), "from c import y\ny\n").unwrap();
+        let a = path_to_uri(&root.join("a.py"));
+
+        let mut state = LspState::new();
+        state.set_workspace(Some(root.clone()), Settings::default());
+        let definition = |state: &LspState| {
+            let params = GotoDefinitionParams {
+                text_document: TextDocumentIdentifier { uri: a.clone() },
+                position: Position {
+                    line: 1,
+                    character: 0,
                 },
-            },
+            };
+            state
+                .snapshot(PositionEncoding::default(), Arc::default())
+                .get_definition(params)
+                .map(|location| location.uri)
+        };
+        assert_ne!(definition(&state), Some(path_to_uri(&root.join("c.py"))));
+
+        std::fs::write(root.join("c.py"), "y = 1\n").unwrap();
+        state.invalidate_module_resolution();
+        assert_eq!(definition(&state), Some(path_to_uri(&root.join("c.py"))));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn environment_detection() {
+        let root = std::env::temp_dir().join(format!("rypy-detection-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("rypy.toml"), "python-path = \"tools/python\"\n").unwrap();
+
+        // Interpreters from settings files aren't trusted to run
+        let mut state = LspState::new();
+        state.set_workspace(Some(root.clone()), Settings::default());
+        let source = state.take_environment_detection().unwrap();
+        assert_eq!(source.python_path, Some(root.join("tools/python")));
+        assert!(!source.trusted);
+        assert_eq!(state.take_environment_detection(), None);
+
+        // Ones the client configured are, and make the earlier detection stale
+        let settings = Settings {
+            python_path: Some(PathBuf::from("/usr/bin/python3")),
+            ..Settings::default()
         };
+        state.set_configuration_settings(settings);
+        let environment = PythonEnvironment {
+            version: Some(PythonVersion::new(3, 9)),
+            ..PythonEnvironment::default()
+        };
+        state.set_python_environment(source, environment.clone());
+        assert_eq!(*state.db.python_environment(), PythonEnvironment::default());
+
+        let source = state.take_environment_detection().unwrap();
+        assert_eq!(source.python_path, Some(PathBuf::from("/usr/bin/python3")));
+        assert!(source.trusted);
+        state.set_python_environment(source, environment.clone());
+        assert_eq!(*state.db.python_environment(), environment);
+        assert_eq!(state.db.python_version(), PythonVersion::new(3, 9));
+        assert_eq!(state.take_environment_detection(), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn documents_open_in_several_connections() {
+        let uri = "file:///tmp/rypy-shared/a.py".to_string();
+        let open = |state: &mut LspState, connection| {
+            let params: DidOpenTextDocumentParams = serde_json::from_value(serde_json::json!({
+                "textDocument": {"uri": uri, "languageId": "python", "version": 1, "text": "x = 1\n"}
+            }))
+            .unwrap();
+            state.open_document(connection, params);
+        };
+        let close = |state: &mut LspState, connection| {
+            let params = DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+            };
+            state.close_document(connection, params)
+        };
+
+        let mut state = LspState::new();
+        open(&mut state, 1);
+        open(&mut state, 2);
+        open(&mut state, 2);
+        assert!(!close(&mut state, 1));
+        assert_eq!(state.open_documents(), vec![uri.clone()]);
+        assert!(!close(&mut state, 1));
+        assert!(close(&mut state, 2));
+        assert!(state.open_documents().is_empty());
 
-        Some(location)
+        // Documents a connection left open are closed when it goes away
+        open(&mut state, 1);
+        open(&mut state, 2);
+        state.close_connection(2);
+        assert_eq!(state.open_documents(), vec![uri.clone()]);
+        state.close_connection(1);
+        assert!(state.open_documents().is_empty());
     }
 }
@@ -0,0 +1,156 @@
+use log::{error, info};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+/// A small builtins stub shipped with the server, used when no typeshed is available
+const BUNDLED_BUILTINS: &str = include_str!("../../stubs/builtins.pyi");
+
+/// Where the bundled builtins stub was written at startup, if it could be
+static BUNDLED_BUILTINS_PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Locate a typeshed checkout, trying the explicitly configured path first,
+/// then `RYPY_TYPESHED`, the directories next to the executable and finally
+/// the copies bundled with tools installed in the python environment.
+pub fn find_typeshed(configured: Option<PathBuf>, site_packages: &[PathBuf]) -> Option<PathBuf> {
+    let mut candidates: Vec<PathBuf> = configured.into_iter().collect();
+    if let Some(path) = std::env::var_os("RYPY_TYPESHED") {
+        candidates.push(PathBuf::from(path));
+    }
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(exe_dir.join("typeshed"));
+        candidates.push(exe_dir.join("../share/rypy/typeshed"));
+    }
+    for directory in site_packages {
+        candidates.push(directory.join("mypy").join("typeshed"));
+        candidates.push(directory.join("jedi").join("third_party").join("typeshed"));
+    }
+
+    let typeshed = candidates.into_iter().find(|path| is_typeshed(path));
+    match &typeshed {
+        Some(path) => info!("Using typeshed at {:?}", path),
+        None => info!("No typeshed found, using the bundled builtins stub"),
+    }
+    typeshed
+}
+
+/// Check if a directory looks like a typeshed checkout
+pub fn is_typeshed(path: &Path) -> bool {
+    path.join("stdlib").join("builtins.pyi").is_file()
+}
+
+/// Write the bundled builtins stub to disk, so that editors can open it when
+/// jumping to a definition. This is done once at startup, before any analysis.
+pub fn write_bundled_builtins() {
+    BUNDLED_BUILTINS_PATH.get_or_init(|| match write_stub(&cache_directory()) {
+        Ok(path) => Some(path),
+        Err(e) => {
+            error!("Failed to write the bundled builtins stub: {}", e);
+            None
+        }
+    });
+}
+
+/// The path of the bundled builtins stub, if it could be written at startup
+pub fn bundled_builtins_path() -> Option<PathBuf> {
+    BUNDLED_BUILTINS_PATH.get().cloned().flatten()
+}
+
+/// A cache directory private to the current user, falling back to the temporary
+/// directory when the platform does not name one
+fn cache_directory() -> PathBuf {
+    let cache = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    let version = env!("CARGO_PKG_VERSION");
+    match cache {
+        Some(cache) => cache.join("rypy").join(version),
+        None => std::env::temp_dir().join(format!("rypy-{}", version)),
+    }
+}
+
+/// Write the stub into `directory`, through a temporary file renamed into place
+/// so that concurrent servers never read a partially written stub
+fn write_stub(directory: &Path) -> std::io::Result<PathBuf> {
+    create_private_dir(directory)?;
+    let path = directory.join("builtins.pyi");
+    if std::fs::read_to_string(&path).is_ok_and(|text| text == BUNDLED_BUILTINS) {
+        return Ok(path);
+    }
+
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let temporary = directory.join(format!(
+        "builtins.pyi.{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&temporary, BUNDLED_BUILTINS)
+        .and_then(|_| std::fs::rename(&temporary, &path))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&temporary);
+        })?;
+    Ok(path)
+}
+
+/// Create a directory only the current user can access. An existing directory
+/// is only accepted if it is a real directory that others cannot write into.
+fn create_private_dir(directory: &Path) -> std::io::Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(directory)?;
+
+    let metadata = std::fs::symlink_metadata(directory)?;
+    if !metadata.is_dir() {
+        return Err(std::io::Error::other(format!(
+            "{:?} is not a directory",
+            directory
+        )));
+    }
+    #[cfg(unix)]
+    if std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o077 != 0 {
+        return Err(std::io::Error::other(format!(
+            "{:?} is accessible to other users",
+            directory
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_stub_concurrently() {
+        let directory = std::env::temp_dir()
+            .join(format!("rypy-typeshed-write-{}", std::process::id()))
+            .join("cache");
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let directory = directory.clone();
+                std::thread::spawn(move || write_stub(&directory).unwrap())
+            })
+            .collect();
+        for thread in threads {
+            let path = thread.join().unwrap();
+            assert_eq!(std::fs::read_to_string(path).unwrap(), BUNDLED_BUILTINS);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&directory).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        std::fs::remove_dir_all(directory.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+use log::{error, info};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+
+/// How the server talks to its clients
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub enum Transport {
+    #[default]
+    Stdio,
+    /// Accept clients on a TCP port of localhost
+    Listen(u16),
+    /// Connect to a client that is listening on `host:port`
+    Connect(String),
+    /// Accept clients on a Unix domain socket
+    Socket(PathBuf),
+}
+
+/// A connection to a client, as a pair of a reader and a writer
+pub struct Connection {
+    pub reader: MessageReader,
+    pub writer: Box<dyn Write + Send>,
+}
+
+impl Connection {
+    pub fn new(reader: impl Read + Send + 'static, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            reader: MessageReader {
+                reader: Box::new(BufReader::new(reader)),
+            },
+            writer: Box::new(writer),
+        }
+    }
+
+    pub fn stdio() -> Self {
+        Self::new(io::stdin(), io::stdout())
+    }
+
+    /// Connect to a client listening on `host:port`
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        info!("Connected to {}", address);
+        Ok(Self::new(stream.try_clone()?, stream))
+    }
+}
+
+/// Reads the messages a client sends
+pub struct MessageReader {
+    reader: Box<dyn BufRead + Send>,
+}
+
+impl MessageReader {
+    /// Extracts the JSON-RPC message from the reader.
+    pub fn read_message(&mut self) -> io::Result<String> {
+        let mut buffer = String::new();
+        let mut content_length = None;
+
+        // Read headers
+        loop {
+            buffer.clear();
+            if self.reader.read_line(&mut buffer)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Connection closed",
+                ));
+            }
+
+            // Check for the end of headers
+            if buffer.trim().is_empty() {
+                break;
+            }
+
+            // Parse headers
+            if let Some((key, value)) = buffer.split_once(": ") {
+                if key == "Content-Length" {
+                    content_length = Some(value.trim().parse::<usize>().map_err(|e| {
+                        error!("Invalid Content-Length: {}", e);
+                        io::Error::new(io::ErrorKind::InvalidData, "Invalid Content-Length")
+                    })?);
+                }
+            } else {
+                error!("Malformed header: {}", buffer);
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Malformed header",
+                ));
+            }
+        }
+
+        // Ensure Content-Length is found
+        let content_length = content_length.ok_or_else(|| {
+            error!("Missing Content-Length header");
+            io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header")
+        })?;
+
+        // Read the body
+        let mut body = vec![0; content_length];
+        self.reader.read_exact(&mut body)?;
+
+        // Convert body to UTF-8 string
+        String::from_utf8(body).map_err(|e| {
+            error!("Invalid UTF-8 body: {}", e);
+            io::Error::new(io::ErrorKind::InvalidData, format!("Invalid UTF-8: {}", e))
+        })
+    }
+}
+
+/// Accepts connections for the `Listen` and `Socket` transports
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+impl Listener {
+    pub fn bind(transport: &Transport) -> io::Result<Self> {
+        match transport {
+            Transport::Listen(port) => {
+                let listener = TcpListener::bind(("127.0.0.1", *port))?;
+                info!("Listening on {}", listener.local_addr()?);
+                Ok(Self::Tcp(listener))
+            }
+            #[cfg(unix)]
+            Transport::Socket(path) => {
+                // A socket left behind by a previous server would make binding fail,
+                // but anything else at the path is left alone
+                use std::os::unix::fs::FileTypeExt;
+                match std::fs::symlink_metadata(path) {
+                    Ok(metadata) if !metadata.file_type().is_socket() => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{:?} exists and is not a socket", path),
+                        ));
+                    }
+                    Ok(_) if std::os::unix::net::UnixStream::connect(path).is_err() => {
+                        std::fs::remove_file(path)?;
+                    }
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+                let listener = std::os::unix::net::UnixListener::bind(path)?;
+                info!("Listening on {:?}", path);
+                Ok(Self::Unix(listener))
+            }
+            #[cfg(not(unix))]
+            Transport::Socket(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Unix sockets are not supported on this platform",
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Transport doesn't accept connections",
+            )),
+        }
+    }
+
+    /// Wait for the next client
+    pub fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, address) = listener.accept()?;
+                info!("Accepted connection from {}", address);
+                Ok(Connection::new(stream.try_clone()?, stream))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                info!("Accepted connection");
+                Ok(Connection::new(stream.try_clone()?, stream))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc;
+
+    /// Send a message over a fresh connection of `listener`, and read it back
+    fn roundtrip(listener: &Listener, connect: impl FnOnce() -> Connection + Send + 'static) {
+        let client = std::thread::spawn(move || {
+            let mut connection = connect();
+            rpc::write_message(&mut connection.writer, r#"{"id":1}"#).unwrap();
+            connection.writer.flush().unwrap();
+            connection.reader.read_message().unwrap()
+        });
+
+        let mut connection = listener.accept().unwrap();
+        assert_eq!(connection.reader.read_message().unwrap(), r#"{"id":1}"#);
+        rpc::write_message(&mut connection.writer, r#"{"id":2}"#).unwrap();
+        connection.writer.flush().unwrap();
+        assert_eq!(client.join().unwrap(), r#"{"id":2}"#);
+    }
+
+    #[test]
+    fn tcp() {
+        let listener = Listener::bind(&Transport::Listen(0)).unwrap();
+        let Listener::Tcp(tcp) = &listener else {
+            panic!("Expected a TCP listener");
+        };
+        let address = tcp.local_addr().unwrap().to_string();
+        roundtrip(&listener, move || Connection::connect(&address).unwrap());
+
+        assert!(Listener::bind(&Transport::Stdio).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket() {
+        use std::os::unix::net::UnixStream;
+
+        let directory = std::env::temp_dir().join(format!("rypy-socket-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("rypy.sock");
+        let transport = Transport::Socket(path.clone());
+        let connect = |path: PathBuf| {
+            move || {
+                let stream = UnixStream::connect(path).unwrap();
+                Connection::new(stream.try_clone().unwrap(), stream)
+            }
+        };
+
+        let listener = Listener::bind(&transport).unwrap();
+        roundtrip(&listener, connect(path.clone()));
+        // Another server can't take over a socket that is in use
+        assert!(Listener::bind(&transport).is_err());
+        assert!(UnixStream::connect(&path).is_ok());
+
+        // The socket a server left behind is replaced
+        drop(listener);
+        let listener = Listener::bind(&transport).unwrap();
+        roundtrip(&listener, connect(path.clone()));
+        drop(listener);
+
+        // Other files are never removed
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, "data").unwrap();
+        let error = Listener::bind(&transport).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
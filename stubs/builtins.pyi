@@ -0,0 +1,170 @@
+# Minimal builtins stub bundled with rypy. It is only used when no
+# typeshed checkout can be found, so that the most common builtins
+# still resolve to something.
+import sys
+from typing import Any, Iterable, Iterator, Callable, TypeVar, Generic
+
+_T = TypeVar("_T")
+_KT = TypeVar("_KT")
+_VT = TypeVar("_VT")
+
+class object:
+    def __init__(self) -> None: ...
+    def __repr__(self) -> str: ...
+    def __str__(self) -> str: ...
+    def __eq__(self, value: object, /) -> bool: ...
+    def __hash__(self) -> int: ...
+
+class type:
+    def __init__(self, o: object, /) -> None: ...
+    def mro(self) -> list[type]: ...
+
+class int:
+    def __init__(self, x: object = ..., /) -> None: ...
+    def bit_length(self) -> int: ...
+    def to_bytes(self, length: int = 1, byteorder: str = "big", *, signed: bool = False) -> bytes: ...
+
+class float:
+    def __init__(self, x: object = ..., /) -> None: ...
+    def is_integer(self) -> bool: ...
+
+class complex:
+    def __init__(self, real: float = ..., imag: float = ...) -> None: ...
+
+class bool(int):
+    def __init__(self, o: object = ..., /) -> None: ...
+
+class str:
+    def __init__(self, object: object = ...) -> None: ...
+    def format(self, *args: object, **kwargs: object) -> str: ...
+    def join(self, iterable: Iterable[str], /) -> str: ...
+    def split(self, sep: str | None = None, maxsplit: int = -1) -> list[str]: ...
+    def strip(self, chars: str | None = None, /) -> str: ...
+    def startswith(self, prefix: str, /) -> bool: ...
+    def endswith(self, suffix: str, /) -> bool: ...
+    def replace(self, old: str, new: str, count: int = -1, /) -> str: ...
+    def lower(self) -> str: ...
+    def upper(self) -> str: ...
+    def encode(self, encoding: str = "utf-8", errors: str = "strict") -> bytes: ...
+    if sys.version_info >= (3, 9):
+        def removeprefix(self, prefix: str, /) -> str: ...
+        def removesuffix(self, suffix: str, /) -> str: ...
+
+class bytes:
+    def __init__(self, o: object = ..., /) -> None: ...
+    def decode(self, encoding: str = "utf-8", errors: str = "strict") -> str: ...
+
+class list(Generic[_T]):
+    def __init__(self, iterable: Iterable[_T] = ..., /) -> None: ...
+    def append(self, object: _T, /) -> None: ...
+    def extend(self, iterable: Iterable[_T], /) -> None: ...
+    def pop(self, index: int = -1, /) -> _T: ...
+    def insert(self, index: int, object: _T, /) -> None: ...
+    def remove(self, value: _T, /) -> None: ...
+    def sort(self, *, key: Callable[[_T], Any] | None = None, reverse: bool = False) -> None: ...
+
+class tuple(Generic[_T]):
+    def __init__(self, iterable: Iterable[_T] = ..., /) -> None: ...
+    def count(self, value: Any, /) -> int: ...
+    def index(self, value: Any, /) -> int: ...
+
+class dict(Generic[_KT, _VT]):
+    def __init__(self, *args: Any, **kwargs: _VT) -> None: ...
+    def keys(self) -> Iterable[_KT]: ...
+    def values(self) -> Iterable[_VT]: ...
+    def items(self) -> Iterable[tuple[_KT, _VT]]: ...
+    def get(self, key: _KT, default: _VT | None = None, /) -> _VT | None: ...
+    def pop(self, key: _KT, /) -> _VT: ...
+    def update(self, *args: Any, **kwargs: _VT) -> None: ...
+
+class set(Generic[_T]):
+    def __init__(self, iterable: Iterable[_T] = ..., /) -> None: ...
+    def add(self, element: _T, /) -> None: ...
+    def discard(self, element: _T, /) -> None: ...
+
+class frozenset(Generic[_T]):
+    def __init__(self, iterable: Iterable[_T] = ..., /) -> None: ...
+
+class range:
+    def __init__(self, start: int, stop: int = ..., step: int = ..., /) -> None: ...
+
+class slice:
+    def __init__(self, start: Any, stop: Any = ..., step: Any = ..., /) -> None: ...
+
+class property:
+    def __init__(self, fget: Callable[[Any], Any] | None = ..., fset: Callable[[Any, Any], None] | None = ...) -> None: ...
+
+class staticmethod(Generic[_T]):
+    def __init__(self, f: Callable[..., _T], /) -> None: ...
+
+class classmethod(Generic[_T]):
+    def __init__(self, f: Callable[..., _T], /) -> None: ...
+
+class BaseException:
+    args: tuple[Any, ...]
+    def __init__(self, *args: object) -> None: ...
+    def with_traceback(self, tb: Any, /) -> BaseException: ...
+
+class Exception(BaseException): ...
+class ArithmeticError(Exception): ...
+class ZeroDivisionError(ArithmeticError): ...
+class AssertionError(Exception): ...
+class AttributeError(Exception): ...
+class ImportError(Exception): ...
+class ModuleNotFoundError(ImportError): ...
+class LookupError(Exception): ...
+class IndexError(LookupError): ...
+class KeyError(LookupError): ...
+class NameError(Exception): ...
+class NotImplementedError(Exception): ...
+class OSError(Exception): ...
+class FileNotFoundError(OSError): ...
+class RuntimeError(Exception): ...
+class StopIteration(Exception): ...
+class TypeError(Exception): ...
+class ValueError(Exception): ...
+class KeyboardInterrupt(BaseException): ...
+
+def abs(x: Any, /) -> Any: ...
+def all(iterable: Iterable[object], /) -> bool: ...
+def any(iterable: Iterable[object], /) -> bool: ...
+def callable(obj: object, /) -> bool: ...
+def chr(i: int, /) -> str: ...
+def dir(o: object = ..., /) -> list[str]: ...
+def enumerate(iterable: Iterable[_T], start: int = 0) -> Iterator[tuple[int, _T]]: ...
+def filter(function: Callable[[_T], Any] | None, iterable: Iterable[_T], /) -> Iterator[_T]: ...
+def getattr(o: object, name: str, default: Any = ..., /) -> Any: ...
+def hasattr(obj: object, name: str, /) -> bool: ...
+def hash(obj: object, /) -> int: ...
+def id(obj: object, /) -> int: ...
+def input(prompt: object = "", /) -> str: ...
+def isinstance(obj: object, class_or_tuple: Any, /) -> bool: ...
+def issubclass(cls: type, class_or_tuple: Any, /) -> bool: ...
+def iter(object: Iterable[_T], /) -> Iterator[_T]: ...
+def len(obj: Any, /) -> int: ...
+def map(func: Callable[..., _T], iterable: Iterable[Any], /) -> Iterator[_T]: ...
+def max(*args: Any, key: Callable[[Any], Any] | None = None) -> Any: ...
+def min(*args: Any, key: Callable[[Any], Any] | None = None) -> Any: ...
+def next(i: Iterator[_T], default: _T = ..., /) -> _T: ...
+def open(file: str, mode: str = "r", buffering: int = -1, encoding: str | None = None) -> Any: ...
+def ord(c: str, /) -> int: ...
+def print(*values: object, sep: str | None = " ", end: str | None = "\n", file: Any = None, flush: bool = False) -> None: ...
+def repr(obj: object, /) -> str: ...
+def reversed(sequence: Iterable[_T], /) -> Iterator[_T]: ...
+def round(number: float, ndigits: int | None = None) -> Any: ...
+def setattr(obj: object, name: str, value: Any, /) -> None: ...
+def sorted(iterable: Iterable[_T], /, *, key: Callable[[_T], Any] | None = None, reverse: bool = False) -> list[_T]: ...
+def sum(iterable: Iterable[Any], /, start: Any = 0) -> Any: ...
+def super(*args: Any) -> Any: ...
+def vars(object: Any = ..., /) -> dict[str, Any]: ...
+def zip(*iterables: Iterable[Any]) -> Iterator[tuple[Any, ...]]: ...
+
+if sys.version_info >= (3, 10):
+    def aiter(async_iterable: Any, /) -> Any: ...
+    def anext(i: Any, default: Any = ..., /) -> Any: ...
+
+Ellipsis: Any
+NotImplemented: Any
+__name__: str
+__file__: str
+__doc__: str | None